@@ -0,0 +1,106 @@
+//! Throughput of the receive path, a second of a bus at 10k frames/s formatted into frame messages
+//! and encoded in batches in each format, the way the websocket sessions send them
+//!
+//! Run by `cargo bench --bench pipeline`, a frame rate above 10k frames/s leaving the bus to a
+//! fraction of a core.
+
+use std::hint::black_box;
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_vue::can::Timestamp;
+use rust_vue::frame::{CanAnyFrame, EmbeddedFrame};
+use rust_vue::protocol::{format_frame, parse_frame, Format, FrameDirection, FrameMessage, ServerMessage};
+
+const FRAMES_PER_SECOND: usize = 10_000;
+// frames of a batch, the bound of the batches of the websocket sessions
+const BATCH: usize = 1000;
+
+fn frames() -> Vec<CanAnyFrame> {
+    (0..FRAMES_PER_SECOND)
+        .map(|i| {
+            let frame = match i % 4 {
+                0 => format!("{:03X}#{:016X}", i % 0x800, i * 0x0101_0101),
+                1 => format!("{:08X}#{:08X}", i * 31, i),
+                2 => format!("{:03X}##1{:032X}", i % 0x800, i),
+                _ => format!("{:03X}#R", i % 0x800),
+            };
+            parse_frame(&frame).unwrap()
+        })
+        .collect()
+}
+
+fn frame_message(interface: &Arc<str>, frame: &CanAnyFrame) -> FrameMessage {
+    let (data, fd) = format_frame(frame);
+    FrameMessage {
+        interface: interface.clone(),
+        frame: data,
+        extended: frame.is_extended(),
+        remote: frame.is_remote_frame(),
+        fd,
+        timestamp: Timestamp::now().into(),
+        direction: FrameDirection::Rx,
+        client: None,
+        decoded: None,
+        name: None,
+        canopen: None,
+        custom: None,
+        changed: None,
+    }
+}
+
+fn batches(interface: &Arc<str>, frames: &[CanAnyFrame]) -> Vec<ServerMessage> {
+    frames
+        .chunks(BATCH)
+        .map(|chunk| ServerMessage::Frames(chunk.iter().map(|frame| frame_message(interface, frame)).collect()))
+        .collect()
+}
+
+fn format(c: &mut Criterion) {
+    let interface: Arc<str> = Arc::from("vcan0");
+    let frames = frames();
+    let mut group = c.benchmark_group("format");
+    group.throughput(Throughput::Elements(FRAMES_PER_SECOND as u64));
+    group.bench_function("format_frame", |b| {
+        b.iter(|| {
+            for frame in &frames {
+                black_box(format_frame(black_box(frame)));
+            }
+        })
+    });
+    group.bench_function("frame_messages", |b| b.iter(|| batches(&interface, black_box(&frames))));
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let interface: Arc<str> = Arc::from("vcan0");
+    let batches = batches(&interface, &frames());
+    let mut group = c.benchmark_group("encode");
+    group.throughput(Throughput::Elements(FRAMES_PER_SECOND as u64));
+    for format in [Format::Json, Format::Cbor, Format::Msgpack] {
+        // a buffer reused for all the batches like the writer of a session
+        group.bench_with_input(BenchmarkId::new("reused", format.name()), &format, |b, format| {
+            let mut buf = Vec::new();
+            b.iter(|| {
+                for batch in &batches {
+                    format.encode(&batch.envelope(), &mut buf).unwrap();
+                    black_box(&buf);
+                }
+            })
+        });
+        // a buffer allocated for each batch, for comparison
+        group.bench_with_input(BenchmarkId::new("allocated", format.name()), &format, |b, format| {
+            b.iter(|| {
+                for batch in &batches {
+                    let mut buf = Vec::new();
+                    format.encode(&batch.envelope(), &mut buf).unwrap();
+                    black_box(buf);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, format, encode);
+criterion_main!(benches);
@@ -1,8 +1,100 @@
 // build.rs
 
+#[cfg(not(feature = "no-webui"))]
+use std::fs::File;
+#[cfg(not(feature = "no-webui"))]
+use std::io::Write;
+use std::path::Path;
+
+#[cfg(not(feature = "no-webui"))]
 use npm_rs::*;
 
-fn main() {
+// assets worth compressing, smaller ones are served as they are
+#[cfg(not(feature = "no-webui"))]
+const COMPRESSIBLE: &[&str] = &["html", "js", "css", "svg", "json", "map", "txt"];
+#[cfg(not(feature = "no-webui"))]
+const MIN_SIZE: u64 = 1024;
+
+/// Write the brotli and gzip variants next to each compressible asset, eg `index.js.br` and `index.js.gz`
+#[cfg(not(feature = "no-webui"))]
+fn compress_assets(dir: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            compress_assets(&path)?;
+            continue;
+        }
+        let compressible = path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| COMPRESSIBLE.contains(&ext));
+        if !compressible || path.metadata()?.len() < MIN_SIZE {
+            continue;
+        }
+        let data = std::fs::read(&path)?;
+        let variant = |suffix: &str| File::create(format!("{}{}", path.display(), suffix));
+
+        let mut gzip = flate2::write::GzEncoder::new(variant(".gz")?, flate2::Compression::best());
+        gzip.write_all(&data)?;
+        gzip.finish()?;
+
+        const QUALITY: u32 = 11;
+        const WINDOW: u32 = 22;
+        let mut brotli = brotli::CompressorWriter::new(variant(".br")?, 4096, QUALITY, WINDOW);
+        brotli.write_all(&data)?;
+        brotli.flush()?;
+    }
+    Ok(())
+}
+
+/// Output of the command, none if missing or failing
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program).args(args).output().ok()?;
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    output.status.success().then(|| stdout.trim().to_string())
+}
+
+/// Build info of `GET /api/version`, the git commit, build time, toolchain and the enabled features
+fn emit_build_info() {
+    // `-dirty` if built of uncommitted changes, `unknown` if built outside of a git checkout
+    let commit = command_output("git", &["rev-parse", "--short=12", "HEAD"]).map(|commit| {
+        let dirty = command_output("git", &["status", "--porcelain", "--untracked-files=no"]).is_some_and(|status| !status.is_empty());
+        if dirty { format!("{}-dirty", commit) } else { commit }
+    });
+    // reproducible builds set the time by SOURCE_DATE_EPOCH
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|epoch| epoch.parse().ok()).unwrap_or_else(|| {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |since| since.as_secs())
+    });
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|feature| feature.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=BUILD_GIT_COMMIT={}", commit.as_deref().unwrap_or("unknown"));
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", timestamp);
+    println!("cargo:rustc-env=BUILD_RUSTC={}", command_output(&rustc, &["--version"]).unwrap_or_default());
+    println!("cargo:rustc-env=BUILD_CARGO={}", command_output(&cargo, &["--version"]).unwrap_or_default());
+    println!("cargo:rustc-env=BUILD_PROFILE={}", std::env::var("PROFILE").unwrap_or_default());
+    println!("cargo:rustc-env=BUILD_FEATURES={}", features.join(","));
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // the commit changes by the ref of HEAD, missing files would rerun on every build
+    if let Ok(head) = std::fs::read_to_string(".git/HEAD") {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        if let Some(reference) = head.trim().strip_prefix("ref: ").filter(|reference| Path::new(".git").join(reference).exists()) {
+            println!("cargo:rerun-if-changed=.git/{}", reference);
+        }
+    }
+}
+
+/// Generate the gRPC service of proto/can.proto, by the protoc bundled with the build dependency
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+    tonic_build::compile_protos("proto/can.proto").unwrap();
+}
+
+/// Build the web UI of webui/ by npm into webui/dist, compressing the assets
+#[cfg(not(feature = "no-webui"))]
+fn build_webui() {
     let _exit_status = NpmEnv::default()
         .with_node_env(&NodeEnv::from_cargo_profile().unwrap_or_default())
         // .with_env("FOO", "bar")
@@ -12,6 +104,15 @@ fn main() {
         .run("build")
         .exec()
         .unwrap();
+    compress_assets(Path::new("webui/dist")).unwrap();
+}
+
+fn main() {
+    emit_build_info();
+    #[cfg(feature = "grpc")]
+    compile_protos();
+    #[cfg(not(feature = "no-webui"))]
+    build_webui();
     // rebuild if build.rs is changed
     build_deps::rerun_if_changed_paths("build.rs").unwrap();
     build_deps::rerun_if_changed_paths("webui/package.json").unwrap();
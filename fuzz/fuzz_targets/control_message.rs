@@ -0,0 +1,16 @@
+//! Control messages of the websocket clients, of text messages in JSON and of binary messages in
+//! each format, e.g. `cargo +nightly fuzz run control_message`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_vue::protocol::{parse_control, Format};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = parse_control(text);
+    }
+    for format in [Format::Json, Format::Cbor, Format::Msgpack] {
+        let _ = format.decode_control(data);
+    }
+});
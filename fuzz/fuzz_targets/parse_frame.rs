@@ -0,0 +1,20 @@
+//! Frames in `cansend` notation as sent by the websocket clients, MQTT commands and sequences,
+//! e.g. `cargo +nightly fuzz run parse_frame`
+//!
+//! A frame accepted is formatted back to the notation it is parsed of again, payloads being padded
+//! to the next CAN FD length once.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_vue::frame::EmbeddedFrame;
+use rust_vue::protocol::{format_frame, parse_frame};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else { return };
+    let Ok(frame) = parse_frame(input) else { return };
+    assert!(frame.data().len() <= 64);
+    let (formatted, _) = format_frame(&frame);
+    let reparsed = parse_frame(&formatted).expect("formatted frame rejected");
+    assert_eq!(format_frame(&reparsed).0, formatted);
+});
@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::Client;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::can::{self, CanEvent, Subscription, Timestamp};
+use crate::decode::DecodedFrame;
+use crate::filter::Filter;
+use crate::frame::CanAnyFrame;
+use crate::presets::write_json_atomic;
+use crate::protocol::{format_frame, FrameTimestamp};
+use crate::server::AppState;
+
+// DTO - condition of an alert rule, a frame matching the filter expression, e.g.
+// `{"frame": "id == 0x7DF"}`, or a signal decoded by `--dbc` compared to a value, e.g.
+// `{"signal": "EngineTemp > 110"}`, the signal optionally qualified by its message like
+// `EngineData.EngineTemp`
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    Frame(String),
+    Signal(String),
+}
+
+// DTO - alert rule of `PUT /api/alerts/:name`, a signal condition raising once held for `for_ms`
+// and clearing once no longer met, a frame condition raising on the frames matching, at most once
+// per second; the alerts are posted as JSON to the webhook, an `http://` URL, if given
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct AlertRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub condition: Condition,
+    #[serde(default)]
+    pub for_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Raised,
+    Cleared,
+}
+
+// DTO - alert raised or cleared by a rule, sent to the clients as `alert`, with the frame in
+// `cansend` notation and the value of the signal of a signal condition
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub state: AlertState,
+    pub message: String,
+    pub interface: String,
+    pub frame: String,
+    pub value: Option<f64>,
+    pub timestamp: FrameTimestamp,
+}
+
+// DTO - rule with its name as listed by `GET /api/alerts`, whether a signal condition is raised,
+// the count of alerts raised since loaded and the most recent alert
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct RuleStatus {
+    pub name: String,
+    #[serde(flatten)]
+    pub rule: AlertRule,
+    pub active: bool,
+    pub raised: u64,
+    pub last: Option<AlertEvent>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    // two-character operators first, `<=` not to be taken for `<`
+    const ALL: [(&'static str, Op); 6] =
+        [("<=", Op::Le), (">=", Op::Ge), ("==", Op::Eq), ("!=", Op::Ne), ("<", Op::Lt), (">", Op::Gt)];
+
+    fn holds(&self, a: f64, b: f64) -> bool {
+        match self {
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+        }
+    }
+}
+
+enum Compiled {
+    Frame(Filter),
+    Signal { message: Option<String>, signal: String, op: Op, threshold: f64 },
+}
+
+impl Compiled {
+    fn new(rule: &AlertRule) -> Result<Compiled, String> {
+        if let Some(url) = &rule.webhook {
+            crate::webhook::url(url)?;
+        }
+        match &rule.condition {
+            Condition::Frame(_) if rule.for_ms > 0 => Err("for_ms requires a signal condition".to_string()),
+            Condition::Frame(expr) => Filter::parse(expr).map(Compiled::Frame).map_err(|e| format!("invalid frame condition: {}", e)),
+            Condition::Signal(expr) => {
+                let invalid = || format!("invalid signal condition `{}`, expected e.g. `EngineTemp > 110`", expr);
+                let (op, (name, threshold)) = Op::ALL
+                    .iter()
+                    .find_map(|(token, op)| expr.split_once(token).map(|split| (*op, split)))
+                    .ok_or_else(invalid)?;
+                let threshold = threshold.trim().parse().map_err(|_| invalid())?;
+                let (message, signal) = match name.trim().split_once('.') {
+                    Some((message, signal)) => (Some(message.to_string()), signal.to_string()),
+                    None => (None, name.trim().to_string()),
+                };
+                if signal.is_empty() || signal.contains(char::is_whitespace) {
+                    return Err(invalid());
+                }
+                Ok(Compiled::Signal { message, signal, op, threshold })
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Status {
+    // monotonic time the signal condition is met since, none if not met
+    since: Option<Duration>,
+    // monotonic time of the last alert raised
+    raised_at: Option<Duration>,
+    active: bool,
+    raised: u64,
+    last: Option<AlertEvent>,
+}
+
+struct Entry {
+    rule: AlertRule,
+    compiled: Compiled,
+    status: Status,
+}
+
+/// Alert rules evaluated by the service on each frame received, persisted as JSON object of the
+/// rules by name like the presets
+pub struct Alerts {
+    path: PathBuf,
+    rules: Mutex<BTreeMap<String, Entry>>,
+}
+
+impl Alerts {
+    // frame conditions raise at most once per interval, not to flood the clients
+    const REPEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Load the rules of the file, none if the file does not exist yet
+    pub fn open(path: &Path) -> Result<Alerts, String> {
+        let rules: BTreeMap<String, AlertRule> = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| format!("malformed alert rules {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("failed to read alert rules {}: {}", path.display(), e)),
+        };
+        let mut entries = BTreeMap::new();
+        for (name, rule) in rules {
+            let compiled = Compiled::new(&rule).map_err(|e| format!("alert rule {} of {}: {}", name, path.display(), e))?;
+            entries.insert(name, Entry { rule, compiled, status: Status::default() });
+        }
+        Ok(Alerts { path: path.to_path_buf(), rules: Mutex::new(entries) })
+    }
+
+    /// Check the condition and webhook of the rule
+    pub fn validate(rule: &AlertRule) -> Result<(), String> {
+        Compiled::new(rule).map(|_| ())
+    }
+
+    pub fn list(&self) -> Vec<RuleStatus> {
+        self.rules
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, entry)| RuleStatus {
+                name: name.clone(),
+                rule: entry.rule.clone(),
+                active: entry.status.active,
+                raised: entry.status.raised,
+                last: entry.status.last.clone(),
+            })
+            .collect()
+    }
+
+    /// Alerts of the signal conditions currently raised, sent to a client on connecting
+    pub fn active(&self) -> Vec<AlertEvent> {
+        self.rules
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|entry| entry.status.active)
+            .filter_map(|entry| entry.status.last.clone())
+            .collect()
+    }
+
+    /// Store the rule, replacing a rule of the same name and its state; true if created
+    pub fn put(&self, name: &str, rule: AlertRule) -> Result<bool, String> {
+        let compiled = Compiled::new(&rule)?;
+        let mut rules = self.rules.lock().unwrap();
+        let mut changed: BTreeMap<&str, &AlertRule> = rules.iter().map(|(name, entry)| (name.as_str(), &entry.rule)).collect();
+        changed.insert(name, &rule);
+        self.save(&changed)?;
+        let entry = Entry { rule, compiled, status: Status::default() };
+        Ok(rules.insert(name.to_string(), entry).is_none())
+    }
+
+    /// Remove the rule, false if unknown
+    pub fn delete(&self, name: &str) -> Result<bool, String> {
+        let mut rules = self.rules.lock().unwrap();
+        if !rules.contains_key(name) {
+            return Ok(false);
+        }
+        let changed: BTreeMap<&str, &AlertRule> = rules
+            .iter()
+            .filter(|(other, _)| other.as_str() != name)
+            .map(|(name, entry)| (name.as_str(), &entry.rule))
+            .collect();
+        self.save(&changed)?;
+        rules.remove(name);
+        Ok(true)
+    }
+
+    fn save(&self, rules: &BTreeMap<&str, &AlertRule>) -> Result<(), String> {
+        write_json_atomic(&self.path, rules)
+    }
+
+    /// Evaluate the rules on the frame, the DBC database decoding it once for the signal
+    /// conditions; the alerts raised or cleared, with the webhook of their rule
+    fn evaluate(&self, interface: &str, frame: &CanAnyFrame, timestamp: Timestamp,
+                decode: impl FnOnce() -> Option<DecodedFrame>) -> Vec<(AlertEvent, Option<String>)> {
+        let mut rules = self.rules.lock().unwrap();
+        let mut decode = Some(decode);
+        let mut decoded = None;
+        let mut alerts = Vec::new();
+        for (name, entry) in rules.iter_mut() {
+            let status = &mut entry.status;
+            let (state, message, value) = match &entry.compiled {
+                Compiled::Frame(filter) => {
+                    if !filter.matches(interface, frame) {
+                        continue;
+                    }
+                    let repeated = status.raised_at.is_some_and(|at| timestamp.monotonic.saturating_sub(at) < Alerts::REPEAT_INTERVAL);
+                    if repeated {
+                        continue;
+                    }
+                    (AlertState::Raised, format!("frame {}", filter.source()), None)
+                }
+                Compiled::Signal { message, signal, op, threshold } => {
+                    if let Some(decode) = decode.take() {
+                        decoded = decode();
+                    }
+                    let Some(decoded) = &decoded else { continue };
+                    if message.as_ref().is_some_and(|message| *message != decoded.message) {
+                        continue;
+                    }
+                    let Some(found) = decoded.signals.iter().find(|value| value.name == *signal) else { continue };
+                    let Condition::Signal(expr) = &entry.rule.condition else { continue };
+                    let message = format!("{} is {} {}", expr.trim(), found.value, found.unit).trim_end().to_string();
+                    if !op.holds(found.value, *threshold) {
+                        status.since = None;
+                        if !status.active {
+                            continue;
+                        }
+                        status.active = false;
+                        (AlertState::Cleared, message, Some(found.value))
+                    } else {
+                        let since = *status.since.get_or_insert(timestamp.monotonic);
+                        let held = timestamp.monotonic.saturating_sub(since) >= Duration::from_millis(entry.rule.for_ms);
+                        if status.active || !held {
+                            continue;
+                        }
+                        status.active = true;
+                        (AlertState::Raised, message, Some(found.value))
+                    }
+                }
+            };
+            let (fmt, _) = format_frame(frame);
+            let alert = AlertEvent {
+                rule: name.clone(),
+                state,
+                message,
+                interface: interface.to_string(),
+                frame: fmt,
+                value,
+                timestamp: timestamp.into(),
+            };
+            if state == AlertState::Raised {
+                status.raised += 1;
+                status.raised_at = Some(timestamp.monotonic);
+            }
+            status.last = Some(alert.clone());
+            alerts.push((alert, entry.rule.webhook.clone()));
+        }
+        alerts
+    }
+}
+
+/// Evaluate the alert rules on the frames received until shutdown, publishing the alerts to the
+/// clients and posting them to the webhooks of their rules, retried like the webhooks of `--webhook`
+pub async fn monitor(state: AppState, alerts: Arc<Alerts>) {
+    let client = Client::new();
+    let mut events = Subscription::new(state.events.subscribe(), state.shutdown.clone(), "alert rules");
+    while let Some((interface, frame, timestamp, _)) = events.next_frame().await {
+        let decode = || state.settings.decoder.borrow().as_ref().and_then(|decoder| decoder.decode(&frame));
+        for (alert, webhook) in alerts.evaluate(&interface, &frame, timestamp, decode) {
+            match alert.state {
+                AlertState::Raised => tracing::warn!(rule = alert.rule, message = alert.message, "alert raised"),
+                AlertState::Cleared => tracing::info!(rule = alert.rule, message = alert.message, "alert cleared"),
+            }
+            if let Some(uri) = webhook.as_deref().and_then(|url| crate::webhook::url(url).ok()) {
+                let body = serde_json::to_string(&alert).unwrap_or_default();
+                let (client, shutdown) = (client.clone(), state.shutdown.clone());
+                state.tasks.spawn(async move { crate::webhook::deliver(&client, &uri, &body, &shutdown).await });
+            }
+            can::publish(&state.events, CanEvent::Alert(Arc::new(alert)));
+        }
+    }
+}
@@ -0,0 +1,829 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+
+use axum::{
+    body::Bytes,
+    extract::{ConnectInfo, Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use schemars::{schema_for, JsonSchema, Schema};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::alerts::{AlertRule, Alerts, RuleStatus};
+use crate::audit::{self, AuditEntry, AuditQuery, Origin};
+use crate::auth::Access;
+use crate::buffer::{BufferQuery, BufferedFrame};
+use crate::can::{CanError, Priority, Transmit};
+use crate::capture::{CaptureInfo, CaptureRequest, Captures};
+use crate::clients::ClientInfo;
+use crate::cyclic::{CyclicJob, CyclicRequest};
+use crate::filter::Filter;
+use crate::frame::{CanAnyFrame, CanDataFrame, CanFdFrame, EmbeddedFrame, ExtendedId, Id, StandardId};
+use crate::gateway::RouteStatus;
+use crate::history::{HistoryEntry, HistoryQuery};
+use crate::netlink::InterfaceState;
+use crate::overview::OverviewEntry;
+use crate::presets::{NamedPreset, Preset};
+use crate::sequence::{Sequence, SequenceRequest};
+use crate::protocol::{format_frame, parse_hex_u32, ControlMessage, Envelope, ServerTime};
+use crate::server::AppState;
+
+// DTO - frame to be sent by `POST /api/frames`, id and data as hex strings
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct SendFrame {
+    pub id: String,
+    pub data: String,
+    #[serde(default)]
+    pub extended: bool,
+    #[serde(default)]
+    pub fd: bool,
+    // CAN interface to write to, the default interface if missing
+    pub interface: Option<String>,
+    // precedence in the transmit queue of the interface, `low`, `normal` or `high`
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+// DTO - response of the REST API, either the frame written in `cansend` notation or an error
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Default)]
+pub struct ApiResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frames: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job: Option<CyclicJob>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jobs: Option<Vec<CyclicJob>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capture: Option<CaptureInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    captures: Option<Vec<CaptureInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    history: Option<Vec<HistoryEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    buffered: Option<Vec<BufferedFrame>>,
+    // sequence number of the first frame of the next page of `GET /api/frames`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    route: Option<RouteStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    routes: Option<Vec<RouteStatus>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clients: Option<Vec<ClientInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequence: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preset: Option<NamedPreset>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presets: Option<Vec<NamedPreset>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alert: Option<RuleStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alerts: Option<Vec<RuleStatus>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interfaces: Option<Vec<InterfaceState>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    overview: Option<Vec<OverviewEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audit: Option<Vec<AuditEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// Query of `POST /api/replay`, speed multiplier of the original timing and target interface
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct ReplayParams {
+    speed: Option<f64>,
+    interface: Option<String>,
+}
+
+// Body of `PUT /api/gateway/:route`
+#[derive(Deserialize, JsonSchema, ToSchema, Debug)]
+pub struct RouteUpdate {
+    enabled: bool,
+}
+
+pub type ApiResult = (StatusCode, Json<ApiResponse>);
+
+pub fn api_error(status: StatusCode, error: &str) -> ApiResult {
+    (status, Json(ApiResponse { error: Some(error.to_string()), ..Default::default() }))
+}
+
+fn build_frame(req: &SendFrame) -> Result<CanAnyFrame, &'static str> {
+    let id = parse_hex_u32(&req.id).or(Err("invalid id"))?;
+    let data = hex::decode(req.data.trim()).or(Err("invalid data"))?;
+    new_frame(id, req.extended, req.fd, &data)
+}
+
+/// Data frame of the raw id, classic or CAN FD
+pub(crate) fn new_frame(id: u32, extended: bool, fd: bool, data: &[u8]) -> Result<CanAnyFrame, &'static str> {
+    let id: Id = if extended {
+        ExtendedId::new(id).ok_or("extended id exceeds 29 bits")?.into()
+    } else {
+        u16::try_from(id).ok()
+            .and_then(StandardId::new)
+            .ok_or("standard id exceeds 11 bits")?
+            .into()
+    };
+
+    if fd {
+        CanFdFrame::new(id, data).map(CanAnyFrame::Fd).ok_or("data exceeds 64 bytes")
+    } else {
+        CanDataFrame::new(id, data).map(CanAnyFrame::Normal).ok_or("data exceeds 8 bytes")
+    }
+}
+
+/// `POST /api/frames` - write a single frame to the CAN device, by its transmit queue
+///
+/// Responds once the frame is written, with 400 if the frame is malformed, 403 if the id is not
+/// allowed for the token or writing is disabled by `--read-only`, 404 if the interface is unknown,
+/// 503 if the CAN device is missing or its transmit queue full and 500 if writing to the CAN
+/// device fails.
+#[utoipa::path(post, path = "/api/frames", request_body = SendFrame, responses(
+    (status = 200, description = "frame written", body = ApiResponse),
+    (status = 400, description = "malformed frame", body = ApiResponse),
+    (status = 403, description = "id not allowed for the token, or read-only", body = ApiResponse),
+    (status = 404, description = "unknown interface", body = ApiResponse),
+    (status = 500, description = "writing failed", body = ApiResponse),
+    (status = 503, description = "missing CAN device, or transmit queue full", body = ApiResponse),
+))]
+pub async fn post_frame(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(req): Json<SendFrame>,
+) -> ApiResult {
+    let frame = match build_frame(&req) {
+        Ok(frame) => frame,
+        Err(e) => return api_error(StatusCode::BAD_REQUEST, e),
+    };
+    if let Err(e) = access.check_id(frame.id()) {
+        return api_error(StatusCode::FORBIDDEN, &e);
+    }
+
+    let transmit = Transmit { client: None, priority: req.priority };
+    let result = state.buses.transmit(req.interface.as_deref(), &frame, transmit).await;
+    let origin = Origin { peer: Some(peer), user: access.user, source: "api" };
+    audit::record(&state, &origin, req.interface.as_deref(), &frame, &result);
+    match result {
+        Ok(_) => {
+            let (fmt, _) = format_frame(&frame);
+            tracing::info!(frame = %fmt, "api wrote frame");
+            (StatusCode::OK, Json(ApiResponse { frame: Some(fmt), ..Default::default() }))
+        }
+        Err(e) => write_error(e),
+    }
+}
+
+pub(crate) fn write_error(e: CanError) -> ApiResult {
+    let status = match e {
+        CanError::UnknownInterface => StatusCode::NOT_FOUND,
+        CanError::Missing | CanError::QueueFull => StatusCode::SERVICE_UNAVAILABLE,
+        CanError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        CanError::ReadOnly => StatusCode::FORBIDDEN,
+        CanError::InvalidLin => StatusCode::BAD_REQUEST,
+    };
+    api_error(status, &e.to_string())
+}
+
+/// `POST /api/replay?speed=1.0&interface=can0` - replay the candump log, the pcapng or pcap
+/// capture, or the Vector ASC or BLF log of the request body
+///
+/// Responds with 202 and the number of frames once the replay has been started in the
+/// background, or 400 if the recording or the speed is malformed, 403 if read-only, 404 if the
+/// interface is unknown.
+#[utoipa::path(post, path = "/api/replay", params(ReplayParams), request_body(content = Vec<u8>, content_type = "application/octet-stream"), responses(
+    (status = 202, description = "replay started, with the number of frames", body = ApiResponse),
+    (status = 400, description = "malformed recording or speed", body = ApiResponse),
+    (status = 403, description = "writing disabled by --read-only", body = ApiResponse),
+    (status = 404, description = "unknown interface", body = ApiResponse),
+))]
+pub async fn post_replay(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Query(params): Query<ReplayParams>,
+    recording: Bytes,
+) -> ApiResult {
+    if state.config.read_only {
+        return write_error(CanError::ReadOnly);
+    }
+    let speed = params.speed.unwrap_or(1.0);
+    if !(speed.is_finite() && speed > 0.0) {
+        return api_error(StatusCode::BAD_REQUEST, "speed must be positive");
+    }
+    if state.buses.get(params.interface.as_deref()).is_none() {
+        return api_error(StatusCode::NOT_FOUND, "unknown CAN interface");
+    }
+    let frames = match crate::replay::parse(&recording, &state.buses.names()) {
+        Ok(frames) => frames,
+        Err(e) => return api_error(StatusCode::BAD_REQUEST, &e),
+    };
+
+    let count = frames.len();
+    let origin = Origin { peer: Some(peer), user: access.user, source: "replay" };
+    state.tasks.spawn(crate::replay::replay(state.clone(), origin, frames, speed, params.interface));
+    (StatusCode::ACCEPTED, Json(ApiResponse { frames: Some(count), ..Default::default() }))
+}
+
+fn cyclic_frame(state: &AppState, req: &CyclicRequest) -> Result<CanAnyFrame, (StatusCode, &'static str)> {
+    if req.interval_ms == 0 {
+        return Err((StatusCode::BAD_REQUEST, "interval must be positive"));
+    }
+    if state.buses.get(req.frame.interface.as_deref()).is_none() {
+        return Err((StatusCode::NOT_FOUND, "unknown CAN interface"));
+    }
+    build_frame(&req.frame).map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// `POST /api/cyclic` - register a job writing the frame once per `interval_ms`
+///
+/// Responds with 201 and the job, 400 if the frame or interval is malformed, 403 if read-only,
+/// 404 if the interface is unknown.
+#[utoipa::path(post, path = "/api/cyclic", request_body = CyclicRequest, responses(
+    (status = 201, description = "job started", body = ApiResponse),
+    (status = 400, description = "malformed frame or interval", body = ApiResponse),
+    (status = 403, description = "writing disabled by --read-only", body = ApiResponse),
+    (status = 404, description = "unknown interface", body = ApiResponse),
+))]
+pub async fn post_cyclic(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(req): Json<CyclicRequest>,
+) -> ApiResult {
+    if state.config.read_only {
+        return write_error(CanError::ReadOnly);
+    }
+    let frame = match cyclic_frame(&state, &req) {
+        Ok(frame) => frame,
+        Err((status, e)) => return api_error(status, e),
+    };
+    let origin = Origin { peer: Some(peer), user: access.user, source: "cyclic" };
+    let job = state.cyclic.start(&state, origin, req, frame);
+    tracing::info!(job, "cyclic job started");
+    (StatusCode::CREATED, Json(ApiResponse { job: state.cyclic.get(job), ..Default::default() }))
+}
+
+/// `GET /api/cyclic` - list all jobs
+#[utoipa::path(get, path = "/api/cyclic", responses(
+    (status = 200, description = "all jobs", body = ApiResponse),
+))]
+pub async fn list_cyclic(Extension(state): Extension<AppState>) -> ApiResult {
+    (StatusCode::OK, Json(ApiResponse { jobs: Some(state.cyclic.list()), ..Default::default() }))
+}
+
+/// `PUT /api/cyclic/:job` - replace frame and interval of the job, 404 if the job is unknown
+#[utoipa::path(put, path = "/api/cyclic/{job}", params(("job" = u64, Path)), request_body = CyclicRequest, responses(
+    (status = 200, description = "job updated", body = ApiResponse),
+    (status = 400, description = "malformed frame or interval", body = ApiResponse),
+    (status = 403, description = "writing disabled by --read-only", body = ApiResponse),
+    (status = 404, description = "unknown job or interface", body = ApiResponse),
+))]
+pub async fn put_cyclic(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Path(job): Path<u64>,
+    Json(req): Json<CyclicRequest>,
+) -> ApiResult {
+    if state.config.read_only {
+        return write_error(CanError::ReadOnly);
+    }
+    let frame = match cyclic_frame(&state, &req) {
+        Ok(frame) => frame,
+        Err((status, e)) => return api_error(status, e),
+    };
+    let origin = Origin { peer: Some(peer), user: access.user, source: "cyclic" };
+    if !state.cyclic.update(&state, origin, job, req, frame) {
+        return api_error(StatusCode::NOT_FOUND, "unknown job");
+    }
+    (StatusCode::OK, Json(ApiResponse { job: state.cyclic.get(job), ..Default::default() }))
+}
+
+/// `DELETE /api/cyclic/:job` - cancel the job, 404 if the job is unknown
+#[utoipa::path(delete, path = "/api/cyclic/{job}", params(("job" = u64, Path)), responses(
+    (status = 200, description = "job cancelled", body = ApiResponse),
+    (status = 404, description = "unknown job", body = ApiResponse),
+))]
+pub async fn delete_cyclic(Extension(state): Extension<AppState>, Path(job): Path<u64>) -> ApiResult {
+    if !state.cyclic.cancel(job) {
+        return api_error(StatusCode::NOT_FOUND, "unknown job");
+    }
+    tracing::info!(job, "cyclic job cancelled");
+    (StatusCode::OK, Json(ApiResponse::default()))
+}
+
+/// `POST /api/captures` - arm a capture, recording the frames around the first frame matching the
+/// trigger, notifying the clients once triggered and once complete
+///
+/// Responds with 201 and the capture, or 400 if the trigger is malformed or a limit exceeded.
+#[utoipa::path(post, path = "/api/captures", request_body = CaptureRequest, responses(
+    (status = 201, description = "capture armed", body = ApiResponse),
+    (status = 400, description = "malformed trigger or limit exceeded", body = ApiResponse),
+))]
+pub async fn post_capture(Extension(state): Extension<AppState>, Json(req): Json<CaptureRequest>) -> ApiResult {
+    let trigger = match Filter::parse(&req.trigger) {
+        Ok(trigger) => trigger,
+        Err(e) => return api_error(StatusCode::BAD_REQUEST, &format!("invalid trigger: {}", e)),
+    };
+    let limits = [req.pre_trigger, req.frames];
+    if limits.iter().flatten().any(|&frames| frames > Captures::MAX_FRAMES) {
+        return api_error(StatusCode::BAD_REQUEST, "frames exceed 100000");
+    }
+    if req.frames == Some(0) || req.duration_ms == Some(0) {
+        return api_error(StatusCode::BAD_REQUEST, "frames and duration must be positive");
+    }
+    let capture = state.captures.start(&state, req, trigger);
+    tracing::info!(capture, "capture armed");
+    (StatusCode::CREATED, Json(ApiResponse { capture: state.captures.get(capture), ..Default::default() }))
+}
+
+/// `GET /api/captures` - list all captures, armed, triggered and complete
+#[utoipa::path(get, path = "/api/captures", responses(
+    (status = 200, description = "all captures", body = ApiResponse),
+))]
+pub async fn list_captures(Extension(state): Extension<AppState>) -> ApiResult {
+    (StatusCode::OK, Json(ApiResponse { captures: Some(state.captures.list()), ..Default::default() }))
+}
+
+/// `GET /api/captures/:capture` - state of the capture, 404 if the capture is unknown
+#[utoipa::path(get, path = "/api/captures/{capture}", params(("capture" = u64, Path)), responses(
+    (status = 200, description = "the capture", body = ApiResponse),
+    (status = 404, description = "unknown capture", body = ApiResponse),
+))]
+pub async fn get_capture(Extension(state): Extension<AppState>, Path(capture): Path<u64>) -> ApiResult {
+    match state.captures.get(capture) {
+        Some(capture) => (StatusCode::OK, Json(ApiResponse { capture: Some(capture), ..Default::default() })),
+        None => api_error(StatusCode::NOT_FOUND, "unknown capture"),
+    }
+}
+
+/// `GET /api/captures/:capture/log` - frames recorded by the capture so far, in candump log format
+/// as replayed by `POST /api/replay`
+///
+/// Responds with 200 and the log as text, empty while armed, or 404 if the capture is unknown.
+#[utoipa::path(get, path = "/api/captures/{capture}/log", params(("capture" = u64, Path)), responses(
+    (status = 200, description = "candump log of the frames recorded", body = String, content_type = "text/plain"),
+    (status = 404, description = "unknown capture", body = ApiResponse),
+))]
+pub async fn get_capture_log(Extension(state): Extension<AppState>, Path(capture): Path<u64>) -> Response {
+    match state.captures.log(capture) {
+        Some(log) => ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], log).into_response(),
+        None => api_error(StatusCode::NOT_FOUND, "unknown capture").into_response(),
+    }
+}
+
+/// `DELETE /api/captures/:capture` - stop the capture and discard its frames, 404 if the capture
+/// is unknown
+#[utoipa::path(delete, path = "/api/captures/{capture}", params(("capture" = u64, Path)), responses(
+    (status = 200, description = "capture deleted", body = ApiResponse),
+    (status = 404, description = "unknown capture", body = ApiResponse),
+))]
+pub async fn delete_capture(Extension(state): Extension<AppState>, Path(capture): Path<u64>) -> ApiResult {
+    if !state.captures.delete(capture) {
+        return api_error(StatusCode::NOT_FOUND, "unknown capture");
+    }
+    tracing::info!(capture, "capture deleted");
+    (StatusCode::OK, Json(ApiResponse::default()))
+}
+
+/// `GET /api/interface` - state of the SocketCAN interfaces by netlink, with bitrate, bus state,
+/// error counters and restart count, the error reported with each interface failing
+#[utoipa::path(get, path = "/api/interface", responses(
+    (status = 200, description = "state of the SocketCAN interfaces", body = ApiResponse),
+))]
+pub async fn get_interface(Extension(state): Extension<AppState>) -> ApiResult {
+    let interfaces = crate::netlink::query_all(&state.buses).await;
+    (StatusCode::OK, Json(ApiResponse { interfaces: Some(interfaces), ..Default::default() }))
+}
+
+/// `GET /api/overview` - the last frame of each id and interface received, with the count of
+/// frames and the interval between the last two, like the overview of `cansniffer`
+#[utoipa::path(get, path = "/api/overview", responses(
+    (status = 200, description = "last frame of each id", body = ApiResponse),
+))]
+pub async fn get_overview(Extension(state): Extension<AppState>) -> ApiResult {
+    (StatusCode::OK, Json(ApiResponse { overview: Some(state.overview.snapshot()), ..Default::default() }))
+}
+
+/// `GET /api/time` - wall and monotonic clock of the service, the monotonic clock being the one of
+/// the frame timestamps
+#[utoipa::path(get, path = "/api/time", responses(
+    (status = 200, description = "clock of the service", body = ServerTime),
+))]
+pub async fn get_time() -> Json<ServerTime> {
+    Json(ServerTime::now())
+}
+
+/// `GET /api/schema` - JSON Schema of the websocket messages and of the REST requests and
+/// responses, by message, so integrators may generate typed clients
+#[utoipa::path(get, path = "/api/schema", responses(
+    (status = 200, description = "JSON Schema by message", body = Object),
+))]
+pub async fn get_schema() -> Json<BTreeMap<&'static str, Schema>> {
+    Json(BTreeMap::from([
+        ("server_message", schema_for!(Envelope<'static>)),
+        ("control_message", schema_for!(ControlMessage)),
+        ("api_response", schema_for!(ApiResponse)),
+        ("send_frame", schema_for!(SendFrame)),
+        ("cyclic_request", schema_for!(CyclicRequest)),
+        ("capture_request", schema_for!(CaptureRequest)),
+        ("sequence_request", schema_for!(SequenceRequest)),
+        ("preset", schema_for!(Preset)),
+        ("alert_rule", schema_for!(AlertRule)),
+        ("route_update", schema_for!(RouteUpdate)),
+        ("login", schema_for!(crate::auth::Login)),
+        ("sdo_read_request", schema_for!(crate::canopen::SdoReadRequest)),
+        ("sdo_read_response", schema_for!(crate::canopen::SdoReadResponse)),
+        ("rdbi_request", schema_for!(crate::diag::RdbiRequest)),
+        ("reset_request", schema_for!(crate::diag::ResetRequest)),
+        ("read_dtc_request", schema_for!(crate::diag::ReadDtcRequest)),
+        ("clear_dtc_request", schema_for!(crate::diag::ClearDtcRequest)),
+        ("target", schema_for!(crate::diag::Target)),
+        ("uds_response", schema_for!(crate::diag::UdsResponse)),
+    ]))
+}
+
+/// `GET /api/clients` - list the connected websocket sessions, with their subscriptions and the
+/// count of frames sent and received
+#[utoipa::path(get, path = "/api/clients", responses(
+    (status = 200, description = "connected websocket sessions", body = ApiResponse),
+))]
+pub async fn list_clients(Extension(state): Extension<AppState>) -> ApiResult {
+    (StatusCode::OK, Json(ApiResponse { clients: Some(state.clients.list()), ..Default::default() }))
+}
+
+/// `DELETE /api/clients/:client` - close the websocket session, 404 if the session is unknown
+#[utoipa::path(delete, path = "/api/clients/{client}", params(("client" = u64, Path)), responses(
+    (status = 200, description = "session closed", body = ApiResponse),
+    (status = 404, description = "unknown client", body = ApiResponse),
+))]
+pub async fn delete_client(Extension(state): Extension<AppState>, Path(client): Path<u64>) -> ApiResult {
+    if !state.clients.kick(client) {
+        return api_error(StatusCode::NOT_FOUND, "unknown client");
+    }
+    tracing::info!(client, "client kicked");
+    (StatusCode::OK, Json(ApiResponse::default()))
+}
+
+/// `POST /api/sequences` - run the steps in the background, each frame followed by its `delay_ms`,
+/// `repeat` times; the progress is notified to all websocket clients
+///
+/// Responds with 202, the number of the sequence and the number of frames, or 400 if a step is
+/// malformed, 403 if read-only, 404 if an interface is unknown.
+#[utoipa::path(post, path = "/api/sequences", request_body = SequenceRequest, responses(
+    (status = 202, description = "sequence started, with its number and count of frames", body = ApiResponse),
+    (status = 400, description = "malformed sequence", body = ApiResponse),
+    (status = 403, description = "writing disabled by --read-only", body = ApiResponse),
+    (status = 404, description = "unknown interface", body = ApiResponse),
+))]
+pub async fn post_sequence(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(req): Json<SequenceRequest>,
+) -> ApiResult {
+    if state.config.read_only {
+        return write_error(CanError::ReadOnly);
+    }
+    let sequence = match Sequence::parse(req) {
+        Ok(sequence) => sequence,
+        Err(e) => return api_error(StatusCode::BAD_REQUEST, &e),
+    };
+    if let Some(step) = sequence.unknown_interface(&state.buses) {
+        return api_error(StatusCode::NOT_FOUND, &format!("step {}: unknown CAN interface", step));
+    }
+    let frames = sequence.frames();
+    let origin = Origin { peer: Some(peer), user: access.user, source: "sequence" };
+    let id = state.sequences.start(&state, origin, sequence);
+    (StatusCode::ACCEPTED, Json(ApiResponse { sequence: Some(id), frames: Some(frames), ..Default::default() }))
+}
+
+/// `DELETE /api/sequences/:sequence` - cancel the sequence, 404 if unknown or finished
+#[utoipa::path(delete, path = "/api/sequences/{sequence}", params(("sequence" = u64, Path)), responses(
+    (status = 200, description = "sequence cancelled", body = ApiResponse),
+    (status = 404, description = "unknown or finished sequence", body = ApiResponse),
+))]
+pub async fn delete_sequence(Extension(state): Extension<AppState>, Path(sequence): Path<u64>) -> ApiResult {
+    if !state.sequences.cancel(sequence) {
+        return api_error(StatusCode::NOT_FOUND, "unknown sequence");
+    }
+    (StatusCode::OK, Json(ApiResponse::default()))
+}
+
+// frames of the preset, the error naming the first malformed frame
+fn preset_frames(state: &AppState, preset: &Preset) -> Result<Vec<CanAnyFrame>, (StatusCode, String)> {
+    if preset.frames.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "preset without frames".to_string()));
+    }
+    preset.frames.iter().enumerate().map(|(i, req)| {
+        if state.buses.get(req.interface.as_deref()).is_none() {
+            return Err((StatusCode::NOT_FOUND, format!("frame {}: unknown CAN interface", i + 1)));
+        }
+        build_frame(req).map_err(|e| (StatusCode::BAD_REQUEST, format!("frame {}: {}", i + 1, e)))
+    }).collect()
+}
+
+/// `GET /api/presets` - list the presets stored by `--presets`, 404 if none are configured
+#[utoipa::path(get, path = "/api/presets", responses(
+    (status = 200, description = "all presets", body = ApiResponse),
+    (status = 404, description = "missing --presets", body = ApiResponse),
+))]
+pub async fn list_presets(Extension(state): Extension<AppState>) -> ApiResult {
+    let Some(presets) = &state.presets else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_PRESETS);
+    };
+    (StatusCode::OK, Json(ApiResponse { presets: Some(presets.list()), ..Default::default() }))
+}
+
+/// `GET /api/presets/:name` - the preset, 404 if unknown
+#[utoipa::path(get, path = "/api/presets/{name}", params(("name" = String, Path)), responses(
+    (status = 200, description = "the preset", body = ApiResponse),
+    (status = 404, description = "unknown preset", body = ApiResponse),
+))]
+pub async fn get_preset(Extension(state): Extension<AppState>, Path(name): Path<String>) -> ApiResult {
+    let Some(presets) = &state.presets else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_PRESETS);
+    };
+    match presets.get(&name) {
+        Some(preset) => (StatusCode::OK, Json(ApiResponse { preset: Some(NamedPreset { name, preset }), ..Default::default() })),
+        None => api_error(StatusCode::NOT_FOUND, "unknown preset"),
+    }
+}
+
+/// `PUT /api/presets/:name` - store the preset, replacing a preset of the same name
+///
+/// Responds with 201 if created, 200 if replaced, 400 if a frame is malformed, 404 if an
+/// interface is unknown and 500 if the presets can not be written.
+#[utoipa::path(put, path = "/api/presets/{name}", params(("name" = String, Path)), request_body = Preset, responses(
+    (status = 200, description = "preset replaced", body = ApiResponse),
+    (status = 201, description = "preset created", body = ApiResponse),
+    (status = 400, description = "malformed frame", body = ApiResponse),
+    (status = 404, description = "missing --presets", body = ApiResponse),
+    (status = 500, description = "writing the presets failed", body = ApiResponse),
+))]
+pub async fn put_preset(
+    Extension(state): Extension<AppState>,
+    Path(name): Path<String>,
+    Json(preset): Json<Preset>,
+) -> ApiResult {
+    let Some(presets) = &state.presets else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_PRESETS);
+    };
+    if let Err((status, e)) = preset_frames(&state, &preset) {
+        return api_error(status, &e);
+    }
+    match presets.put(&name, preset.clone()) {
+        Ok(created) => {
+            tracing::info!(preset = name, created, "preset stored");
+            let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+            (status, Json(ApiResponse { preset: Some(NamedPreset { name, preset }), ..Default::default() }))
+        }
+        Err(e) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+/// `DELETE /api/presets/:name` - remove the preset, 404 if unknown
+#[utoipa::path(delete, path = "/api/presets/{name}", params(("name" = String, Path)), responses(
+    (status = 200, description = "preset removed", body = ApiResponse),
+    (status = 404, description = "unknown preset", body = ApiResponse),
+    (status = 500, description = "writing the presets failed", body = ApiResponse),
+))]
+pub async fn delete_preset(Extension(state): Extension<AppState>, Path(name): Path<String>) -> ApiResult {
+    let Some(presets) = &state.presets else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_PRESETS);
+    };
+    match presets.delete(&name) {
+        Ok(true) => {
+            tracing::info!(preset = name, "preset deleted");
+            (StatusCode::OK, Json(ApiResponse::default()))
+        }
+        Ok(false) => api_error(StatusCode::NOT_FOUND, "unknown preset"),
+        Err(e) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+/// `POST /api/presets/:name/send` - write the frames of the preset in order
+///
+/// Responds with the number of frames written, 404 if the preset or an interface is unknown, and
+/// like `POST /api/frames` if writing fails, the frames before having been written.
+#[utoipa::path(post, path = "/api/presets/{name}/send", params(("name" = String, Path)), responses(
+    (status = 200, description = "frames written", body = ApiResponse),
+    (status = 404, description = "unknown preset or interface", body = ApiResponse),
+    (status = 500, description = "writing failed", body = ApiResponse),
+    (status = 503, description = "missing CAN device", body = ApiResponse),
+))]
+pub async fn send_preset(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Path(name): Path<String>,
+) -> ApiResult {
+    let Some(presets) = &state.presets else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_PRESETS);
+    };
+    let Some(preset) = presets.get(&name) else {
+        return api_error(StatusCode::NOT_FOUND, "unknown preset");
+    };
+    let frames = match preset_frames(&state, &preset) {
+        Ok(frames) => frames,
+        Err((status, e)) => return api_error(status, &e),
+    };
+    let origin = Origin { peer: Some(peer), user: access.user, source: "preset" };
+    for (req, frame) in preset.frames.iter().zip(&frames) {
+        let result = state.buses.write_frame(req.interface.as_deref(), frame).await;
+        audit::record(&state, &origin, req.interface.as_deref(), frame, &result);
+        if let Err(e) = result {
+            return write_error(e);
+        }
+    }
+    tracing::info!(preset = name, frames = frames.len(), "api sent preset");
+    (StatusCode::OK, Json(ApiResponse { frames: Some(frames.len()), ..Default::default() }))
+}
+
+static MSG_NO_PRESETS: &str = "presets disabled, missing --presets";
+
+/// `GET /api/alerts` - list the alert rules of `--alerts`, with their state and the most recent
+/// alert, 404 if none are configured
+#[utoipa::path(get, path = "/api/alerts", responses(
+    (status = 200, description = "all alert rules", body = ApiResponse),
+    (status = 404, description = "missing --alerts", body = ApiResponse),
+))]
+pub async fn list_alerts(Extension(state): Extension<AppState>) -> ApiResult {
+    let Some(alerts) = &state.alerts else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_ALERTS);
+    };
+    (StatusCode::OK, Json(ApiResponse { alerts: Some(alerts.list()), ..Default::default() }))
+}
+
+/// `PUT /api/alerts/:name` - store the alert rule, replacing a rule of the same name and its state
+///
+/// Responds with 201 if created, 200 if replaced, 400 if the condition or webhook is malformed,
+/// 404 if no rules are configured and 500 if the rules can not be written.
+#[utoipa::path(put, path = "/api/alerts/{name}", params(("name" = String, Path)), request_body = AlertRule, responses(
+    (status = 200, description = "rule replaced", body = ApiResponse),
+    (status = 201, description = "rule created", body = ApiResponse),
+    (status = 400, description = "malformed condition or webhook", body = ApiResponse),
+    (status = 404, description = "missing --alerts", body = ApiResponse),
+    (status = 500, description = "writing the rules failed", body = ApiResponse),
+))]
+pub async fn put_alert(
+    Extension(state): Extension<AppState>,
+    Path(name): Path<String>,
+    Json(rule): Json<AlertRule>,
+) -> ApiResult {
+    let Some(alerts) = &state.alerts else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_ALERTS);
+    };
+    if let Err(e) = Alerts::validate(&rule) {
+        return api_error(StatusCode::BAD_REQUEST, &e);
+    }
+    match alerts.put(&name, rule) {
+        Ok(created) => {
+            tracing::info!(rule = name, created, "alert rule stored");
+            let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+            let alert = alerts.list().into_iter().find(|rule| rule.name == name);
+            (status, Json(ApiResponse { alert, ..Default::default() }))
+        }
+        Err(e) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+/// `DELETE /api/alerts/:name` - remove the alert rule, 404 if unknown
+#[utoipa::path(delete, path = "/api/alerts/{name}", params(("name" = String, Path)), responses(
+    (status = 200, description = "rule deleted", body = ApiResponse),
+    (status = 404, description = "unknown rule", body = ApiResponse),
+    (status = 500, description = "writing the rules failed", body = ApiResponse),
+))]
+pub async fn delete_alert(Extension(state): Extension<AppState>, Path(name): Path<String>) -> ApiResult {
+    let Some(alerts) = &state.alerts else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_ALERTS);
+    };
+    match alerts.delete(&name) {
+        Ok(true) => {
+            tracing::info!(rule = name, "alert rule deleted");
+            (StatusCode::OK, Json(ApiResponse::default()))
+        }
+        Ok(false) => api_error(StatusCode::NOT_FOUND, "unknown alert rule"),
+        Err(e) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+static MSG_NO_ALERTS: &str = "alerts disabled, missing --alerts";
+
+/// `GET /api/history?id=123&interface=can0&since=1436509052.2&until=...&limit=1000` - query
+/// the latest frames stored by `--db`, with timestamps in seconds since epoch
+///
+/// Responds with 400 if the id is malformed, 404 if no database is configured and 500 if
+/// the query fails.
+#[utoipa::path(get, path = "/api/history", params(HistoryQuery), responses(
+    (status = 200, description = "frames stored, the latest first", body = ApiResponse),
+    (status = 400, description = "malformed query", body = ApiResponse),
+    (status = 404, description = "missing --db", body = ApiResponse),
+    (status = 500, description = "query failed", body = ApiResponse),
+))]
+pub async fn get_history(Extension(state): Extension<AppState>, Query(query): Query<HistoryQuery>) -> ApiResult {
+    let Some(history) = &state.history else {
+        return api_error(StatusCode::NOT_FOUND, "history disabled, missing --db");
+    };
+    let can_id = match query.id.as_deref().map(parse_hex_u32).transpose() {
+        Ok(can_id) => can_id,
+        Err(_) => return api_error(StatusCode::BAD_REQUEST, "invalid id"),
+    };
+    match history.query(query, can_id).await {
+        Ok(entries) => (StatusCode::OK, Json(ApiResponse { history: Some(entries), ..Default::default() })),
+        Err(e) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("history query failed: {}", e)),
+    }
+}
+
+/// `GET /api/frames?offset=0&limit=1000&id=123&interface=can0` - page of the latest frames
+/// received, of up to `--buffer-len` kept in memory, in chronological order; the latest frames if
+/// no offset is given, the frames from the sequence number of the offset else, followed by the
+/// page of `next_offset`
+///
+/// Responds with 400 if the id is malformed and 404 if the buffer is disabled.
+#[utoipa::path(get, path = "/api/frames", params(BufferQuery), responses(
+    (status = 200, description = "frames buffered, the latest last", body = ApiResponse),
+    (status = 400, description = "malformed query", body = ApiResponse),
+    (status = 404, description = "--buffer-len 0", body = ApiResponse),
+))]
+pub async fn get_frames(Extension(state): Extension<AppState>, Query(query): Query<BufferQuery>) -> ApiResult {
+    let Some(buffer) = &state.buffer else {
+        return api_error(StatusCode::NOT_FOUND, "frame buffer disabled by --buffer-len 0");
+    };
+    let can_id = match query.id.as_deref().map(parse_hex_u32).transpose() {
+        Ok(can_id) => can_id,
+        Err(_) => return api_error(StatusCode::BAD_REQUEST, "invalid id"),
+    };
+    let (frames, next) = buffer.page(&query, can_id);
+    (StatusCode::OK, Json(ApiResponse { buffered: Some(frames), next_offset: Some(next), ..Default::default() }))
+}
+
+/// `GET /api/audit?limit=100` - the latest frames written by clients, of up to 1000 kept since
+/// start or loaded of the audit log, in chronological order
+///
+/// Responds with 404 if no audit log is configured.
+#[utoipa::path(get, path = "/api/audit", params(AuditQuery), responses(
+    (status = 200, description = "frames written, the latest last", body = ApiResponse),
+    (status = 404, description = "missing --audit-log", body = ApiResponse),
+))]
+pub async fn get_audit(Extension(state): Extension<AppState>, Query(query): Query<AuditQuery>) -> ApiResult {
+    let Some(audit) = &state.audit else {
+        return api_error(StatusCode::NOT_FOUND, "audit disabled, missing --audit-log");
+    };
+    (StatusCode::OK, Json(ApiResponse { audit: Some(audit.latest(query.limit)), ..Default::default() }))
+}
+
+/// `POST /api/reload` - re-read the config file as on SIGHUP, applying the changed receive
+/// filters, DBC database and log level; 400 if the config or the DBC file is invalid
+#[utoipa::path(post, path = "/api/reload", responses(
+    (status = 200, description = "config reloaded", body = ApiResponse),
+    (status = 400, description = "malformed config", body = ApiResponse),
+))]
+pub async fn post_reload(Extension(state): Extension<AppState>) -> ApiResult {
+    match state.settings.reload(&state.config) {
+        Ok(()) => (StatusCode::OK, Json(ApiResponse::default())),
+        Err(e) => api_error(StatusCode::BAD_REQUEST, &e),
+    }
+}
+
+/// `GET /api/gateway` - list the routes of `--gateway` with the frames forwarded and dropped,
+/// 404 if no gateway is configured
+#[utoipa::path(get, path = "/api/gateway", responses(
+    (status = 200, description = "routes of the gateway", body = ApiResponse),
+    (status = 404, description = "missing --gateway", body = ApiResponse),
+))]
+pub async fn list_gateway(Extension(state): Extension<AppState>) -> ApiResult {
+    let Some(gateway) = &state.gateway else {
+        return api_error(StatusCode::NOT_FOUND, "gateway disabled, missing --gateway");
+    };
+    (StatusCode::OK, Json(ApiResponse { routes: Some(gateway.list()), ..Default::default() }))
+}
+
+/// `PUT /api/gateway/:route` - enable or disable the route by `{"enabled": false}`, 404 if
+/// the route is unknown or no gateway is configured
+#[utoipa::path(put, path = "/api/gateway/{route}", params(("route" = usize, Path)), request_body = RouteUpdate, responses(
+    (status = 200, description = "route updated", body = ApiResponse),
+    (status = 404, description = "unknown route", body = ApiResponse),
+))]
+pub async fn put_gateway(
+    Extension(state): Extension<AppState>,
+    Path(route): Path<usize>,
+    Json(update): Json<RouteUpdate>,
+) -> ApiResult {
+    let Some(gateway) = &state.gateway else {
+        return api_error(StatusCode::NOT_FOUND, "gateway disabled, missing --gateway");
+    };
+    if !gateway.set_enabled(route, update.enabled) {
+        return api_error(StatusCode::NOT_FOUND, "unknown route");
+    }
+    tracing::info!(route, enabled = update.enabled, "gateway route updated");
+    (StatusCode::OK, Json(ApiResponse { route: gateway.get(route), ..Default::default() }))
+}
@@ -0,0 +1,205 @@
+use std::borrow::Cow;
+use std::path::{Component, Path};
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+
+use axum::{
+    body::{boxed, Body, Full},
+    http::{header, uri::PathAndQuery, HeaderMap, Request, StatusCode, Uri},
+    response::Response,
+    Extension,
+};
+use hyper::client::HttpConnector;
+use rust_embed::RustEmbed;
+
+use crate::server::AppState;
+
+// the assets include the variants compressed by build.rs, eg `index.js.br` and `index.js.gz`
+#[cfg(not(feature = "no-webui"))]
+#[derive(RustEmbed)]
+#[folder = "webui/dist/"]
+struct Assets;
+
+// a static page instead of the web UI, not built without npm
+#[cfg(feature = "no-webui")]
+#[derive(RustEmbed)]
+#[folder = "webui/placeholder/"]
+struct Assets;
+
+static INDEX_HTML: &str = "index.html";
+
+// assets of vite carry a content hash in their name, all others are revalidated by ETag
+static IMMUTABLE_PREFIX: &str = "assets/";
+static CACHE_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+static CACHE_REVALIDATE: &str = "no-cache";
+
+// pre-compressed variants in order of preference, by content coding and file suffix
+static ENCODINGS: &[(&str, &str)] = &[("br", ".br"), ("gzip", ".gz")];
+
+pub async fn static_handler(Extension(state): Extension<AppState>, request: Request<Body>) -> Response {
+    if let Some(dev_server) = &state.config.dev_proxy {
+        return proxy(dev_server, request).await;
+    }
+
+    let (uri, headers) = (request.uri(), request.headers());
+    let path = uri.path().trim_start_matches('/');
+    let root = state.config.assets.as_deref();
+
+    if path.is_empty() || path == INDEX_HTML {
+        return index_html(root, headers).await;
+    }
+
+    match serve(root, path, headers).await {
+        Some(response) => response,
+        None => {
+            if path.contains('.') {
+                return not_found().await;
+            }
+
+            index_html(root, headers).await
+        }
+    }
+}
+
+/// Content of an asset with its ETag
+struct Asset {
+    data: Cow<'static, [u8]>,
+    etag: String,
+}
+
+/// Source of the assets, the directory of `--assets` or the assets embedded at build time
+#[derive(Clone, Copy)]
+enum Source<'a> {
+    Dir(&'a Path),
+    Embedded,
+}
+
+impl Source<'_> {
+    /// The asset of the directory of `--assets` if existing, else the embedded one, with its
+    /// source, so the variants of an asset are never mixed of both
+    async fn find<'a>(root: Option<&'a Path>, path: &str) -> Option<(Source<'a>, Asset)> {
+        if let Some(root) = root {
+            if let Some(asset) = Source::Dir(root).get(path).await {
+                return Some((Source::Dir(root), asset));
+            }
+        }
+        Some((Source::Embedded, Source::Embedded.get(path).await?))
+    }
+
+    async fn get(self, path: &str) -> Option<Asset> {
+        match self {
+            Source::Dir(root) => {
+                // no escaping the directory by `..` or absolute paths
+                if !Path::new(path).components().all(|component| matches!(component, Component::Normal(_))) {
+                    return None;
+                }
+                let path = root.join(path);
+                let metadata = tokio::fs::metadata(&path).await.ok().filter(|metadata| metadata.is_file())?;
+                let data = tokio::fs::read(&path).await.ok()?;
+                let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+                let etag = format!("\"{:x}-{:x}\"", data.len(), modified.as_nanos());
+                Some(Asset { data: data.into(), etag })
+            }
+            Source::Embedded => {
+                let file = Assets::get(path)?;
+                let etag = format!("\"{}\"", hex::encode(&file.metadata.sha256_hash()[..16]));
+                Some(Asset { data: file.data, etag })
+            }
+        }
+    }
+}
+
+/// Forward the request to the dev server of `--dev-proxy`, responding 502 if not reachable
+///
+/// The hot module reload of Vite connects to the dev server directly, see `server.hmr` of vite.config.ts.
+async fn proxy(dev_server: &Uri, mut request: Request<Body>) -> Response {
+    static CLIENT: OnceLock<hyper::Client<HttpConnector>> = OnceLock::new();
+    let client = CLIENT.get_or_init(hyper::Client::new);
+
+    let mut uri = dev_server.clone().into_parts();
+    uri.path_and_query = request.uri().path_and_query().cloned().or(Some(PathAndQuery::from_static("/")));
+    *request.uri_mut() = Uri::from_parts(uri).unwrap();
+    // the host header is set by the client according to the URI
+    request.headers_mut().remove(header::HOST);
+
+    match client.request(request).await {
+        Ok(response) => response.map(boxed),
+        Err(e) => {
+            tracing::warn!(dev_server = %dev_server, error = %e, "failed to proxy to dev server");
+            Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(boxed(Full::from(format!("dev server {} not reachable: {}", dev_server, e))))
+                .unwrap()
+        }
+    }
+}
+
+/// Whether the `Accept-Encoding` header accepts the content coding, unless given a quality of 0
+fn accepts(headers: &HeaderMap, encoding: &str) -> bool {
+    let Some(accepted) = headers.get(header::ACCEPT_ENCODING).and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    accepted.split(',').any(|coding| {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let rejected = params.any(|param| {
+            param.trim().strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0)
+        });
+        name.eq_ignore_ascii_case(encoding) && !rejected
+    })
+}
+
+/// Serve the asset of `--assets`, else the embedded one, the best pre-compressed variant accepted
+/// by the client if any
+///
+/// The ETag is the hash of the served variant, or its size and modification time if of
+/// `--assets`, responding with 304 if matching `If-None-Match`.
+async fn serve(root: Option<&Path>, path: &str, headers: &HeaderMap) -> Option<Response> {
+    let (source, identity) = Source::find(root, path).await?;
+    let mut variant = None;
+    for (encoding, suffix) in ENCODINGS.iter().filter(|(encoding, _)| accepts(headers, encoding)) {
+        if let Some(content) = source.get(&format!("{}{}", path, suffix)).await {
+            variant = Some((content, Some(*encoding)));
+            break;
+        }
+    }
+    let (content, encoding) = variant.unwrap_or((identity, None));
+
+    let etag = content.etag;
+    let cache = if path.starts_with(IMMUTABLE_PREFIX) { CACHE_IMMUTABLE } else { CACHE_REVALIDATE };
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, mime.as_ref())
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, cache)
+        .header(header::VARY, "accept-encoding");
+    if let Some(encoding) = encoding {
+        response = response.header(header::CONTENT_ENCODING, encoding);
+    }
+
+    let matches = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+    let response = if matches {
+        response.status(StatusCode::NOT_MODIFIED).body(boxed(Full::default()))
+    } else {
+        response.body(boxed(Full::from(content.data)))
+    };
+    Some(response.unwrap())
+}
+
+async fn index_html(root: Option<&Path>, headers: &HeaderMap) -> Response {
+    match serve(root, INDEX_HTML, headers).await {
+        Some(response) => response,
+        None => not_found().await,
+    }
+}
+
+async fn not_found() -> Response {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(boxed(Full::from("404")))
+        .unwrap()
+}
@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::can::CanError;
+use crate::frame::CanAnyFrame;
+use crate::protocol::format_frame;
+use crate::server::AppState;
+
+// DTO - frame written by a client, as appended to `--audit-log` and listed by `GET /api/audit`
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct AuditEntry {
+    // seconds since epoch
+    pub timestamp: f64,
+    // address of the client
+    #[schema(value_type = Option<String>)]
+    pub peer: Option<SocketAddr>,
+    // the token of the client, none if no auth token is configured
+    pub user: Option<String>,
+    // `ws`, `api`, `preset`, `grpc`, `cyclic`, `sequence`, `replay`, `isotp`, `uds`, `canopen`,
+    // `mqtt` or `script`
+    pub source: String,
+    // the default interface if missing
+    pub interface: Option<String>,
+    // frame in `cansend` notation
+    pub frame: String,
+    // reason the frame was not written, none if written
+    pub error: Option<String>,
+}
+
+// Query of `GET /api/audit`
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub limit: Option<usize>,
+}
+
+/// Originating client of written frames
+#[derive(Debug, Clone, Default)]
+pub struct Origin {
+    pub peer: Option<SocketAddr>,
+    pub user: Option<String>,
+    pub source: &'static str,
+}
+
+impl Origin {
+    /// Origin of the frames written by the service on its own, e.g. of the MQTT bridge
+    pub fn service(source: &'static str) -> Origin {
+        Origin { peer: None, user: None, source }
+    }
+
+    /// Origin of the same client by another source, e.g. the cyclic job started by the client
+    pub fn with_source(&self, source: &'static str) -> Origin {
+        Origin { source, ..self.clone() }
+    }
+}
+
+/// Append-only log of the frames written by clients, the latest entries kept for `GET /api/audit`
+///
+/// The lines are appended by the [writer] task, not to block the writing clients.
+pub struct Audit {
+    lines: mpsc::UnboundedSender<String>,
+    latest: Mutex<VecDeque<AuditEntry>>,
+}
+
+/// File of the audit log and the lines to append, see [writer]
+pub struct Log {
+    file: tokio::fs::File,
+    lines: mpsc::UnboundedReceiver<String>,
+}
+
+impl Audit {
+    // bound of the entries kept in memory
+    const MAX_LATEST: usize = 1000;
+
+    /// Open the log for appending, loading the latest entries of a log written before
+    pub fn open(path: &Path) -> Result<(Audit, Log), String> {
+        let failed = |e: std::io::Error| format!("failed to open audit log {}: {}", path.display(), e);
+        let mut latest = VecDeque::new();
+        if path.is_file() {
+            let reader = BufReader::new(File::open(path).map_err(failed)?);
+            for line in reader.lines() {
+                // a line cut by a crash is skipped
+                let Ok(entry) = serde_json::from_str::<AuditEntry>(&line.map_err(failed)?) else { continue };
+                if latest.len() == Audit::MAX_LATEST {
+                    latest.pop_front();
+                }
+                latest.push_back(entry);
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path).map_err(failed)?;
+        let (lines, receiver) = mpsc::unbounded_channel();
+        let log = Log { file: tokio::fs::File::from_std(file), lines: receiver };
+        Ok((Audit { lines, latest: Mutex::new(latest) }, log))
+    }
+
+    /// Record the frame written by the client, with the result of writing it
+    pub fn record(&self, origin: &Origin, interface: Option<&str>, frame: &CanAnyFrame, result: &Result<(), CanError>) {
+        let error = result.as_ref().err().map(CanError::to_string);
+        let entry = AuditEntry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+            peer: origin.peer,
+            user: origin.user.clone(),
+            source: origin.source.to_string(),
+            interface: interface.map(str::to_string),
+            frame: format_frame(frame).0,
+            error,
+        };
+        let mut line = serde_json::to_string(&entry).unwrap();
+        line.push('\n');
+        // failing only once the writer failed
+        let _ = self.lines.send(line);
+        let mut latest = self.latest.lock().unwrap();
+        if latest.len() == Audit::MAX_LATEST {
+            latest.pop_front();
+        }
+        latest.push_back(entry);
+    }
+
+    /// The latest entries, in chronological order
+    pub fn latest(&self, limit: Option<usize>) -> Vec<AuditEntry> {
+        let latest = self.latest.lock().unwrap();
+        let limit = limit.unwrap_or(Audit::MAX_LATEST).min(latest.len());
+        latest.iter().skip(latest.len() - limit).cloned().collect()
+    }
+}
+
+/// Writer task, appending the lines recorded until shutdown, then the ones still queued
+pub async fn writer(log: Log, shutdown: CancellationToken) {
+    let Log { file, mut lines } = log;
+    let mut writer = BufWriter::new(file);
+    loop {
+        let line = tokio::select! {
+            line = lines.recv() => line,
+            _ = shutdown.cancelled() => None,
+        };
+        let Some(line) = line else { break };
+        let mut written = writer.write_all(line.as_bytes()).await;
+        // flushed once no more lines are queued
+        if written.is_ok() && lines.is_empty() {
+            written = writer.flush().await;
+        }
+        if let Err(e) = written {
+            tracing::error!(error = %e, "failed to append to audit log");
+            return;
+        }
+    }
+    while let Ok(line) = lines.try_recv() {
+        if writer.write_all(line.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+    if let Err(e) = writer.flush().await {
+        tracing::error!(error = %e, "failed to append to audit log");
+    }
+}
+
+/// Record the frame written by the client, if `--audit-log` is given
+pub fn record(state: &AppState, origin: &Origin, interface: Option<&str>, frame: &CanAnyFrame, result: &Result<(), CanError>) {
+    if let Some(audit) = &state.audit {
+        audit.record(origin, interface, frame, result);
+    }
+}
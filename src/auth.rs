@@ -0,0 +1,227 @@
+use std::ops::RangeInclusive;
+
+use axum::{
+    http::{header, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::api::{api_error, ApiResponse};
+use crate::frame::Id;
+use crate::gateway::raw_id;
+use crate::protocol::{format_id, parse_hex_u32};
+use crate::server::AppState;
+
+/// Cookie set by `POST /api/login`, as browsers can not set headers for WebSocket requests
+pub const COOKIE: &str = "rust_vue_token";
+
+// DTO - credentials of `POST /api/login`
+#[derive(Deserialize, JsonSchema, ToSchema, Debug)]
+pub struct Login {
+    token: String,
+}
+
+/// Compare in constant time, not revealing the length of a matching prefix
+fn token_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Scope of a client by its token, see `--auth-token` and `--monitor-token`
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    // receiving frames only, by `/ws/monitor`, `/events` and the GET requests of the REST API
+    Monitor,
+    // writing frames too, all clients if no auth token is configured
+    Control,
+}
+
+/// Ids a token of `[[auth.tokens]]` may write, single ids or ranges in hex, e.g. `0x7DF` or `0x100-0x1FF`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "Vec<String>")]
+pub struct Allowlist(Vec<RangeInclusive<u32>>);
+
+impl TryFrom<Vec<String>> for Allowlist {
+    type Error = String;
+
+    fn try_from(items: Vec<String>) -> Result<Allowlist, String> {
+        let range = |item: &str| {
+            let (start, end) = item.split_once('-').unwrap_or((item, item));
+            let (start, end) = (parse_hex_u32(start).ok()?, parse_hex_u32(end).ok()?);
+            (start <= end).then_some(start..=end)
+        };
+        items
+            .iter()
+            .map(|item| range(item).ok_or_else(|| format!("invalid id or range {} of allowlist", item)))
+            .collect::<Result<_, _>>()
+            .map(Allowlist)
+    }
+}
+
+impl Allowlist {
+    pub fn allows(&self, id: u32) -> bool {
+        self.0.iter().any(|range| range.contains(&id))
+    }
+}
+
+/// Restriction of a token of `[[auth.tokens]]`, writing only the ids of its allowlist
+#[derive(Debug, Clone)]
+pub struct Restriction {
+    pub name: String,
+    pub transmit: Allowlist,
+}
+
+impl Restriction {
+    /// Check the id may be written, the error naming the token otherwise
+    pub fn check_id(&self, id: Id) -> Result<(), String> {
+        if self.transmit.allows(raw_id(id)) {
+            Ok(())
+        } else {
+            Err(format!("id {} not allowed for token {}", format_id(id), self.name))
+        }
+    }
+}
+
+/// Access granted by a token, the scope and the restriction of tokens of `[[auth.tokens]]`
+#[derive(Debug, Clone)]
+pub struct Access {
+    pub scope: Scope,
+    pub restricted: Option<Restriction>,
+    // the token as named in the audit log, `auth_token`, `monitor_token` or the name of a token
+    // of `[[auth.tokens]]`; none if no auth token is configured
+    pub user: Option<String>,
+}
+
+impl Access {
+    pub fn unrestricted(scope: Scope) -> Access {
+        Access { scope, restricted: None, user: None }
+    }
+
+    fn token(scope: Scope, user: &str) -> Access {
+        Access { scope, restricted: None, user: Some(user.to_string()) }
+    }
+
+    /// Check the raw id may be written by the token, see [Restriction::check_id]
+    pub fn check_id(&self, id: Id) -> Result<(), String> {
+        self.restricted.as_ref().map_or(Ok(()), |restriction| restriction.check_id(id))
+    }
+}
+
+/// The access granted by the token, None if the token is invalid
+pub(crate) fn token_access(state: &AppState, token: Option<&str>) -> Option<Access> {
+    let config = &state.config;
+    let Some(expected) = &config.auth_token else { return Some(Access::unrestricted(Scope::Control)) };
+    let token = token?;
+    if token_eq(token, expected) {
+        return Some(Access::token(Scope::Control, "auth_token"));
+    }
+    if let Some(grant) = config.tokens.iter().find(|grant| token_eq(token, &grant.token)) {
+        let restriction = Restriction { name: grant.name.clone(), transmit: grant.transmit.clone() };
+        return Some(Access { scope: Scope::Control, restricted: Some(restriction), user: Some(grant.name.clone()) });
+    }
+    if config.monitor_token.as_deref().is_some_and(|expected| token_eq(token, expected)) {
+        Some(Access::token(Scope::Monitor, "monitor_token"))
+    } else {
+        None
+    }
+}
+
+/// The token of the request, from the `Authorization: Bearer` header or the login cookie
+fn request_token<B>(req: &Request<B>) -> Option<&str> {
+    let headers = req.headers();
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let cookie = || {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .find_map(|pair| pair.trim().strip_prefix(COOKIE)?.strip_prefix('='))
+    };
+    // the WebSocket of a page of another origin, see `--cors-origin`, can neither set the header
+    // nor send the cookie
+    let query = || {
+        let upgrade = headers.get(header::UPGRADE).and_then(|value| value.to_str().ok());
+        let query = req.uri().query().filter(|_| upgrade.is_some_and(|value| value.eq_ignore_ascii_case("websocket")))?;
+        query.split('&').find_map(|pair| pair.strip_prefix("access_token="))
+    };
+    bearer.or_else(cookie).or_else(query)
+}
+
+// requests of restricted tokens other than GET, checking the id of each frame written
+const RESTRICTED_WRITES: &[&str] = &["/api/frames"];
+
+/// Middleware rejecting requests without valid token with 401, if a token is configured, and
+/// requests of the monitor scope other than GET with 403, as well as those of restricted tokens
+/// writing other than single frames
+///
+/// The scope and the access granted are added to the request, see [Scope] and [Access].
+pub async fn require_token<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let access = match req.extensions().get::<AppState>() {
+        Some(state) => token_access(state, request_token(&req)),
+        None => Some(Access::unrestricted(Scope::Control)),
+    };
+
+    match access {
+        None => api_error(StatusCode::UNAUTHORIZED, "missing or invalid token").into_response(),
+        Some(Access { scope: Scope::Monitor, .. }) if req.method() != Method::GET => {
+            api_error(StatusCode::FORBIDDEN, "token of read-only access").into_response()
+        }
+        Some(Access { restricted: Some(restriction), .. })
+            if req.method() != Method::GET && !RESTRICTED_WRITES.contains(&req.uri().path()) =>
+        {
+            let error = format!("token {} may only write single frames", restriction.name);
+            api_error(StatusCode::FORBIDDEN, &error).into_response()
+        }
+        Some(access) => {
+            req.extensions_mut().insert(access.scope);
+            req.extensions_mut().insert(access);
+            next.run(req).await
+        }
+    }
+}
+
+fn cookie(state: &AppState, value: &str, max_age: Option<u32>) -> String {
+    let mut cookie = format!("{}={}; Path=/; HttpOnly; SameSite=Strict", COOKIE, value);
+    if state.config.tls_cert.is_some() {
+        cookie.push_str("; Secure");
+    }
+    if let Some(max_age) = max_age {
+        cookie.push_str(&format!("; Max-Age={}", max_age));
+    }
+    cookie
+}
+
+/// `POST /api/login` - verify the auth token or monitor token and set it as cookie for the WebSocket and API requests
+#[utoipa::path(post, path = "/api/login", request_body = Login, responses(
+    (status = 200, description = "token valid, set as cookie", body = ApiResponse),
+    (status = 401, description = "invalid token", body = ApiResponse),
+))]
+pub async fn login(Extension(state): Extension<AppState>, Json(login): Json<Login>) -> Response {
+    match token_access(&state, Some(&login.token)) {
+        None => {
+            tracing::warn!("login failed");
+            api_error(StatusCode::UNAUTHORIZED, "invalid token").into_response()
+        }
+        Some(_) => {
+            let cookie = cookie(&state, &login.token, None);
+            (StatusCode::OK, [(header::SET_COOKIE, cookie)], Json(ApiResponse::default())).into_response()
+        }
+    }
+}
+
+/// `POST /api/logout` - clear the login cookie
+#[utoipa::path(post, path = "/api/logout", responses(
+    (status = 200, description = "cookie cleared", body = ApiResponse),
+))]
+pub async fn logout(Extension(state): Extension<AppState>) -> Response {
+    let cookie = cookie(&state, "", Some(0));
+    (StatusCode::OK, [(header::SET_COOKIE, cookie)], Json(ApiResponse::default())).into_response()
+}
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::can::{CanEvent, Subscription, Timestamp};
+use crate::frame::{CanAnyFrame, Frame};
+use crate::protocol::{format_frame, FrameTimestamp};
+
+// DTO - frame of the buffer of the latest frames, listed by `GET /api/frames`
+#[derive(Serialize, JsonSchema, ToSchema, Debug)]
+pub struct BufferedFrame {
+    // sequence number of the frame since start, the `offset` of the query
+    pub seq: u64,
+    pub interface: String,
+    // frame in `cansend` notation
+    pub frame: String,
+    pub timestamp: FrameTimestamp,
+}
+
+// Query of `GET /api/frames`, all optional: sequence number of the first frame, the latest frames
+// if missing, and CAN id as hex string
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct BufferQuery {
+    pub offset: Option<u64>,
+    pub limit: Option<usize>,
+    pub id: Option<String>,
+    pub interface: Option<String>,
+}
+
+struct Entry {
+    seq: u64,
+    interface: Arc<str>,
+    frame: CanAnyFrame,
+    timestamp: Timestamp,
+}
+
+/// Ring buffer of the latest frames received, the oldest replaced once full, so a client
+/// connecting backfills its monitor
+pub struct Buffer {
+    len: usize,
+    // sequence number of the next frame and the frames, the oldest first
+    entries: Mutex<(u64, VecDeque<Entry>)>,
+}
+
+impl Buffer {
+    // frames of a page if no limit is given, and at most
+    const DEFAULT_LIMIT: usize = 1000;
+    const MAX_LIMIT: usize = 10_000;
+
+    pub fn new(len: usize) -> Buffer {
+        Buffer { len, entries: Mutex::new((0, VecDeque::with_capacity(len))) }
+    }
+
+    fn push(&self, interface: Arc<str>, frame: CanAnyFrame, timestamp: Timestamp) {
+        let mut entries = self.entries.lock().unwrap();
+        let (next, frames) = &mut *entries;
+        if frames.len() == self.len {
+            frames.pop_front();
+        }
+        frames.push_back(Entry { seq: *next, interface, frame, timestamp });
+        *next += 1;
+    }
+
+    /// Page of the frames of the id and interface, in chronological order, from the sequence
+    /// number of the offset or the latest ones; with the sequence number of the next page
+    pub fn page(&self, query: &BufferQuery, can_id: Option<u32>) -> (Vec<BufferedFrame>, u64) {
+        let limit = query.limit.unwrap_or(Buffer::DEFAULT_LIMIT).min(Buffer::MAX_LIMIT);
+        let entries = self.entries.lock().unwrap();
+        let (next, frames) = &*entries;
+        let matches = |entry: &&Entry| {
+            can_id.is_none_or(|id| entry.frame.raw_id() == id)
+                && query.interface.as_deref().is_none_or(|name| *entry.interface == *name)
+        };
+        let selected: Vec<&Entry> = match query.offset {
+            Some(offset) => {
+                // frames replaced since are skipped
+                let start = offset.saturating_sub(frames.front().map_or(*next, |entry| entry.seq)) as usize;
+                frames.iter().skip(start).filter(matches).take(limit).collect()
+            }
+            None => {
+                let mut latest: Vec<&Entry> = frames.iter().rev().filter(matches).take(limit).collect();
+                latest.reverse();
+                latest
+            }
+        };
+        // a page limited ends at its last frame, all others at the end of the buffer
+        let next = match selected.last() {
+            Some(last) if query.offset.is_some() && selected.len() == limit => last.seq + 1,
+            _ => *next,
+        };
+        let page = selected
+            .into_iter()
+            .map(|entry| BufferedFrame {
+                seq: entry.seq,
+                interface: entry.interface.to_string(),
+                frame: format_frame(&entry.frame).0,
+                timestamp: entry.timestamp.into(),
+            })
+            .collect();
+        (page, next)
+    }
+}
+
+/// Append the received frames to the buffer until shutdown
+pub async fn recorder(buffer: Arc<Buffer>, events: broadcast::Receiver<CanEvent>, shutdown: CancellationToken) {
+    let mut events = Subscription::new(events, shutdown, "frame buffer");
+    while let Some((interface, frame, timestamp, _)) = events.next_frame().await {
+        buffer.push(interface, frame, timestamp);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
+
+use crate::can::{self, CanEvent, Subscription};
+use crate::frame::CanAnyFrame;
+use crate::protocol::ErrorClass;
+use crate::server::AppState;
+use crate::transport::Transport;
+
+// health of a CAN bus, by the error frames of the controller and the state reported by netlink;
+// error-warning counts as error-active
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BusState {
+    ErrorActive,
+    ErrorPassive,
+    BusOff,
+    // bus-off, the controller being restarted by `--restart-ms` or by the kernel
+    Restarting,
+}
+
+// DTO - state change of a CAN bus, sent to the clients as `bus_state`
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct BusStateEvent {
+    pub interface: String,
+    pub state: BusState,
+    // none for the first state seen
+    pub previous: Option<BusState>,
+}
+
+/// Current state of each CAN bus, none until reported
+#[derive(Default)]
+pub struct BusStates {
+    states: Mutex<HashMap<Arc<str>, BusState>>,
+}
+
+impl BusStates {
+    pub fn get(&self, interface: &str) -> Option<BusState> {
+        self.states.lock().unwrap().get(interface).copied()
+    }
+
+    fn set(&self, interface: &Arc<str>, state: BusState) -> Option<BusState> {
+        self.states.lock().unwrap().insert(interface.clone(), state)
+    }
+}
+
+/// State reported by the error frame, none if not changing the state
+fn frame_state(classes: &[ErrorClass]) -> Option<BusState> {
+    if classes.contains(&ErrorClass::BusOff) {
+        Some(BusState::BusOff)
+    } else if classes.contains(&ErrorClass::Restarted) || classes.contains(&ErrorClass::ErrorActive) {
+        Some(BusState::ErrorActive)
+    } else if classes.contains(&ErrorClass::ErrorPassive) {
+        Some(BusState::ErrorPassive)
+    } else {
+        None
+    }
+}
+
+/// State reported by netlink, see [crate::netlink::InterfaceState]
+fn netlink_state(state: &str) -> Option<BusState> {
+    match state {
+        "error-active" | "error-warning" => Some(BusState::ErrorActive),
+        "error-passive" => Some(BusState::ErrorPassive),
+        "bus-off" => Some(BusState::BusOff),
+        _ => None,
+    }
+}
+
+/// Track the state of each CAN bus until shutdown, publishing its changes to the clients
+///
+/// Once bus-off, a SocketCAN device is restarted by netlink after `--restart-ms`, unless its
+/// `restart-ms` of the kernel restarts it anyway; the bus is `restarting` until reported active or
+/// passive again.
+pub async fn monitor(state: AppState) {
+    let restart = state.config.restart_ms.map(Duration::from_millis);
+    let mut events = Subscription::new(state.events.subscribe(), state.shutdown.clone(), "bus state monitor");
+    // restarts failed, of the interface and the error
+    let (failed_tx, mut failed) = mpsc::unbounded_channel::<(Arc<str>, String)>();
+    // interfaces restarted by the kernel, by the `restart-ms` reported by netlink
+    let mut kernel_restart: HashMap<Arc<str>, bool> = HashMap::new();
+    loop {
+        let event = tokio::select! {
+            event = events.next() => event,
+            Some((interface, error)) = failed.recv() => {
+                tracing::error!(%interface, %error, "failed to restart CAN device after bus-off");
+                // not repeated, the bus stays bus-off until reported active again
+                change(&state, &interface, BusState::BusOff);
+                continue;
+            }
+        };
+        let (interface, reported) = match event {
+            Some(CanEvent::Frame(interface, CanAnyFrame::Error(frame), _, _)) => {
+                let Some(reported) = frame_state(&can::error_classes(&frame)) else { continue };
+                (interface, reported)
+            }
+            Some(CanEvent::Interface(interfaces)) => {
+                for link in interfaces.iter() {
+                    let Some(bus) = state.buses.get(Some(&link.interface)) else { continue };
+                    let by_kernel = link.restart_ms.unwrap_or_default() > 0;
+                    kernel_restart.insert(bus.name.clone(), by_kernel);
+                    if let Some(reported) = link.state.as_deref().and_then(netlink_state) {
+                        on_state(&state, &bus.name, reported, restart, by_kernel, &failed_tx);
+                    }
+                }
+                continue;
+            }
+            Some(_) => continue,
+            None => return,
+        };
+        let by_kernel = kernel_restart.get(&interface).copied().unwrap_or_default();
+        on_state(&state, &interface, reported, restart, by_kernel, &failed_tx);
+    }
+}
+
+/// Apply the state reported, restarting the bus once bus-off if configured
+fn on_state(state: &AppState, interface: &Arc<str>, reported: BusState, restart: Option<Duration>, by_kernel: bool,
+            failed: &mpsc::UnboundedSender<(Arc<str>, String)>) {
+    match (state.bus_states.get(interface), reported) {
+        // bus-off reported again, or by netlink lagging behind the restart
+        (Some(BusState::BusOff | BusState::Restarting), BusState::BusOff) => return,
+        (_, BusState::BusOff) => change(state, interface, BusState::BusOff),
+        _ => return change(state, interface, reported),
+    }
+    if by_kernel {
+        return change(state, interface, BusState::Restarting);
+    }
+    let socketcan = state.buses.get(Some(interface)).is_some_and(|bus| bus.transport == Transport::SocketCan);
+    let Some(delay) = restart.filter(|_| socketcan) else { return };
+    change(state, interface, BusState::Restarting);
+    let (interface, failed) = (interface.clone(), failed.clone());
+    let shutdown = state.shutdown.clone();
+    state.tasks.spawn(async move {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => (),
+            _ = shutdown.cancelled() => return,
+        }
+        let name = interface.clone();
+        let result = tokio::task::spawn_blocking(move || crate::setup::restart(&name)).await;
+        match result.unwrap_or_else(|e| Err(e.to_string())) {
+            Ok(()) => tracing::info!(%interface, "CAN device restarted after bus-off"),
+            Err(error) => {
+                let _ = failed.send((interface, error));
+            }
+        }
+    });
+}
+
+/// Record the state, publishing it to the clients if changed
+fn change(state: &AppState, interface: &Arc<str>, new: BusState) {
+    let previous = state.bus_states.set(interface, new);
+    if previous == Some(new) {
+        return;
+    }
+    if new == BusState::BusOff {
+        tracing::warn!(%interface, ?previous, "CAN bus off");
+    } else {
+        tracing::info!(%interface, state = ?new, ?previous, "CAN bus state changed");
+    }
+    let event = BusStateEvent { interface: interface.to_string(), state: new, previous };
+    can::publish(&state.events, CanEvent::BusState(Arc::new(event)));
+}
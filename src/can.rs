@@ -0,0 +1,696 @@
+use std::cmp;
+use std::collections::{BinaryHeap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures_util::future::BoxFuture;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Mutex, Notify, RwLock};
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::frame::{CanAnyFrame, CanErrorFrame, EmbeddedFrame, Frame};
+use crate::lin;
+use crate::obd::Telemetry;
+use crate::protocol::ErrorClass;
+use crate::stats::BusStats;
+use crate::transport::{Rx, RxFilter, Transport, Tx};
+
+// Events published by the CAN reader tasks to all WebSocket sessions, tagged by interface name
+#[derive(Clone, Debug)]
+pub enum CanEvent {
+    Frame(Arc<str>, CanAnyFrame, Timestamp, Direction),
+    Connected(Arc<str>),
+    Disconnected(Arc<str>),
+    // notice of background jobs such as replay progress
+    Notice(Arc<str>),
+    // periodic statistics of all interfaces
+    Stats(Arc<[BusStats]>),
+    // periodic state of the SocketCAN interfaces, by netlink
+    Interface(Arc<[crate::netlink::InterfaceState]>),
+    // OBD-II values reported by an ECU
+    Telemetry(Arc<Telemetry>),
+    // state change of the bus health, error-active, error-passive, bus-off or restarting
+    BusState(Arc<crate::busstate::BusStateEvent>),
+    // state change of a CANopen node
+    Canopen(Arc<crate::canopen::NodeEvent>),
+    // alert raised or cleared by a rule of `--alerts`
+    Alert(Arc<crate::alerts::AlertEvent>),
+    // timeout or recovery of a periodic message
+    Timeout(Arc<crate::timeouts::TimeoutEvent>),
+    // J1939 parameter group, of a single frame or reassembled
+    #[cfg(feature = "j1939")]
+    J1939(Arc<crate::j1939::ParameterGroup>),
+    // NMEA 2000 PGN, of a single frame or reassembled of a fast packet
+    #[cfg(feature = "nmea2000")]
+    Nmea2000(Arc<crate::nmea2000::PgnMessage>),
+}
+
+/// Publish the event to its subscribers, dropped if none is subscribed, e.g. no client connected
+pub fn publish(events: &broadcast::Sender<CanEvent>, event: CanEvent) {
+    let _ = events.send(event);
+}
+
+/// Subscription of a background task to the [CanEvent]s, ending on shutdown or once the CAN readers
+/// are gone
+///
+/// The events are broadcast by a bounded queue, the CAN readers never waiting for their
+/// subscribers: a subscriber falling behind skips the events it missed, warned of by its name,
+/// rather than delaying the others.
+pub struct Subscription {
+    events: broadcast::Receiver<CanEvent>,
+    shutdown: CancellationToken,
+    name: &'static str,
+}
+
+impl Subscription {
+    pub fn new(events: broadcast::Receiver<CanEvent>, shutdown: CancellationToken, name: &'static str) -> Subscription {
+        Subscription { events, shutdown, name }
+    }
+
+    /// Wait for the next event, `None` once the subscription ended; cancel-safe
+    pub async fn next(&mut self) -> Option<CanEvent> {
+        loop {
+            let event = tokio::select! {
+                event = self.events.recv() => event,
+                _ = self.shutdown.cancelled() => return None,
+            };
+            match event {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    tracing::warn!(count, "{} lagging, skipped events", self.name);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// The next event if already queued
+    pub fn try_next(&mut self) -> Option<CanEvent> {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::TryRecvError::Lagged(count)) => {
+                    tracing::warn!(count, "{} lagging, skipped events", self.name);
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Wait for the next frame, received or transmitted, the other events skipped
+    pub async fn next_frame(&mut self) -> Option<(Arc<str>, CanAnyFrame, Timestamp, Direction)> {
+        loop {
+            if let CanEvent::Frame(interface, frame, timestamp, direction) = self.next().await? {
+                return Some((interface, frame, timestamp, direction));
+            }
+        }
+    }
+}
+
+/// Direction of a frame, received of the bus or transmitted by this service, by the websocket
+/// client of the id if written by a client
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Rx,
+    Tx(Option<u64>),
+}
+
+/// Reception time of a frame
+#[derive(Clone, Copy, Debug)]
+pub struct Timestamp {
+    // wall clock, taken by the kernel if supported by the transport, else by the reader
+    pub wall: SystemTime,
+    // monotonic time since service start, for inter-frame timing
+    pub monotonic: Duration,
+    // raw clock of the CAN controller, if supporting hardware timestamps
+    pub hardware: Option<Duration>,
+}
+
+// start of the monotonic clock of the timestamps
+static START: OnceLock<Instant> = OnceLock::new();
+
+impl Timestamp {
+    /// Start the monotonic clock at service start, before any frame is timestamped
+    pub fn start() {
+        START.get_or_init(Instant::now);
+    }
+
+    /// Timestamp taken by the reader, of the kernel's wall clock if given
+    pub fn new(wall: Option<SystemTime>, hardware: Option<Duration>) -> Timestamp {
+        let start = *START.get_or_init(Instant::now);
+        Timestamp { wall: wall.unwrap_or_else(SystemTime::now), monotonic: start.elapsed(), hardware }
+    }
+
+    pub fn now() -> Timestamp {
+        Timestamp::new(None, None)
+    }
+}
+
+/// Error of writing a frame to a CAN interface
+#[derive(thiserror::Error, Debug)]
+pub enum CanError {
+    #[error("unknown CAN interface")]
+    UnknownInterface,
+    #[error("missing CAN device")]
+    Missing,
+    #[error("CAN write failed: {0}")]
+    Failed(#[from] std::io::Error),
+    // all writing disabled by `--read-only`
+    #[error("writing disabled by --read-only")]
+    ReadOnly,
+    // frames awaiting their write exceeding the bound of the transmit queue
+    #[error("transmit queue full")]
+    QueueFull,
+    // frame of a LIN bus of an id exceeding 3F, or of CAN FD
+    #[error("invalid LIN frame, expecting an id up to 3F of at most 8 bytes")]
+    InvalidLin,
+}
+
+/// Priority of a frame in the transmit queue of its bus, the frames of higher priority written
+/// first, those of the same priority in order
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+/// Frame transmitted of the websocket client of the id if written by a client, and its priority
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Transmit {
+    pub client: Option<u64>,
+    pub priority: Priority,
+}
+
+// frame awaiting its write, completed by the result
+struct Queued {
+    priority: Priority,
+    // order of the frames of the same priority
+    seq: u64,
+    frame: CanAnyFrame,
+    client: Option<u64>,
+    done: oneshot::Sender<Result<(), CanError>>,
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.priority.cmp(&other.priority).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+// Frames awaiting their write by the transmitter of the bus, by priority
+#[derive(Default)]
+struct TxQueue {
+    frames: std::sync::Mutex<Frames>,
+    queued: Notify,
+}
+
+#[derive(Default)]
+struct Frames {
+    heap: BinaryHeap<Queued>,
+    seq: u64,
+    // set once the transmitter ended, no more frames being written
+    closed: bool,
+}
+
+impl TxQueue {
+    fn push(&self, priority: Priority, frame: CanAnyFrame, client: Option<u64>) -> Result<Confirmation, CanError> {
+        let (done, confirmed) = oneshot::channel();
+        let mut frames = self.frames.lock().unwrap();
+        if frames.closed {
+            return Err(CanError::Missing);
+        }
+        if frames.heap.len() >= Bus::MAX_QUEUE {
+            return Err(CanError::QueueFull);
+        }
+        frames.seq += 1;
+        let seq = frames.seq;
+        frames.heap.push(Queued { priority, seq, frame, client, done });
+        self.queued.notify_one();
+        Ok(Confirmation(confirmed))
+    }
+
+    async fn pop(&self) -> Queued {
+        loop {
+            if let Some(queued) = self.frames.lock().unwrap().heap.pop() {
+                return queued;
+            }
+            self.queued.notified().await;
+        }
+    }
+
+    // reject the frames pushed from now on, failing the ones queued
+    fn close(&self) {
+        let mut frames = self.frames.lock().unwrap();
+        frames.closed = true;
+        for queued in frames.heap.drain() {
+            let _ = queued.done.send(Err(CanError::Missing));
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.frames.lock().unwrap().heap.len()
+    }
+}
+
+/// Completion of a frame queued for transmission, once written to the bus or failed
+pub struct Confirmation(oneshot::Receiver<Result<(), CanError>>);
+
+impl Future for Confirmation {
+    type Output = Result<(), CanError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // completed by the transmitter, failing the frames queued once it ends on shutdown
+        Pin::new(&mut self.0).poll(cx).map(|result| result.unwrap_or(Err(CanError::Missing)))
+    }
+}
+
+/// A CAN interface, the transmit side is present while the device is open
+pub struct Bus {
+    pub name: Arc<str>,
+    pub transport: Transport,
+    // bitrate set up by transports configuring the channel on open
+    bitrate: u32,
+    // kernel filters of the receiving side, all frames passing if empty, changed on reload
+    pub filters: watch::Receiver<Vec<RxFilter>>,
+    tx: RwLock<Option<Tx>>,
+    // frames awaiting their write, see [transmitter]
+    queue: TxQueue,
+    // frames written, awaiting their echo by transports receiving the frames written
+    pending: std::sync::Mutex<VecDeque<Pending>>,
+    // notified by the link monitor once the device is present and up
+    pub link_up: Notify,
+}
+
+// frame written and the client writing it
+struct Pending {
+    frame: CanAnyFrame,
+    client: Option<u64>,
+    written: Instant,
+}
+
+impl Bus {
+    // bound of the frames awaiting their echo, and the time they are awaited, e.g. delayed by the
+    // arbitration of a busy bus; frames not echoed, e.g. dropped by the receive filters, expire
+    const MAX_PENDING: usize = 256;
+    const ECHO_TIMEOUT: Duration = Duration::from_secs(1);
+    // bound of the frames awaiting their write, and the retries of a write failing by the transmit
+    // queue of the kernel being full, the first retry after 1 ms, doubling the delay up to 128 ms
+    const MAX_QUEUE: usize = 1024;
+    const TX_RETRIES: u32 = 8;
+    const TX_RETRY_DELAY: Duration = Duration::from_millis(1);
+
+    pub async fn is_connected(&self) -> bool {
+        self.tx.read().await.is_some()
+    }
+
+    pub async fn write_frame(&self, frame: &CanAnyFrame) -> Result<(), CanError> {
+        self.write_frame_by(frame, None).await
+    }
+
+    /// Write the frame of the websocket client of the id, its echo tagged as transmitted by the client
+    pub async fn write_frame_by(&self, frame: &CanAnyFrame, client: Option<u64>) -> Result<(), CanError> {
+        self.transmit(frame, Transmit { client, priority: Priority::Normal }).await
+    }
+
+    /// Queue the frame for transmission, waiting for its write to the bus
+    pub async fn transmit(&self, frame: &CanAnyFrame, transmit: Transmit) -> Result<(), CanError> {
+        self.enqueue(frame, transmit).await?.await
+    }
+
+    /// Queue the frame for transmission, its write to the bus confirmed by the [Confirmation]
+    pub async fn enqueue(&self, frame: &CanAnyFrame, transmit: Transmit) -> Result<Confirmation, CanError> {
+        // failing at once while the device is missing, rather than once reopened
+        if !self.is_connected().await {
+            return Err(CanError::Missing);
+        }
+        if self.transport.is_lin() && !lin::writable(frame) {
+            return Err(CanError::InvalidLin);
+        }
+        self.queue.push(transmit.priority, *frame, transmit.client)
+    }
+
+    /// Frames awaiting their write
+    pub fn queue_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Write the frame, retrying while the transmit queue of the kernel is full
+    async fn write_queued(&self, frame: &CanAnyFrame, client: Option<u64>) -> Result<(), CanError> {
+        // awaited before writing, the echo may be received before the write returns
+        let echoed = self.transport.echoes();
+        if echoed {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.len() >= Self::MAX_PENDING {
+                pending.pop_front();
+            }
+            pending.push_back(Pending { frame: *frame, client, written: Instant::now() });
+        }
+        let mut delay = Self::TX_RETRY_DELAY;
+        let mut retries = 0;
+        let result = loop {
+            // released while retrying, not to delay closing the device
+            let result = match &*self.tx.read().await {
+                Some(tx) => tx.write_frame(frame).await.map_err(CanError::from),
+                None => Err(CanError::Missing),
+            };
+            match result {
+                Err(CanError::Failed(e)) if no_buffer_space(&e) && retries < Self::TX_RETRIES => {
+                    tracing::debug!(interface = &*self.name, retries, "transmit queue of the device full, retrying");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    retries += 1;
+                }
+                result => break result,
+            }
+        };
+        if result.is_err() && echoed {
+            let mut pending = self.pending.lock().unwrap();
+            if let Some(i) = pending.iter().rposition(|pending| same_frame(&pending.frame, frame)) {
+                pending.remove(i);
+            }
+        }
+        result
+    }
+
+    /// Direction of a frame received, transmitted if the echo of a frame written shortly before
+    pub fn direction(&self, frame: &CanAnyFrame) -> Direction {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.is_empty() {
+            return Direction::Rx;
+        }
+        while pending.front().is_some_and(|pending| pending.written.elapsed() > Self::ECHO_TIMEOUT) {
+            pending.pop_front();
+        }
+        match pending.iter().position(|pending| same_frame(&pending.frame, frame)) {
+            Some(i) => Direction::Tx(pending.remove(i).and_then(|pending| pending.client)),
+            None => Direction::Rx,
+        }
+    }
+
+    /// Open the device by its transport, receiving by the filters, the transmit side being present until closed
+    pub async fn open(&self, filters: &[RxFilter]) -> std::io::Result<Rx> {
+        let (rx, tx) = self.transport.open(&self.name, self.bitrate, filters).await?;
+        *self.tx.write().await = Some(tx);
+        Ok(rx)
+    }
+
+    /// Close the transmit side, waiting for a pending write to complete
+    pub async fn close(&self) {
+        *self.tx.write().await = None;
+    }
+}
+
+/// Write the frames queued for the bus until shutdown, in order of their priority, completing each
+/// one by the result of its write; the frames still queued on shutdown fail as missing device
+pub async fn transmitter(bus: Arc<Bus>, shutdown: CancellationToken) {
+    loop {
+        let queued = tokio::select! {
+            biased;
+            _ = shutdown.cancelled() => return bus.queue.close(),
+            queued = bus.queue.pop() => queued,
+        };
+        let result = bus.write_queued(&queued.frame, queued.client).await;
+        // the writer may have stopped waiting, e.g. a client disconnected
+        let _ = queued.done.send(result);
+    }
+}
+
+// errno of a write exceeding the transmit queue of a SocketCAN device, see linux/errno.h, retried
+// rather than dropping the frame
+fn no_buffer_space(error: &io::Error) -> bool {
+    const ENOBUFS: i32 = 105;
+    cfg!(target_os = "linux") && error.raw_os_error() == Some(ENOBUFS)
+}
+
+// equal id, flags and payload, the frames of a bus not being distinguished otherwise
+pub(crate) fn same_frame(frame: &CanAnyFrame, other: &CanAnyFrame) -> bool {
+    frame.id_word() == other.id_word() && frame.dlc() == other.dlc() && frame.data() == other.data()
+}
+
+/// Frame I/O of a CAN interface provided by the embedding application, registered for a device of
+/// `--can-dev` by [crate::server::ServerBuilder::transport], e.g. the in-memory [MockTransport]
+///
+/// Frames are received by a single reader, the supervisor of the interface, while the sessions
+/// write frames; receive filters are not applied.
+pub trait CanTransport: Send + Sync {
+    /// Wait for the next frame and its reception time, `None` if the device is gone, reopened by
+    /// the supervisor with backoff
+    fn recv(&self) -> BoxFuture<'_, Option<(CanAnyFrame, Timestamp)>>;
+
+    /// Write the frame to the bus
+    fn send<'a>(&'a self, frame: &'a CanAnyFrame) -> BoxFuture<'a, io::Result<()>>;
+}
+
+/// In-memory [CanTransport] of tests, receiving the frames given to [MockTransport::receive] and
+/// keeping the frames written for [MockTransport::written]
+pub struct MockTransport {
+    received: mpsc::UnboundedSender<CanAnyFrame>,
+    receiver: Mutex<mpsc::UnboundedReceiver<CanAnyFrame>>,
+    written: mpsc::UnboundedSender<CanAnyFrame>,
+    writes: Mutex<mpsc::UnboundedReceiver<CanAnyFrame>>,
+    failing: AtomicBool,
+    // writes blocked until released, as by a busy bus
+    held: watch::Sender<bool>,
+}
+
+impl MockTransport {
+    pub fn new() -> Arc<MockTransport> {
+        let (received, receiver) = mpsc::unbounded_channel();
+        let (written, writes) = mpsc::unbounded_channel();
+        Arc::new(MockTransport {
+            received,
+            receiver: Mutex::new(receiver),
+            written,
+            writes: Mutex::new(writes),
+            failing: AtomicBool::new(false),
+            held: watch::Sender::new(false),
+        })
+    }
+
+    /// Deliver the frame as received from the bus, queued until the interface is read
+    pub fn receive(&self, frame: CanAnyFrame) {
+        let _ = self.received.send(frame);
+    }
+
+    /// Wait for the next frame written to the bus
+    pub async fn written(&self) -> Option<CanAnyFrame> {
+        self.writes.lock().await.recv().await
+    }
+
+    /// Fail the writes with an I/O error, as by a controller being bus-off
+    pub fn fail_writes(&self, failing: bool) {
+        self.failing.store(failing, Ordering::Relaxed);
+    }
+
+    /// Block the writes until released, the frames written meanwhile awaiting their turn in the
+    /// transmit queue
+    pub fn hold_writes(&self, held: bool) {
+        self.held.send_replace(held);
+    }
+}
+
+impl CanTransport for MockTransport {
+    fn recv(&self) -> BoxFuture<'_, Option<(CanAnyFrame, Timestamp)>> {
+        Box::pin(async move { Some((self.receiver.lock().await.recv().await?, Timestamp::now())) })
+    }
+
+    fn send<'a>(&'a self, frame: &'a CanAnyFrame) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            let _ = self.held.subscribe().wait_for(|held| !held).await;
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(io::Error::other("mock transport failing"));
+            }
+            let _ = self.written.send(*frame);
+            Ok(())
+        })
+    }
+}
+
+// error classes of the error frame's id, see linux/can/error.h
+const CAN_ERR_TX_TIMEOUT: u32 = 0x0001;
+const CAN_ERR_LOSTARB: u32 = 0x0002;
+const CAN_ERR_CRTL: u32 = 0x0004;
+const CAN_ERR_PROT: u32 = 0x0008;
+const CAN_ERR_TRX: u32 = 0x0010;
+const CAN_ERR_ACK: u32 = 0x0020;
+const CAN_ERR_BUSOFF: u32 = 0x0040;
+const CAN_ERR_BUSERROR: u32 = 0x0080;
+const CAN_ERR_RESTARTED: u32 = 0x0100;
+
+// controller status of data byte 1
+const CAN_ERR_CRTL_OVERFLOW: u8 = 0x03;
+const CAN_ERR_CRTL_WARNING: u8 = 0x0C;
+const CAN_ERR_CRTL_PASSIVE: u8 = 0x30;
+const CAN_ERR_CRTL_ACTIVE: u8 = 0x40;
+
+/// Classify the error frame by its error bits, a frame may report multiple classes at once
+pub fn error_classes(frame: &CanErrorFrame) -> Vec<ErrorClass> {
+    let bits = frame.error_bits();
+    let controller = frame.data().get(1).copied().unwrap_or_default();
+    let mut classes = Vec::new();
+    let mut add = |present: bool, class: ErrorClass| if present { classes.push(class) };
+
+    add(bits & CAN_ERR_TX_TIMEOUT != 0, ErrorClass::TxTimeout);
+    add(bits & CAN_ERR_LOSTARB != 0, ErrorClass::ArbitrationLost);
+    if bits & CAN_ERR_CRTL != 0 {
+        add(controller & CAN_ERR_CRTL_OVERFLOW != 0, ErrorClass::ControllerOverflow);
+        add(controller & CAN_ERR_CRTL_WARNING != 0, ErrorClass::ErrorWarning);
+        add(controller & CAN_ERR_CRTL_PASSIVE != 0, ErrorClass::ErrorPassive);
+        add(controller & CAN_ERR_CRTL_ACTIVE != 0, ErrorClass::ErrorActive);
+    }
+    add(bits & CAN_ERR_PROT != 0, ErrorClass::ProtocolViolation);
+    add(bits & CAN_ERR_TRX != 0, ErrorClass::TransceiverError);
+    add(bits & CAN_ERR_ACK != 0, ErrorClass::NoAck);
+    add(bits & CAN_ERR_BUSOFF != 0, ErrorClass::BusOff);
+    add(bits & CAN_ERR_BUSERROR != 0, ErrorClass::BusError);
+    add(bits & CAN_ERR_RESTARTED != 0, ErrorClass::Restarted);
+    classes
+}
+
+/// Routing of frames to the configured CAN interfaces, the first one being the default
+#[derive(Clone)]
+pub struct Buses {
+    buses: Arc<Vec<Arc<Bus>>>,
+    // rejecting all frames written, of any client, job or service
+    read_only: bool,
+}
+
+impl Buses {
+    /// Interfaces of the names, opened by the transport of the same position, SocketCAN if missing,
+    /// all receiving by the filters, writing only unless read-only
+    pub fn new(names: &[String], transports: &[Transport], bitrate: u32, read_only: bool,
+               filters: watch::Receiver<Vec<RxFilter>>) -> Buses {
+        let buses = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let transport = transports.get(i).cloned().unwrap_or_default();
+                Arc::new(Bus {
+                    name: name.as_str().into(),
+                    transport,
+                    bitrate,
+                    filters: filters.clone(),
+                    tx: RwLock::new(None),
+                    queue: TxQueue::default(),
+                    pending: Default::default(),
+                    link_up: Notify::new(),
+                })
+            })
+            .collect();
+        Buses { buses: Arc::new(buses), read_only }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Bus>> {
+        self.buses.iter()
+    }
+
+    /// Look up the interface by name, or the default interface if no name is given
+    pub fn get(&self, name: Option<&str>) -> Option<&Arc<Bus>> {
+        match name {
+            Some(name) => self.buses.iter().find(|bus| &*bus.name == name),
+            None => self.buses.first(),
+        }
+    }
+
+    pub fn names(&self) -> Vec<Arc<str>> {
+        self.buses.iter().map(|bus| bus.name.clone()).collect()
+    }
+
+    pub async fn any_connected(&self) -> bool {
+        for bus in self.buses.iter() {
+            if bus.is_connected().await {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub async fn write_frame(&self, name: Option<&str>, frame: &CanAnyFrame) -> Result<(), CanError> {
+        self.write_frame_by(name, frame, None).await
+    }
+
+    /// Write the frame of the websocket client of the id, see [Bus::write_frame_by]
+    pub async fn write_frame_by(&self, name: Option<&str>, frame: &CanAnyFrame, client: Option<u64>) -> Result<(), CanError> {
+        self.transmit(name, frame, Transmit { client, priority: Priority::Normal }).await
+    }
+
+    /// Write the frame by the transmit queue of the interface, see [Bus::transmit]
+    pub async fn transmit(&self, name: Option<&str>, frame: &CanAnyFrame, transmit: Transmit) -> Result<(), CanError> {
+        self.enqueue(name, frame, transmit).await?.await
+    }
+
+    /// Queue the frame for transmission by the interface, see [Bus::enqueue]
+    pub async fn enqueue(&self, name: Option<&str>, frame: &CanAnyFrame, transmit: Transmit) -> Result<Confirmation, CanError> {
+        if self.read_only {
+            return Err(CanError::ReadOnly);
+        }
+        match self.get(name) {
+            Some(bus) => bus.enqueue(frame, transmit).await,
+            // no default interface with `--no-can`
+            None if name.is_none() => Err(CanError::Missing),
+            None => Err(CanError::UnknownInterface),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{CanDataFrame, StandardId};
+    use crate::transport::Custom;
+
+    fn frame(data: u8) -> CanAnyFrame {
+        CanAnyFrame::Normal(CanDataFrame::new(StandardId::new(0x123).unwrap(), &[data]).unwrap())
+    }
+
+    #[tokio::test]
+    async fn frames_queued_failed_on_shutdown() {
+        let mock = MockTransport::new();
+        let (_, filters) = watch::channel(Vec::new());
+        let buses = Buses::new(&["mock0".to_string()], &[Transport::Custom(Custom(mock.clone()))], 500_000, false, filters);
+        let bus = buses.get(None).unwrap().clone();
+        bus.open(&[]).await.unwrap();
+        let queued = bus.enqueue(&frame(1), Transmit::default()).await.unwrap();
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+        transmitter(bus.clone(), shutdown).await;
+        assert!(matches!(queued.await, Err(CanError::Missing)));
+        assert!(matches!(bus.transmit(&frame(2), Transmit::default()).await, Err(CanError::Missing)));
+        assert_eq!(bus.queue_len(), 0);
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+
+use crate::can::{self, CanEvent, Direction, Subscription, Timestamp};
+use crate::frame::{can_frame_default, canfd_frame_default, CanAnyFrame, EmbeddedFrame, Frame};
+use crate::server::AppState;
+
+// header of the data packets, version, op code, sequence number and count of frames, see
+// https://github.com/mguentner/cannelloni
+const VERSION: u8 = 2;
+const OP_DATA: u8 = 0;
+const HEADER_LEN: usize = 5;
+// flag of the length of FD frames, followed by the flags of the FD frame
+const CANFD_FRAME: u8 = 0x80;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+// frames batched into a packet, below the MTU of ethernet even for FD frames of 70 bytes
+const MAX_FRAMES: usize = 20;
+// bound of the peers named, e.g. the senders of a multicast group
+const MAX_PEERS: usize = 64;
+
+/// Data packet of the frames, the id word in network byte order followed by the length and the
+/// data, the flags of FD frames after the length; remote frames without data
+fn encode(seq: u8, frames: &[CanAnyFrame]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_LEN + frames.len() * 16);
+    packet.extend_from_slice(&[VERSION, OP_DATA, seq]);
+    packet.extend_from_slice(&(frames.len() as u16).to_be_bytes());
+    for frame in frames {
+        packet.extend_from_slice(&frame.id_word().to_be_bytes());
+        match frame {
+            CanAnyFrame::Fd(fd) => packet.extend_from_slice(&[fd.data().len() as u8 | CANFD_FRAME, fd.flags().bits()]),
+            CanAnyFrame::Remote(_) => packet.push(frame.dlc() as u8),
+            _ => packet.push(frame.data().len() as u8),
+        }
+        if !frame.is_remote_frame() {
+            packet.extend_from_slice(frame.data());
+        }
+    }
+    packet
+}
+
+/// Frames of a data packet
+fn decode(packet: &[u8]) -> Result<Vec<CanAnyFrame>, String> {
+    let header = packet.get(..HEADER_LEN).ok_or("truncated header")?;
+    if header[0] != VERSION || header[1] != OP_DATA {
+        return Err(format!("unsupported packet of version {} and op code {}", header[0], header[1]));
+    }
+    let count = u16::from_be_bytes([header[3], header[4]]) as usize;
+    let mut frames = Vec::with_capacity(count.min(MAX_FRAMES));
+    let mut rest = &packet[HEADER_LEN..];
+    for _ in 0..count {
+        let truncated = || "truncated frame".to_string();
+        let id = u32::from_be_bytes(rest.get(..4).ok_or_else(truncated)?.try_into().unwrap());
+        let len = *rest.get(4).ok_or_else(truncated)?;
+        if len & CANFD_FRAME != 0 {
+            let mut frame = canfd_frame_default();
+            frame.can_id = id;
+            frame.len = (len & !CANFD_FRAME).min(64);
+            frame.flags = *rest.get(5).ok_or_else(truncated)?;
+            let data = rest.get(6..6 + frame.len as usize).ok_or_else(truncated)?;
+            frame.data[..data.len()].copy_from_slice(data);
+            rest = &rest[6 + data.len()..];
+            frames.push(frame.into());
+        } else {
+            let mut frame = can_frame_default();
+            frame.can_id = id;
+            frame.can_dlc = len.min(8);
+            let data = if id & CAN_RTR_FLAG == 0 { rest.get(5..5 + frame.can_dlc as usize).ok_or_else(truncated)? } else { &[] };
+            frame.data[..data.len()].copy_from_slice(data);
+            rest = &rest[5 + data.len()..];
+            frames.push(frame.into());
+        }
+    }
+    Ok(frames)
+}
+
+/// Socket of `--cannelloni`, bound to the local port and joining the group if multicast
+pub fn socket(remote: SocketAddr, port: u16) -> Result<UdpSocket, String> {
+    let unspecified = match remote.ip() {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let local = SocketAddr::new(unspecified, port);
+    let socket = UdpSocket::bind(local).map_err(|e| format!("failed to bind {}: {}", local, e))?;
+    let joined = match remote.ip() {
+        // frames sent are not looped back, not to be received as frames of the remote side
+        IpAddr::V4(group) if group.is_multicast() => {
+            socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED).and_then(|_| socket.set_multicast_loop_v4(false))
+        }
+        IpAddr::V6(group) if group.is_multicast() => {
+            socket.join_multicast_v6(&group, 0).and_then(|_| socket.set_multicast_loop_v6(false))
+        }
+        _ => Ok(()),
+    };
+    joined.map_err(|e| format!("failed to join multicast group {}: {}", remote.ip(), e))?;
+    socket.set_nonblocking(true).map_err(|e| format!("failed to bind {}: {}", local, e))?;
+    Ok(socket)
+}
+
+/// Tunnel task, sending the frames received by the CAN devices to the peer or multicast group in
+/// the format of cannelloni, and publishing the frames of the remote side until shutdown
+///
+/// Frames of the remote side are tagged by the address of the sender as interface, e.g.
+/// `192.168.1.20:20000`, and are not sent back. Error frames are not tunneled.
+pub async fn tunnel(state: AppState, socket: UdpSocket, remote: SocketAddr) {
+    let socket = match tokio::net::UdpSocket::from_std(socket) {
+        Ok(socket) => socket,
+        Err(e) => return tracing::error!(error = %e, "failed to tunnel frames by UDP"),
+    };
+    let mut events = Subscription::new(state.events.subscribe(), state.shutdown.clone(), "UDP tunnel");
+    let mut peers: HashMap<SocketAddr, Arc<str>> = HashMap::new();
+    let mut buf = vec![0u8; u16::MAX as usize];
+    let mut seq: u8 = 0;
+    let mut dropped: u64 = 0;
+    // frames of the CAN devices, not those of the remote side or error frames
+    let tunneled = |interface: &str, frame: &CanAnyFrame| {
+        !matches!(frame, CanAnyFrame::Error(_)) && state.buses.get(Some(interface)).is_some()
+    };
+
+    loop {
+        let mut frames = Vec::new();
+        tokio::select! {
+            frame = events.next_frame() => match frame {
+                Some((interface, frame, _, _)) if tunneled(&interface, &frame) => frames.push(frame),
+                Some(_) => continue,
+                None => return,
+            },
+            received = socket.recv_from(&mut buf) => {
+                let (len, peer) = match received {
+                    Ok(received) => received,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "failed to receive UDP packet");
+                        continue;
+                    }
+                };
+                let received = match decode(&buf[..len]) {
+                    Ok(received) => received,
+                    Err(e) => {
+                        tracing::debug!(%peer, error = %e, "ignored UDP packet");
+                        continue;
+                    }
+                };
+                if peers.len() >= MAX_PEERS && !peers.contains_key(&peer) {
+                    peers.clear();
+                }
+                let interface = peers.entry(peer).or_insert_with(|| peer.to_string().into());
+                for frame in received {
+                    can::publish(&state.events, CanEvent::Frame(interface.clone(), frame, Timestamp::now(), Direction::Rx));
+                }
+                continue;
+            },
+        }
+        // frames queued meanwhile share the packet
+        while frames.len() < MAX_FRAMES {
+            match events.try_next() {
+                Some(CanEvent::Frame(interface, frame, _, _)) if tunneled(&interface, &frame) => frames.push(frame),
+                Some(_) => (),
+                None => break,
+            }
+        }
+        let packet = encode(seq, &frames);
+        seq = seq.wrapping_add(1);
+        if let Err(e) = socket.send_to(&packet, remote).await {
+            dropped += 1;
+            if dropped.is_power_of_two() {
+                tracing::warn!(dropped, error = %e, "failed to send UDP packets, dropping frames");
+            }
+        }
+    }
+}
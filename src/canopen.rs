@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::ConnectInfo,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::api::{api_error, write_error};
+use crate::audit::{self, Origin};
+use crate::auth::Access;
+use crate::can::{self, CanError, CanEvent, Subscription};
+use crate::frame::{CanAnyFrame, CanDataFrame, EmbeddedFrame, Id, StandardId};
+use crate::protocol::parse_hex_u32;
+use crate::server::AppState;
+
+// function codes of the predefined connection set, the upper 4 bits of the COB-ID
+const FC_NMT: u16 = 0x0;
+const FC_SYNC_EMCY: u16 = 0x1;
+const FC_TIME: u16 = 0x2;
+const FC_SDO_TX: u16 = 0xB;
+const FC_SDO_RX: u16 = 0xC;
+const FC_NMT_ERROR_CONTROL: u16 = 0xE;
+
+// command specifiers of SDO uploads, the upper 3 bits of the first byte, of the client (ccs)
+// and the server (scs)
+const CCS_INITIATE_UPLOAD: u8 = 2;
+const SCS_INITIATE_UPLOAD: u8 = 2;
+const CCS_UPLOAD_SEGMENT: u8 = 3;
+const SCS_UPLOAD_SEGMENT: u8 = 0;
+const CS_ABORT: u8 = 4;
+
+const SDO_TIMEOUT: Duration = Duration::from_secs(1);
+// max size of an uploaded object, larger ones are aborted
+const SDO_MAX_SIZE: usize = 64 * 1024;
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NmtCommand {
+    Start,
+    Stop,
+    EnterPreOperational,
+    ResetNode,
+    ResetCommunication,
+}
+
+impl NmtCommand {
+    fn from_byte(byte: u8) -> Option<NmtCommand> {
+        match byte {
+            0x01 => Some(NmtCommand::Start),
+            0x02 => Some(NmtCommand::Stop),
+            0x80 => Some(NmtCommand::EnterPreOperational),
+            0x81 => Some(NmtCommand::ResetNode),
+            0x82 => Some(NmtCommand::ResetCommunication),
+            _ => None,
+        }
+    }
+}
+
+// NMT state of a node, as reported by its heartbeat
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeState {
+    Bootup,
+    Stopped,
+    Operational,
+    PreOperational,
+}
+
+impl NodeState {
+    fn from_byte(byte: u8) -> Option<NodeState> {
+        // the toggle bit of node guarding is ignored
+        match byte & 0x7F {
+            0x00 => Some(NodeState::Bootup),
+            0x04 => Some(NodeState::Stopped),
+            0x05 => Some(NodeState::Operational),
+            0x7F => Some(NodeState::PreOperational),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SdoCommand {
+    InitiateDownload,
+    DownloadSegment,
+    InitiateUpload,
+    UploadSegment,
+    Abort,
+    Block,
+}
+
+// DTO - CANopen service of a frame by its COB-ID, index of SDO as hex string, e.g.
+// `{"service": "tpdo", "pdo": 1, "node": 5}`
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(tag = "service", rename_all = "snake_case")]
+pub enum Service {
+    // node 0 addressing all nodes
+    Nmt { command: Option<NmtCommand>, node: u8 },
+    Sync,
+    Emcy { node: u8, code: Option<String>, register: Option<u8> },
+    Time,
+    Tpdo { pdo: u8, node: u8 },
+    Rpdo { pdo: u8, node: u8 },
+    // request of the client to the SDO server of the node, and its response
+    SdoRequest { node: u8, command: Option<SdoCommand>, index: Option<String>, subindex: Option<u8> },
+    SdoResponse { node: u8, command: Option<SdoCommand>, index: Option<String>, subindex: Option<u8> },
+    Heartbeat { node: u8, state: Option<NodeState> },
+}
+
+fn sdo_command(data: &[u8], response: bool) -> Option<SdoCommand> {
+    // the command specifiers of segments and downloads differ by direction, see CiA 301
+    let command = match (data.first()? >> 5, response) {
+        (0, false) => SdoCommand::DownloadSegment,
+        (0, true) => SdoCommand::UploadSegment,
+        (1, false) => SdoCommand::InitiateDownload,
+        (1, true) => SdoCommand::DownloadSegment,
+        (2, _) => SdoCommand::InitiateUpload,
+        (3, false) => SdoCommand::UploadSegment,
+        (3, true) => SdoCommand::InitiateDownload,
+        (4, _) => SdoCommand::Abort,
+        (5 | 6, _) => SdoCommand::Block,
+        _ => return None,
+    };
+    Some(command)
+}
+
+/// Index and subindex of initiating and aborting SDO frames
+fn sdo_object(command: Option<SdoCommand>, data: &[u8]) -> (Option<String>, Option<u8>) {
+    match (command, data) {
+        (Some(SdoCommand::InitiateDownload | SdoCommand::InitiateUpload | SdoCommand::Abort), [_, lo, hi, sub, ..]) => {
+            (Some(format!("{:04X}", u16::from_le_bytes([*lo, *hi]))), Some(*sub))
+        }
+        _ => (None, None),
+    }
+}
+
+/// The CANopen service of the frame by the predefined connection set
+pub fn classify(frame: &CanAnyFrame) -> Option<Service> {
+    let CanAnyFrame::Normal(frame) = frame else { return None };
+    let Id::Standard(id) = frame.id() else { return None };
+    let (function, node) = (id.as_raw() >> 7, (id.as_raw() & 0x7F) as u8);
+    let data = frame.data();
+    let service = match (function, node) {
+        (FC_NMT, 0) => Service::Nmt {
+            command: data.first().copied().and_then(NmtCommand::from_byte),
+            node: data.get(1).copied().unwrap_or_default(),
+        },
+        (FC_SYNC_EMCY, 0) => Service::Sync,
+        (FC_SYNC_EMCY, node) => Service::Emcy {
+            node,
+            code: data.get(..2).map(|code| format!("{:04X}", u16::from_le_bytes([code[0], code[1]]))),
+            register: data.get(2).copied(),
+        },
+        (FC_TIME, 0) => Service::Time,
+        // TPDO1 0x180 + node, RPDO1 0x200 + node, ..., RPDO4 0x500 + node
+        (0x3..=0xA, 1..) => {
+            let pdo = ((function - 1) / 2) as u8;
+            if function % 2 == 1 { Service::Tpdo { pdo, node } } else { Service::Rpdo { pdo, node } }
+        }
+        (FC_SDO_TX, 1..) => {
+            let command = sdo_command(data, true);
+            let (index, subindex) = sdo_object(command, data);
+            Service::SdoResponse { node, command, index, subindex }
+        }
+        (FC_SDO_RX, 1..) => {
+            let command = sdo_command(data, false);
+            let (index, subindex) = sdo_object(command, data);
+            Service::SdoRequest { node, command, index, subindex }
+        }
+        (FC_NMT_ERROR_CONTROL, 1..) => Service::Heartbeat { node, state: data.first().copied().and_then(NodeState::from_byte) },
+        _ => return None,
+    };
+    Some(service)
+}
+
+// DTO - state change of a node reported by its heartbeat, without previous state if seen first
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct NodeEvent {
+    pub interface: String,
+    pub node: u8,
+    pub state: NodeState,
+    pub previous: Option<NodeState>,
+}
+
+/// Monitor the heartbeats of all nodes, publishing their state changes to all sessions until shutdown
+pub async fn monitor(events: broadcast::Sender<CanEvent>, shutdown: CancellationToken) {
+    let mut states: HashMap<(Arc<str>, u8), NodeState> = HashMap::new();
+    let mut received = Subscription::new(events.subscribe(), shutdown, "CANopen monitor");
+    while let Some((interface, frame, _, _)) = received.next_frame().await {
+        let Some(Service::Heartbeat { node, state: Some(state) }) = classify(&frame) else { continue };
+        let previous = states.insert((interface.clone(), node), state);
+        if previous != Some(state) {
+            tracing::info!(%interface, node, ?state, "CANopen node state changed");
+            let event = NodeEvent { interface: interface.to_string(), node, state, previous };
+            can::publish(&events, CanEvent::Canopen(Arc::new(event)));
+        }
+    }
+}
+
+// DTO - `POST /api/canopen/sdo/read`, object index as hex string, e.g. `1018` for the identity
+#[derive(Deserialize, JsonSchema, ToSchema, Debug)]
+pub struct SdoReadRequest {
+    node: u8,
+    index: String,
+    #[serde(default)]
+    subindex: u8,
+    interface: Option<String>,
+}
+
+// DTO - value of the object uploaded from the node, as hex string
+#[derive(Serialize, JsonSchema, ToSchema, Debug)]
+pub struct SdoReadResponse {
+    node: u8,
+    index: String,
+    subindex: u8,
+    data: String,
+}
+
+fn abort_name(code: u32) -> &'static str {
+    match code {
+        0x0503_0000 => "toggle bit not alternated",
+        0x0504_0000 => "SDO protocol timed out",
+        0x0504_0001 => "command specifier not valid",
+        0x0504_0005 => "out of memory",
+        0x0601_0000 => "unsupported access to an object",
+        0x0601_0001 => "attempt to read a write only object",
+        0x0602_0000 => "object does not exist",
+        0x0604_0047 => "general internal incompatibility",
+        0x0606_0000 => "access failed due to a hardware error",
+        0x0609_0011 => "sub-index does not exist",
+        0x0800_0000 => "general error",
+        0x0800_0020 => "data cannot be transferred",
+        0x0800_0024 => "no data available",
+        _ => "unknown",
+    }
+}
+
+/// Client of the SDO server of a node, uploading objects by expedited or segmented transfer
+struct SdoClient<'a> {
+    state: &'a AppState,
+    origin: Origin,
+    interface: Arc<str>,
+    node: u8,
+    events: broadcast::Receiver<CanEvent>,
+}
+
+impl SdoClient<'_> {
+    /// Send the request and wait for the response of the node
+    async fn request(&mut self, request: [u8; 8]) -> Result<[u8; 8], Response> {
+        let failed = |error: &str| api_error(StatusCode::BAD_GATEWAY, error).into_response();
+        let tx_id = StandardId::new(0x600 + self.node as u16).unwrap();
+        let rx_id = StandardId::new(0x580 + self.node as u16).unwrap();
+        let frame = CanAnyFrame::Normal(CanDataFrame::new(tx_id, &request).unwrap());
+        self.write(&frame).await.map_err(|_| failed("CAN write failed"))?;
+
+        let deadline = tokio::time::Instant::now() + SDO_TIMEOUT;
+        loop {
+            let event = tokio::time::timeout_at(deadline, self.events.recv())
+                .await
+                .map_err(|_| api_error(StatusCode::GATEWAY_TIMEOUT, "no response").into_response())?;
+            match event {
+                Ok(CanEvent::Frame(interface, CanAnyFrame::Normal(frame), _, _))
+                    if interface == self.interface && frame.id() == Id::Standard(rx_id) =>
+                {
+                    let Ok(response) = <[u8; 8]>::try_from(frame.data()) else {
+                        return Err(failed("malformed SDO response"));
+                    };
+                    if response[0] >> 5 == CS_ABORT {
+                        let code = u32::from_le_bytes([response[4], response[5], response[6], response[7]]);
+                        return Err(failed(&format!("SDO abort 0x{:08X} {}", code, abort_name(code))));
+                    }
+                    return Ok(response);
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => (),
+                Err(broadcast::error::RecvError::Closed) => return Err(failed("channel closed")),
+            }
+        }
+    }
+
+    /// Abort the transfer, responding with the error
+    async fn abort(&mut self, index: u16, subindex: u8, code: u32, error: &str) -> Response {
+        let mut abort = [CS_ABORT << 5, 0, 0, subindex, 0, 0, 0, 0];
+        abort[1..3].copy_from_slice(&index.to_le_bytes());
+        abort[4..].copy_from_slice(&code.to_le_bytes());
+        let frame = CanDataFrame::new(StandardId::new(0x600 + self.node as u16).unwrap(), &abort).unwrap();
+        let _ = self.write(&CanAnyFrame::Normal(frame)).await;
+        api_error(StatusCode::BAD_GATEWAY, error).into_response()
+    }
+
+    async fn write(&self, frame: &CanAnyFrame) -> Result<(), CanError> {
+        let result = self.state.buses.write_frame(Some(&self.interface), frame).await;
+        audit::record(self.state, &self.origin, Some(&self.interface), frame, &result);
+        result
+    }
+
+    async fn upload(&mut self, index: u16, subindex: u8) -> Result<Vec<u8>, Response> {
+        let failed = |error: &str| api_error(StatusCode::BAD_GATEWAY, error).into_response();
+        let mut request = [CCS_INITIATE_UPLOAD << 5, 0, 0, subindex, 0, 0, 0, 0];
+        request[1..3].copy_from_slice(&index.to_le_bytes());
+        let response = self.request(request).await?;
+        if response[0] >> 5 != SCS_INITIATE_UPLOAD || response[1..4] != request[1..4] {
+            return Err(failed("unexpected SDO response"));
+        }
+
+        // expedited transfer of up to 4 bytes, the size given by the unused bytes if indicated
+        const EXPEDITED: u8 = 0x02;
+        const SIZE_INDICATED: u8 = 0x01;
+        if response[0] & EXPEDITED != 0 {
+            let unused = if response[0] & SIZE_INDICATED != 0 { ((response[0] >> 2) & 0x3) as usize } else { 0 };
+            return Ok(response[4..8 - unused].to_vec());
+        }
+
+        let size = (response[0] & SIZE_INDICATED != 0)
+            .then(|| u32::from_le_bytes([response[4], response[5], response[6], response[7]]) as usize);
+        if size.is_some_and(|size| size > SDO_MAX_SIZE) {
+            return Err(self.abort(index, subindex, 0x0504_0005, "object too large").await);
+        }
+        let mut data = Vec::with_capacity(size.unwrap_or_default());
+        let mut toggle = 0;
+        loop {
+            let response = self.request([(CCS_UPLOAD_SEGMENT << 5) | (toggle << 4), 0, 0, 0, 0, 0, 0, 0]).await?;
+            if response[0] >> 5 != SCS_UPLOAD_SEGMENT {
+                return Err(failed("unexpected SDO response"));
+            }
+            if (response[0] >> 4) & 0x1 != toggle {
+                return Err(self.abort(index, subindex, 0x0503_0000, "toggle bit not alternated").await);
+            }
+            let unused = ((response[0] >> 1) & 0x7) as usize;
+            data.extend_from_slice(&response[1..8 - unused]);
+            if data.len() > SDO_MAX_SIZE {
+                return Err(self.abort(index, subindex, 0x0504_0005, "object too large").await);
+            }
+            // the last segment is flagged by the c bit
+            if response[0] & 0x1 != 0 {
+                break;
+            }
+            toggle ^= 1;
+        }
+        if let Some(size) = size {
+            data.truncate(size);
+        }
+        Ok(data)
+    }
+}
+
+/// `POST /api/canopen/sdo/read` - upload the object of the node's object dictionary
+///
+/// Responds with 400 if the node or index is malformed, 404 if the interface is unknown, 502
+/// on an abort of the transfer and 504 if the node does not respond.
+#[utoipa::path(post, path = "/api/canopen/sdo/read", request_body = SdoReadRequest, responses(
+    (status = 200, description = "object uploaded", body = SdoReadResponse),
+    (status = 400, description = "malformed node or index", body = crate::api::ApiResponse),
+    (status = 404, description = "unknown interface", body = crate::api::ApiResponse),
+    (status = 502, description = "transfer aborted", body = crate::api::ApiResponse),
+    (status = 504, description = "no response", body = crate::api::ApiResponse),
+))]
+pub async fn sdo_read(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(req): Json<SdoReadRequest>,
+) -> Response {
+    if state.config.read_only {
+        return write_error(CanError::ReadOnly).into_response();
+    }
+    if !(1..=127).contains(&req.node) {
+        return api_error(StatusCode::BAD_REQUEST, "node must be within 1..127").into_response();
+    }
+    let Some(index) = parse_hex_u32(&req.index).ok().and_then(|index| u16::try_from(index).ok()) else {
+        return api_error(StatusCode::BAD_REQUEST, "invalid index").into_response();
+    };
+    let Some(bus) = state.buses.get(req.interface.as_deref()) else {
+        return api_error(StatusCode::NOT_FOUND, "unknown CAN interface").into_response();
+    };
+
+    // subscribed before the request is sent, not to miss the response
+    let mut client = SdoClient {
+        state: &state,
+        origin: Origin { peer: Some(peer), user: access.user, source: "canopen" },
+        interface: bus.name.clone(),
+        node: req.node,
+        events: state.events.subscribe(),
+    };
+    match client.upload(index, req.subindex).await {
+        Ok(data) => {
+            tracing::info!(node = req.node, index = %req.index, subindex = req.subindex, "SDO upload");
+            let response = SdoReadResponse {
+                node: req.node,
+                index: format!("{:04X}", index),
+                subindex: req.subindex,
+                data: hex::encode_upper(data),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => e,
+    }
+}
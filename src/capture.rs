@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::can::{self, CanEvent, Subscription};
+use crate::filter::Filter;
+use crate::frame::CanAnyFrame;
+use crate::protocol::format_frame;
+use crate::record::log_line;
+use crate::server::AppState;
+
+// DTO - capture of `POST /api/captures`, armed until a frame matches the trigger, a filter
+// expression like `id == 0x123 && data[0] & 0x80 == 0x80` or `error` for error frames; recording
+// the frames received before the trigger, the trigger and the frames after it, until `frames`
+// frames or `duration_ms` since the trigger, whichever is reached first
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct CaptureRequest {
+    pub trigger: String,
+    // frames kept before the trigger, 100 if missing
+    pub pre_trigger: Option<usize>,
+    // frames recorded from the trigger on, 1000 if neither frames nor duration is given
+    pub frames: Option<usize>,
+    pub duration_ms: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureState {
+    #[default]
+    Armed,
+    Triggered,
+    Complete,
+}
+
+// DTO - registered capture, with the frame triggering it in `cansend` notation, its wall clock
+// time in seconds since epoch, and the count of frames recorded including the pre-trigger frames
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct CaptureInfo {
+    pub capture: u64,
+    #[serde(flatten)]
+    pub request: CaptureRequest,
+    pub state: CaptureState,
+    pub triggered_by: Option<String>,
+    pub triggered_at: Option<f64>,
+    pub recorded: usize,
+}
+
+// frame recorded with the interface and the wall clock time of reception
+type Recorded = (Arc<str>, CanAnyFrame, SystemTime);
+
+#[derive(Default)]
+struct Recording {
+    state: CaptureState,
+    trigger: Option<(String, SystemTime)>,
+    frames: Vec<Recorded>,
+}
+
+struct Capture {
+    request: CaptureRequest,
+    recording: Arc<Mutex<Recording>>,
+    cancel: CancellationToken,
+}
+
+impl Capture {
+    fn info(&self, capture: u64) -> CaptureInfo {
+        let recording = self.recording.lock().unwrap();
+        let (triggered_by, triggered_at) = match &recording.trigger {
+            Some((frame, at)) => {
+                let at = at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+                (Some(frame.clone()), Some(at))
+            }
+            None => (None, None),
+        };
+        CaptureInfo {
+            capture,
+            request: self.request.clone(),
+            state: recording.state,
+            triggered_by,
+            triggered_at,
+            recorded: recording.frames.len(),
+        }
+    }
+}
+
+/// Captures started by a trigger condition like a single-shot oscilloscope, each a task recording
+/// the frames around the trigger in memory until deleted by `DELETE /api/captures/:capture`
+#[derive(Default)]
+pub struct Captures {
+    captures: Mutex<BTreeMap<u64, Capture>>,
+    next: AtomicU64,
+}
+
+impl Captures {
+    const DEFAULT_PRE_TRIGGER: usize = 100;
+    const DEFAULT_FRAMES: usize = 1000;
+    // bound of the frames before and of the frames from the trigger on
+    pub const MAX_FRAMES: usize = 100_000;
+
+    /// Arm the capture, returning its number
+    pub fn start(&self, state: &AppState, request: CaptureRequest, trigger: Filter) -> u64 {
+        let capture = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        let recording = Arc::new(Mutex::new(Recording::default()));
+        let cancel = state.shutdown.child_token();
+        let limits = Limits {
+            pre_trigger: request.pre_trigger.unwrap_or(Captures::DEFAULT_PRE_TRIGGER),
+            frames: match (request.frames, request.duration_ms) {
+                (Some(frames), _) => frames,
+                (None, Some(_)) => Captures::MAX_FRAMES,
+                (None, None) => Captures::DEFAULT_FRAMES,
+            },
+            duration: request.duration_ms.map(Duration::from_millis),
+        };
+        let events = state.events.subscribe();
+        state.tasks.spawn(run(state.clone(), capture, trigger, limits, recording.clone(), events, cancel.clone()));
+        self.captures.lock().unwrap().insert(capture, Capture { request, recording, cancel });
+        capture
+    }
+
+    /// Stop and remove the capture with its frames, false if unknown
+    pub fn delete(&self, capture: u64) -> bool {
+        match self.captures.lock().unwrap().remove(&capture) {
+            Some(capture) => {
+                capture.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, capture: u64) -> Option<CaptureInfo> {
+        self.captures.lock().unwrap().get(&capture).map(|c| c.info(capture))
+    }
+
+    pub fn list(&self) -> Vec<CaptureInfo> {
+        self.captures.lock().unwrap().iter().map(|(capture, c)| c.info(*capture)).collect()
+    }
+
+    /// Frames recorded so far in candump log format, to be replayed by `POST /api/replay`; none
+    /// if unknown
+    pub fn log(&self, capture: u64) -> Option<String> {
+        let captures = self.captures.lock().unwrap();
+        let recording = captures.get(&capture)?.recording.lock().unwrap();
+        let log = recording.frames.iter().map(|(interface, frame, at)| log_line(*at, interface, frame)).collect();
+        Some(log)
+    }
+}
+
+struct Limits {
+    pre_trigger: usize,
+    frames: usize,
+    duration: Option<Duration>,
+}
+
+fn notify(state: &AppState, notice: String) {
+    tracing::info!("{}", notice);
+    can::publish(&state.events, CanEvent::Notice(notice.into()));
+}
+
+/// Wait for the capture duration to elapse, forever if not triggered or not limited by time
+async fn until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Capture task, keeping the latest frames while armed and recording from the trigger on
+async fn run(state: AppState, capture: u64, trigger: Filter, limits: Limits, recording: Arc<Mutex<Recording>>,
+             events: broadcast::Receiver<CanEvent>, cancel: CancellationToken) {
+    let mut events = Subscription::new(events, cancel, "capture");
+    let mut pre_trigger: VecDeque<Recorded> = VecDeque::with_capacity(limits.pre_trigger);
+    // frames recorded from the trigger on, and the end of the capture by its duration
+    let mut recorded = 0;
+    let mut deadline = None;
+    let outcome = loop {
+        let frame = tokio::select! {
+            frame = events.next_frame() => frame,
+            _ = until(deadline) => break "duration elapsed",
+        };
+        let Some((interface, frame, timestamp, _)) = frame else { return };
+        let mut recording = recording.lock().unwrap();
+        if recording.state == CaptureState::Armed {
+            if !trigger.matches(&interface, &frame) {
+                if limits.pre_trigger > 0 {
+                    if pre_trigger.len() == limits.pre_trigger {
+                        pre_trigger.pop_front();
+                    }
+                    pre_trigger.push_back((interface, frame, timestamp.wall));
+                }
+                continue;
+            }
+            let (fmt, _) = format_frame(&frame);
+            notify(&state, format!("capture {} triggered by {} on {}", capture, fmt, interface));
+            recording.state = CaptureState::Triggered;
+            recording.trigger = Some((fmt, timestamp.wall));
+            recording.frames.extend(pre_trigger.drain(..));
+            deadline = limits.duration.map(|duration| Instant::now() + duration);
+        }
+        recording.frames.push((interface, frame, timestamp.wall));
+        recorded += 1;
+        if recorded >= limits.frames {
+            break "frames recorded";
+        }
+    };
+    let mut recording = recording.lock().unwrap();
+    recording.state = CaptureState::Complete;
+    notify(&state, format!("capture {} complete, {}, {} frames", capture, outcome, recording.frames.len()));
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::auth::Scope;
+use crate::frame::CanFilter;
+
+// DTO - websocket session listed by `GET /api/clients`
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct ClientInfo {
+    pub id: u64,
+    #[schema(value_type = String)]
+    pub peer: SocketAddr,
+    pub user_agent: Option<String>,
+    pub scope: Scope,
+    // seconds since epoch
+    pub connected_at: f64,
+    // subscriptions as `<id>/<mask>` in hex, all frames if empty
+    pub subscriptions: Vec<String>,
+    pub filter: Option<String>,
+    // frames sent to the client and frames written by the client
+    pub frames_sent: u64,
+    pub frames_received: u64,
+}
+
+/// Activity of a session, updated by the session and read by the registry
+#[derive(Default)]
+pub(crate) struct Activity {
+    pub frames_sent: AtomicU64,
+    pub frames_received: AtomicU64,
+    filters: Mutex<(Vec<String>, Option<String>)>,
+}
+
+impl Activity {
+    pub fn set_subscriptions(&self, filters: &[CanFilter]) {
+        let subscriptions = filters.iter()
+            .map(|f| f.as_ref())
+            .map(|f| format!("{:X}/{:X}", f.can_id, f.can_mask))
+            .collect();
+        self.filters.lock().unwrap().0 = subscriptions;
+    }
+
+    pub fn set_filter(&self, filter: Option<&str>) {
+        self.filters.lock().unwrap().1 = filter.map(str::to_string);
+    }
+}
+
+struct Client {
+    peer: SocketAddr,
+    user_agent: Option<String>,
+    scope: Scope,
+    connected_at: SystemTime,
+    activity: Arc<Activity>,
+    kicked: CancellationToken,
+}
+
+/// Registry of the connected websocket sessions, for admins of a shared setup
+#[derive(Default)]
+pub(crate) struct Registry {
+    clients: Mutex<BTreeMap<u64, Client>>,
+    next: AtomicU64,
+}
+
+/// A session registered until dropped, on closing the session
+pub(crate) struct Registration {
+    registry: Arc<Registry>,
+    pub id: u64,
+    pub activity: Arc<Activity>,
+    // cancelled by `DELETE /api/clients/:id`, closing the session
+    pub kicked: CancellationToken,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.registry.clients.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Registry {
+    pub fn register(self: &Arc<Self>, peer: SocketAddr, user_agent: Option<String>, scope: Scope) -> Registration {
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        let activity = Arc::<Activity>::default();
+        let kicked = CancellationToken::new();
+        let client = Client {
+            peer,
+            user_agent,
+            scope,
+            connected_at: SystemTime::now(),
+            activity: activity.clone(),
+            kicked: kicked.clone(),
+        };
+        self.clients.lock().unwrap().insert(id, client);
+        Registration { registry: self.clone(), id, activity, kicked }
+    }
+
+    pub fn list(&self) -> Vec<ClientInfo> {
+        self.clients
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, client)| {
+                let (subscriptions, filter) = client.activity.filters.lock().unwrap().clone();
+                ClientInfo {
+                    id: *id,
+                    peer: client.peer,
+                    user_agent: client.user_agent.clone(),
+                    scope: client.scope,
+                    connected_at: client.connected_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+                    subscriptions,
+                    filter,
+                    frames_sent: client.activity.frames_sent.load(Ordering::Relaxed),
+                    frames_received: client.activity.frames_received.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+
+    /// Close the session, false if unknown
+    pub fn kick(&self, id: u64) -> bool {
+        match self.clients.lock().unwrap().get(&id) {
+            Some(client) => {
+                client.kicked.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Id};
+
+/// Decoder of a custom payload, e.g. of a proprietary protocol, registered for CAN ids by
+/// [crate::server::ServerBuilder::decoder]
+///
+/// The decoded value is forwarded with the frame as `custom`, next to the signals decoded by the
+/// DBC database. Closures `Fn(&CanAnyFrame) -> Option<Value>` are decoders too.
+pub trait FrameDecoder: Send + Sync {
+    /// Decode the payload of the frame, None if the frame is not decodable
+    fn decode(&self, frame: &CanAnyFrame) -> Option<Value>;
+}
+
+impl<F> FrameDecoder for F
+where
+    F: Fn(&CanAnyFrame) -> Option<Value> + Send + Sync,
+{
+    fn decode(&self, frame: &CanAnyFrame) -> Option<Value> {
+        self(frame)
+    }
+}
+
+/// Decoders keyed by the CAN id of the frames decoded, standard and extended ids being distinct
+#[derive(Default, Clone)]
+pub(crate) struct Registry {
+    decoders: HashMap<Id, Arc<dyn FrameDecoder>>,
+}
+
+impl Registry {
+    /// Register the decoder for the id, replacing a decoder registered before
+    pub fn register(&mut self, id: Id, decoder: Arc<dyn FrameDecoder>) {
+        self.decoders.insert(id, decoder);
+    }
+
+    /// Decode the frame by the decoder of its id, remote and error frames carrying no payload
+    pub fn decode(&self, frame: &CanAnyFrame) -> Option<Value> {
+        if frame.is_remote_frame() || matches!(frame, CanAnyFrame::Error(_)) {
+            return None;
+        }
+        self.decoders.get(&frame.id())?.decode(frame)
+    }
+}
@@ -0,0 +1,633 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use axum::http::{HeaderName, HeaderValue, Method, Uri};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser};
+use serde::Deserialize;
+use tracing::Level;
+
+use crate::auth::Allowlist;
+use crate::limit::Rate;
+use crate::setup::BitTiming;
+use crate::timeouts::CycleTime;
+use crate::transport::{RxFilter, Transport};
+
+/// Config files searched if no `--config` is given, the first one found being loaded
+pub const CONFIG_PATHS: &[&str] = &["config.toml", "/etc/rust-vue-demo/config.toml"];
+
+/// Configuration of the web-service, from command line arguments with fallback to environment,
+/// then to the config file
+#[derive(Parser, Debug, Clone)]
+#[command(version, about = "Monitor and write CAN frames from a web browser")]
+pub struct Config {
+    /// Config file in TOML format, searched as `config.toml` in the working directory and in `/etc/rust-vue-demo/` if missing
+    #[arg(long, env = "CONFIG_FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Port the web-service is listening at
+    #[arg(short, long, env = "PORT", default_value_t = 3000)]
+    pub port: u16,
+
+    /// Addresses the web-service is bound to, comma separated or repeated, each listening at
+    /// `--port` or at a port of its own, e.g. `0.0.0.0`, `192.168.1.10:8080` or `[::]:3000`; `::`
+    /// accepting IPv4 too, unless IPv4 is bound to the same port
+    #[arg(short, long, env = "BIND", value_delimiter = ',', default_value = "0.0.0.0")]
+    pub bind: Vec<Bind>,
+
+    /// CAN devices to read from and write to, comma separated or repeated; the first one is the default for writing
+    #[arg(short = 'c', long, env = "CANDEV", value_delimiter = ',', default_value = "vcan0")]
+    pub can_dev: Vec<String>,
+
+    /// Transport of each CAN device in order, `socketcan`, `slcan:<serial port>[@<baud rate>]`, `gs_usb[:<serial number>]` or `sllin` of a LIN bus, eg `slcan:/dev/ttyACM0@115200`; SocketCAN if missing
+    #[arg(long, env = "TRANSPORT", value_delimiter = ',')]
+    pub transport: Vec<Transport>,
+
+    /// Receive filters of the kernel, `<id>:<mask>` or inverted `<id>~<mask>` in hex, comma separated, eg `0x100:0x700,0x200:0x7FF`;
+    /// a frame passes if matching any of them, all frames if none is given
+    #[arg(long, env = "CAN_FILTER", value_delimiter = ',')]
+    pub filter: Vec<RxFilter>,
+
+    /// Simulate traffic on all CAN devices instead of opening them, for demos without CAN hardware
+    #[arg(long, env = "SIMULATE")]
+    pub simulate: bool,
+
+    /// Message set of the simulation, a message `<id> <period ms> <payload>` per line, eg `123 100 ++00????`, with `++` a counter and `??` a random byte
+    #[arg(long, env = "SIMULATE_MESSAGES", requires = "simulate")]
+    pub simulate_messages: Option<PathBuf>,
+
+    /// Profile of the simulation in TOML format, the messages of `--dbc` with the curves of their signals, eg
+    /// `[[message]]` of `name = "EngineData"` and `signals.EngineSpeed = { ramp = { from = 800, to = 6000, duration_s = 10 } }`
+    #[arg(long, env = "DEMO_PROFILE", requires = "simulate", conflicts_with = "simulate_messages")]
+    pub demo_profile: Option<PathBuf>,
+
+    /// Open no CAN devices, serving the web-page and the REST API only, e.g. in a container
+    /// without access to the CAN interfaces of the host
+    #[arg(long, env = "NO_CAN", conflicts_with_all = ["simulate", "setup", "transport"])]
+    pub no_can: bool,
+
+    /// Vite dev server to proxy the web-page to instead of serving the embedded assets, eg `http://localhost:8080`, for hot module reload
+    #[arg(long, env = "DEV_PROXY")]
+    pub dev_proxy: Option<Uri>,
+
+    /// Directory serving the web-page, eg a patched `webui/dist`, falling back to the embedded assets
+    /// for the files missing in it
+    #[arg(long, env = "ASSETS", conflicts_with = "dev_proxy")]
+    pub assets: Option<PathBuf>,
+
+    /// Max level of log output: error, warn, info, debug or trace; `RUST_LOG` directives take precedence, eg `rust_vue=debug,tower_http=warn`
+    #[arg(short, long, env = "LOG_LEVEL", default_value_t = Level::INFO)]
+    pub log_level: Level,
+
+    /// DBC database used to decode the signals of received frames
+    #[arg(long, env = "DBC")]
+    pub dbc: Option<PathBuf>,
+
+    /// Names of the CAN ids attached to each frame forwarded, by a TOML file of a `[[message]]`
+    /// table per id, e.g. `id = "1A0"`, `name = "Brake Status"`, `description = "..."`
+    #[arg(long, env = "NAMES")]
+    pub names: Option<PathBuf>,
+
+    /// Record all received frames to this file in candump log format
+    #[arg(long, env = "RECORD")]
+    pub record: Option<PathBuf>,
+
+    /// Record all received frames to this file as pcapng capture of link type CAN_SOCKETCAN, for Wireshark
+    #[arg(long, env = "RECORD_PCAP")]
+    pub record_pcap: Option<PathBuf>,
+
+    /// Record all received frames to this file as Vector ASC log, replacing existing content
+    #[arg(long, env = "RECORD_ASC")]
+    pub record_asc: Option<PathBuf>,
+
+    /// Record all received frames to this file as Vector BLF log, replacing existing content
+    #[arg(long, env = "RECORD_BLF")]
+    pub record_blf: Option<PathBuf>,
+
+    /// Frames kept in memory, the latest received, listed by `GET /api/frames` so a client connecting
+    /// backfills its monitor; 0 disabling the buffer
+    #[arg(long, env = "BUFFER_LEN", default_value_t = 10_000)]
+    pub buffer_len: usize,
+
+    /// Store all received frames in this SQLite database, queried by `GET /api/history`
+    #[arg(long, env = "DB")]
+    pub db: Option<PathBuf>,
+
+    /// Append every frame written by the clients, with the client's address and token, to this
+    /// file as JSON lines, the latest listed by `GET /api/audit`
+    #[arg(long, env = "AUDIT_LOG")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Store the presets of `/api/presets`, named frames written by `POST /api/presets/<name>/send`,
+    /// in this JSON file
+    #[arg(long, env = "PRESETS")]
+    pub presets: Option<PathBuf>,
+
+    /// Evaluate the alert rules of `/api/alerts`, stored in this JSON file, on each received frame,
+    /// e.g. `{"overheat": {"condition": {"signal": "EngineTemp > 110"}, "for_ms": 5000}}`
+    #[arg(long, env = "ALERTS")]
+    pub alerts: Option<PathBuf>,
+
+    /// Expected cycle time of a periodic message, `<id>:<ms>` with the id in hex, comma separated
+    /// or repeated, e.g. `0x123:100`; a message missing for `--timeout-factor` cycles is reported
+    /// to the clients as `timeout`, and once received again
+    #[arg(long, env = "CYCLE_TIME", value_delimiter = ',')]
+    pub cycle_time: Vec<CycleTime>,
+
+    /// Report the timeouts of the messages of `--dbc` too, by their cycle time `GenMsgCycleTime`
+    #[arg(long, env = "MESSAGE_TIMEOUTS")]
+    pub message_timeouts: bool,
+
+    /// Cycles a periodic message may be missing before reported as timed out
+    #[arg(long, env = "TIMEOUT_FACTOR", default_value_t = 3.0)]
+    pub timeout_factor: f64,
+
+    /// Run the Rhai scripts of this directory on each received frame, stored as `<name>.rhai`
+    /// and managed by `/api/scripts`
+    #[cfg(feature = "scripting")]
+    #[arg(long, env = "SCRIPTS")]
+    pub scripts: Option<PathBuf>,
+
+    /// Forward frames between the CAN devices by the rules of this TOML file, a `[[rule]]` table per rule
+    #[arg(long, env = "GATEWAY")]
+    pub gateway: Option<PathBuf>,
+
+    /// Certificate chain in PEM format, serving HTTPS and WSS instead of plaintext
+    #[arg(long, env = "TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// Private key in PEM format, matching the certificate
+    #[arg(long, env = "TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Nominal bitrate of the CAN bus in bit/s, used to estimate the bus load
+    #[arg(long, env = "BITRATE", default_value_t = 500_000)]
+    pub bitrate: u32,
+
+    /// Data phase bitrate of CAN FD frames with bit rate switch in bit/s
+    #[arg(long, env = "DATA_BITRATE", default_value_t = 2_000_000)]
+    pub data_bitrate: u32,
+
+    /// Set the bit timing of the CAN devices via netlink and bring them up at startup, requires CAP_NET_ADMIN
+    #[arg(long, env = "CAN_SETUP")]
+    pub setup: bool,
+
+    /// Sample point of the nominal bitrate for `--setup`, e.g. 0.875
+    #[arg(long, env = "SAMPLE_POINT", requires = "setup")]
+    pub sample_point: Option<f32>,
+
+    /// Enable CAN FD with the data bitrate for `--setup`
+    #[arg(long, env = "CAN_FD", requires = "setup")]
+    pub fd: bool,
+
+    /// Sample point of the data bitrate for `--setup --fd`, e.g. 0.75
+    #[arg(long, env = "DATA_SAMPLE_POINT", requires = "fd")]
+    pub data_sample_point: Option<f32>,
+
+    /// Restart a SocketCAN device by netlink this many milliseconds after bus-off, requires
+    /// CAP_NET_ADMIN; unless its `restart-ms` of the kernel restarts it anyway
+    #[arg(long, env = "RESTART_MS")]
+    pub restart_ms: Option<u64>,
+
+    /// Poll the standard OBD-II PIDs (engine speed, vehicle speed, coolant temperature, ...) on the default CAN device
+    #[arg(long, env = "OBD")]
+    pub obd: bool,
+
+    /// Polling interval of the OBD-II PIDs in milliseconds
+    #[arg(long, env = "OBD_INTERVAL", default_value_t = 1000)]
+    pub obd_interval: u64,
+
+    /// Decode the CANopen services of received frames and report the state changes of the nodes by their heartbeats
+    #[arg(long, env = "CANOPEN")]
+    pub canopen: bool,
+
+    /// Decode the J1939 parameter groups of 29-bit frames, reassembling BAM transfers
+    #[cfg(feature = "j1939")]
+    #[arg(long, env = "J1939")]
+    pub j1939: bool,
+
+    /// Decode the NMEA 2000 PGNs of position, navigation and engine parameters of 29-bit frames, reassembling fast packets
+    #[cfg(feature = "nmea2000")]
+    #[arg(long, env = "NMEA2000")]
+    pub nmea2000: bool,
+
+    /// MQTT broker to mirror all received frames to, e.g. `tcp://localhost:1883`, publishing to `can/<iface>/<id>`
+    #[arg(long, env = "MQTT_BROKER")]
+    pub mqtt_broker: Option<String>,
+
+    /// MQTT topic to subscribe to, writing the published frames in `cansend` notation to the CAN bus
+    #[arg(long, env = "MQTT_COMMAND_TOPIC", requires = "mqtt_broker")]
+    pub mqtt_command_topic: Option<String>,
+
+    /// InfluxDB or Grafana Live URL to write the signals decoded by `--dbc` to in line protocol,
+    /// e.g. `http://localhost:8086/api/v2/write?org=lab&bucket=can`
+    #[arg(long, env = "INFLUX_URL")]
+    pub influx_url: Option<String>,
+
+    /// Authorization header of the InfluxDB writes, e.g. `Token <token>` or `Bearer <token>` of Grafana
+    #[arg(long, env = "INFLUX_AUTH", hide_env_values = true)]
+    pub influx_auth: Option<String>,
+
+    /// Webhook URLs, comma separated or repeated, each POSTed a JSON notification of the service
+    /// starting and stopping, the CAN devices connecting and disconnecting, the bus state changes
+    /// and the alerts; retried with backoff, plain HTTP only
+    #[arg(long, env = "WEBHOOK", value_delimiter = ',')]
+    pub webhook: Vec<String>,
+
+    /// Peer or multicast group to tunnel the received frames to in the format of cannelloni, e.g.
+    /// `239.0.0.1:20000`, receiving the frames of the remote side at `--cannelloni-port`
+    #[arg(long, env = "CANNELLONI")]
+    pub cannelloni: Option<SocketAddr>,
+
+    /// Local port of `--cannelloni`, the port of the peer or group if missing
+    #[arg(long, env = "CANNELLONI_PORT")]
+    pub cannelloni_port: Option<u16>,
+
+    /// Announce the web UI as `_can-monitor._tcp.local` by mDNS, found on the LAN without knowing
+    /// the address of the gateway, e.g. by `avahi-browse _can-monitor._tcp`
+    #[arg(long, env = "MDNS")]
+    pub mdns: bool,
+
+    /// Instance name of the mDNS announcement, e.g. `Test Bench 2`; the host name if missing
+    #[arg(long, env = "MDNS_NAME", requires = "mdns")]
+    pub mdns_name: Option<String>,
+
+    /// Kafka producer to mirror all received frames to as JSON records, e.g.
+    /// `brokers=localhost:9092,topic=can-frames`, further librdkafka properties by `<key>=<value>`
+    #[cfg(feature = "kafka")]
+    #[arg(long, env = "KAFKA")]
+    pub kafka: Option<String>,
+
+    /// Port of the gRPC service streaming and writing frames, see proto/can.proto; disabled by default
+    #[cfg(feature = "grpc")]
+    #[arg(long, env = "GRPC_PORT")]
+    pub grpc_port: Option<u16>,
+
+    /// Disable writing frames, of the websocket clients, the REST API and its jobs, e.g. monitoring
+    /// a vehicle on the road; the frames rejected with 403 or the error `read_only`
+    #[arg(long, env = "READ_ONLY", conflicts_with_all = ["obd", "gateway", "mqtt_command_topic"])]
+    pub read_only: bool,
+
+    /// Max rate of frames written by each websocket client, e.g. `100/s` or `600/min`; unlimited by default
+    #[arg(long, env = "TX_RATE_LIMIT")]
+    pub tx_rate_limit: Option<Rate>,
+
+    /// Interval of the pings sent to each websocket client in seconds, 0 disabling the heartbeat
+    #[arg(long, env = "PING_INTERVAL", default_value_t = 10)]
+    pub ping_interval: u64,
+
+    /// Close a websocket client not responding for this many seconds, eg a laptop gone to sleep
+    #[arg(long, env = "PING_TIMEOUT", default_value_t = 30)]
+    pub ping_timeout: u64,
+
+    /// Coalesce the frames received within this many milliseconds into a single websocket
+    /// message, e.g. 50 for buses of thousands of frames per second; 0 sending each frame at once
+    #[arg(long, env = "BATCH_INTERVAL", default_value_t = 0)]
+    pub batch_interval: u64,
+
+    /// Max size in bytes of a message of a websocket client, closing the connection of a client
+    /// exceeding it
+    #[arg(long, env = "MAX_MESSAGE_SIZE", default_value_t = 64 * 1024)]
+    pub max_message_size: usize,
+
+    /// Max size in bytes of the body of a REST request, e.g. of a recording replayed, rejected by
+    /// 413 if exceeded
+    #[arg(long, env = "MAX_BODY_SIZE", default_value_t = 2 * 1024 * 1024)]
+    pub max_body_size: usize,
+
+    /// Max messages queued for each websocket client, the oldest dropped if the client is too slow
+    #[arg(long, env = "CLIENT_QUEUE_LEN", default_value_t = 1024)]
+    pub client_queue_len: usize,
+
+    /// Max frames held for each websocket client having paused the stream, sent on resuming; the
+    /// oldest dropped once exceeded, 0 dropping all frames while paused
+    #[arg(long, env = "PAUSE_LEN", default_value_t = 10_000)]
+    pub pause_len: usize,
+
+    /// Worker threads of the runtime serving HTTP, the websocket sessions and the background
+    /// jobs, the count of CPU cores if missing, e.g. 1 or 2 on small embedded boards
+    #[arg(long, env = "WORKER_THREADS")]
+    pub worker_threads: Option<usize>,
+
+    /// Read the CAN devices on a thread of their own, running a single-threaded runtime, so heavy
+    /// web traffic does not delay the frames received
+    #[arg(long, env = "CAN_THREAD")]
+    pub can_thread: bool,
+
+    /// Token required for the websocket and the REST API, sent as bearer token or login cookie
+    #[arg(long, env = "AUTH_TOKEN", hide_env_values = true)]
+    pub auth_token: Option<String>,
+
+    /// Token granting read-only access, to `/ws/monitor` and the GET requests of the REST API;
+    /// writing frames requires the auth token
+    #[arg(long, env = "MONITOR_TOKEN", hide_env_values = true)]
+    pub monitor_token: Option<String>,
+
+    /// Origins of the web pages allowed to call the REST API, comma separated or repeated, e.g.
+    /// `https://grafana.example.com`, or `*` for any origin; none allowed by default
+    #[arg(long, env = "CORS_ORIGIN", value_delimiter = ',')]
+    pub cors_origin: Vec<String>,
+
+    /// Methods of the requests allowed of `--cors-origin`, comma separated
+    #[arg(long, env = "CORS_METHODS", value_delimiter = ',', default_value = "GET,POST,PUT,DELETE")]
+    pub cors_methods: Vec<Method>,
+
+    /// Headers of the requests allowed of `--cors-origin`, comma separated
+    #[arg(long, env = "CORS_HEADERS", value_delimiter = ',', default_value = "authorization,content-type")]
+    pub cors_headers: Vec<HeaderName>,
+
+    /// Tokens writing only the ids of their allowlist, by `[[auth.tokens]]` of the config file
+    #[arg(skip)]
+    pub tokens: Vec<TokenGrant>,
+
+    // command line arguments and environment variables loaded of, see [Config::reload]
+    #[arg(skip)]
+    args: Option<ArgMatches>,
+}
+
+// Settings of the config file, overridden by command line arguments and environment variables
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    port: Option<u16>,
+    bind: Option<Binds>,
+    can_dev: Option<Vec<String>>,
+    log_level: Option<String>,
+    filter: Option<Vec<String>>,
+    dbc: Option<PathBuf>,
+    names: Option<PathBuf>,
+    tls: Option<TlsSection>,
+    auth: Option<AuthSection>,
+    influx: Option<InfluxSection>,
+}
+
+// `bind` of the config file, an address or a list of addresses
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Binds {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Address of `--bind`, listening at `--port` if none is given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bind {
+    pub ip: IpAddr,
+    pub port: Option<u16>,
+}
+
+impl FromStr for Bind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(Bind { ip: addr.ip(), port: Some(addr.port()) });
+        }
+        // IPv6 addresses in brackets too, as of URLs
+        let ip = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')).unwrap_or(s);
+        ip.parse()
+            .map(|ip| Bind { ip, port: None })
+            .map_err(|_| format!("invalid bind address {}, expecting <ip> or <ip>:<port>", s))
+    }
+}
+
+// `[tls]` of the config file, paths relative to the config file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct TlsSection {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+// `[auth]` of the config file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct AuthSection {
+    token: String,
+    monitor_token: Option<String>,
+    #[serde(default)]
+    tokens: Vec<TokenGrant>,
+}
+
+/// `[[auth.tokens]]` of the config file, a token granting the control scope restricted to
+/// writing the ids of the allowlist, e.g. `transmit = ["0x7DF", "0x100-0x1FF"]`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TokenGrant {
+    // named in errors and logs
+    pub name: String,
+    pub token: String,
+    pub transmit: Allowlist,
+}
+
+// `[influx]` of the config file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct InfluxSection {
+    url: String,
+    auth: Option<String>,
+}
+
+impl Config {
+    /// Parse the command line arguments and environment variables, falling back to the
+    /// settings of the config file, and validate the result
+    ///
+    /// Exits on malformed command line arguments, like [Parser::parse].
+    pub fn load() -> Result<Config, String> {
+        let matches = Config::command().get_matches();
+        let config = Config::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        config.with_file(matches)
+    }
+
+    /// Load the config anew of the same command line arguments and environment variables,
+    /// re-reading the config file; a config not loaded by [Config::load] of the defaults and
+    /// environment variables
+    pub fn reload(&self) -> Result<Config, String> {
+        let matches = match &self.args {
+            Some(matches) => matches.clone(),
+            None => Config::command().try_get_matches_from(["rust-vue"]).map_err(|e| e.to_string())?,
+        };
+        let mut config = Config::from_arg_matches(&matches).map_err(|e| e.to_string())?;
+        config.config = config.config.or_else(|| self.config.clone());
+        config.with_file(matches)
+    }
+
+    fn with_file(mut self, matches: ArgMatches) -> Result<Config, String> {
+        let path = match &self.config {
+            Some(path) => Some(path.clone()),
+            None => CONFIG_PATHS.iter().map(PathBuf::from).find(|path| path.is_file()),
+        };
+        if let Some(path) = path {
+            self.merge_file(&path, &matches)?;
+            self.config = Some(path);
+        }
+        self.validate()?;
+        self.args = Some(matches);
+        Ok(self)
+    }
+
+    /// Apply the settings of the file not given as command line argument or environment variable
+    fn merge_file(&mut self, path: &Path, matches: &ArgMatches) -> Result<(), String> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read config file {}: {}", path.display(), e))?;
+        let file: ConfigFile = toml::from_str(&s)
+            .map_err(|e| format!("invalid config file {}: {}", path.display(), e))?;
+        let given = |id: &str| matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable));
+
+        if let Some(port) = file.port.filter(|_| !given("port")) {
+            self.port = port;
+        }
+        if let Some(bind) = file.bind.filter(|_| !given("bind")) {
+            let binds = match bind {
+                Binds::One(bind) => vec![bind],
+                Binds::Many(binds) => binds,
+            };
+            self.bind = binds
+                .iter()
+                .map(|bind| bind.parse().map_err(|e| format!("{}: {}", path.display(), e)))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(can_dev) = file.can_dev.filter(|_| !given("can_dev")) {
+            self.can_dev = can_dev;
+        }
+        if let Some(level) = file.log_level.filter(|_| !given("log_level")) {
+            self.log_level = Level::from_str(&level).map_err(|_| {
+                format!("{}: invalid log_level `{}`, expected error, warn, info, debug or trace", path.display(), level)
+            })?;
+        }
+        let dir = path.parent().unwrap_or(Path::new(""));
+        if let Some(filters) = file.filter.filter(|_| !given("filter")) {
+            self.filter = filters
+                .iter()
+                .map(|filter| filter.parse().map_err(|e| format!("{}: {}", path.display(), e)))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(dbc) = file.dbc.filter(|_| !given("dbc")) {
+            self.dbc = Some(dir.join(dbc));
+        }
+        if let Some(names) = file.names.filter(|_| !given("names")) {
+            self.names = Some(dir.join(names));
+        }
+        if let Some(tls) = file.tls.filter(|_| !given("tls_cert") && !given("tls_key")) {
+            self.tls_cert = Some(dir.join(tls.cert));
+            self.tls_key = Some(dir.join(tls.key));
+        }
+        if let Some(auth) = file.auth {
+            if !given("auth_token") {
+                self.auth_token = Some(auth.token);
+            }
+            if let Some(token) = auth.monitor_token.filter(|_| !given("monitor_token")) {
+                self.monitor_token = Some(token);
+            }
+            self.tokens = auth.tokens;
+        }
+        if let Some(influx) = file.influx.filter(|_| !given("influx_url")) {
+            self.influx_url = Some(influx.url);
+            if !given("influx_auth") {
+                self.influx_auth = influx.auth;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the settings not validated by the command line parser, as they may be given by the config file
+    fn validate(&self) -> Result<(), String> {
+        if (self.can_dev.is_empty() && !self.no_can) || self.can_dev.iter().any(|name| name.is_empty()) {
+            return Err("missing CAN device name".to_string());
+        }
+        if self.bind.is_empty() {
+            return Err("missing bind address".to_string());
+        }
+        let addrs = self.listen_addrs();
+        if let Some(addr) = addrs.iter().enumerate().find_map(|(i, addr)| addrs[..i].contains(addr).then_some(addr)) {
+            return Err(format!("bind address {} given twice", addr));
+        }
+        if self.max_message_size == 0 || self.max_body_size == 0 {
+            return Err("invalid max message or body size 0".to_string());
+        }
+        if let Some(name) = self.mdns_name.as_ref().filter(|name| name.is_empty() || name.len() > 63) {
+            return Err(format!("invalid mDNS name {:?}, expecting 1 to 63 bytes", name));
+        }
+        if self.timeout_factor.is_nan() || self.timeout_factor < 1.0 {
+            return Err(format!("invalid timeout factor {}, expecting at least 1", self.timeout_factor));
+        }
+        if self.worker_threads == Some(0) {
+            return Err("invalid worker threads 0, expecting at least 1".to_string());
+        }
+        if let Some(dir) = self.assets.as_ref().filter(|dir| !dir.is_dir()) {
+            return Err(format!("assets directory {} not found", dir.display()));
+        }
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                for (name, path) in [("certificate", cert), ("key", key)] {
+                    if !path.is_file() {
+                        return Err(format!("TLS {} {} not found", name, path.display()));
+                    }
+                }
+            }
+            (None, None) => (),
+            _ => return Err("TLS requires both a certificate and a key".to_string()),
+        }
+        if self.auth_token.as_deref() == Some("") || self.monitor_token.as_deref() == Some("") {
+            return Err("empty auth token".to_string());
+        }
+        if self.monitor_token.is_some() && self.auth_token.is_none() {
+            return Err("monitor token without auth token, all clients could write frames".to_string());
+        }
+        if self.monitor_token.is_some() && self.monitor_token == self.auth_token {
+            return Err("monitor token same as auth token".to_string());
+        }
+        if self.cors_origin.len() > 1 && self.cors_origin.iter().any(|origin| origin == "*") {
+            return Err("CORS origin * together with other origins".to_string());
+        }
+        if let Some(origin) = self.cors_origin.iter().find(|origin| HeaderValue::from_str(origin).is_err() || origin.is_empty()) {
+            return Err(format!("invalid CORS origin {:?}", origin));
+        }
+        for (i, grant) in self.tokens.iter().enumerate() {
+            if grant.token.is_empty() {
+                return Err(format!("empty auth token {}", grant.name));
+            }
+            let duplicate = [&self.auth_token, &self.monitor_token].iter().any(|token| token.as_deref() == Some(&grant.token))
+                || self.tokens[..i].iter().any(|other| other.token == grant.token);
+            if duplicate {
+                return Err(format!("auth token {} same as another token", grant.name));
+            }
+        }
+        for url in &self.webhook {
+            crate::webhook::url(url)?;
+        }
+        if let Some(url) = &self.influx_url {
+            crate::influx::write_url(url)?;
+        } else if self.influx_auth.is_some() {
+            return Err("InfluxDB authorization without URL".to_string());
+        }
+        Ok(())
+    }
+
+    /// Addresses of `--bind` with their ports
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        self.bind.iter().map(|bind| SocketAddr::new(bind.ip, bind.port.unwrap_or(self.port))).collect()
+    }
+
+    /// First address of `--bind`, of the service URL
+    pub fn listen_addr(&self) -> SocketAddr {
+        let bind = self.bind.first().copied().unwrap_or(Bind { ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED), port: None });
+        SocketAddr::new(bind.ip, bind.port.unwrap_or(self.port))
+    }
+
+    /// Bit timing of `--setup`, sample points in tenths of a percent as expected by netlink
+    pub fn bit_timing(&self) -> BitTiming {
+        let tenths = |sample_point: f32| (sample_point * 1000.0).round() as u32;
+        BitTiming {
+            bitrate: self.bitrate,
+            sample_point: self.sample_point.map(tenths),
+            fd: self.fd.then(|| (self.data_bitrate, self.data_sample_point.map(tenths))),
+        }
+    }
+
+    /// URL scheme of the web-service, https if a certificate is configured
+    pub fn scheme(&self) -> &'static str {
+        if self.tls_cert.is_some() { "https" } else { "http" }
+    }
+}
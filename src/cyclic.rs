@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::api::SendFrame;
+use crate::audit::{self, Origin};
+use crate::frame::CanAnyFrame;
+use crate::server::AppState;
+
+// DTO - periodic transmission of `POST /api/cyclic` and `PUT /api/cyclic/:job`
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct CyclicRequest {
+    #[serde(flatten)]
+    pub frame: SendFrame,
+    pub interval_ms: u64,
+}
+
+// DTO - registered job, with the number of frames sent so far
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct CyclicJob {
+    pub job: u64,
+    #[serde(flatten)]
+    pub request: CyclicRequest,
+    pub sent: u64,
+}
+
+struct Job {
+    request: CyclicRequest,
+    sent: Arc<AtomicU64>,
+    cancel: CancellationToken,
+}
+
+/// Scheduler of cyclic transmissions, like the cyclic TX_SETUP of the broadcast manager,
+/// each job being a task writing its frame once per interval until cancelled or shutdown
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Mutex<BTreeMap<u64, Job>>,
+    next: AtomicU64,
+}
+
+async fn transmit(state: AppState, origin: Origin, interface: Option<String>, frame: CanAnyFrame, interval: Duration,
+                  sent: Arc<AtomicU64>, cancel: CancellationToken) {
+    let mut ticker = tokio::time::interval(interval);
+    // keep the interval after delays, rather than sending a burst of frames
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                // a missing device is not fatal, sending resumes once it is back
+                let result = state.buses.write_frame(interface.as_deref(), &frame).await;
+                audit::record(&state, &origin, interface.as_deref(), &frame, &result);
+                if result.is_ok() {
+                    sent.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            _ = cancel.cancelled() => return,
+        }
+    }
+}
+
+impl Scheduler {
+    fn spawn(state: &AppState, origin: Origin, request: CyclicRequest, frame: CanAnyFrame, sent: Arc<AtomicU64>) -> Job {
+        let cancel = state.shutdown.child_token();
+        let interval = Duration::from_millis(request.interval_ms);
+        state.tasks.spawn(transmit(
+            state.clone(),
+            origin,
+            request.frame.interface.clone(),
+            frame,
+            interval,
+            sent.clone(),
+            cancel.clone(),
+        ));
+        Job { request, sent, cancel }
+    }
+
+    /// Register a job of the client, returning its number
+    pub fn start(&self, state: &AppState, origin: Origin, request: CyclicRequest, frame: CanAnyFrame) -> u64 {
+        let job = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        let spawned = Self::spawn(state, origin, request, frame, Arc::default());
+        self.jobs.lock().unwrap().insert(job, spawned);
+        job
+    }
+
+    /// Replace frame and interval of the job, keeping its count of frames sent; false if unknown
+    pub fn update(&self, state: &AppState, origin: Origin, job: u64, request: CyclicRequest, frame: CanAnyFrame) -> bool {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(old) = jobs.remove(&job) else { return false };
+        old.cancel.cancel();
+        jobs.insert(job, Self::spawn(state, origin, request, frame, old.sent));
+        true
+    }
+
+    /// Cancel the job, false if unknown
+    pub fn cancel(&self, job: u64) -> bool {
+        match self.jobs.lock().unwrap().remove(&job) {
+            Some(job) => {
+                job.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, job: u64) -> Option<CyclicJob> {
+        self.jobs.lock().unwrap().get(&job).map(|j| CyclicJob {
+            job,
+            request: j.request.clone(),
+            sent: j.sent.load(Ordering::Relaxed),
+        })
+    }
+
+    pub fn list(&self) -> Vec<CyclicJob> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(job, j)| CyclicJob { job: *job, request: j.request.clone(), sent: j.sent.load(Ordering::Relaxed) })
+            .collect()
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use can_dbc::{AttributeValue, ByteOrder, Dbc, Message, MultiplexIndicator, NumericValue, Signal, ValueType};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Frame};
+
+// DTO - signal value of a decoded frame
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct SignalValue {
+    pub name: String,
+    pub value: f64,
+    pub unit: String,
+}
+
+// DTO - frame decoded by the message definition of the DBC database
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct DecodedFrame {
+    pub message: String,
+    pub signals: Vec<SignalValue>,
+}
+
+/// Decoder of CAN frames, using the messages of a DBC database
+pub struct Decoder {
+    // messages keyed by the raw message id, having bit 31 set for extended ids
+    messages: HashMap<u32, Message>,
+    // cycle times of the periodic messages by `GenMsgCycleTime`, keyed like the messages
+    cycle_times: HashMap<u32, Duration>,
+}
+
+impl Decoder {
+    pub fn from_file(path: &Path) -> Result<Decoder, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read DBC file {}: {}", path.display(), e))?;
+        let dbc = Dbc::try_from(content.as_str())
+            .map_err(|e| format!("failed to parse DBC file {}: {}", path.display(), e))?;
+        Ok(Decoder::from_dbc(dbc))
+    }
+
+    pub fn from_dbc(dbc: Dbc) -> Decoder {
+        // the default of the attribute applying to the messages not assigning it, 0 of event messages
+        let millis = |value: &AttributeValue| match *value {
+            AttributeValue::Uint(ms) => Some(ms),
+            AttributeValue::Int(ms) => u64::try_from(ms).ok(),
+            AttributeValue::Double(ms) if ms >= 0.0 => Some(ms as u64),
+            _ => None,
+        };
+        let default = dbc.attribute_default("GenMsgCycleTime").and_then(millis);
+        let cycle_times = dbc.messages
+            .iter()
+            .filter_map(|message| {
+                let ms = dbc.message_attribute(message.id, "GenMsgCycleTime").map_or(default, millis)?;
+                (ms > 0).then(|| (message.id.raw(), Duration::from_millis(ms)))
+            })
+            .collect();
+        let messages = dbc.messages
+            .into_iter()
+            .map(|message| (message.id.raw(), message))
+            .collect();
+        Decoder { messages, cycle_times }
+    }
+
+    /// Cycle time of the periodic message of the id, see [message_id]
+    pub fn cycle_time(&self, id: u32) -> Option<Duration> {
+        self.cycle_times.get(&id).copied()
+    }
+
+    /// Name of the message of the id
+    pub fn message_name(&self, id: u32) -> Option<&str> {
+        self.messages.get(&id).map(|message| message.name.as_str())
+    }
+
+    /// Message of the name, with its id as keyed by [message_id]
+    pub fn message_by_name(&self, name: &str) -> Option<(u32, &Message)> {
+        self.messages.iter().find(|(_, message)| message.name == name).map(|(id, message)| (*id, message))
+    }
+
+    /// Whether a message, of the name if given, has the signal
+    pub fn has_signal(&self, message: Option<&str>, signal: &str) -> bool {
+        self.messages
+            .values()
+            .filter(|m| message.is_none_or(|message| m.name == message))
+            .any(|m| m.signals.iter().any(|s| s.name == signal))
+    }
+
+    /// Decode all signals of the frame, None if the frame's id is unknown
+    pub fn decode(&self, frame: &CanAnyFrame) -> Option<DecodedFrame> {
+        // remote frames carry no signals
+        if frame.is_remote_frame() {
+            return None;
+        }
+        let message = self.messages.get(&message_id(frame))?;
+        let data = frame.data();
+
+        // value of the multiplexor switch, selecting the multiplexed signals present in this frame
+        let switch = message.signals
+            .iter()
+            .find(|s| matches!(s.multiplexer_indicator,
+                MultiplexIndicator::Multiplexor | MultiplexIndicator::MultiplexorAndMultiplexedSignal(_)))
+            .and_then(|s| raw_bits(s, data));
+
+        let signals = message.signals
+            .iter()
+            .filter(|s| match s.multiplexer_indicator {
+                MultiplexIndicator::MultiplexedSignal(n)
+                | MultiplexIndicator::MultiplexorAndMultiplexedSignal(n) => switch == Some(n),
+                _ => true,
+            })
+            .filter_map(|s| {
+                raw_bits(s, data).map(|raw| SignalValue {
+                    name: s.name.clone(),
+                    value: physical_value(s, raw),
+                    unit: s.unit.clone(),
+                })
+            })
+            .collect();
+
+        Some(DecodedFrame { message: message.name.clone(), signals })
+    }
+}
+
+/// Id of the message of the frame as keyed by the DBC database, bit 31 set for extended ids
+pub fn message_id(frame: &CanAnyFrame) -> u32 {
+    if frame.is_extended() { frame.raw_id() | 1 << 31 } else { frame.raw_id() }
+}
+
+/// Encode the physical values of the signals into the payload of the message, the inverse of
+/// [Decoder::decode]; values are limited to the range of their signal, signals without value
+/// encoded as raw 0, multiplexed signals only if selected by the value of the multiplexor
+pub fn encode(message: &Message, value: impl Fn(&Signal) -> Option<f64>) -> Vec<u8> {
+    let raw = |signal: &Signal| value(signal).map_or(0, |value| raw_value(signal, value));
+    let switch = message.signals
+        .iter()
+        .find(|s| matches!(s.multiplexer_indicator,
+            MultiplexIndicator::Multiplexor | MultiplexIndicator::MultiplexorAndMultiplexedSignal(_)))
+        .map(raw);
+
+    let mut data = vec![0; message.size as usize];
+    for signal in message.signals.iter().filter(|s| match s.multiplexer_indicator {
+        MultiplexIndicator::MultiplexedSignal(n)
+        | MultiplexIndicator::MultiplexorAndMultiplexedSignal(n) => switch == Some(n),
+        _ => true,
+    }) {
+        set_raw_bits(signal, &mut data, raw(signal));
+    }
+    data
+}
+
+fn bit(data: &[u8], pos: u64) -> Option<u64> {
+    let byte = data.get((pos / 8) as usize)?;
+    Some(((byte >> (pos % 8)) & 1) as u64)
+}
+
+/// Extract the raw bits of the signal, None if the frame is too short to carry the signal
+fn raw_bits(signal: &Signal, data: &[u8]) -> Option<u64> {
+    if signal.size == 0 || signal.size > 64 {
+        return None;
+    }
+
+    let mut raw: u64 = 0;
+    match signal.byte_order {
+        // start bit is the least significant bit, counting upwards
+        ByteOrder::LittleEndian => {
+            for i in 0..signal.size {
+                raw |= bit(data, signal.start_bit + i)? << i;
+            }
+        }
+        // start bit is the most significant bit, counting downwards within each byte
+        ByteOrder::BigEndian => {
+            let mut pos = signal.start_bit;
+            for _ in 0..signal.size {
+                raw = (raw << 1) | bit(data, pos)?;
+                pos = if pos.is_multiple_of(8) { pos + 15 } else { pos - 1 };
+            }
+        }
+    }
+
+    Some(raw)
+}
+
+/// Store the raw bits of the signal, the bits beyond the payload dropped
+fn set_raw_bits(signal: &Signal, data: &mut [u8], raw: u64) {
+    if signal.size == 0 || signal.size > 64 {
+        return;
+    }
+    let mut set = |pos: u64, bit: u64| {
+        if let Some(byte) = data.get_mut((pos / 8) as usize) {
+            *byte = (*byte & !(1 << (pos % 8))) | ((bit as u8) << (pos % 8));
+        }
+    };
+    match signal.byte_order {
+        ByteOrder::LittleEndian => {
+            for i in 0..signal.size {
+                set(signal.start_bit + i, (raw >> i) & 1);
+            }
+        }
+        ByteOrder::BigEndian => {
+            let mut pos = signal.start_bit;
+            for i in (0..signal.size).rev() {
+                set(pos, (raw >> i) & 1);
+                pos = if pos.is_multiple_of(8) { pos + 15 } else { pos - 1 };
+            }
+        }
+    }
+}
+
+/// Raw bits of the physical value, `(value - offset) / factor` rounded, limited to the range of
+/// the signal and to the values of its bits
+fn raw_value(signal: &Signal, value: f64) -> u64 {
+    let (min, max) = (numeric(signal.min), numeric(signal.max));
+    let value = if min < max { value.clamp(min, max) } else { value };
+    let factor = if signal.factor == 0.0 { 1.0 } else { signal.factor };
+    let raw = ((value - signal.offset) / factor).round();
+    let bits = signal.size.min(64);
+    match signal.value_type {
+        ValueType::Signed => {
+            let max = ((1u128 << (bits - 1)) - 1) as f64;
+            // two's complement of the signal's bits
+            let raw = raw.clamp(-max - 1.0, max) as i64 as u64;
+            if bits == 64 { raw } else { raw & ((1 << bits) - 1) }
+        }
+        ValueType::Unsigned => raw.clamp(0.0, ((1u128 << bits) - 1) as f64) as u64,
+    }
+}
+
+fn numeric(value: NumericValue) -> f64 {
+    match value {
+        NumericValue::Uint(value) => value as f64,
+        NumericValue::Int(value) => value as f64,
+        NumericValue::Double(value) => value,
+    }
+}
+
+/// Scale the raw bits to the physical value, `raw * factor + offset`
+fn physical_value(signal: &Signal, raw: u64) -> f64 {
+    let raw = match signal.value_type {
+        ValueType::Signed => {
+            // sign extension of the signal's most significant bit
+            let shift = 64 - signal.size;
+            (((raw << shift) as i64) >> shift) as f64
+        }
+        ValueType::Unsigned => raw as f64,
+    };
+    raw * signal.factor + signal.offset
+}
@@ -0,0 +1,292 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::{
+    extract::ConnectInfo,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::api::{api_error, write_error};
+use crate::audit::Origin;
+use crate::auth::Access;
+use crate::can::CanError;
+use crate::isotp;
+use crate::protocol::{parse_frame_id, parse_hex_u32};
+use crate::server::AppState;
+
+// service identifiers, the positive response carries the SID + 0x40
+const SID_ECU_RESET: u8 = 0x11;
+const SID_CLEAR_DTC: u8 = 0x14;
+const SID_READ_DTC: u8 = 0x19;
+const SID_RDBI: u8 = 0x22;
+const SID_TESTER_PRESENT: u8 = 0x3E;
+const SID_NEGATIVE_RESPONSE: u8 = 0x7F;
+const POSITIVE_RESPONSE: u8 = 0x40;
+
+// sub-function reportDTCByStatusMask of ReadDTCInformation
+const REPORT_DTC_BY_STATUS_MASK: u8 = 0x02;
+const NRC_RESPONSE_PENDING: u8 = 0x78;
+
+// P2 and P2* timeouts, the latter after the ECU signalled a pending response
+const P2: Duration = Duration::from_secs(1);
+const P2_EXTENDED: Duration = Duration::from_secs(5);
+
+// DTO - diagnostic target, the ECU's request and response ids as hex strings
+#[derive(Deserialize, JsonSchema, ToSchema, Debug)]
+pub struct Target {
+    tx_id: String,
+    rx_id: String,
+    interface: Option<String>,
+}
+
+// DTO - `POST /api/uds/rdbi`, data identifier as hex string, e.g. `F190` for the VIN
+#[derive(Deserialize, JsonSchema, ToSchema, Debug)]
+pub struct RdbiRequest {
+    #[serde(flatten)]
+    target: Target,
+    did: String,
+}
+
+// DTO - `POST /api/uds/reset`, reset type 1 (hard), 2 (key off/on) or 3 (soft)
+#[derive(Deserialize, JsonSchema, ToSchema, Debug)]
+pub struct ResetRequest {
+    #[serde(flatten)]
+    target: Target,
+    reset_type: Option<u8>,
+}
+
+// DTO - `POST /api/uds/dtc`, DTCs matching any bit of the status mask, all by default
+#[derive(Deserialize, JsonSchema, ToSchema, Debug)]
+pub struct ReadDtcRequest {
+    #[serde(flatten)]
+    target: Target,
+    status_mask: Option<u8>,
+}
+
+// DTO - `POST /api/uds/dtc/clear`, group of DTCs as hex string, all DTCs (`FFFFFF`) by default
+#[derive(Deserialize, JsonSchema, ToSchema, Debug)]
+pub struct ClearDtcRequest {
+    #[serde(flatten)]
+    target: Target,
+    group: Option<String>,
+}
+
+// DTO - diagnostic trouble code as 6 hex digits, with its status byte
+#[derive(Serialize, JsonSchema, ToSchema, Debug)]
+pub struct Dtc {
+    dtc: String,
+    status: u8,
+}
+
+// DTO - positive response as hex string, with the data or DTCs decoded of it
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Default)]
+pub struct UdsResponse {
+    response: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dtcs: Option<Vec<Dtc>>,
+}
+
+fn nrc_name(nrc: u8) -> &'static str {
+    match nrc {
+        0x10 => "generalReject",
+        0x11 => "serviceNotSupported",
+        0x12 => "subFunctionNotSupported",
+        0x13 => "incorrectMessageLengthOrInvalidFormat",
+        0x14 => "responseTooLong",
+        0x21 => "busyRepeatRequest",
+        0x22 => "conditionsNotCorrect",
+        0x24 => "requestSequenceError",
+        0x31 => "requestOutOfRange",
+        0x33 => "securityAccessDenied",
+        0x35 => "invalidKey",
+        0x72 => "generalProgrammingFailure",
+        0x7E => "subFunctionNotSupportedInActiveSession",
+        0x7F => "serviceNotSupportedInActiveSession",
+        _ => "unknown",
+    }
+}
+
+/// Send the request to the target via ISO-TP and wait for the response to the service
+///
+/// Responds with 400 if the ids are malformed, 404 if the interface is unknown, 502 on a
+/// negative response or transport failure and 504 if the ECU does not respond.
+async fn request(state: &AppState, origin: Origin, target: &Target, request: Vec<u8>) -> Result<Vec<u8>, Response> {
+    if state.config.read_only {
+        return Err(write_error(CanError::ReadOnly).into_response());
+    }
+    let (Ok(tx_id), Ok(rx_id)) = (parse_frame_id(&target.tx_id), parse_frame_id(&target.rx_id)) else {
+        return Err(api_error(StatusCode::BAD_REQUEST, "invalid id").into_response());
+    };
+    if state.buses.get(target.interface.as_deref()).is_none() {
+        return Err(api_error(StatusCode::NOT_FOUND, "unknown CAN interface").into_response());
+    }
+    let failed = |error: &str| api_error(StatusCode::BAD_GATEWAY, error).into_response();
+
+    let sid = request[0];
+    // the channel is closed when dropped at return
+    let mut channels = isotp::Channels::new(origin);
+    channels.send(state, target.interface.as_deref(), tx_id, rx_id, Some(request)).await.map_err(failed)?;
+
+    let mut timeout = P2;
+    loop {
+        let event = tokio::time::timeout(timeout, channels.received.recv())
+            .await
+            .map_err(|_| api_error(StatusCode::GATEWAY_TIMEOUT, "no response").into_response())?;
+        match event {
+            Some(isotp::Event::Received { data, .. }) => match data.as_slice() {
+                [SID_NEGATIVE_RESPONSE, s, NRC_RESPONSE_PENDING, ..] if *s == sid => timeout = P2_EXTENDED,
+                [SID_NEGATIVE_RESPONSE, s, nrc, ..] if *s == sid => {
+                    return Err(failed(&format!("negative response 0x{:02X} {}", nrc, nrc_name(*nrc))));
+                }
+                [s, ..] if *s == sid | POSITIVE_RESPONSE => {
+                    tracing::info!(sid, response = %hex::encode_upper(&data), "UDS response");
+                    return Ok(data);
+                }
+                // response to another request on the same ids
+                _ => (),
+            },
+            Some(isotp::Event::Failed { error, .. }) => return Err(failed(error)),
+            None => return Err(failed("channel closed")),
+        }
+    }
+}
+
+fn respond(response: &[u8], data: Option<&[u8]>, dtcs: Option<Vec<Dtc>>) -> Response {
+    let response = UdsResponse {
+        response: hex::encode_upper(response),
+        data: data.map(hex::encode_upper),
+        dtcs,
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// `POST /api/uds/rdbi` - ReadDataByIdentifier, responding with the data record
+#[utoipa::path(post, path = "/api/uds/rdbi", request_body = RdbiRequest, responses(
+    (status = 200, description = "positive response", body = UdsResponse),
+    (status = 400, description = "malformed data identifier", body = crate::api::ApiResponse),
+    (status = 502, description = "negative response", body = crate::api::ApiResponse),
+    (status = 504, description = "no response", body = crate::api::ApiResponse),
+))]
+pub async fn rdbi(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(req): Json<RdbiRequest>,
+) -> Response {
+    let origin = Origin { peer: Some(peer), user: access.user, source: "uds" };
+    let Some(did) = parse_hex_u32(&req.did).ok().and_then(|did| u16::try_from(did).ok()) else {
+        return api_error(StatusCode::BAD_REQUEST, "invalid data identifier").into_response();
+    };
+    let mut message = vec![SID_RDBI];
+    message.extend_from_slice(&did.to_be_bytes());
+
+    match request(&state, origin, &req.target, message).await {
+        // positive response echoes the data identifier
+        Ok(response) => respond(&response, response.get(3..), None),
+        Err(e) => e,
+    }
+}
+
+/// `POST /api/uds/tester-present` - TesterPresent, keeping a diagnostic session alive
+#[utoipa::path(post, path = "/api/uds/tester-present", request_body = Target, responses(
+    (status = 200, description = "positive response", body = UdsResponse),
+    (status = 502, description = "negative response", body = crate::api::ApiResponse),
+    (status = 504, description = "no response", body = crate::api::ApiResponse),
+))]
+pub async fn tester_present(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(target): Json<Target>,
+) -> Response {
+    let origin = Origin { peer: Some(peer), user: access.user, source: "uds" };
+    match request(&state, origin, &target, vec![SID_TESTER_PRESENT, 0x00]).await {
+        Ok(response) => respond(&response, None, None),
+        Err(e) => e,
+    }
+}
+
+/// `POST /api/uds/reset` - ECUReset, a hard reset by default
+#[utoipa::path(post, path = "/api/uds/reset", request_body = ResetRequest, responses(
+    (status = 200, description = "positive response", body = UdsResponse),
+    (status = 502, description = "negative response", body = crate::api::ApiResponse),
+    (status = 504, description = "no response", body = crate::api::ApiResponse),
+))]
+pub async fn reset(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(req): Json<ResetRequest>,
+) -> Response {
+    const HARD_RESET: u8 = 0x01;
+    let origin = Origin { peer: Some(peer), user: access.user, source: "uds" };
+    let reset_type = req.reset_type.unwrap_or(HARD_RESET);
+    match request(&state, origin, &req.target, vec![SID_ECU_RESET, reset_type]).await {
+        Ok(response) => respond(&response, None, None),
+        Err(e) => e,
+    }
+}
+
+/// `POST /api/uds/dtc` - ReadDTCInformation, reporting the DTCs by status mask
+#[utoipa::path(post, path = "/api/uds/dtc", request_body = ReadDtcRequest, responses(
+    (status = 200, description = "positive response with the DTCs", body = UdsResponse),
+    (status = 502, description = "negative response", body = crate::api::ApiResponse),
+    (status = 504, description = "no response", body = crate::api::ApiResponse),
+))]
+pub async fn read_dtc(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(req): Json<ReadDtcRequest>,
+) -> Response {
+    let origin = Origin { peer: Some(peer), user: access.user, source: "uds" };
+    let mask = req.status_mask.unwrap_or(0xFF);
+    match request(&state, origin, &req.target, vec![SID_READ_DTC, REPORT_DTC_BY_STATUS_MASK, mask]).await {
+        Ok(response) => {
+            // records of 3 bytes DTC and 1 byte status, following the availability mask
+            let dtcs = response
+                .get(3..)
+                .unwrap_or_default()
+                .chunks_exact(4)
+                .map(|record| Dtc { dtc: hex::encode_upper(&record[..3]), status: record[3] })
+                .collect();
+            respond(&response, None, Some(dtcs))
+        }
+        Err(e) => e,
+    }
+}
+
+/// `POST /api/uds/dtc/clear` - ClearDiagnosticInformation
+#[utoipa::path(post, path = "/api/uds/dtc/clear", request_body = ClearDtcRequest, responses(
+    (status = 200, description = "positive response", body = UdsResponse),
+    (status = 400, description = "malformed DTC group", body = crate::api::ApiResponse),
+    (status = 502, description = "negative response", body = crate::api::ApiResponse),
+    (status = 504, description = "no response", body = crate::api::ApiResponse),
+))]
+pub async fn clear_dtc(
+    Extension(state): Extension<AppState>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(req): Json<ClearDtcRequest>,
+) -> Response {
+    const ALL_GROUPS: u32 = 0xFF_FFFF;
+    let origin = Origin { peer: Some(peer), user: access.user, source: "uds" };
+    let group = match req.group.as_deref().map(parse_hex_u32).unwrap_or(Ok(ALL_GROUPS)) {
+        Ok(group) if group <= ALL_GROUPS => group,
+        _ => return api_error(StatusCode::BAD_REQUEST, "invalid DTC group").into_response(),
+    };
+    let mut message = vec![SID_CLEAR_DTC];
+    message.extend_from_slice(&group.to_be_bytes()[1..]);
+
+    match request(&state, origin, &req.target, message).await {
+        Ok(response) => respond(&response, None, None),
+        Err(e) => e,
+    }
+}
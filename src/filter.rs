@@ -0,0 +1,404 @@
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Frame};
+
+/// Filter expression of a websocket client, e.g. `id == 0x123 && data[0] > 0x80`
+///
+/// * values: `id`, `len` (the data length), `data[<index>]` and integers, decimal or hex (`0x7FF`),
+///   combined by `&`, e.g. `id & 0x700 == 0x100` or `(id & 0x700) == 0x100`
+/// * comparisons: `==`, `!=`, `<`, `<=`, `>`, `>=`
+/// * flags: `extended`, `fd`, `remote`, `error` for error frames
+/// * the interface: `interface == "can0"` or `interface != "can0"`
+/// * logic: `&&`, `||`, `!` and parentheses, `&&` binding stronger than `||`
+///
+/// Comparing a data byte beyond the length of the frame is false. Filters are limited to 4096
+/// bytes and 32 levels of parentheses and `!`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Value, Op, Value),
+    Flag(Flag),
+    Interface(String, bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Id,
+    Len,
+    Data(usize),
+    Literal(u64),
+    BitAnd(Box<Value>, Box<Value>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flag {
+    Extended,
+    Fd,
+    Remote,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Str(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    BitAnd,
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+}
+
+// bounds of a filter, its expression evaluated by recursion
+const MAX_LEN: usize = 4096;
+const MAX_DEPTH: usize = 32;
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Filter, String> {
+        if source.len() > MAX_LEN {
+            return Err(format!("filter exceeds {} bytes", MAX_LEN));
+        }
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0, depth: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {:?} at token {}", token, parser.pos));
+        }
+        Ok(Filter { source: source.trim().to_string(), expr })
+    }
+
+    /// The expression as given by the client
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn matches(&self, interface: &str, frame: &CanAnyFrame) -> bool {
+        eval(&self.expr, interface, frame)
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        let token = match (c, next) {
+            (c, _) if c.is_whitespace() => continue,
+            ('=', Some('=')) => { chars.next(); Token::Op(Op::Eq) }
+            ('!', Some('=')) => { chars.next(); Token::Op(Op::Ne) }
+            ('<', Some('=')) => { chars.next(); Token::Op(Op::Le) }
+            ('>', Some('=')) => { chars.next(); Token::Op(Op::Ge) }
+            ('&', Some('&')) => { chars.next(); Token::And }
+            ('|', Some('|')) => { chars.next(); Token::Or }
+            ('<', _) => Token::Op(Op::Lt),
+            ('>', _) => Token::Op(Op::Gt),
+            ('!', _) => Token::Not,
+            ('&', _) => Token::BitAnd,
+            ('(', _) => Token::Open,
+            (')', _) => Token::Close,
+            ('[', _) => Token::OpenBracket,
+            (']', _) => Token::CloseBracket,
+            ('"', _) => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => s.push(c),
+                        None => return Err(format!("unterminated string at {}", i)),
+                    }
+                }
+                Token::Str(s)
+            }
+            (c, _) if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+                    word.push(c);
+                }
+                if c.is_ascii_digit() {
+                    Token::Number(parse_number(&word).ok_or_else(|| format!("invalid number `{}` at {}", word, i))?)
+                } else {
+                    Token::Ident(word)
+                }
+            }
+            (c, _) => return Err(format!("unexpected `{}` at {}", c, i)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Option<u64> {
+    match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
+
+// recursive descent by precedence, `||` < `&&` < `!` < comparison < `&`
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // levels of parentheses and `!` entered
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected {:?}, found {:?}", expected, token)),
+            None => Err(format!("expected {:?} at end of filter", expected)),
+        }
+    }
+
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("filter nested deeper than {} levels", MAX_DEPTH));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.nested(Self::unary)?)))
+            }
+            Some(Token::Open) => {
+                // a parenthesized expression, else a comparison of a parenthesized value like `(id & 0x700) == 0x100`
+                let start = self.pos;
+                self.pos += 1;
+                match self.nested(|parser| parser.or().and_then(|expr| parser.expect(Token::Close).map(|_| expr))) {
+                    Ok(expr) => Ok(expr),
+                    Err(_) => {
+                        self.pos = start;
+                        self.comparison()
+                    }
+                }
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Ident(name)) if name == "interface" => {
+                self.pos += 1;
+                let equal = match self.next() {
+                    Some(Token::Op(Op::Eq)) => true,
+                    Some(Token::Op(Op::Ne)) => false,
+                    _ => return Err("expected `==` or `!=` after interface".to_string()),
+                };
+                match self.next() {
+                    Some(Token::Str(name)) => Ok(Expr::Interface(name, equal)),
+                    _ => Err("expected interface name in quotes".to_string()),
+                }
+            }
+            Some(Token::Ident(name)) if flag(name).is_some() => {
+                let flag = flag(name).unwrap();
+                self.pos += 1;
+                Ok(Expr::Flag(flag))
+            }
+            _ => {
+                let left = self.value()?;
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    Some(token) => return Err(format!("expected comparison, found {:?}", token)),
+                    None => return Err("expected comparison at end of filter".to_string()),
+                };
+                Ok(Expr::Compare(left, op, self.value()?))
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let mut value = self.operand()?;
+        while self.peek() == Some(&Token::BitAnd) {
+            self.pos += 1;
+            value = Value::BitAnd(Box::new(value), Box::new(self.operand()?));
+        }
+        Ok(value)
+    }
+
+    fn operand(&mut self) -> Result<Value, String> {
+        match self.next() {
+            Some(Token::Open) => self.nested(|parser| {
+                let value = parser.value()?;
+                parser.expect(Token::Close)?;
+                Ok(value)
+            }),
+            Some(Token::Number(n)) => Ok(Value::Literal(n)),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "id" => Ok(Value::Id),
+                "len" => Ok(Value::Len),
+                "data" => {
+                    self.expect(Token::OpenBracket)?;
+                    let index = match self.next() {
+                        Some(Token::Number(n)) if n < 64 => n as usize,
+                        _ => return Err("expected data index 0..63".to_string()),
+                    };
+                    self.expect(Token::CloseBracket)?;
+                    Ok(Value::Data(index))
+                }
+                name => Err(format!("unknown field `{}`", name)),
+            },
+            Some(token) => Err(format!("expected value, found {:?}", token)),
+            None => Err("expected value at end of filter".to_string()),
+        }
+    }
+}
+
+fn flag(name: &str) -> Option<Flag> {
+    match name {
+        "extended" => Some(Flag::Extended),
+        "fd" => Some(Flag::Fd),
+        "remote" => Some(Flag::Remote),
+        "error" => Some(Flag::Error),
+        _ => None,
+    }
+}
+
+fn eval(expr: &Expr, interface: &str, frame: &CanAnyFrame) -> bool {
+    match expr {
+        Expr::Or(a, b) => eval(a, interface, frame) || eval(b, interface, frame),
+        Expr::And(a, b) => eval(a, interface, frame) && eval(b, interface, frame),
+        Expr::Not(a) => !eval(a, interface, frame),
+        Expr::Flag(Flag::Extended) => frame.is_extended(),
+        Expr::Flag(Flag::Fd) => matches!(frame, CanAnyFrame::Fd(_)),
+        Expr::Flag(Flag::Remote) => frame.is_remote_frame(),
+        Expr::Flag(Flag::Error) => matches!(frame, CanAnyFrame::Error(_)),
+        Expr::Interface(name, equal) => (name == interface) == *equal,
+        Expr::Compare(a, op, b) => {
+            let (Some(a), Some(b)) = (value(a, frame), value(b, frame)) else { return false };
+            match op {
+                Op::Eq => a == b,
+                Op::Ne => a != b,
+                Op::Lt => a < b,
+                Op::Le => a <= b,
+                Op::Gt => a > b,
+                Op::Ge => a >= b,
+            }
+        }
+    }
+}
+
+// None for a data byte beyond the length of the frame
+fn value(value: &Value, frame: &CanAnyFrame) -> Option<u64> {
+    match value {
+        Value::Id => Some(frame.raw_id() as u64),
+        // the requested length of remote frames
+        Value::Len if frame.is_remote_frame() => Some(frame.dlc() as u64),
+        Value::Len => Some(frame.data().len() as u64),
+        Value::Data(index) => frame.data().get(*index).map(|byte| *byte as u64),
+        Value::Literal(n) => Some(*n),
+        Value::BitAnd(a, b) => Some(self::value(a, frame)? & self::value(b, frame)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{CanDataFrame, ExtendedId, StandardId};
+
+    fn frame(id: u16, data: &[u8]) -> CanAnyFrame {
+        CanAnyFrame::Normal(CanDataFrame::new(StandardId::new(id).unwrap(), data).unwrap())
+    }
+
+    fn matches(source: &str, frame: &CanAnyFrame) -> bool {
+        Filter::parse(source).unwrap().matches("can0", frame)
+    }
+
+    #[test]
+    fn and_binds_stronger_than_or() {
+        let frame = frame(0x123, &[0x90]);
+        assert!(matches("id == 0x123 || id == 1 && len == 5", &frame));
+        assert!(!matches("(id == 0x123 || id == 1) && len == 5", &frame));
+        assert!(matches("!extended && data[0] > 0x80", &frame));
+        assert!(!matches("!(id == 0x123 || len == 5)", &frame));
+    }
+
+    #[test]
+    fn masked_id_compared() {
+        assert!(matches("(id & 0x700) == 0x100", &frame(0x1AB, &[])));
+        assert!(matches("id & 0x700 == 0x100", &frame(0x1AB, &[])));
+        assert!(!matches("(id & 0x700) == 0x100", &frame(0x2AB, &[])));
+        assert!(matches("((id & 0x700) == 0x100) && interface == \"can0\"", &frame(0x100, &[])));
+
+        let extended = CanAnyFrame::Normal(CanDataFrame::new(ExtendedId::new(0x18FEF100).unwrap(), &[]).unwrap());
+        assert!(matches("extended && (id & 0xFFFF00) == 0xFEF100", &extended));
+    }
+
+    #[test]
+    fn data_beyond_frame_false() {
+        let frame = frame(0x123, &[1, 2]);
+        assert!(matches("data[1] == 2", &frame));
+        assert!(!matches("data[2] == 0", &frame));
+        assert!(!matches("data[2] != 0", &frame));
+        assert!(matches("!(data[2] == 0)", &frame));
+        assert!(Filter::parse("data[64] == 0").is_err());
+    }
+
+    #[test]
+    fn nesting_limited() {
+        let nested = |depth: usize| format!("{}id == 1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(matches(&nested(MAX_DEPTH), &frame(1, &[])));
+        assert!(Filter::parse(&nested(MAX_DEPTH + 1)).unwrap_err().contains("nested"));
+        assert!(Filter::parse(&format!("{}id == 1", "!".repeat(MAX_DEPTH + 1))).unwrap_err().contains("nested"));
+        assert!(Filter::parse(&format!("{}id{} == 1", "(".repeat(MAX_DEPTH + 1), ")".repeat(MAX_DEPTH + 1))).is_err());
+        // rejected rather than overflowing the stack
+        assert!(Filter::parse(&"(".repeat(MAX_LEN)).unwrap_err().contains("nested"));
+        assert!(Filter::parse(&"(".repeat(60000)).unwrap_err().contains("exceeds"));
+    }
+}
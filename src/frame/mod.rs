@@ -0,0 +1,15 @@
+//! CAN frames shared by all transports, the frame types of the socketcan crate on Linux and a
+//! portable implementation of the same API on other platforms, e.g. Windows and macOS
+
+#[cfg(target_os = "linux")]
+pub use socketcan::{
+    frame::{can_frame_default, canfd_frame_default},
+    id::{FdFlags, CAN_EFF_MASK, CAN_SFF_MASK},
+    CanAnyFrame, CanDataFrame, CanErrorFrame, CanFdFrame, CanFilter, CanRemoteFrame, EmbeddedFrame, ExtendedId, Frame,
+    Id, StandardId,
+};
+
+#[cfg(not(target_os = "linux"))]
+mod portable;
+#[cfg(not(target_os = "linux"))]
+pub use portable::*;
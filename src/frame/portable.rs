@@ -0,0 +1,527 @@
+use std::fmt;
+
+use bitflags::bitflags;
+pub use embedded_can::{ExtendedId, Frame as EmbeddedFrame, Id, StandardId};
+
+// id word and payload limits of linux/can.h, the layout the other transports and log formats share
+pub const CAN_EFF_FLAG: u32 = 0x8000_0000;
+pub const CAN_RTR_FLAG: u32 = 0x4000_0000;
+pub const CAN_ERR_FLAG: u32 = 0x2000_0000;
+pub const CAN_SFF_MASK: u32 = 0x0000_07FF;
+pub const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+pub const CAN_ERR_MASK: u32 = 0x1FFF_FFFF;
+const CAN_INV_FILTER: u32 = 0x2000_0000;
+const CAN_MAX_DLEN: usize = 8;
+const CANFD_MAX_DLEN: usize = 64;
+// payload lengths of FD frames above 8 bytes, by DLC 9 to 15
+const FD_LENGTHS: [usize; 7] = [12, 16, 20, 24, 32, 48, 64];
+
+bitflags! {
+    /// Flags of FD frames
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct FdFlags: u8 {
+        const BRS = 0x01;
+        const ESI = 0x02;
+        const FDF = 0x04;
+    }
+}
+
+/// Classic frame as `struct can_frame` of linux/can.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[allow(non_camel_case_types)]
+pub struct can_frame {
+    pub can_id: u32,
+    pub can_dlc: u8,
+    pub data: [u8; CAN_MAX_DLEN],
+}
+
+/// FD frame as `struct canfd_frame` of linux/can.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
+pub struct canfd_frame {
+    pub can_id: u32,
+    pub len: u8,
+    pub flags: u8,
+    pub data: [u8; CANFD_MAX_DLEN],
+}
+
+pub fn can_frame_default() -> can_frame {
+    can_frame::default()
+}
+
+pub fn canfd_frame_default() -> canfd_frame {
+    canfd_frame { can_id: 0, len: 0, flags: 0, data: [0; CANFD_MAX_DLEN] }
+}
+
+fn id_to_word(id: impl Into<Id>) -> u32 {
+    match id.into() {
+        Id::Standard(id) => id.as_raw() as u32,
+        Id::Extended(id) => id.as_raw() | CAN_EFF_FLAG,
+    }
+}
+
+fn id_of_word(word: u32) -> Id {
+    if word & CAN_EFF_FLAG != 0 {
+        ExtendedId::new(word & CAN_EFF_MASK).unwrap().into()
+    } else {
+        StandardId::new((word & CAN_SFF_MASK) as u16).unwrap().into()
+    }
+}
+
+/// Payload too long for the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConstructionError;
+
+impl fmt::Display for ConstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "too much data for the CAN frame")
+    }
+}
+
+impl std::error::Error for ConstructionError {}
+
+/// Frames of the id word of SocketCAN, the id and its flags
+#[allow(clippy::len_without_is_empty)]
+pub trait Frame: EmbeddedFrame {
+    fn from_raw_id(id: u32, data: &[u8]) -> Option<Self> {
+        let id: Id = match id {
+            id if id <= CAN_SFF_MASK => StandardId::new(id as u16)?.into(),
+            id => ExtendedId::new(id)?.into(),
+        };
+        Self::new(id, data)
+    }
+
+    fn id_word(&self) -> u32;
+
+    fn raw_id(&self) -> u32 {
+        self.id_word() & if self.is_extended() { CAN_EFF_MASK } else { CAN_SFF_MASK }
+    }
+
+    fn len(&self) -> usize {
+        self.dlc()
+    }
+
+    fn is_error_frame(&self) -> bool {
+        self.id_word() & CAN_ERR_FLAG != 0
+    }
+
+    fn set_id(&mut self, id: impl Into<Id>);
+
+    fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError>;
+}
+
+// common parts of the classic frames
+macro_rules! classic_frame {
+    ($frame:ident) => {
+        impl $frame {
+            fn word(&self) -> u32 {
+                self.0.can_id
+            }
+        }
+
+        impl From<$frame> for can_frame {
+            fn from(frame: $frame) -> can_frame {
+                frame.0
+            }
+        }
+
+        impl Frame for $frame {
+            fn id_word(&self) -> u32 {
+                self.word()
+            }
+
+            fn set_id(&mut self, id: impl Into<Id>) {
+                self.0.can_id = id_to_word(id) | (self.0.can_id & (CAN_ERR_FLAG | CAN_RTR_FLAG));
+            }
+
+            fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
+                if data.len() > CAN_MAX_DLEN {
+                    return Err(ConstructionError);
+                }
+                self.0.can_dlc = data.len() as u8;
+                self.0.data = [0; CAN_MAX_DLEN];
+                self.0.data[..data.len()].copy_from_slice(data);
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Classic data frame of up to 8 bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CanDataFrame(can_frame);
+
+classic_frame!(CanDataFrame);
+
+impl EmbeddedFrame for CanDataFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        let mut frame = CanDataFrame(can_frame { can_id: id_to_word(id), ..Default::default() });
+        frame.set_data(data).ok()?;
+        Some(frame)
+    }
+
+    fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
+        None
+    }
+
+    fn is_extended(&self) -> bool {
+        self.word() & CAN_EFF_FLAG != 0
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        false
+    }
+
+    fn id(&self) -> Id {
+        id_of_word(self.word())
+    }
+
+    fn dlc(&self) -> usize {
+        self.0.can_dlc as usize
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.0.data[..self.0.can_dlc as usize]
+    }
+}
+
+/// Classic remote frame, a request of the data of its length
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CanRemoteFrame(can_frame);
+
+classic_frame!(CanRemoteFrame);
+
+impl EmbeddedFrame for CanRemoteFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        Self::new_remote(id, data.len())
+    }
+
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        if dlc > CAN_MAX_DLEN {
+            return None;
+        }
+        Some(CanRemoteFrame(can_frame { can_id: id_to_word(id) | CAN_RTR_FLAG, can_dlc: dlc as u8, ..Default::default() }))
+    }
+
+    fn is_extended(&self) -> bool {
+        self.word() & CAN_EFF_FLAG != 0
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        true
+    }
+
+    fn id(&self) -> Id {
+        id_of_word(self.word())
+    }
+
+    fn dlc(&self) -> usize {
+        self.0.can_dlc as usize
+    }
+
+    fn data(&self) -> &[u8] {
+        &[]
+    }
+}
+
+/// Error frame of the CAN controller, the error classes in the id word
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CanErrorFrame(can_frame);
+
+classic_frame!(CanErrorFrame);
+
+impl CanErrorFrame {
+    pub fn error_bits(&self) -> u32 {
+        self.word() & CAN_ERR_MASK
+    }
+}
+
+impl EmbeddedFrame for CanErrorFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        if data.len() > CAN_MAX_DLEN {
+            return None;
+        }
+        let mut frame = can_frame { can_id: (id_to_word(id) & CAN_ERR_MASK) | CAN_ERR_FLAG, can_dlc: CAN_MAX_DLEN as u8, ..Default::default() };
+        frame.data[..data.len()].copy_from_slice(data);
+        Some(CanErrorFrame(frame))
+    }
+
+    fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
+        None
+    }
+
+    fn is_extended(&self) -> bool {
+        self.word() & CAN_EFF_FLAG != 0
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        false
+    }
+
+    fn id(&self) -> Id {
+        id_of_word(self.word())
+    }
+
+    fn dlc(&self) -> usize {
+        self.0.can_dlc as usize
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.0.data[..self.0.can_dlc as usize]
+    }
+}
+
+/// FD data frame of up to 64 bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CanFdFrame(canfd_frame);
+
+impl CanFdFrame {
+    pub fn with_flags(id: impl Into<Id>, data: &[u8], flags: FdFlags) -> Option<Self> {
+        let mut frame = CanFdFrame(canfd_frame { can_id: id_to_word(id), flags: (flags | FdFlags::FDF).bits(), ..canfd_frame_default() });
+        frame.set_data(data).ok()?;
+        Some(frame)
+    }
+
+    pub fn flags(&self) -> FdFlags {
+        FdFlags::from_bits_truncate(self.0.flags)
+    }
+
+    pub fn is_brs(&self) -> bool {
+        self.flags().contains(FdFlags::BRS)
+    }
+
+    pub fn is_esi(&self) -> bool {
+        self.flags().contains(FdFlags::ESI)
+    }
+}
+
+impl EmbeddedFrame for CanFdFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        Self::with_flags(id, data, FdFlags::empty())
+    }
+
+    fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
+        None
+    }
+
+    fn is_extended(&self) -> bool {
+        self.0.can_id & CAN_EFF_FLAG != 0
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        false
+    }
+
+    fn id(&self) -> Id {
+        id_of_word(self.0.can_id)
+    }
+
+    fn dlc(&self) -> usize {
+        let len = self.0.len as usize;
+        match FD_LENGTHS.iter().position(|&fd_len| fd_len == len) {
+            Some(i) => CAN_MAX_DLEN + 1 + i,
+            None => len.min(CAN_MAX_DLEN),
+        }
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.0.data[..self.0.len as usize]
+    }
+}
+
+impl Frame for CanFdFrame {
+    fn id_word(&self) -> u32 {
+        self.0.can_id
+    }
+
+    fn len(&self) -> usize {
+        self.0.len as usize
+    }
+
+    fn set_id(&mut self, id: impl Into<Id>) {
+        self.0.can_id = id_to_word(id) | (self.0.can_id & (CAN_ERR_FLAG | CAN_RTR_FLAG));
+    }
+
+    fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
+        if data.len() > CANFD_MAX_DLEN {
+            return Err(ConstructionError);
+        }
+        // padded to the next length of a DLC
+        let len = match data.len() {
+            len if len <= CAN_MAX_DLEN => len,
+            len => FD_LENGTHS.into_iter().find(|&fd_len| fd_len >= len).unwrap(),
+        };
+        self.0.data = [0; CANFD_MAX_DLEN];
+        self.0.data[..data.len()].copy_from_slice(data);
+        self.0.len = len as u8;
+        Ok(())
+    }
+}
+
+impl From<canfd_frame> for CanFdFrame {
+    fn from(frame: canfd_frame) -> CanFdFrame {
+        CanFdFrame(frame)
+    }
+}
+
+/// Frame of any kind, as received by a CAN interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CanAnyFrame {
+    Normal(CanDataFrame),
+    Remote(CanRemoteFrame),
+    Error(CanErrorFrame),
+    Fd(CanFdFrame),
+}
+
+impl EmbeddedFrame for CanAnyFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        if data.len() <= CAN_MAX_DLEN {
+            CanDataFrame::new(id, data).map(CanAnyFrame::Normal)
+        } else {
+            CanFdFrame::new(id, data).map(CanAnyFrame::Fd)
+        }
+    }
+
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        CanRemoteFrame::new_remote(id, dlc).map(CanAnyFrame::Remote)
+    }
+
+    fn is_extended(&self) -> bool {
+        self.id_word() & CAN_EFF_FLAG != 0
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        matches!(self, CanAnyFrame::Remote(_))
+    }
+
+    fn id(&self) -> Id {
+        id_of_word(self.id_word())
+    }
+
+    fn dlc(&self) -> usize {
+        match self {
+            CanAnyFrame::Normal(frame) => frame.dlc(),
+            CanAnyFrame::Remote(frame) => frame.dlc(),
+            CanAnyFrame::Error(frame) => frame.dlc(),
+            CanAnyFrame::Fd(frame) => frame.dlc(),
+        }
+    }
+
+    fn data(&self) -> &[u8] {
+        match self {
+            CanAnyFrame::Normal(frame) => frame.data(),
+            CanAnyFrame::Remote(frame) => frame.data(),
+            CanAnyFrame::Error(frame) => frame.data(),
+            CanAnyFrame::Fd(frame) => frame.data(),
+        }
+    }
+}
+
+impl Frame for CanAnyFrame {
+    fn id_word(&self) -> u32 {
+        match self {
+            CanAnyFrame::Normal(frame) => frame.id_word(),
+            CanAnyFrame::Remote(frame) => frame.id_word(),
+            CanAnyFrame::Error(frame) => frame.id_word(),
+            CanAnyFrame::Fd(frame) => frame.id_word(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            CanAnyFrame::Fd(frame) => Frame::len(frame),
+            _ => self.dlc(),
+        }
+    }
+
+    fn set_id(&mut self, id: impl Into<Id>) {
+        match self {
+            CanAnyFrame::Normal(frame) => frame.set_id(id),
+            CanAnyFrame::Remote(frame) => frame.set_id(id),
+            CanAnyFrame::Error(frame) => frame.set_id(id),
+            CanAnyFrame::Fd(frame) => frame.set_id(id),
+        }
+    }
+
+    fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
+        match self {
+            CanAnyFrame::Normal(frame) => frame.set_data(data),
+            CanAnyFrame::Remote(frame) => frame.set_data(data),
+            CanAnyFrame::Error(frame) => frame.set_data(data),
+            CanAnyFrame::Fd(frame) => frame.set_data(data),
+        }
+    }
+}
+
+impl From<can_frame> for CanAnyFrame {
+    /// Frame of the kind of the flags of the id word, the payload cut to 8 bytes
+    fn from(mut frame: can_frame) -> CanAnyFrame {
+        frame.can_dlc = frame.can_dlc.min(CAN_MAX_DLEN as u8);
+        if frame.can_id & CAN_ERR_FLAG != 0 {
+            CanAnyFrame::Error(CanErrorFrame(frame))
+        } else if frame.can_id & CAN_RTR_FLAG != 0 {
+            CanAnyFrame::Remote(CanRemoteFrame(frame))
+        } else {
+            CanAnyFrame::Normal(CanDataFrame(frame))
+        }
+    }
+}
+
+impl From<canfd_frame> for CanAnyFrame {
+    fn from(mut frame: canfd_frame) -> CanAnyFrame {
+        frame.len = frame.len.min(CANFD_MAX_DLEN as u8);
+        CanAnyFrame::Fd(CanFdFrame(frame))
+    }
+}
+
+impl From<CanDataFrame> for CanAnyFrame {
+    fn from(frame: CanDataFrame) -> CanAnyFrame {
+        CanAnyFrame::Normal(frame)
+    }
+}
+
+impl From<CanRemoteFrame> for CanAnyFrame {
+    fn from(frame: CanRemoteFrame) -> CanAnyFrame {
+        CanAnyFrame::Remote(frame)
+    }
+}
+
+impl From<CanErrorFrame> for CanAnyFrame {
+    fn from(frame: CanErrorFrame) -> CanAnyFrame {
+        CanAnyFrame::Error(frame)
+    }
+}
+
+impl From<CanFdFrame> for CanAnyFrame {
+    fn from(frame: CanFdFrame) -> CanAnyFrame {
+        CanAnyFrame::Fd(frame)
+    }
+}
+
+/// Filter as `struct can_filter` of linux/can.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
+pub struct can_filter {
+    pub can_id: u32,
+    pub can_mask: u32,
+}
+
+/// Receive filter of the id word, matching if `id & mask == can_id & mask`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CanFilter(can_filter);
+
+impl CanFilter {
+    pub fn new(id: u32, mask: u32) -> CanFilter {
+        CanFilter(can_filter { can_id: id, can_mask: mask })
+    }
+
+    pub fn new_inverted(id: u32, mask: u32) -> CanFilter {
+        CanFilter::new(id | CAN_INV_FILTER, mask)
+    }
+}
+
+impl AsRef<can_filter> for CanFilter {
+    fn as_ref(&self) -> &can_filter {
+        &self.0
+    }
+}
@@ -0,0 +1,194 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::can::{Buses, CanEvent, Direction, Subscription};
+use crate::frame::{CanAnyFrame, EmbeddedFrame, ExtendedId, Frame, Id, StandardId, CAN_EFF_MASK, CAN_SFF_MASK};
+use crate::protocol::{parse_frame_id, parse_hex_u32};
+
+// DTO - rule of the gateway rules file, forwarding the frames of `from` to `to`
+//
+// Without `id` all frames are forwarded, else those matching `id` and `mask` with SocketCAN
+// filter semantics, the mask defaulting to an exact match. With `remap` the id bits of the
+// mask are replaced, eg `{ id = "100", mask = "700", remap = "300" }` forwards 0x123 as 0x323.
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub from: String,
+    pub to: String,
+    pub id: Option<String>,
+    pub mask: Option<String>,
+    pub remap: Option<String>,
+}
+
+// Rules file of `--gateway`, a `[[rule]]` table per rule
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+// DTO - route of `GET /api/gateway`, the rule with its state and counters
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct RouteStatus {
+    pub route: usize,
+    #[serde(flatten)]
+    pub rule: Rule,
+    pub enabled: bool,
+    pub forwarded: u64,
+    // frames failed to write, eg while the target device is missing
+    pub dropped: u64,
+}
+
+struct Route {
+    rule: Rule,
+    // id and mask of matching frames, all frames if missing
+    filter: Option<(Id, u32)>,
+    remap: Option<Id>,
+    enabled: AtomicBool,
+    forwarded: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Route {
+    fn parse(rule: Rule, interfaces: &[String]) -> Result<Route, String> {
+        for interface in [&rule.from, &rule.to] {
+            if !interfaces.contains(interface) {
+                return Err(format!("unknown CAN interface {}", interface));
+            }
+        }
+        if rule.from == rule.to {
+            return Err(format!("forwarding {} to itself", rule.from));
+        }
+        let filter = match &rule.id {
+            Some(id) => {
+                let id = parse_frame_id(id).map_err(|_| format!("invalid id {}", id))?;
+                let mask = match (&rule.mask, id) {
+                    (Some(mask), _) => parse_hex_u32(mask).map_err(|_| format!("invalid mask {}", mask))?,
+                    (None, Id::Standard(_)) => CAN_SFF_MASK,
+                    (None, Id::Extended(_)) => CAN_EFF_MASK,
+                };
+                Some((id, mask))
+            }
+            None if rule.mask.is_some() => return Err("mask without id".to_string()),
+            None => None,
+        };
+        let remap = match &rule.remap {
+            Some(remap) => Some(parse_frame_id(remap).map_err(|_| format!("invalid remap {}", remap))?),
+            None => None,
+        };
+        Ok(Route {
+            rule,
+            filter,
+            remap,
+            enabled: AtomicBool::new(true),
+            forwarded: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    fn matches(&self, frame: &CanAnyFrame) -> bool {
+        match self.filter {
+            Some((id, mask)) => {
+                frame.is_extended() == matches!(id, Id::Extended(_)) && frame.raw_id() & mask == raw_id(id) & mask
+            }
+            None => true,
+        }
+    }
+
+    /// The frame as forwarded, the bits of the mask replaced by the remapped id
+    fn forward(&self, mut frame: CanAnyFrame) -> CanAnyFrame {
+        if let Some(remap) = self.remap {
+            let mask = self.filter.map_or(CAN_EFF_MASK, |(_, mask)| mask);
+            let raw = (raw_id(remap) & mask) | (frame.raw_id() & !mask);
+            let id: Id = match remap {
+                Id::Standard(_) => StandardId::new((raw & CAN_SFF_MASK) as u16).unwrap().into(),
+                Id::Extended(_) => ExtendedId::new(raw & CAN_EFF_MASK).unwrap().into(),
+            };
+            frame.set_id(id);
+        }
+        frame
+    }
+}
+
+pub(crate) fn raw_id(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw() as u32,
+        Id::Extended(id) => id.as_raw(),
+    }
+}
+
+/// Gateway forwarding frames between the CAN interfaces by the rules of `--gateway`
+pub struct Gateway {
+    routes: Vec<Route>,
+}
+
+impl Gateway {
+    /// Load the rules file, validating the rules against the configured CAN interfaces
+    pub fn load(path: &Path, interfaces: &[String]) -> Result<Gateway, String> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read gateway rules {}: {}", path.display(), e))?;
+        let file: RulesFile = toml::from_str(&s)
+            .map_err(|e| format!("invalid gateway rules {}: {}", path.display(), e))?;
+        let routes = file.rules.into_iter().enumerate()
+            .map(|(i, rule)| Route::parse(rule, interfaces).map_err(|e| format!("{}: rule {}: {}", path.display(), i, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Gateway { routes })
+    }
+
+    pub fn list(&self) -> Vec<RouteStatus> {
+        (0..self.routes.len()).filter_map(|route| self.get(route)).collect()
+    }
+
+    pub fn get(&self, route: usize) -> Option<RouteStatus> {
+        self.routes.get(route).map(|r| RouteStatus {
+            route,
+            rule: r.rule.clone(),
+            enabled: r.enabled.load(Ordering::Relaxed),
+            forwarded: r.forwarded.load(Ordering::Relaxed),
+            dropped: r.dropped.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Enable or disable the route, false if unknown
+    pub fn set_enabled(&self, route: usize, enabled: bool) -> bool {
+        match self.routes.get(route) {
+            Some(r) => {
+                r.enabled.store(enabled, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Forward the received frames by the routes of the gateway until shutdown
+///
+/// Error frames are not forwarded, nor the frames transmitted on an interface, the echoes of the
+/// frames forwarded among them, so routes of both directions, eg `can0` to `can1` and back, do
+/// not loop.
+pub async fn forwarder(gateway: Arc<Gateway>, buses: Buses, events: broadcast::Receiver<CanEvent>,
+                       shutdown: CancellationToken) {
+    let mut events = Subscription::new(events, shutdown, "gateway");
+    while let Some((interface, frame, _, direction)) = events.next_frame().await {
+        if matches!(frame, CanAnyFrame::Error(_)) || direction != Direction::Rx {
+            continue;
+        }
+        for route in &gateway.routes {
+            if *route.rule.from != *interface || !route.enabled.load(Ordering::Relaxed) || !route.matches(&frame) {
+                continue;
+            }
+            match buses.write_frame(Some(&route.rule.to), &route.forward(frame)).await {
+                Ok(_) => route.forwarded.fetch_add(1, Ordering::Relaxed),
+                Err(_) => route.dropped.fetch_add(1, Ordering::Relaxed),
+            };
+        }
+    }
+}
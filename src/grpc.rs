@@ -0,0 +1,162 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::UNIX_EPOCH;
+
+use futures_util::{stream, Stream};
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+
+use crate::audit::{self, Origin};
+use crate::auth::{token_access, Access, Scope};
+use crate::can::{CanError, Subscription, Timestamp};
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Frame as _};
+use crate::protocol::format_frame;
+use crate::server::AppState;
+use crate::ws::service_url;
+
+mod proto {
+    tonic::include_proto!("can");
+}
+
+use proto::can_bus_server::{CanBus, CanBusServer};
+
+/// Service of proto/can.proto, sharing the events and CAN devices of the WebSocket clients
+struct Service {
+    state: AppState,
+}
+
+impl Service {
+    /// Access of the token of the `authorization: Bearer` metadata, see [token_access]
+    // the status as returned by the handlers of tonic
+    #[allow(clippy::result_large_err)]
+    fn access<T>(&self, request: &Request<T>) -> Result<Access, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        token_access(&self.state, token).ok_or_else(|| Status::unauthenticated("invalid token"))
+    }
+}
+
+fn proto_frame(interface: &str, frame: &CanAnyFrame, timestamp: Timestamp) -> proto::Frame {
+    proto::Frame {
+        interface: interface.to_string(),
+        id: frame.raw_id(),
+        extended: frame.is_extended(),
+        remote: frame.is_remote_frame(),
+        fd: matches!(frame, CanAnyFrame::Fd(_)),
+        error: matches!(frame, CanAnyFrame::Error(_)),
+        data: frame.data().to_vec(),
+        timestamp_us: timestamp.wall.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64,
+    }
+}
+
+// receiving side of `StreamFrames`
+struct Subscriber {
+    events: Subscription,
+    // all interfaces if empty
+    interfaces: Vec<String>,
+    errors: bool,
+}
+
+/// Next frame of the subscribed interfaces, ending the stream on shutdown
+async fn next_frame(mut sub: Subscriber) -> Option<(Result<proto::Frame, Status>, Subscriber)> {
+    loop {
+        let (interface, frame, timestamp, _) = sub.events.next_frame().await?;
+        let subscribed = sub.interfaces.is_empty() || sub.interfaces.iter().any(|name| **name == *interface);
+        if subscribed && (sub.errors || !matches!(frame, CanAnyFrame::Error(_))) {
+            return Some((Ok(proto_frame(&interface, &frame, timestamp)), sub));
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl CanBus for Service {
+    type StreamFramesStream = Pin<Box<dyn Stream<Item = Result<proto::Frame, Status>> + Send>>;
+
+    async fn stream_frames(
+        &self,
+        request: Request<proto::StreamFramesRequest>,
+    ) -> Result<Response<Self::StreamFramesStream>, Status> {
+        self.access(&request)?;
+        let proto::StreamFramesRequest { interfaces, errors } = request.into_inner();
+        if let Some(name) = interfaces.iter().find(|name| self.state.buses.get(Some(name)).is_none()) {
+            return Err(Status::not_found(format!("unknown CAN interface {}", name)));
+        }
+        let sub = Subscriber {
+            events: Subscription::new(self.state.events.subscribe(), self.state.shutdown.clone(), "gRPC client"),
+            interfaces,
+            errors,
+        };
+        Ok(Response::new(Box::pin(stream::unfold(sub, next_frame))))
+    }
+
+    async fn send_frame(&self, request: Request<proto::SendFrameRequest>) -> Result<Response<proto::SendFrameReply>, Status> {
+        let access = self.access(&request)?;
+        if access.scope == Scope::Monitor {
+            return Err(Status::permission_denied("monitor token can not write frames"));
+        }
+        let origin = Origin { peer: request.remote_addr(), user: access.user.clone(), source: "grpc" };
+        let req = request.into_inner();
+        let frame = crate::api::new_frame(req.id, req.extended, req.fd, &req.data).map_err(Status::invalid_argument)?;
+        access.check_id(frame.id()).map_err(Status::permission_denied)?;
+        let interface = Some(req.interface.as_str()).filter(|name| !name.is_empty());
+        let result = self.state.buses.write_frame(interface, &frame).await;
+        audit::record(&self.state, &origin, interface, &frame, &result);
+        match result {
+            Ok(_) => {
+                let (fmt, _) = format_frame(&frame);
+                tracing::info!(frame = %fmt, "gRPC client wrote frame");
+                Ok(Response::new(proto::SendFrameReply { frame: fmt }))
+            }
+            Err(e @ CanError::UnknownInterface) => Err(Status::not_found(e.to_string())),
+            Err(e @ CanError::Missing) => Err(Status::unavailable(e.to_string())),
+            Err(e @ CanError::Failed(_)) => Err(Status::internal(e.to_string())),
+            Err(e @ CanError::ReadOnly) => Err(Status::permission_denied(e.to_string())),
+            Err(e @ CanError::QueueFull) => Err(Status::resource_exhausted(e.to_string())),
+            Err(e @ CanError::InvalidLin) => Err(Status::invalid_argument(e.to_string())),
+        }
+    }
+
+    async fn get_status(&self, request: Request<proto::GetStatusRequest>) -> Result<Response<proto::Status>, Status> {
+        self.access(&request)?;
+        let mut interfaces = Vec::new();
+        for bus in self.state.buses.iter() {
+            interfaces.push(proto::Interface { name: bus.name.to_string(), connected: bus.is_connected().await });
+        }
+        Ok(Response::new(proto::Status {
+            service_url: service_url(&self.state.config),
+            interfaces,
+            clients: self.state.clients.list().len() as u32,
+        }))
+    }
+}
+
+/// Listeners of `--grpc-port`, at each address the web-service is bound to
+pub fn listeners(state: &AppState, port: u16) -> Result<Vec<(SocketAddr, std::net::TcpListener)>, String> {
+    let mut addrs: Vec<SocketAddr> = state.config.listen_addrs().into_iter().map(|addr| SocketAddr::new(addr.ip(), port)).collect();
+    addrs.sort();
+    addrs.dedup();
+    addrs.iter()
+        .map(|&addr| Ok((addr, crate::server::bind(addr, crate::server::dual_stack(&addrs, addr))?)))
+        .collect()
+}
+
+/// Serve the gRPC service at the listener until shutdown
+pub async fn serve(state: AppState, listener: std::net::TcpListener) {
+    let incoming = match tokio::net::TcpListener::from_std(listener).map(|listener| TcpIncoming::from_listener(listener, true, None)) {
+        Ok(Ok(incoming)) => incoming,
+        Ok(Err(e)) => return tracing::error!(error = %e, "failed to serve gRPC"),
+        Err(e) => return tracing::error!(error = %e, "failed to serve gRPC"),
+    };
+    let shutdown = state.shutdown.clone();
+    let service = CanBusServer::new(Service { state });
+    let result = tonic::transport::Server::builder()
+        .add_service(service)
+        .serve_with_incoming_shutdown(incoming, shutdown.cancelled_owned())
+        .await;
+    if let Err(e) = result {
+        tracing::error!(error = %e, "failed to serve gRPC");
+    }
+}
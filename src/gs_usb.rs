@@ -0,0 +1,177 @@
+use std::io;
+
+use nusb::transfer::{ControlIn, ControlOut, ControlType, Direction, EndpointType, Queue, Recipient, RequestBuffer};
+use tokio::sync::Mutex;
+
+use crate::frame::{can_frame_default, CanAnyFrame, EmbeddedFrame, Frame};
+
+// adapters of the gs_usb protocol, candleLight and its clones, see drivers/net/can/usb/gs_usb.c of Linux
+const DEVICES: [(u16, u16); 4] = [(0x1d50, 0x606f), (0x1209, 0x2323), (0x1cd2, 0x606f), (0x16d0, 0x10b8)];
+
+// vendor requests of the interface
+const BREQ_HOST_FORMAT: u8 = 0;
+const BREQ_BITTIMING: u8 = 1;
+const BREQ_MODE: u8 = 2;
+const BREQ_BT_CONST: u8 = 4;
+const MODE_RESET: u32 = 0;
+const MODE_START: u32 = 1;
+
+// frame of the bulk endpoints, echo id, id word as SocketCAN, dlc, channel, flags, reserved and data
+const HOST_FRAME_LEN: usize = 20;
+// echo id of received frames, other echo ids confirm the frames transmitted
+const RX_ECHO_ID: u32 = 0xFFFF_FFFF;
+// echo ids in flight, as many as the TX buffers of candleLight
+const MAX_ECHOES: u32 = 10;
+// bulk transfers queued for receiving
+const RX_TRANSFERS: usize = 8;
+// the only channel opened, adapters of multiple channels are rare
+const CHANNEL: u16 = 0;
+
+fn invalid(error: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
+/// Bit timing of the bitrate at a sample point of 87.5%, for the clock and limits of `BT_CONST`
+fn bittiming(bt_const: &[u8], bitrate: u32) -> Option<[u32; 5]> {
+    let field = |i: usize| Some(u32::from_le_bytes(bt_const.get(4 * i..4 * i + 4)?.try_into().ok()?));
+    let [fclk, tseg1_min, tseg1_max, tseg2_min, tseg2_max, sjw_max, brp_min, brp_max, brp_inc] =
+        [1, 2, 3, 4, 5, 6, 7, 8, 9].map(field);
+    let (fclk, brp_inc) = (fclk?, brp_inc?.max(1));
+    let mut brp = brp_min?.max(1);
+    while brp <= brp_max? {
+        let tq = fclk / (brp * bitrate);
+        if fclk % (brp * bitrate) == 0 && tq > 2 {
+            // time quanta before the sample point, the sync segment included
+            let tseg1 = (tq * 7 + 4) / 8 - 1;
+            let tseg2 = tq - 1 - tseg1;
+            if (tseg1_min?..=tseg1_max?).contains(&tseg1) && (tseg2_min?..=tseg2_max?).contains(&tseg2) {
+                // propagation segment, phase segment 1 and 2, jump width and prescaler
+                return Some([1, tseg1 - 1, tseg2, tseg2.min(sjw_max?), brp]);
+            }
+        }
+        brp += brp_inc;
+    }
+    None
+}
+
+/// Decode a received frame of the bulk in endpoint, `None` if echoing a transmitted frame
+fn decode(packet: &[u8]) -> Option<CanAnyFrame> {
+    let packet = packet.get(..HOST_FRAME_LEN)?;
+    if u32::from_le_bytes(packet[..4].try_into().unwrap()) != RX_ECHO_ID {
+        return None;
+    }
+    let mut frame = can_frame_default();
+    frame.can_id = u32::from_le_bytes(packet[4..8].try_into().unwrap());
+    frame.can_dlc = packet[8].min(8);
+    frame.data.copy_from_slice(&packet[12..20]);
+    Some(frame.into())
+}
+
+/// Encode a classic data or remote frame for the bulk out endpoint
+fn encode(echo_id: u32, frame: &CanAnyFrame) -> io::Result<Vec<u8>> {
+    if !matches!(frame, CanAnyFrame::Normal(_) | CanAnyFrame::Remote(_)) {
+        return Err(invalid("gs_usb supports classic frames only"));
+    }
+    let mut packet = Vec::with_capacity(HOST_FRAME_LEN);
+    packet.extend_from_slice(&echo_id.to_le_bytes());
+    packet.extend_from_slice(&frame.id_word().to_le_bytes());
+    packet.extend_from_slice(&[frame.dlc() as u8, CHANNEL as u8, 0, 0]);
+    let mut data = [0u8; 8];
+    if !frame.is_remote_frame() {
+        data[..frame.data().len()].copy_from_slice(frame.data());
+    }
+    packet.extend_from_slice(&data);
+    Ok(packet)
+}
+
+/// Receiving side of the adapter
+pub struct Reader {
+    queue: Queue<RequestBuffer>,
+}
+
+impl Reader {
+    /// Wait for the next received frame, skipping the echoes of transmitted frames
+    pub async fn next(&mut self) -> Option<io::Result<CanAnyFrame>> {
+        loop {
+            let packet = match self.queue.next_complete().await.into_result() {
+                Ok(packet) => packet,
+                Err(e) => return Some(Err(e.into())),
+            };
+            let frame = decode(&packet);
+            self.queue.submit(RequestBuffer::reuse(packet, HOST_FRAME_LEN));
+            if let Some(frame) = frame {
+                return Some(Ok(frame));
+            }
+        }
+    }
+}
+
+/// Transmitting side of the adapter
+pub struct Writer {
+    interface: nusb::Interface,
+    endpoint: u8,
+    echo_id: Mutex<u32>,
+}
+
+impl Writer {
+    pub async fn write_frame(&self, frame: &CanAnyFrame) -> io::Result<()> {
+        let echo_id = {
+            let mut echo_id = self.echo_id.lock().await;
+            *echo_id = (*echo_id + 1) % MAX_ECHOES;
+            *echo_id
+        };
+        let packet = encode(echo_id, frame)?;
+        self.interface.bulk_out(self.endpoint, packet).await.into_result()?;
+        Ok(())
+    }
+}
+
+async fn control_out(interface: &nusb::Interface, request: u8, value: u16, data: &[u8]) -> io::Result<()> {
+    let control = ControlOut { control_type: ControlType::Vendor, recipient: Recipient::Interface, request, value, index: 0, data };
+    interface.control_out(control).await.into_result()?;
+    Ok(())
+}
+
+/// Open the first gs_usb adapter, or the adapter of the serial number, and its channel at the bitrate
+pub async fn open(serial: Option<&str>, bitrate: u32) -> io::Result<(Reader, Writer)> {
+    let device = nusb::list_devices()?
+        .filter(|info| DEVICES.contains(&(info.vendor_id(), info.product_id())))
+        .find(|info| serial.is_none() || info.serial_number() == serial)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no gs_usb adapter found"))?;
+    let interface = device.open()?.detach_and_claim_interface(0)?;
+    let endpoint = |direction: Direction| {
+        interface
+            .descriptors()
+            .find_map(|setting| {
+                let mut endpoints = setting.endpoints();
+                let endpoint = endpoints.find(|e| e.transfer_type() == EndpointType::Bulk && e.direction() == direction);
+                endpoint.map(|endpoint| endpoint.address())
+            })
+            .ok_or_else(|| invalid("missing bulk endpoint of gs_usb adapter"))
+    };
+    let (rx_endpoint, tx_endpoint) = (endpoint(Direction::In)?, endpoint(Direction::Out)?);
+
+    // byte order of the host, ignored by candleLight which is little endian only
+    control_out(&interface, BREQ_HOST_FORMAT, 1, &0x0000_beef_u32.to_le_bytes()).await?;
+    let control = ControlIn {
+        control_type: ControlType::Vendor,
+        recipient: Recipient::Interface,
+        request: BREQ_BT_CONST,
+        value: CHANNEL,
+        index: 0,
+        length: 40,
+    };
+    let bt_const = interface.control_in(control).await.into_result()?;
+    let timing = bittiming(&bt_const, bitrate).ok_or_else(|| invalid("bitrate not supported by gs_usb adapter"))?;
+    let mut mode = [MODE_RESET, 0].map(u32::to_le_bytes).concat();
+    control_out(&interface, BREQ_MODE, CHANNEL, &mode).await?;
+    control_out(&interface, BREQ_BITTIMING, CHANNEL, &timing.map(u32::to_le_bytes).concat()).await?;
+    mode[..4].copy_from_slice(&MODE_START.to_le_bytes());
+    control_out(&interface, BREQ_MODE, CHANNEL, &mode).await?;
+
+    let mut queue = interface.bulk_in_queue(rx_endpoint);
+    for _ in 0..RX_TRANSFERS {
+        queue.submit(RequestBuffer::new(HOST_FRAME_LEN));
+    }
+    Ok((Reader { queue }, Writer { interface, endpoint: tx_endpoint, echo_id: Mutex::new(0) }))
+}
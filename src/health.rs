@@ -0,0 +1,61 @@
+use axum::{http::StatusCode, Extension, Json};
+use schemars::JsonSchema;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::busstate::BusState;
+use crate::server::AppState;
+
+// DTO - problem found at startup not preventing the service to run, e.g. a virtual CAN
+// interface not created without CAP_NET_ADMIN
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct StartupError {
+    // CAN interface of the problem, none if of the service
+    pub interface: Option<String>,
+    pub error: String,
+}
+
+// DTO - connection state and bus health of a CAN interface
+#[derive(Serialize, JsonSchema, ToSchema, Debug)]
+pub struct InterfaceHealth {
+    interface: String,
+    transport: String,
+    connected: bool,
+    // none until reported by an error frame or netlink
+    bus_state: Option<BusState>,
+}
+
+// DTO - response of `GET /healthz`, `ok` if all CAN interfaces are connected and not bus-off, and
+// no problem was found at startup, else `degraded`
+#[derive(Serialize, JsonSchema, ToSchema, Debug)]
+pub struct Health {
+    status: &'static str,
+    // none with `--no-can`
+    interfaces: Vec<InterfaceHealth>,
+    errors: Vec<StartupError>,
+}
+
+/// `GET /healthz` - health of the service for container orchestration, the connection state of
+/// the CAN interfaces and the problems found at startup; no token required
+///
+/// Responds with 200 if healthy, else with 503 and the same body.
+#[utoipa::path(get, path = "/healthz", responses(
+    (status = 200, description = "all CAN interfaces connected", body = Health),
+    (status = 503, description = "CAN interface disconnected or bus-off, or startup problem", body = Health),
+))]
+pub async fn healthz(Extension(state): Extension<AppState>) -> (StatusCode, Json<Health>) {
+    let mut interfaces = Vec::new();
+    for bus in state.buses.iter() {
+        interfaces.push(InterfaceHealth {
+            interface: bus.name.to_string(),
+            transport: bus.transport.to_string(),
+            connected: bus.is_connected().await,
+            bus_state: state.bus_states.get(&bus.name),
+        });
+    }
+    let errors = state.startup_errors.to_vec();
+    let bus_off = |interface: &InterfaceHealth| matches!(interface.bus_state, Some(BusState::BusOff | BusState::Restarting));
+    let healthy = errors.is_empty() && interfaces.iter().all(|interface| interface.connected && !bus_off(interface));
+    let (status, code) = if healthy { ("ok", StatusCode::OK) } else { ("degraded", StatusCode::SERVICE_UNAVAILABLE) };
+    (code, Json(Health { status, interfaces, errors }))
+}
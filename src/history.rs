@@ -0,0 +1,190 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OpenFlags};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::can::{CanEvent, Subscription, Timestamp};
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Frame};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS frames (
+        timestamp INTEGER NOT NULL,
+        interface TEXT NOT NULL,
+        can_id INTEGER NOT NULL,
+        extended INTEGER NOT NULL,
+        fd_flags INTEGER,
+        data BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS frames_timestamp ON frames (timestamp);
+    CREATE INDEX IF NOT EXISTS frames_can_id ON frames (can_id, timestamp);
+";
+
+// DTO - frame of the history, timestamp in seconds since epoch and frame in `cansend` notation
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct HistoryEntry {
+    pub timestamp: f64,
+    pub interface: String,
+    pub frame: String,
+}
+
+// Query of `GET /api/history`, all optional: CAN id as hex string, time range in seconds since epoch
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    pub id: Option<String>,
+    pub interface: Option<String>,
+    pub since: Option<f64>,
+    pub until: Option<f64>,
+    pub limit: Option<usize>,
+}
+
+struct Row {
+    timestamp: i64,
+    interface: Arc<str>,
+    can_id: u32,
+    extended: bool,
+    fd_flags: Option<u8>,
+    data: Vec<u8>,
+}
+
+/// Database of received frames, written by the writer task and queried by the REST API
+pub struct History {
+    writer: Arc<Mutex<Connection>>,
+    reader: Arc<Mutex<Connection>>,
+}
+
+fn micros(timestamp: SystemTime) -> i64 {
+    timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as i64
+}
+
+impl History {
+    /// Open the database, creating the schema if missing
+    pub fn open(path: &Path) -> Result<History, String> {
+        let failed = |e: rusqlite::Error| format!("failed to open database {}: {}", path.display(), e);
+        let writer = Connection::open(path).map_err(failed)?;
+        // concurrent queries while writing
+        writer.pragma_update(None, "journal_mode", "WAL").map_err(failed)?;
+        writer.execute_batch(SCHEMA).map_err(failed)?;
+        let reader = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(failed)?;
+        Ok(History { writer: Arc::new(Mutex::new(writer)), reader: Arc::new(Mutex::new(reader)) })
+    }
+
+    fn insert(writer: &Mutex<Connection>, rows: &[Row]) -> rusqlite::Result<()> {
+        let mut conn = writer.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO frames (timestamp, interface, can_id, extended, fd_flags, data) VALUES (?, ?, ?, ?, ?, ?)",
+            )?;
+            for row in rows {
+                stmt.execute(params![row.timestamp, &*row.interface, row.can_id, row.extended, row.fd_flags, row.data])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Query the latest frames matching the query, in chronological order
+    pub async fn query(&self, query: HistoryQuery, can_id: Option<u32>) -> Result<Vec<HistoryEntry>, String> {
+        const DEFAULT_LIMIT: usize = 1000;
+        const MAX_LIMIT: usize = 10000;
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+        let to_micros = |secs: f64| (secs * 1e6) as i64;
+        let reader = self.reader.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = reader.lock().unwrap();
+            let mut stmt = conn.prepare_cached(
+                "SELECT timestamp, interface, can_id, extended, fd_flags, data FROM frames
+                 WHERE (?1 IS NULL OR can_id = ?1) AND (?2 IS NULL OR interface = ?2)
+                   AND (?3 IS NULL OR timestamp >= ?3) AND (?4 IS NULL OR timestamp <= ?4)
+                 ORDER BY timestamp DESC LIMIT ?5",
+            )?;
+            let rows = stmt.query_map(
+                params![can_id, query.interface, query.since.map(to_micros), query.until.map(to_micros), limit as i64],
+                |row| {
+                    Ok(Row {
+                        timestamp: row.get(0)?,
+                        interface: row.get::<_, String>(1)?.into(),
+                        can_id: row.get(2)?,
+                        extended: row.get(3)?,
+                        fd_flags: row.get(4)?,
+                        data: row.get(5)?,
+                    })
+                },
+            )?;
+            let mut entries = rows.map(|row| row.map(entry)).collect::<rusqlite::Result<Vec<_>>>()?;
+            entries.reverse();
+            Ok(entries)
+        })
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e: rusqlite::Error| e.to_string())
+    }
+}
+
+fn entry(row: Row) -> HistoryEntry {
+    let id = if row.extended { format!("{:08X}", row.can_id) } else { format!("{:03X}", row.can_id) };
+    let data = hex::encode_upper(&row.data);
+    let frame = match row.fd_flags {
+        Some(flags) => format!("{}##{:X}{}", id, flags, data),
+        None => format!("{}#{}", id, data),
+    };
+    HistoryEntry { timestamp: row.timestamp as f64 / 1e6, interface: row.interface.to_string(), frame }
+}
+
+fn row(interface: Arc<str>, frame: &CanAnyFrame, timestamp: Timestamp) -> Option<Row> {
+    let fd_flags = match frame {
+        CanAnyFrame::Normal(_) => None,
+        CanAnyFrame::Fd(fd) => Some(fd.flags().bits() & 0x03),
+        _ => return None,
+    };
+    Some(Row {
+        timestamp: micros(timestamp.wall),
+        interface,
+        can_id: frame.raw_id(),
+        extended: frame.is_extended(),
+        fd_flags,
+        data: frame.data().to_vec(),
+    })
+}
+
+/// Writer task, inserting every received data frame in batches until shutdown
+pub async fn writer(history: Arc<History>, events: broadcast::Receiver<CanEvent>, shutdown: CancellationToken) {
+    const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+    const MAX_BATCH: usize = 1000;
+    let mut batch = Vec::new();
+    let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+    let mut events = Subscription::new(events, shutdown, "history writer");
+
+    loop {
+        let (due, done) = tokio::select! {
+            frame = events.next_frame() => match frame {
+                Some((interface, frame, timestamp, _)) => {
+                    batch.extend(row(interface, &frame, timestamp));
+                    (batch.len() >= MAX_BATCH, false)
+                }
+                None => (true, true),
+            },
+            _ = flush.tick() => (true, false),
+        };
+
+        if due && !batch.is_empty() {
+            let rows = std::mem::take(&mut batch);
+            let writer = history.writer.clone();
+            match tokio::task::spawn_blocking(move || History::insert(&writer, &rows)).await {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => tracing::error!(error = %e, "history writer failed inserting frames"),
+                Err(e) => tracing::error!(error = %e, "history writer failed"),
+            }
+        }
+        if done {
+            return;
+        }
+    }
+}
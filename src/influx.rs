@@ -0,0 +1,102 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use hyper::{client::HttpConnector, header, Body, Method, Request, Uri};
+
+use crate::can::{Subscription, Timestamp};
+use crate::decode::DecodedFrame;
+use crate::frame::CanAnyFrame;
+use crate::server::AppState;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// bound of the lines buffered, flushed early on busy buses and dropped while the server fails
+const MAX_LINES: usize = 10_000;
+
+/// Write URL of `--influx-url`, e.g. `http://localhost:8086/api/v2/write?org=lab&bucket=can` of
+/// InfluxDB or `http://localhost:3000/api/live/push/can` of Grafana Live
+pub fn write_url(url: &str) -> Result<Uri, String> {
+    let uri: Uri = url.parse().map_err(|e| format!("invalid InfluxDB URL {}: {}", url, e))?;
+    if uri.scheme_str() != Some("http") || uri.host().is_none() {
+        return Err(format!("unsupported InfluxDB URL {}, expecting http://host:port/path", url));
+    }
+    Ok(uri)
+}
+
+// escape the commas, equal signs and spaces of measurements, tag keys and values and field keys
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Line of the decoded frame, measured as the message with the interface as tag, e.g.
+/// `EngineData,interface=can0 EngineSpeed=1250,EngineTemp=85 1436509052200000000`
+fn line(interface: &str, decoded: &DecodedFrame, timestamp: Timestamp) -> Option<String> {
+    let fields: Vec<String> = decoded.signals
+        .iter()
+        .filter(|signal| signal.value.is_finite())
+        .map(|signal| format!("{}={}", escape(&signal.name), signal.value))
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+    let nanos = timestamp.wall.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    Some(format!("{},interface={} {} {}", escape(&decoded.message), escape(interface), fields.join(","), nanos))
+}
+
+/// Exporter task, writing the signals of the frames decoded by the DBC database of `--dbc` to
+/// InfluxDB or Grafana Live in line protocol, once per second until shutdown
+///
+/// The authorization is sent as is, e.g. `Token <token>` for InfluxDB or `Bearer <token>` for
+/// Grafana. Lines failed to write are dropped, not to flood a server once it is back.
+pub async fn exporter(state: AppState, url: Uri, authorization: Option<String>) {
+    let client = hyper::Client::<HttpConnector>::new();
+    let mut events = Subscription::new(state.events.subscribe(), state.shutdown.clone(), "InfluxDB exporter");
+    let mut lines: Vec<String> = Vec::new();
+    let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+    if state.config.dbc.is_none() {
+        tracing::warn!("InfluxDB exporter without --dbc, exporting no signals until reloaded with a DBC file");
+    }
+
+    loop {
+        tokio::select! {
+            frame = events.next_frame() => match frame {
+                Some((_, CanAnyFrame::Error(_), _, _)) => (),
+                Some((interface, frame, timestamp, _)) => {
+                    let decoded = state.settings.decoder.borrow().as_ref().and_then(|decoder| decoder.decode(&frame));
+                    if let Some(line) = decoded.and_then(|decoded| line(&interface, &decoded, timestamp)) {
+                        lines.push(line);
+                    }
+                    if lines.len() < MAX_LINES {
+                        continue;
+                    }
+                }
+                None => return,
+            },
+            _ = ticker.tick() => (),
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        let count = lines.len();
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(url.clone())
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8");
+        if let Some(authorization) = &authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        let request = request.body(Body::from(std::mem::take(&mut lines).join("\n"))).unwrap();
+        match tokio::time::timeout(REQUEST_TIMEOUT, client.request(request)).await {
+            Ok(Ok(response)) if response.status().is_success() => tracing::trace!(count, "exported signals"),
+            Ok(Ok(response)) => tracing::warn!(count, status = %response.status(), "InfluxDB rejected signals"),
+            Ok(Err(e)) => tracing::warn!(count, error = %e, "InfluxDB not reachable, dropped signals"),
+            Err(_) => tracing::warn!(count, "InfluxDB timed out, dropped signals"),
+        }
+    }
+}
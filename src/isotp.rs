@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::audit::{Audit, Origin};
+use crate::can::{Buses, CanEvent, Priority, Transmit};
+use crate::frame::{CanAnyFrame, CanDataFrame, EmbeddedFrame, Id};
+use crate::server::AppState;
+
+/// Max payload of classic ISO-TP, limited by the 12 bit length of the first frame
+pub const MAX_PAYLOAD: usize = 4095;
+const FRAME_LEN: usize = 8;
+const PADDING: u8 = 0xCC;
+// N_Bs and N_Cr, timeout waiting for a flow control or consecutive frame
+const TIMEOUT: Duration = Duration::from_secs(1);
+const QUEUE_LEN: usize = 16;
+
+// protocol control information, the high nibble of the first byte
+const PCI_SINGLE: u8 = 0x0;
+const PCI_FIRST: u8 = 0x1;
+const PCI_CONSECUTIVE: u8 = 0x2;
+const PCI_FLOW_CONTROL: u8 = 0x3;
+
+// flow status of the flow control frame
+const FS_CTS: u8 = 0;
+const FS_WAIT: u8 = 1;
+const FS_OVERFLOW: u8 = 2;
+
+/// Event of a channel, delivered to the WebSocket session that opened the channel
+pub enum Event {
+    Received { interface: Arc<str>, tx_id: Id, rx_id: Id, data: Vec<u8> },
+    Failed { tx_id: Id, rx_id: Id, error: &'static str },
+}
+
+/// Minimum separation time of consecutive frames, encoded as by ISO 15765-2
+fn separation_time(st_min: u8) -> Duration {
+    match st_min {
+        0x00..=0x7F => Duration::from_millis(st_min as u64),
+        0xF1..=0xF9 => Duration::from_micros((st_min - 0xF0) as u64 * 100),
+        // reserved values are to be interpreted as the maximum
+        _ => Duration::from_millis(0x7F),
+    }
+}
+
+// reassembly of a multi-frame payload
+struct Reception {
+    data: Vec<u8>,
+    len: usize,
+    sn: u8,
+    deadline: Instant,
+}
+
+/// ISO-TP connection of a pair of CAN ids, sending on `tx_id` and receiving on `rx_id`
+struct Channel {
+    buses: Buses,
+    // recording the frames written, of `--audit-log`
+    audit: Option<Arc<Audit>>,
+    origin: Origin,
+    interface: Arc<str>,
+    tx_id: Id,
+    rx_id: Id,
+    events: broadcast::Receiver<CanEvent>,
+    delivery: mpsc::Sender<Event>,
+}
+
+impl Channel {
+    /// Channel task, sending the requested payloads and reassembling received payloads until
+    /// the session closes the channel or shutdown
+    async fn run(mut self, mut requests: mpsc::Receiver<Vec<u8>>, shutdown: CancellationToken) {
+        let mut reception: Option<Reception> = None;
+        loop {
+            let deadline = reception.as_ref().map(|r| r.deadline);
+            tokio::select! {
+                request = requests.recv() => match request {
+                    Some(data) => {
+                        if let Err(error) = self.send(&data).await {
+                            self.fail(error).await;
+                        }
+                    }
+                    None => return,
+                },
+                event = self.events.recv() => match event {
+                    Ok(CanEvent::Frame(interface, frame, _, _)) if interface == self.interface => {
+                        if let Some(data) = self.rx_data(&frame) {
+                            self.receive(&mut reception, &data).await;
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        if reception.take().is_some() {
+                            self.fail("lost frames").await;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    reception = None;
+                    self.fail("timeout waiting for consecutive frame").await;
+                }
+                _ = shutdown.cancelled() => return,
+            }
+        }
+    }
+
+    async fn fail(&self, error: &'static str) {
+        tracing::warn!(tx_id = ?self.tx_id, rx_id = ?self.rx_id, error, "ISO-TP transfer failed");
+        let _ = self.delivery.send(Event::Failed { tx_id: self.tx_id, rx_id: self.rx_id, error }).await;
+    }
+
+    /// Data of a classic frame addressed to this channel
+    fn rx_data(&self, frame: &CanAnyFrame) -> Option<Vec<u8>> {
+        match frame {
+            CanAnyFrame::Normal(frame) if frame.id() == self.rx_id => Some(frame.data().to_vec()),
+            _ => None,
+        }
+    }
+
+    /// Write a single frame, padded to 8 bytes
+    async fn write(&self, payload: &[u8]) -> Result<(), &'static str> {
+        let mut data = [PADDING; FRAME_LEN];
+        data[..payload.len()].copy_from_slice(payload);
+        let frame = CanAnyFrame::Normal(CanDataFrame::new(self.tx_id, &data).ok_or("invalid frame")?);
+        // flow control and consecutive frames are awaited by the peer within its timeouts
+        let transmit = Transmit { client: None, priority: Priority::High };
+        let result = self.buses.transmit(Some(&self.interface), &frame, transmit).await;
+        if let Some(audit) = &self.audit {
+            audit.record(&self.origin, Some(&self.interface), &frame, &result);
+        }
+        result.or(Err("CAN write failed"))
+    }
+
+    async fn receive(&self, reception: &mut Option<Reception>, data: &[u8]) {
+        let Some(&pci) = data.first() else { return };
+        match pci >> 4 {
+            PCI_SINGLE => {
+                let len = (pci & 0x0F) as usize;
+                if len > 0 && len < data.len() {
+                    *reception = None;
+                    self.deliver(data[1..=len].to_vec()).await;
+                }
+            }
+            PCI_FIRST if data.len() == FRAME_LEN => {
+                let len = ((pci & 0x0F) as usize) << 8 | data[1] as usize;
+                if len < FRAME_LEN {
+                    return;
+                }
+                *reception = Some(Reception { data: data[2..].to_vec(), len, sn: 1, deadline: Instant::now() + TIMEOUT });
+                // clear to send all consecutive frames without delay
+                if let Err(error) = self.write(&[PCI_FLOW_CONTROL << 4 | FS_CTS, 0, 0]).await {
+                    *reception = None;
+                    self.fail(error).await;
+                }
+            }
+            PCI_CONSECUTIVE => {
+                let Some(r) = reception.as_mut() else { return };
+                if pci & 0x0F != r.sn {
+                    *reception = None;
+                    self.fail("wrong sequence number").await;
+                    return;
+                }
+                let remaining = r.len - r.data.len();
+                r.data.extend_from_slice(&data[1..data.len().min(remaining + 1)]);
+                r.sn = (r.sn + 1) & 0x0F;
+                r.deadline = Instant::now() + TIMEOUT;
+                if r.data.len() >= r.len {
+                    let data = std::mem::take(&mut r.data);
+                    *reception = None;
+                    self.deliver(data).await;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    async fn deliver(&self, data: Vec<u8>) {
+        let event = Event::Received { interface: self.interface.clone(), tx_id: self.tx_id, rx_id: self.rx_id, data };
+        let _ = self.delivery.send(event).await;
+    }
+
+    /// Segment the payload, waiting for flow control before each block of consecutive frames
+    async fn send(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        if data.is_empty() {
+            return Err("empty payload");
+        }
+        if data.len() > MAX_PAYLOAD {
+            return Err("payload exceeds 4095 bytes");
+        }
+        if data.len() < FRAME_LEN {
+            let pci = [PCI_SINGLE << 4 | data.len() as u8];
+            return self.write(&[&pci[..], data].concat()).await;
+        }
+
+        let len = data.len();
+        let pci = [PCI_FIRST << 4 | (len >> 8) as u8, len as u8];
+        self.write(&[&pci[..], &data[..6]].concat()).await?;
+
+        let mut offset = 6;
+        let mut sn: u8 = 1;
+        loop {
+            let (block_size, st_min) = self.flow_control().await?;
+            let mut count = 0;
+            loop {
+                let end = len.min(offset + FRAME_LEN - 1);
+                let pci = [PCI_CONSECUTIVE << 4 | sn];
+                self.write(&[&pci[..], &data[offset..end]].concat()).await?;
+                offset = end;
+                sn = (sn + 1) & 0x0F;
+                count += 1;
+                if offset >= len {
+                    return Ok(());
+                }
+                if block_size != 0 && count == block_size {
+                    break;
+                }
+                tokio::time::sleep(st_min).await;
+            }
+        }
+    }
+
+    /// Wait for the receiver's flow control, returning block size and separation time
+    async fn flow_control(&mut self) -> Result<(u8, Duration), &'static str> {
+        let mut deadline = Instant::now() + TIMEOUT;
+        loop {
+            let event = tokio::time::timeout_at(deadline, self.events.recv())
+                .await
+                .or(Err("timeout waiting for flow control"))?;
+            let data = match event {
+                Ok(CanEvent::Frame(interface, frame, _, _)) if interface == self.interface => self.rx_data(&frame),
+                Ok(_) => None,
+                Err(broadcast::error::RecvError::Lagged(_)) => return Err("lost frames"),
+                Err(broadcast::error::RecvError::Closed) => return Err("CAN reader closed"),
+            };
+            match data.as_deref() {
+                Some(&[pci, block_size, st_min, ..]) if pci >> 4 == PCI_FLOW_CONTROL => match pci & 0x0F {
+                    FS_CTS => return Ok((block_size, separation_time(st_min))),
+                    FS_WAIT => deadline = Instant::now() + TIMEOUT,
+                    FS_OVERFLOW => return Err("receiver overflow"),
+                    _ => return Err("invalid flow status"),
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
+/// ISO-TP channels opened by a WebSocket session, closed when the session ends
+pub struct Channels {
+    channels: HashMap<(Arc<str>, Id, Id), mpsc::Sender<Vec<u8>>>,
+    // client of the frames written
+    origin: Origin,
+    delivery: mpsc::Sender<Event>,
+    pub received: mpsc::Receiver<Event>,
+}
+
+impl Channels {
+    pub fn new(origin: Origin) -> Channels {
+        let (delivery, received) = mpsc::channel(QUEUE_LEN);
+        Channels { channels: HashMap::new(), origin, delivery, received }
+    }
+
+    /// Send the payload on the channel, opening the channel if not yet open
+    ///
+    /// Without payload the channel is opened for reception only.
+    pub async fn send(&mut self, state: &AppState, interface: Option<&str>, tx_id: Id, rx_id: Id,
+                      data: Option<Vec<u8>>) -> Result<(), &'static str> {
+        if data.as_ref().is_some_and(|data| data.is_empty()) {
+            return Err("empty payload");
+        }
+        if data.as_ref().is_some_and(|data| data.len() > MAX_PAYLOAD) {
+            return Err("payload exceeds 4095 bytes");
+        }
+        let interface = state.buses.get(interface).ok_or("unknown CAN interface")?.name.clone();
+
+        let requests = self.channels.entry((interface.clone(), tx_id, rx_id)).or_insert_with(|| {
+            let (requests, rx) = mpsc::channel(QUEUE_LEN);
+            let channel = Channel {
+                buses: state.buses.clone(),
+                audit: state.audit.clone(),
+                origin: self.origin.clone(),
+                interface,
+                tx_id,
+                rx_id,
+                events: state.events.subscribe(),
+                delivery: self.delivery.clone(),
+            };
+            state.tasks.spawn(channel.run(rx, state.shutdown.clone()));
+            requests
+        });
+        match data {
+            Some(data) => requests.send(data).await.or(Err("channel closed")),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::watch;
+
+    use super::*;
+    use crate::can::{transmitter, Direction, MockTransport, Timestamp};
+    use crate::frame::StandardId;
+    use crate::transport::{Custom, Transport};
+
+    struct Peer {
+        mock: Arc<MockTransport>,
+        events: broadcast::Sender<CanEvent>,
+        delivered: mpsc::Receiver<Event>,
+        _shutdown: tokio_util::sync::DropGuard,
+    }
+
+    impl Peer {
+        // frame of the peer, received on the channel's rx id
+        fn reply(&self, data: &[u8]) {
+            let frame = CanDataFrame::new(id(0x7E8), data).unwrap();
+            let event = CanEvent::Frame("mock0".into(), CanAnyFrame::Normal(frame), Timestamp::now(), Direction::Rx);
+            self.events.send(event).unwrap();
+        }
+
+        async fn written(&self) -> Vec<u8> {
+            let frame = self.mock.written().await.unwrap();
+            assert_eq!(frame.id(), id(0x7E0));
+            frame.data().to_vec()
+        }
+    }
+
+    fn id(raw: u16) -> Id {
+        Id::Standard(StandardId::new(raw).unwrap())
+    }
+
+    // channel of 7E0/7E8 on the open in-memory bus `mock0`, written by its transmitter
+    async fn channel() -> (Channel, Peer) {
+        let mock = MockTransport::new();
+        let transport = Transport::Custom(Custom(mock.clone()));
+        let (_, filters) = watch::channel(Vec::new());
+        let buses = Buses::new(&["mock0".to_string()], &[transport], 500_000, false, filters);
+        let bus = buses.get(None).unwrap().clone();
+        bus.open(&[]).await.unwrap();
+        let shutdown = CancellationToken::new();
+        tokio::spawn(transmitter(bus, shutdown.clone()));
+        let (events, receiver) = broadcast::channel(64);
+        let (delivery, delivered) = mpsc::channel(QUEUE_LEN);
+        let channel = Channel {
+            buses,
+            audit: None,
+            origin: Origin::default(),
+            interface: "mock0".into(),
+            tx_id: id(0x7E0),
+            rx_id: id(0x7E8),
+            events: receiver,
+            delivery,
+        };
+        (channel, Peer { mock, events, delivered, _shutdown: shutdown.drop_guard() })
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn single_frame_padded() {
+        let (mut channel, peer) = channel().await;
+        channel.send(&[0x22, 0xF1, 0x90]).await.unwrap();
+        assert_eq!(peer.written().await, [0x03, 0x22, 0xF1, 0x90, PADDING, PADDING, PADDING, PADDING]);
+
+        channel.send(&payload(7)).await.unwrap();
+        assert_eq!(peer.written().await, [0x07, 0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn empty_payload_rejected() {
+        let (mut channel, _peer) = channel().await;
+        assert_eq!(channel.send(&[]).await, Err("empty payload"));
+        assert_eq!(channel.send(&payload(MAX_PAYLOAD + 1)).await, Err("payload exceeds 4095 bytes"));
+    }
+
+    #[tokio::test]
+    async fn segmented_by_flow_control_wrapping_sequence_number() {
+        let (mut channel, peer) = channel().await;
+        // first frame of 6 bytes and 28 consecutive frames, the sequence number wrapping after 15
+        let data = payload(200);
+        let peer = async {
+            assert_eq!(peer.written().await, [0x10, 200, 0, 1, 2, 3, 4, 5]);
+            // blocks of 20 frames
+            peer.reply(&[0x30, 20, 0]);
+            let mut received = data[..6].to_vec();
+            for n in 1..=28usize {
+                if n == 21 {
+                    peer.reply(&[0x30, 20, 0]);
+                }
+                let frame = peer.written().await;
+                assert_eq!(frame[0], 0x20 | (n % 16) as u8, "frame {}", n);
+                received.extend_from_slice(&frame[1..]);
+            }
+            received.truncate(200);
+            received
+        };
+        let (sent, received) = tokio::join!(channel.send(&data), peer);
+        sent.unwrap();
+        assert_eq!(received, data);
+    }
+
+    #[tokio::test]
+    async fn flow_control_wait_and_overflow() {
+        let (mut channel, peer) = channel().await;
+        let data = payload(10);
+        let waiting = async {
+            peer.written().await;
+            peer.reply(&[0x31, 0, 0]);
+            peer.reply(&[0x30, 0, 0]);
+            peer.written().await
+        };
+        let (sent, last) = tokio::join!(channel.send(&data), waiting);
+        sent.unwrap();
+        assert_eq!(last, [0x21, 6, 7, 8, 9, PADDING, PADDING, PADDING]);
+
+        let overflow = async {
+            peer.written().await;
+            peer.reply(&[0x32, 0, 0]);
+        };
+        let (sent, _) = tokio::join!(channel.send(&data), overflow);
+        assert_eq!(sent, Err("receiver overflow"));
+    }
+
+    #[tokio::test]
+    async fn reassembled_after_flow_control() {
+        let (channel, mut peer) = channel().await;
+        let mut reception = None;
+        channel.receive(&mut reception, &[0x02, 0x50, 0x03, 0, 0, 0, 0, 0]).await;
+        let Some(Event::Received { data, .. }) = peer.delivered.recv().await else { panic!("not delivered") };
+        assert_eq!(data, [0x50, 0x03]);
+
+        // 6 bytes of the first frame and 17 consecutive frames, the last one of a single byte
+        let data = payload(6 + 16 * 7 + 1);
+        channel.receive(&mut reception, &[&[0x10, data.len() as u8][..], &data[..6]].concat()).await;
+        assert_eq!(peer.written().await, [0x30, 0, 0, PADDING, PADDING, PADDING, PADDING, PADDING]);
+        for (n, chunk) in data[6..].chunks(7).enumerate() {
+            let pci = 0x20 | ((n + 1) % 16) as u8;
+            channel.receive(&mut reception, &[&[pci][..], chunk].concat()).await;
+        }
+        assert!(reception.is_none());
+        let Some(Event::Received { data: received, .. }) = peer.delivered.recv().await else { panic!("not delivered") };
+        assert_eq!(received, data);
+    }
+
+    #[tokio::test]
+    async fn wrong_sequence_number_fails() {
+        let (channel, mut peer) = channel().await;
+        let mut reception = None;
+        channel.receive(&mut reception, &[0x10, 20, 0, 1, 2, 3, 4, 5]).await;
+        peer.written().await;
+        channel.receive(&mut reception, &[0x22, 6, 7, 8, 9, 10, 11, 12]).await;
+        assert!(reception.is_none());
+        let Some(Event::Failed { error, .. }) = peer.delivered.recv().await else { panic!("not failed") };
+        assert_eq!(error, "wrong sequence number");
+    }
+}
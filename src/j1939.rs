@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use crate::can::{self, CanEvent, Subscription};
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Id};
+
+// parameter groups of the transport protocol, see J1939-21
+const PGN_TP_CM: u32 = 0xEC00;
+const PGN_TP_DT: u32 = 0xEB00;
+// control bytes of TP.CM
+const TP_CM_BAM: u8 = 32;
+const TP_CM_ABORT: u8 = 255;
+// max time between the packets of a transfer (T1)
+const TP_TIMEOUT: Duration = Duration::from_millis(750);
+// max size of a transfer, 255 packets of 7 bytes
+const TP_MAX_SIZE: usize = 1785;
+
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+
+/// Header of a J1939 frame, by the fields of the 29-bit identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub priority: u8,
+    pub pgn: u32,
+    pub source: u8,
+    // destination of PDU1 format, the global address for PDU2 format
+    pub destination: u8,
+}
+
+impl Header {
+    pub fn from_id(id: u32) -> Header {
+        let priority = ((id >> 26) & 0x7) as u8;
+        // extended data page and data page
+        let page = (id >> 24) & 0x3;
+        let format = (id >> 16) & 0xFF;
+        let specific = (id >> 8) & 0xFF;
+        let source = (id & 0xFF) as u8;
+        if format < 240 {
+            // PDU1, the PDU specific field is the destination address
+            Header { priority, pgn: (page << 16) | (format << 8), source, destination: specific as u8 }
+        } else {
+            // PDU2, the PDU specific field is the group extension
+            Header { priority, pgn: (page << 16) | (format << 8) | specific, source, destination: GLOBAL_ADDRESS }
+        }
+    }
+}
+
+// DTO - parameter group of a single frame or reassembled from a BAM transfer, data as hex string
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct ParameterGroup {
+    pub interface: String,
+    pub pgn: u32,
+    pub priority: u8,
+    pub source: u8,
+    pub destination: u8,
+    pub data: String,
+    // whether reassembled from a transfer of the transport protocol
+    pub transport: bool,
+}
+
+// transfer announced by TP.CM BAM, the packets of TP.DT following in order
+struct Transfer {
+    pgn: u32,
+    priority: u8,
+    size: usize,
+    packets: u8,
+    data: Vec<u8>,
+    last: Instant,
+}
+
+/// Decoder of the J1939 parameter groups of 29-bit frames, reassembling BAM transfers per
+/// interface and source address
+///
+/// Connection mode transfers (RTS/CTS) are not reassembled, only their TP frames are reported.
+#[derive(Default)]
+pub struct Decoder {
+    transfers: HashMap<(Arc<str>, u8), Transfer>,
+}
+
+impl Decoder {
+    /// The parameter group of the frame, if complete
+    pub fn decode(&mut self, interface: &Arc<str>, frame: &CanAnyFrame) -> Option<ParameterGroup> {
+        let (id, data) = match frame {
+            CanAnyFrame::Normal(frame) => (frame.id(), frame.data()),
+            CanAnyFrame::Fd(frame) => (frame.id(), frame.data()),
+            _ => return None,
+        };
+        let Id::Extended(id) = id else { return None };
+        let header = Header::from_id(id.as_raw());
+        let group = |pgn, priority, data: &[u8], transport| ParameterGroup {
+            interface: interface.to_string(),
+            pgn,
+            priority,
+            source: header.source,
+            destination: header.destination,
+            data: hex::encode_upper(data),
+            transport,
+        };
+
+        let key = (interface.clone(), header.source);
+        match header.pgn {
+            PGN_TP_CM if header.destination == GLOBAL_ADDRESS && data.first() == Some(&TP_CM_BAM) && data.len() == 8 => {
+                let size = u16::from_le_bytes([data[1], data[2]]) as usize;
+                let pgn = u32::from_le_bytes([data[5], data[6], data[7], 0]);
+                if size <= TP_MAX_SIZE && data[3] > 0 {
+                    let transfer = Transfer {
+                        pgn,
+                        priority: header.priority,
+                        size,
+                        packets: data[3],
+                        data: Vec::with_capacity(size),
+                        last: Instant::now(),
+                    };
+                    self.transfers.insert(key, transfer);
+                }
+                None
+            }
+            PGN_TP_CM if data.first() == Some(&TP_CM_ABORT) => {
+                self.transfers.remove(&key);
+                None
+            }
+            PGN_TP_DT if header.destination == GLOBAL_ADDRESS => {
+                let transfer = self.transfers.get_mut(&key)?;
+                let sequence = *data.first()?;
+                // packets are numbered from 1, a missing packet or timeout drops the transfer
+                let expected = (transfer.data.len() / 7 + 1) as u8;
+                if sequence != expected || transfer.last.elapsed() > TP_TIMEOUT {
+                    self.transfers.remove(&key);
+                    return None;
+                }
+                transfer.data.extend_from_slice(&data[1..]);
+                transfer.last = Instant::now();
+                if sequence < transfer.packets {
+                    return None;
+                }
+                let mut transfer = self.transfers.remove(&key)?;
+                transfer.data.truncate(transfer.size);
+                Some(group(transfer.pgn, transfer.priority, &transfer.data, true))
+            }
+            // all others, including the frames of connection mode transfers
+            pgn => Some(group(pgn, header.priority, data, false)),
+        }
+    }
+}
+
+/// Decode the parameter groups of all received frames, publishing them to all sessions until shutdown
+pub async fn decoder(events: broadcast::Sender<CanEvent>, shutdown: CancellationToken) {
+    let mut decoder = Decoder::default();
+    let mut received = Subscription::new(events.subscribe(), shutdown, "J1939 decoder");
+    while let Some((interface, frame, _, _)) = received.next_frame().await {
+        if let Some(group) = decoder.decode(&interface, &frame) {
+            can::publish(&events, CanEvent::J1939(Arc::new(group)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{CanDataFrame, ExtendedId};
+
+    fn frame(priority: u32, pgn: u32, destination: u8, source: u8, data: &[u8]) -> CanAnyFrame {
+        let specific = if pgn & 0xFF00 < 0xF000 { destination as u32 } else { pgn & 0xFF };
+        let id = priority << 26 | (pgn & 0x3FF00) << 8 | specific << 8 | source as u32;
+        CanAnyFrame::Normal(CanDataFrame::new(ExtendedId::new(id).unwrap(), data).unwrap())
+    }
+
+    // announcement and packets of a BAM transfer of the payload by the source
+    fn bam(pgn: u32, source: u8, payload: &[u8]) -> Vec<CanAnyFrame> {
+        let size = (payload.len() as u16).to_le_bytes();
+        let packets = payload.len().div_ceil(7) as u8;
+        let pgn = pgn.to_le_bytes();
+        let announce = [TP_CM_BAM, size[0], size[1], packets, 0xFF, pgn[0], pgn[1], pgn[2]];
+        let mut frames = vec![frame(7, PGN_TP_CM, GLOBAL_ADDRESS, source, &announce)];
+        for (i, chunk) in payload.chunks(7).enumerate() {
+            let mut data = [0xFF; 8];
+            data[0] = (i + 1) as u8;
+            data[1..=chunk.len()].copy_from_slice(chunk);
+            frames.push(frame(7, PGN_TP_DT, GLOBAL_ADDRESS, source, &data));
+        }
+        frames
+    }
+
+    fn decode_all(decoder: &mut Decoder, frames: &[CanAnyFrame]) -> Vec<ParameterGroup> {
+        let interface: Arc<str> = "can0".into();
+        frames.iter().filter_map(|frame| decoder.decode(&interface, frame)).collect()
+    }
+
+    #[test]
+    fn header_of_pdu_formats() {
+        // PDU1, request to 0x21 from 0xF9
+        assert_eq!(Header::from_id(0x18EA21F9), Header { priority: 6, pgn: 0xEA00, source: 0xF9, destination: 0x21 });
+        // PDU2, EEC1 of engine 0x00, and of the data page
+        assert_eq!(Header::from_id(0x0CF00400), Header { priority: 3, pgn: 0xF004, source: 0, destination: GLOBAL_ADDRESS });
+        assert_eq!(Header::from_id(0x19FEF100).pgn, 0x1FEF1);
+    }
+
+    #[test]
+    fn bam_reassembled() {
+        let mut decoder = Decoder::default();
+        let payload: Vec<u8> = (0..18).collect();
+        let groups = decode_all(&mut decoder, &bam(0xFECA, 0x00, &payload));
+        assert_eq!(groups.len(), 1);
+        let group = &groups[0];
+        assert_eq!((group.pgn, group.priority, group.source, group.transport), (0xFECA, 7, 0x00, true));
+        assert_eq!(group.data, hex::encode_upper(&payload));
+
+        // of the largest size, the padding of the last packet dropped
+        let payload = vec![0x5A; TP_MAX_SIZE];
+        let groups = decode_all(&mut decoder, &bam(0xFEEC, 0x03, &payload));
+        assert_eq!(groups[0].data.len(), 2 * TP_MAX_SIZE);
+        assert!(decode_all(&mut decoder, &bam(0xFEEC, 0x03, &vec![0x5A; TP_MAX_SIZE + 1])).is_empty());
+        assert!(decoder.transfers.is_empty());
+    }
+
+    #[test]
+    fn bam_of_sources_interleaved() {
+        let mut decoder = Decoder::default();
+        let (a, b) = (bam(0xFECA, 0x00, &[1; 10]), bam(0xFECA, 0x17, &[2; 20]));
+        let mut frames = Vec::new();
+        for (i, frame) in b.iter().enumerate() {
+            frames.extend(a.get(i).copied());
+            frames.push(*frame);
+        }
+        let groups = decode_all(&mut decoder, &frames);
+        let sources: Vec<_> = groups.iter().map(|group| (group.source, group.data.len() / 2)).collect();
+        assert_eq!(sources, [(0x00, 10), (0x17, 20)]);
+    }
+
+    #[test]
+    fn bam_dropped_on_missing_packet_or_abort() {
+        let mut decoder = Decoder::default();
+        let mut frames = bam(0xFECA, 0x00, &[1; 20]);
+        frames.remove(2);
+        assert!(decode_all(&mut decoder, &frames).is_empty());
+        assert!(decoder.transfers.is_empty());
+
+        let mut frames = bam(0xFECA, 0x00, &[1; 20]);
+        frames.insert(2, frame(7, PGN_TP_CM, GLOBAL_ADDRESS, 0x00, &[TP_CM_ABORT, 0xFF, 0xFF, 0xFF, 0xFF, 0xCA, 0xFE, 0]));
+        assert!(decode_all(&mut decoder, &frames).is_empty());
+    }
+
+    #[test]
+    fn single_frames_and_connection_mode_reported() {
+        let mut decoder = Decoder::default();
+        let frames = [
+            frame(3, 0xF004, GLOBAL_ADDRESS, 0x00, &[0xF0, 0x7D, 0x7D, 0x00, 0x1A, 0xFF, 0xFF, 0xFF]),
+            // RTS to 0x21, not reassembled
+            frame(7, PGN_TP_CM, 0x21, 0xF9, &[16, 20, 0, 3, 0xFF, 0xCA, 0xFE, 0]),
+            CanAnyFrame::Normal(CanDataFrame::new(crate::frame::StandardId::new(0x123).unwrap(), &[1]).unwrap()),
+        ];
+        let groups = decode_all(&mut decoder, &frames);
+        let pgns: Vec<_> = groups.iter().map(|group| (group.pgn, group.destination, group.transport)).collect();
+        assert_eq!(pgns, [(0xF004, GLOBAL_ADDRESS, false), (PGN_TP_CM, 0x21, false)]);
+        assert_eq!(groups[0].data, "F07D7D001AFFFFFF");
+    }
+}
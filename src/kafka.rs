@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+
+use crate::can::Subscription;
+use crate::frame::{CanAnyFrame, EmbeddedFrame};
+use crate::protocol::format_id;
+use crate::server::AppState;
+use crate::ws::frame_data;
+
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Producer options of `--kafka`, e.g. `brokers=host1:9092,host2:9092,topic=can-frames`
+///
+/// Values may contain commas, an item without `=` continuing the value of the previous key.
+/// Keys other than `brokers` and `topic` are passed to librdkafka, e.g. `compression.type=lz4`.
+pub struct Options {
+    topic: String,
+    producer: FutureProducer,
+}
+
+/// Options of the spec, with the producer created of the librdkafka properties; connecting the
+/// brokers in the background
+pub fn options(spec: &str) -> Result<Options, String> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for item in spec.split(',') {
+        match (item.split_once('='), pairs.last_mut()) {
+            (Some((key, value)), _) => pairs.push((key.trim().to_string(), value.to_string())),
+            (None, Some((_, value))) => {
+                value.push(',');
+                value.push_str(item);
+            }
+            (None, None) => return Err(format!("invalid Kafka options {}, expecting brokers=...,topic=...", spec)),
+        }
+    }
+    let mut topic = None;
+    let mut config = ClientConfig::new();
+    // frames not delivered within the timeout are dropped, not queued while the brokers are down
+    config.set("message.timeout.ms", "5000");
+    for (key, value) in pairs {
+        match key.as_str() {
+            "topic" => topic = Some(value),
+            "brokers" => {
+                config.set("bootstrap.servers", value);
+            }
+            _ => {
+                config.set(key, value);
+            }
+        }
+    }
+    if config.get("bootstrap.servers").is_none() {
+        return Err(format!("missing brokers of Kafka options {}", spec));
+    }
+    let topic = topic.ok_or_else(|| format!("missing topic of Kafka options {}", spec))?;
+    let producer = config.create().map_err(|e| format!("invalid Kafka options {}: {}", spec, e))?;
+    Ok(Options { topic, producer })
+}
+
+/// Producer task, mirroring every received frame as JSON record of the `frame` message of the
+/// websocket, keyed by `<iface>/<id>` so the frames of an id keep their order in a partition
+///
+/// Frames are dropped while the queue of the producer is full, e.g. the brokers being unreachable.
+/// The queued records are flushed on shutdown.
+pub async fn producer(state: AppState, options: Options) {
+    let Options { topic, producer } = options;
+    let mut events = Subscription::new(state.events.subscribe(), state.shutdown.clone(), "Kafka producer");
+    let mut dropped: u64 = 0;
+
+    while let Some((interface, frame, timestamp, direction)) = events.next_frame().await {
+        if matches!(frame, CanAnyFrame::Error(_)) {
+            continue;
+        }
+        let key = format!("{}/{}", interface, format_id(frame.id()));
+        let Ok(payload) = serde_json::to_vec(&frame_data(&state, &interface, &frame, timestamp, direction)) else {
+            continue;
+        };
+        let record = FutureRecord::to(&topic).key(&key).payload(&payload);
+        // enqueued without waiting for the delivery, reported by librdkafka on failure
+        match producer.send_result(record) {
+            Ok(_) => (),
+            Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => {
+                dropped += 1;
+                if dropped.is_power_of_two() {
+                    tracing::warn!(dropped, "Kafka queue full, dropping frames");
+                }
+            }
+            Err((e, _)) => tracing::warn!(error = %e, "failed to produce Kafka record"),
+        }
+    }
+
+    let result = tokio::task::spawn_blocking(move || producer.flush(FLUSH_TIMEOUT)).await;
+    if let Ok(Err(e)) = result {
+        tracing::warn!(error = %e, "failed to flush Kafka records");
+    }
+}
@@ -0,0 +1,291 @@
+//! CAN-to-WebSocket bridge, serving a web-page to monitor and write CAN frames from a browser
+//!
+//! The bridge may be embedded in other applications by the [server::Server] builder, eg
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), String> {
+//! use clap::Parser;
+//! use rust_vue::{config::Config, server::Server};
+//!
+//! let config = Config::parse_from(["rust-vue", "--can-dev", "vcan0", "--port", "8080"]);
+//! let server = Server::builder().config(config).build()?;
+//! server.run().await
+//! # }
+//! ```
+//!
+//! Proprietary payloads may be decoded by a [codec::FrameDecoder] registered for their CAN id,
+//! the decoded JSON being forwarded with each frame as `custom`
+//!
+//! ```no_run
+//! # fn build() -> Result<rust_vue::server::Server, String> {
+//! use rust_vue::server::Server;
+//! use rust_vue::frame::{CanAnyFrame, EmbeddedFrame, StandardId};
+//!
+//! let id = StandardId::new(0x321).unwrap();
+//! Server::builder()
+//!     .decoder(id, |frame: &CanAnyFrame| Some(serde_json::json!({"level": frame.data().first()?})))
+//!     .build()
+//! # }
+//! ```
+
+pub mod can;
+pub mod codec;
+pub mod config;
+pub mod frame;
+pub mod protocol;
+pub mod server;
+
+mod alerts;
+mod api;
+mod assets;
+mod audit;
+mod auth;
+mod buffer;
+mod busstate;
+mod cannelloni;
+mod capture;
+mod canopen;
+mod clients;
+mod cyclic;
+mod decode;
+mod diag;
+mod filter;
+mod gateway;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "gs_usb")]
+mod gs_usb;
+mod health;
+mod history;
+mod influx;
+mod isotp;
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(feature = "j1939")]
+mod j1939;
+mod limit;
+mod lin;
+mod logformats;
+mod mdns;
+mod mqtt;
+mod names;
+mod netlink;
+#[cfg(feature = "nmea2000")]
+mod nmea2000;
+mod obd;
+mod openapi;
+mod outbox;
+mod overview;
+mod pcap;
+mod presets;
+mod profile;
+mod record;
+mod reload;
+mod replay;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod sequence;
+mod setup;
+mod simulate;
+mod slcan;
+mod sse;
+mod stats;
+mod supervisor;
+mod timeouts;
+#[cfg(feature = "systemd")]
+mod systemd;
+mod transport;
+mod version;
+mod webhook;
+mod ws;
+
+#[cfg_attr(doc, aquamarine::aquamarine)]
+/// WebBased Client Server Communication
+///
+/// Author: Frank Rehberger
+///
+/// Repo: https://github.com/frehberg/rust-vue-demo
+///
+/// ```mermaid
+/// graph LR
+///      u[[WebUI]] --> s[[HTTP Service]]
+///      s <-- read-write --> c[[CAN Device]]
+///      u <-. websocket .-> s
+///
+///      subgraph browser[Browser]
+///         u
+///      end
+///
+///      subgraph rustc[Web Service]
+///      s -. read .-> db([Embedded Assets webui/dist])
+///      end
+/// ```
+mod slide1 {}
+
+#[cfg_attr(doc, aquamarine::aquamarine)]
+/// Components
+///
+/// Repo: https://github.com/frehberg/rust-vue-demo
+///
+/// MessageMonitor displaying  CANBUS frames asynchronously in WebUI
+/// * Axum: Tokio web application framework
+/// * VueJS: JavaScript framework for building user interfaces
+///
+/// ```mermaid
+/// graph BT
+///   SocketCan --> Rust((Rust Backend App))
+///   RustDoc[[RustDoc]] --> Rust
+///   RustEmbed[RustEmbed] --> Rust
+///   Axum[Axum] --> Rust
+///   WebSocket -.- Axum
+///   Aquamarine --> RustDoc
+///   MermaidJS -.- Aquamarine
+///   VueJS[VueJS/UI Framework] --> UI
+///   NPM[NPM Build Env] --> UI((Web User Interface))
+///   Element-Plus --> VueJS
+///   Rust --> MessageMonitor((Message Monitor App))
+///   UI --> MessageMonitor
+/// ```
+mod slide2 {}
+
+/// Project Files
+///
+/// Vue/Node.JS proect in directory webui/
+///
+/// npm artifacts will be stored at webui/dist
+///
+/// ```text
+/// ├── build.rs
+/// ├── Cargo.toml
+/// ├── LICENSE
+/// ├── package-lock.json
+/// ├── proto
+/// │ └── can.proto
+/// ├── README.md
+/// ├── src
+/// │ ├── alerts.rs
+/// │ ├── api.rs
+/// │ ├── assets.rs
+/// │ ├── audit.rs
+/// │ ├── auth.rs
+/// │ ├── buffer.rs
+/// │ ├── busstate.rs
+/// │ ├── can.rs
+/// │ ├── cannelloni.rs
+/// │ ├── capture.rs
+/// │ ├── canopen.rs
+/// │ ├── clients.rs
+/// │ ├── codec.rs
+/// │ ├── config.rs
+/// │ ├── cyclic.rs
+/// │ ├── decode.rs
+/// │ ├── diag.rs
+/// │ ├── filter.rs
+/// │ ├── frame
+/// │ │ ├── mod.rs
+/// │ │ └── portable.rs
+/// │ ├── gateway.rs
+/// │ ├── grpc.rs
+/// │ ├── gs_usb.rs
+/// │ ├── health.rs
+/// │ ├── history.rs
+/// │ ├── influx.rs
+/// │ ├── isotp.rs
+/// │ ├── j1939.rs
+/// │ ├── kafka.rs
+/// │ ├── lib.rs
+/// │ ├── limit.rs
+/// │ ├── lin.rs
+/// │ ├── logformats
+/// │ │ ├── asc.rs
+/// │ │ ├── blf.rs
+/// │ │ └── mod.rs
+/// │ ├── main.rs
+/// │ ├── mdns.rs
+/// │ ├── mqtt.rs
+/// │ ├── names.rs
+/// │ ├── netlink.rs
+/// │ ├── nmea2000.rs
+/// │ ├── obd.rs
+/// │ ├── openapi.rs
+/// │ ├── outbox.rs
+/// │ ├── overview.rs
+/// │ ├── pcap.rs
+/// │ ├── presets.rs
+/// │ ├── profile.rs
+/// │ ├── protocol.rs
+/// │ ├── record.rs
+/// │ ├── reload.rs
+/// │ ├── replay.rs
+/// │ ├── scripting.rs
+/// │ ├── sequence.rs
+/// │ ├── server.rs
+/// │ ├── setup.rs
+/// │ ├── simulate.rs
+/// │ ├── slcan.rs
+/// │ ├── sse.rs
+/// │ ├── stats.rs
+/// │ ├── supervisor.rs
+/// │ ├── systemd.rs
+/// │ ├── timeouts.rs
+/// │ ├── transport.rs
+/// │ ├── version.rs
+/// │ ├── webhook.rs
+/// │ └── ws.rs
+/// ├── systemd
+/// │ ├── rust-vue-demo.service
+/// │ └── rust-vue-demo.socket
+/// └── webui
+///     ├── index.html
+///     ├── package.json
+///     ├── package-lock.json
+///     ├── placeholder
+///     │ └── index.html
+///     ├── public
+///     │ ├── CNAME
+///     │ ├── element-plus-logo-small.svg
+///     │ └── favicon.svg
+///     ├── README.md
+///     ├── src
+///     │ ├── App.vue
+///     │ ├── assets
+///     │ │ └── logo.png
+///     │ ├── components
+///     │ │ ├── layouts
+///     │ │ │ └── BaseSide.vue
+///     │ │ └── MessageMonitor.vue
+///     │ ├── components.d.ts
+///     │ ├── composables
+///     │ │ ├── dark.ts
+///     │ │ └── index.ts
+///     │ ├── env.d.ts
+///     │ ├── main.ts
+///     │ └── styles
+///     │     ├── element
+///     │     │ ├── dark.scss
+///     │     │ └── index.scss
+///     │     └── index.scss
+///     ├── tsconfig.json
+///     └── vite.config.ts
+///```
+mod slide3 {}
+
+#[cfg_attr(doc, aquamarine::aquamarine)]
+/// Build Process
+///
+/// Repo: https://github.com/frehberg/rust-vue-demo
+///
+/// ```mermaid
+///  graph
+///     s([Rust Source]) --> m[[Rust macro processor]]
+///     v([Vue App]) --> b[[build-script invokes npm build]]
+///
+///     c[[Rust compiler/linker]]--> f([executable])
+///     subgraph rustc[Cargo Builder]
+///        b -. generate files .-> d([webui/dist])
+///        d -. include .-> m
+///        m -->  i([Rust intermediate code])
+///        i --> c
+///     end
+/// ```
+mod slide4 {}
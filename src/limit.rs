@@ -0,0 +1,90 @@
+use std::str::FromStr;
+use std::time::Instant;
+
+use axum::{
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::api::api_error;
+use crate::server::AppState;
+
+/// Rate of frames, parsed of `<count>/s` or `<count>/min`, a plain count is per second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub per_sec: f64,
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, unit) = s.trim().split_once('/').unwrap_or((s.trim(), "s"));
+        let count: f64 = count.trim().parse().map_err(|_| format!("invalid rate {}", s))?;
+        let secs = match unit.trim() {
+            "s" | "sec" => 1.0,
+            "m" | "min" => 60.0,
+            _ => return Err(format!("invalid unit of rate {}, expecting /s or /min", s)),
+        };
+        if !(count.is_finite() && count > 0.0) {
+            return Err(format!("rate {} must be positive", s));
+        }
+        Ok(Rate { per_sec: count / secs })
+    }
+}
+
+impl std::fmt::Display for Rate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/s", self.per_sec)
+    }
+}
+
+/// Token bucket, permitting bursts of up to one second's worth of frames
+pub struct TokenBucket {
+    rate: Rate,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: Rate) -> TokenBucket {
+        TokenBucket { rate, tokens: Self::capacity(rate), updated: Instant::now() }
+    }
+
+    fn capacity(rate: Rate) -> f64 {
+        rate.per_sec.max(1.0)
+    }
+
+    /// Take a token if available, refilling the bucket by the time passed
+    pub fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.updated).as_secs_f64() * self.rate.per_sec;
+        self.tokens = (self.tokens + refill).min(Self::capacity(self.rate));
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Middleware rejecting requests of a body declared exceeding `--max-body-size` with 413; bodies
+/// of no length declared are bounded while read by the extractors, see [DefaultBodyLimit]
+///
+/// [DefaultBodyLimit]: axum::extract::DefaultBodyLimit
+pub async fn limit_body<B>(req: Request<B>, next: Next<B>) -> Response {
+    let Some(max) = req.extensions().get::<AppState>().map(|state| state.config.max_body_size) else {
+        return next.run(req).await;
+    };
+    let length = req.headers().get(header::CONTENT_LENGTH).and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+    match length {
+        Some(length) if length > max as u64 => {
+            let error = format!("request body of {} bytes exceeding the limit of {} bytes", length, max);
+            api_error(StatusCode::PAYLOAD_TOO_LARGE, &error).into_response()
+        }
+        _ => next.run(req).await,
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::can::{Direction, Timestamp};
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Id};
+use crate::protocol::{FrameDirection, FrameTimestamp};
+
+// ids of the LIN frames, of 6 bits
+pub const MAX_ID: u16 = 0x3F;
+// diagnostic frames of the master request and the slave response, of the classic checksum
+const MASTER_REQUEST: u8 = 0x3C;
+const SLAVE_RESPONSE: u8 = 0x3D;
+
+// DTO - frame of a LIN bus, of a `sllin` interface, data as hex string; `header` if the header of
+// the master went without response
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct LinMessage {
+    pub interface: Arc<str>,
+    pub id: u8,
+    // protected id, the id with its parity bits as sent on the bus
+    pub pid: u8,
+    pub data: String,
+    pub header: bool,
+    // checksum of LIN 2.x, the enhanced checksum of the protected id and the data but for the
+    // diagnostic frames
+    pub checksum: u8,
+    pub timestamp: FrameTimestamp,
+    #[serde(default)]
+    pub direction: FrameDirection,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<u64>,
+}
+
+/// Id of the frame if a LIN frame, a standard frame of an id up to 0x3F; sllin configures its
+/// frame cache by extended frames and has no CAN FD
+pub fn lin_id(frame: &CanAnyFrame) -> Option<u8> {
+    match (frame, frame.id()) {
+        (CanAnyFrame::Normal(_) | CanAnyFrame::Remote(_), Id::Standard(id)) if id.as_raw() <= MAX_ID => Some(id.as_raw() as u8),
+        _ => None,
+    }
+}
+
+/// Whether the frame can be written to a LIN interface, a LIN frame or a control frame of sllin
+pub fn writable(frame: &CanAnyFrame) -> bool {
+    lin_id(frame).is_some() || (matches!(frame, CanAnyFrame::Normal(_) | CanAnyFrame::Remote(_)) && frame.is_extended())
+}
+
+/// Protected id, the id with the parity bits P0 = ID0 ^ ID1 ^ ID2 ^ ID4 and P1 = !(ID1 ^ ID3 ^ ID4 ^ ID5)
+pub fn protected_id(id: u8) -> u8 {
+    let bit = |n: u8| (id >> n) & 1;
+    let p0 = bit(0) ^ bit(1) ^ bit(2) ^ bit(4);
+    let p1 = !(bit(1) ^ bit(3) ^ bit(4) ^ bit(5)) & 1;
+    (id & 0x3F) | p0 << 6 | p1 << 7
+}
+
+/// Checksum of the data, the inverted sum with carry, of the protected id too if enhanced
+pub fn checksum(pid: Option<u8>, data: &[u8]) -> u8 {
+    let sum = pid.iter().chain(data).fold(0u16, |sum, byte| {
+        let sum = sum + *byte as u16;
+        if sum > 0xFF { sum - 0xFF } else { sum }
+    });
+    !(sum as u8)
+}
+
+/// Message of a frame of a LIN interface, `None` if not a LIN frame like the control frames of sllin
+pub fn lin_message(interface: &Arc<str>, frame: &CanAnyFrame, timestamp: Timestamp, direction: Direction) -> Option<LinMessage> {
+    let id = lin_id(frame)?;
+    let pid = protected_id(id);
+    let data = frame.data();
+    let enhanced = !matches!(id, MASTER_REQUEST | SLAVE_RESPONSE);
+    let (direction, client) = match direction {
+        Direction::Rx => (FrameDirection::Rx, None),
+        Direction::Tx(client) => (FrameDirection::Tx, client),
+    };
+    Some(LinMessage {
+        interface: interface.clone(),
+        id,
+        pid,
+        data: hex::encode_upper(data),
+        header: frame.is_remote_frame(),
+        checksum: checksum(enhanced.then_some(pid), data),
+        timestamp: timestamp.into(),
+        direction,
+        client,
+    })
+}
@@ -0,0 +1,224 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::{channel, frame, interface, DateTime, Payload};
+use crate::frame::{CanAnyFrame, EmbeddedFrame, FdFlags, Frame};
+use crate::replay::ReplayFrame;
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+// flags of CANFD events, EDL marking FD frames
+const FLAG_EDL: u32 = 0x1000;
+const FLAG_BRS: u32 = 0x2000;
+const FLAG_ESI: u32 = 0x4000;
+
+/// Writer of a Vector ASC log, in hex with timestamps relative to the start of the measurement
+///
+/// The channels are numbered from 1 in the order of the configured CAN interfaces.
+pub struct Writer {
+    names: Vec<Arc<str>>,
+    start: SystemTime,
+}
+
+impl Writer {
+    pub fn new(names: Vec<Arc<str>>, start: SystemTime) -> Writer {
+        Writer { names, start }
+    }
+
+    /// Header of the log, starting the trigger block of the measurement
+    pub fn header(&self) -> Vec<u8> {
+        let date = format_date(self.start);
+        format!("date {}\nbase hex  timestamps absolute\ninternal events logged\n\
+                 Begin Triggerblock {}\n   0.000000 Start of measurement\n", date, date)
+            .into_bytes()
+    }
+
+    pub fn frame(&self, interface: &str, frame: &CanAnyFrame, timestamp: SystemTime) -> Vec<u8> {
+        let time = timestamp.duration_since(self.start).unwrap_or_default().as_secs_f64();
+        let channel = channel(&self.names, interface);
+        let id = match frame.is_extended() {
+            true => format!("{:X}x", frame.raw_id()),
+            false => format!("{:X}", frame.raw_id()),
+        };
+        let data = frame.data().iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ");
+        let event = match frame {
+            CanAnyFrame::Error(_) => format!("{}  ErrorFrame", channel),
+            CanAnyFrame::Remote(_) => format!("{}  {:<15} Rx   r {:x}", channel, id, frame.dlc()),
+            CanAnyFrame::Normal(_) => format!("{}  {:<15} Rx   d {:x} {}", channel, id, frame.dlc(), data),
+            CanAnyFrame::Fd(fd) => {
+                let mut flags = FLAG_EDL;
+                if fd.is_brs() {
+                    flags |= FLAG_BRS;
+                }
+                if fd.is_esi() {
+                    flags |= FLAG_ESI;
+                }
+                format!("CANFD {:>3} Rx   {:>8}  {:>32} {} {} {:x} {:>2} {} {:>8} {:>4} {:>8X} {:>8X} {:>8X} {:>8X} {:>8X} {:>8X}",
+                        channel, id, "", u8::from(fd.is_brs()), u8::from(fd.is_esi()), fd.dlc(), fd.data().len(),
+                        data, 0, 0, flags, 0, 0, 0, 0, 0)
+            }
+        };
+        format!("{:>11.6} {}\n", time, event).into_bytes()
+    }
+
+    pub fn footer(&self) -> Vec<u8> {
+        b"End TriggerBlock\n".to_vec()
+    }
+}
+
+// e.g. `Wed Oct 14 07:30:41.510 am 2026`
+fn format_date(time: SystemTime) -> String {
+    let date = DateTime::from(time);
+    let (hour, meridiem) = match date.hour {
+        0 => (12, "am"),
+        hour @ 1..=11 => (hour, "am"),
+        12 => (12, "pm"),
+        hour => (hour - 12, "pm"),
+    };
+    format!("{} {} {:02} {:02}:{:02}:{:02}.{:03} {} {}",
+            WEEKDAYS[date.weekday as usize], MONTHS[date.month as usize - 1], date.day,
+            hour, date.minute, date.second, date.millis, meridiem, date.year)
+}
+
+/// Whether the log is an ASC log, by the `date` or `base` of its header
+pub fn is_asc(log: &str) -> bool {
+    const HEADER_LINES: usize = 10;
+    log.lines()
+        .take(HEADER_LINES)
+        .map(str::trim)
+        .any(|line| line.starts_with("date ") || line.starts_with("base hex") || line.starts_with("base dec"))
+}
+
+/// Parse the CAN and CAN FD frames of an ASC log, skipping other events like error frames or
+/// statistics; Err carries the number of the first malformed frame line
+///
+/// The channels are mapped to the configured CAN interfaces by their order.
+pub fn parse(log: &str, names: &[Arc<str>]) -> Result<Vec<ReplayFrame>, usize> {
+    let mut hex = true;
+    let mut relative = false;
+    let mut time = Duration::ZERO;
+    let mut first = None;
+    let mut frames = Vec::new();
+    for (n, line) in log.lines().enumerate() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["base", base, "timestamps", mode, ..] => {
+                hex = *base == "hex";
+                relative = *mode == "relative";
+                continue;
+            }
+            [] => continue,
+            [timestamp, ..] if timestamp.parse::<f64>().is_err() => continue,
+            _ => (),
+        }
+        let event = parse_event(&tokens, hex).map_err(|_| n + 1)?;
+        // relative to the previous event, of any kind
+        let timestamp = tokens[0].parse().ok().and_then(|t| Duration::try_from_secs_f64(t).ok()).ok_or(n + 1)?;
+        time = if relative { time + timestamp } else { timestamp };
+        let Some((channel, frame)) = event else { continue };
+        let first = *first.get_or_insert(time);
+        frames.push(ReplayFrame { offset: time.saturating_sub(first), interface: interface(names, channel), frame });
+    }
+    Ok(frames)
+}
+
+// channel and frame of the event line, None for other events
+fn parse_event(tokens: &[&str], hex: bool) -> Result<Option<(usize, CanAnyFrame)>, ()> {
+    let number = |s: &str| if hex { u32::from_str_radix(s, 16) } else { s.parse() }.or(Err(()));
+    let bytes = |s: &[&str]| s.iter().map(|byte| number(byte).and_then(|b| u8::try_from(b).or(Err(())))).collect::<Result<Vec<u8>, ()>>();
+    let id = |s: &str| match s.strip_suffix('x') {
+        Some(id) => number(id).map(|id| (id, true)),
+        None => number(s).map(|id| (id, false)),
+    };
+    match tokens {
+        // CANFD <channel> <dir> <id> [symbolic name] <brs> <esi> <dlc> <data length> <data> ...
+        [_, "CANFD", channel, "Rx" | "Tx", can_id, rest @ ..] => {
+            let rest = match rest {
+                [brs, esi, ..] if ["0", "1"].contains(brs) && ["0", "1"].contains(esi) => rest,
+                [_name, rest @ ..] => rest,
+                [] => return Err(()),
+            };
+            let [brs, esi, _dlc, len, data @ ..] = rest else { return Err(()) };
+            let len = len.parse::<usize>().or(Err(()))?;
+            let data = bytes(data.get(..len).ok_or(())?)?;
+            let mut flags = FdFlags::empty();
+            flags.set(FdFlags::BRS, *brs == "1");
+            flags.set(FdFlags::ESI, *esi == "1");
+            let (can_id, extended) = id(can_id)?;
+            let channel = channel.parse().or(Err(()))?;
+            Ok(Some((channel, frame(can_id, extended, Payload::Fd(&data, flags)).ok_or(())?)))
+        }
+        // <channel> <id> <dir> d <dlc> <data> ... or <channel> <id> <dir> r [dlc]
+        [_, channel, can_id, "Rx" | "Tx", kind @ ("d" | "r"), rest @ ..] => {
+            let (can_id, extended) = id(can_id)?;
+            let channel = channel.parse().or(Err(()))?;
+            let payload_dlc = rest.first().map(|dlc| usize::from_str_radix(dlc, 16).or(Err(()))).transpose()?;
+            let frame = match (*kind, payload_dlc) {
+                ("r", dlc) => frame(can_id, extended, Payload::Remote(dlc.unwrap_or(0))),
+                (_, Some(dlc)) => {
+                    let data = bytes(rest.get(1..1 + dlc.min(8)).ok_or(())?)?;
+                    frame(can_id, extended, Payload::Data(&data))
+                }
+                (_, None) => return Err(()),
+            };
+            Ok(Some((channel, frame.ok_or(())?)))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logformats::fixtures::{assert_same, frames};
+
+    #[test]
+    fn round_trip() {
+        let names: Vec<Arc<str>> = vec!["can0".into(), "can1".into()];
+        // Wed Oct 14 07:30:41.510 am 2026
+        let start = SystemTime::UNIX_EPOCH + Duration::from_millis(1_791_963_041_510);
+        let writer = Writer::new(names.clone(), start);
+        let mut log = writer.header();
+        for (i, frame) in frames().iter().enumerate() {
+            log.extend(writer.frame(&names[i % 2], frame, start + Duration::from_micros(i as u64 * 1500)));
+        }
+        log.extend(writer.footer());
+        let log = String::from_utf8(log).unwrap();
+        assert!(log.starts_with("date Wed Oct 14 07:30:41.510 am 2026\n"));
+        assert!(is_asc(&log));
+
+        let parsed = parse(&log, &names).unwrap();
+        assert_eq!(parsed.len(), 5);
+        for (i, replayed) in parsed.iter().enumerate() {
+            assert_eq!(replayed.offset, Duration::from_micros(i as u64 * 1500));
+            assert_eq!(replayed.interface, &*names[i % 2]);
+            assert_same(&replayed.frame, &frames()[i]);
+        }
+    }
+
+    #[test]
+    fn decimal_relative_and_symbolic_names() {
+        let log = "date Wed Oct 14 07:30:41.510 am 2026\n\
+                   base dec  timestamps relative\n\
+                   Begin Triggerblock\n\
+                      0.500000 1  2015  Rx   d 3 2 1 12\n\
+                      0.250000 CANFD   2 Tx   291  EngineData  1 0 9 12 1 2 3 4 5 6 7 8 9 10 11 12\n\
+                      0.250000 1  ErrorFrame\n\
+                   \n\
+                      0.250000 3  100x  Rx   r\n\
+                   End TriggerBlock\n";
+        let parsed = parse(log, &["can0".into()]).unwrap();
+        let offsets: Vec<_> = parsed.iter().map(|frame| frame.offset.as_millis()).collect();
+        assert_eq!(offsets, [0, 250, 750]);
+        let interfaces: Vec<_> = parsed.iter().map(|frame| frame.interface.as_str()).collect();
+        assert_eq!(interfaces, ["can0", "", ""]);
+        assert_same(&parsed[0].frame, &frames()[0]);
+        assert_eq!(parsed[1].frame.raw_id(), 291);
+        assert_eq!(parsed[1].frame.data(), (1..=12).collect::<Vec<u8>>());
+        assert!(matches!(parsed[1].frame, CanAnyFrame::Fd(fd) if fd.is_brs()));
+        assert!(parsed[2].frame.is_remote_frame() && parsed[2].frame.is_extended());
+
+        assert_eq!(parse("base hex  timestamps absolute\n   0.1 1  7DF  Rx   d 8 01\n", &[]).err(), Some(2));
+    }
+}
@@ -0,0 +1,433 @@
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use super::{channel, frame, interface, DateTime, Payload};
+use crate::frame::{CanAnyFrame, EmbeddedFrame, FdFlags, Frame};
+use crate::replay::ReplayFrame;
+
+// layout of the Vector binary log format, as read and written by python-can and CANoe
+const FILE_SIGNATURE: &[u8; 4] = b"LOGG";
+const FILE_HEADER_SIZE: usize = 144;
+const OBJECT_SIGNATURE: &[u8; 4] = b"LOBJ";
+// base header of 16 bytes and the header of version 1, of 16 bytes
+const OBJECT_HEADER_SIZE: usize = 32;
+const CONTAINER_HEADER_SIZE: usize = 32;
+// application id of the file header, other than the ids of Vector tools
+const APPLICATION_ID: u8 = 5;
+
+// object types
+const CAN_MESSAGE: u32 = 1;
+const LOG_CONTAINER: u32 = 10;
+const CAN_MESSAGE2: u32 = 86;
+const CAN_FD_MESSAGE: u32 = 100;
+const CAN_FD_MESSAGE_64: u32 = 101;
+
+// compression of log containers
+const NO_COMPRESSION: u16 = 0;
+const ZLIB_DEFLATE: u16 = 2;
+// uncompressed size of the containers written
+const CONTAINER_SIZE: usize = 128 * 1024;
+// bound of the objects parsed, uncompressed
+const MAX_OBJECTS_SIZE: usize = 64 * 1024 * 1024;
+
+// flags of the object header, by the unit of the timestamp
+const TIME_TEN_MICS: u32 = 0x1;
+const TIME_ONE_NANS: u32 = 0x2;
+
+const CAN_MSG_EXT: u32 = 0x8000_0000;
+const REMOTE_FLAG: u8 = 0x80;
+// flags of CAN_FD_MESSAGE
+const EDL: u8 = 0x1;
+const BRS: u8 = 0x2;
+const ESI: u8 = 0x4;
+// flags of CAN_FD_MESSAGE_64
+const FD64_REMOTE: u32 = 0x0010;
+const FD64_EDL: u32 = 0x1000;
+const FD64_BRS: u32 = 0x2000;
+const FD64_ESI: u32 = 0x4000;
+
+/// Writer of a Vector BLF log, the frames zlib compressed in log containers
+///
+/// The file header carries the count of objects and the size of the file, so it is rewritten
+/// at the end of the recording. The channels are numbered from 1 in the order of the
+/// configured CAN interfaces; error frames are not logged.
+pub struct Writer {
+    names: Vec<Arc<str>>,
+    start: SystemTime,
+    stop: SystemTime,
+    // objects not yet written in a container
+    buffer: Vec<u8>,
+    objects: u32,
+    file_size: u64,
+    uncompressed_size: u64,
+}
+
+impl Writer {
+    pub fn new(names: Vec<Arc<str>>, start: SystemTime) -> Writer {
+        Writer {
+            names,
+            start,
+            stop: start,
+            buffer: Vec::new(),
+            objects: 0,
+            file_size: FILE_HEADER_SIZE as u64,
+            uncompressed_size: FILE_HEADER_SIZE as u64,
+        }
+    }
+
+    /// File header of the objects written so far, to be written first and rewritten at the end
+    pub fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
+        header.extend_from_slice(FILE_SIGNATURE);
+        header.extend_from_slice(&(FILE_HEADER_SIZE as u32).to_le_bytes());
+        // application id and version, binary log version 2.6.8.1
+        header.extend_from_slice(&[APPLICATION_ID, 0, 0, 0, 2, 6, 8, 1]);
+        header.extend_from_slice(&self.file_size.to_le_bytes());
+        header.extend_from_slice(&self.uncompressed_size.to_le_bytes());
+        header.extend_from_slice(&self.objects.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        for time in [self.start, self.stop] {
+            let date = DateTime::from(time);
+            for field in [date.year, date.month, date.weekday, date.day, date.hour, date.minute, date.second, date.millis] {
+                header.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        header.resize(FILE_HEADER_SIZE, 0);
+        header
+    }
+
+    /// Add the frame, returning a log container once enough frames are buffered
+    pub fn frame(&mut self, interface: &str, frame: &CanAnyFrame, timestamp: SystemTime) -> Vec<u8> {
+        let channel = channel(&self.names, interface) as u16;
+        let mut id = frame.raw_id();
+        if frame.is_extended() {
+            id |= CAN_MSG_EXT;
+        }
+        let mut data = [0u8; 64];
+        data[..frame.data().len()].copy_from_slice(frame.data());
+        let (kind, object) = match frame {
+            CanAnyFrame::Error(_) => return Vec::new(),
+            CanAnyFrame::Fd(fd) => {
+                let mut fd_flags = EDL;
+                if fd.is_brs() {
+                    fd_flags |= BRS;
+                }
+                if fd.is_esi() {
+                    fd_flags |= ESI;
+                }
+                let mut object = Vec::with_capacity(84);
+                object.extend_from_slice(&channel.to_le_bytes());
+                object.extend_from_slice(&[0, fd.dlc() as u8]);
+                object.extend_from_slice(&id.to_le_bytes());
+                // frame length and bit count unknown
+                object.extend_from_slice(&[0, 0, 0, 0, 0, fd_flags, fd.data().len() as u8, 0, 0, 0, 0, 0]);
+                object.extend_from_slice(&data);
+                (CAN_FD_MESSAGE, object)
+            }
+            _ => {
+                let flags = if frame.is_remote_frame() { REMOTE_FLAG } else { 0 };
+                let mut object = Vec::with_capacity(16);
+                object.extend_from_slice(&channel.to_le_bytes());
+                object.extend_from_slice(&[flags, frame.dlc() as u8]);
+                object.extend_from_slice(&id.to_le_bytes());
+                object.extend_from_slice(&data[..8]);
+                (CAN_MESSAGE, object)
+            }
+        };
+
+        // timestamps in nanoseconds since the start
+        let nanos = timestamp.duration_since(self.start).unwrap_or_default().as_nanos() as u64;
+        self.buffer.extend_from_slice(OBJECT_SIGNATURE);
+        self.buffer.extend_from_slice(&(OBJECT_HEADER_SIZE as u16).to_le_bytes());
+        self.buffer.extend_from_slice(&1u16.to_le_bytes());
+        self.buffer.extend_from_slice(&((OBJECT_HEADER_SIZE + object.len()) as u32).to_le_bytes());
+        self.buffer.extend_from_slice(&kind.to_le_bytes());
+        self.buffer.extend_from_slice(&TIME_ONE_NANS.to_le_bytes());
+        self.buffer.extend_from_slice(&[0; 4]);
+        self.buffer.extend_from_slice(&nanos.to_le_bytes());
+        self.buffer.extend_from_slice(&object);
+        self.buffer.resize(self.buffer.len() + object.len() % 4, 0);
+        self.objects += 1;
+        self.stop = self.stop.max(timestamp);
+
+        if self.buffer.len() >= CONTAINER_SIZE {
+            self.flush()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Log container of the buffered frames, nothing if none is buffered
+    pub fn flush(&mut self) -> Vec<u8> {
+        if self.buffer.is_empty() {
+            return Vec::new();
+        }
+        let uncompressed = std::mem::take(&mut self.buffer);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let compressed = match encoder.write_all(&uncompressed).and_then(|_| encoder.finish()) {
+            Ok(compressed) => compressed,
+            Err(_) => return Vec::new(),
+        };
+
+        let size = CONTAINER_HEADER_SIZE + compressed.len();
+        let mut container = Vec::with_capacity(size + 4);
+        container.extend_from_slice(OBJECT_SIGNATURE);
+        container.extend_from_slice(&16u16.to_le_bytes());
+        container.extend_from_slice(&1u16.to_le_bytes());
+        container.extend_from_slice(&(size as u32).to_le_bytes());
+        container.extend_from_slice(&LOG_CONTAINER.to_le_bytes());
+        container.extend_from_slice(&ZLIB_DEFLATE.to_le_bytes());
+        container.extend_from_slice(&[0; 6]);
+        container.extend_from_slice(&(uncompressed.len() as u32).to_le_bytes());
+        container.extend_from_slice(&[0; 4]);
+        container.extend_from_slice(&compressed);
+        container.resize(container.len() + size % 4, 0);
+
+        self.file_size += container.len() as u64;
+        self.uncompressed_size += (CONTAINER_HEADER_SIZE + uncompressed.len()) as u64;
+        container
+    }
+}
+
+/// Whether the log is a BLF log, by its signature
+pub fn is_blf(log: &[u8]) -> bool {
+    log.starts_with(FILE_SIGNATURE)
+}
+
+const TRUNCATED: &str = "truncated log";
+
+fn u16_at(bytes: &[u8], at: usize) -> Result<u16, String> {
+    Ok(u16::from_le_bytes(bytes.get(at..at + 2).ok_or(TRUNCATED)?.try_into().unwrap()))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Result<u32, String> {
+    Ok(u32::from_le_bytes(bytes.get(at..at + 4).ok_or(TRUNCATED)?.try_into().unwrap()))
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> Result<u64, String> {
+    Ok(u64::from_le_bytes(bytes.get(at..at + 8).ok_or(TRUNCATED)?.try_into().unwrap()))
+}
+
+/// Parse the CAN and CAN FD frames of a BLF log, skipping other objects
+///
+/// The channels are mapped to the configured CAN interfaces by their order. Containers are
+/// decompressed up to the size given by their header, the objects of the log up to 64 MiB.
+pub fn parse(log: &[u8], names: &[Arc<str>]) -> Result<Vec<ReplayFrame>, String> {
+    let header_size = u32_at(log, 4)? as usize;
+    // the objects of all containers, as objects may span containers
+    let mut objects = Vec::new();
+    let mut pos = header_size;
+    while pos + 16 <= log.len() {
+        let (kind, size) = object_header(log, pos)?;
+        let object = log.get(pos..pos + size).ok_or(TRUNCATED)?;
+        if kind == LOG_CONTAINER {
+            let data = object.get(CONTAINER_HEADER_SIZE..).ok_or(TRUNCATED)?;
+            match u16_at(object, 16)? {
+                NO_COMPRESSION => objects.extend_from_slice(data),
+                ZLIB_DEFLATE => {
+                    let size = u32_at(object, 24)? as u64;
+                    if objects.len() as u64 + size > MAX_OBJECTS_SIZE as u64 {
+                        return Err(format!("log exceeds {} MiB uncompressed", MAX_OBJECTS_SIZE >> 20));
+                    }
+                    ZlibDecoder::new(data)
+                        .take(size)
+                        .read_to_end(&mut objects)
+                        .map_err(|e| format!("invalid log container at offset {}: {}", pos, e))?;
+                }
+                method => return Err(format!("unsupported compression {} at offset {}", method, pos)),
+            }
+        } else {
+            objects.extend_from_slice(object);
+            objects.resize(objects.len() + size % 4, 0);
+        }
+        pos += size + size % 4;
+    }
+
+    let mut frames = Vec::new();
+    let mut first = None;
+    let mut pos = 0;
+    while pos + 16 <= objects.len() {
+        let (kind, size) = object_header(&objects, pos)?;
+        let object = objects.get(pos..pos + size).ok_or(TRUNCATED)?;
+        if let Some((timestamp, channel, frame)) = parse_object(kind, object)? {
+            let first = *first.get_or_insert(timestamp);
+            frames.push(ReplayFrame { offset: timestamp.saturating_sub(first), interface: interface(names, channel), frame });
+        }
+        // objects of CAN_FD_MESSAGE_64 are not padded
+        pos += size + if kind == CAN_FD_MESSAGE_64 { 0 } else { size % 4 };
+    }
+    frames.sort_by_key(|frame| frame.offset);
+    Ok(frames)
+}
+
+// type and size of the object at the position
+fn object_header(bytes: &[u8], pos: usize) -> Result<(u32, usize), String> {
+    if bytes.get(pos..pos + 4) != Some(OBJECT_SIGNATURE) {
+        return Err(format!("invalid object at offset {}", pos));
+    }
+    let size = u32_at(bytes, pos + 8)? as usize;
+    if size < 16 {
+        return Err(format!("invalid object size at offset {}", pos));
+    }
+    Ok((u32_at(bytes, pos + 12)?, size))
+}
+
+// timestamp, channel and frame of the CAN objects
+fn parse_object(kind: u32, object: &[u8]) -> Result<Option<(Duration, usize, CanAnyFrame)>, String> {
+    if ![CAN_MESSAGE, CAN_MESSAGE2, CAN_FD_MESSAGE, CAN_FD_MESSAGE_64].contains(&kind) {
+        return Ok(None);
+    }
+    let header_size = u16_at(object, 4)? as usize;
+    // flags and timestamp at the same offsets of the headers of version 1 and 2
+    let units = u64_at(object, 24)?;
+    let timestamp = match u32_at(object, 16)? {
+        TIME_TEN_MICS => Duration::from_micros(units * 10),
+        // TIME_ONE_NANS
+        _ => Duration::from_nanos(units),
+    };
+    let body = object.get(header_size..).ok_or(TRUNCATED)?;
+    let malformed = || format!("malformed frame of object type {}", kind);
+
+    let (channel, id, payload) = match kind {
+        CAN_FD_MESSAGE_64 => {
+            let channel = *body.first().ok_or(TRUNCATED)? as usize;
+            let len = *body.get(2).ok_or(TRUNCATED)? as usize;
+            let id = u32_at(body, 4)?;
+            let flags = u32_at(body, 12)?;
+            let data = body.get(40..40 + len).ok_or(TRUNCATED)?;
+            let payload = if flags & FD64_EDL != 0 {
+                let mut fd = FdFlags::empty();
+                fd.set(FdFlags::BRS, flags & FD64_BRS != 0);
+                fd.set(FdFlags::ESI, flags & FD64_ESI != 0);
+                Payload::Fd(data, fd)
+            } else if flags & FD64_REMOTE != 0 {
+                Payload::Remote(*body.get(1).ok_or(TRUNCATED)? as usize)
+            } else {
+                Payload::Data(data)
+            };
+            (channel, id, payload)
+        }
+        CAN_FD_MESSAGE => {
+            let fd_flags = *body.get(13).ok_or(TRUNCATED)?;
+            let len = *body.get(14).ok_or(TRUNCATED)? as usize;
+            let data = body.get(20..20 + len).ok_or(TRUNCATED)?;
+            let payload = if fd_flags & EDL != 0 {
+                let mut fd = FdFlags::empty();
+                fd.set(FdFlags::BRS, fd_flags & BRS != 0);
+                fd.set(FdFlags::ESI, fd_flags & ESI != 0);
+                Payload::Fd(data, fd)
+            } else if body[2] & REMOTE_FLAG != 0 {
+                Payload::Remote(body[3] as usize)
+            } else {
+                Payload::Data(data)
+            };
+            (u16_at(body, 0)? as usize, u32_at(body, 4)?, payload)
+        }
+        _ => {
+            let flags = *body.get(2).ok_or(TRUNCATED)?;
+            let dlc = (*body.get(3).ok_or(TRUNCATED)? as usize).min(8);
+            let payload = if flags & REMOTE_FLAG != 0 {
+                Payload::Remote(dlc)
+            } else {
+                Payload::Data(body.get(8..8 + dlc).ok_or(TRUNCATED)?)
+            };
+            (u16_at(body, 0)? as usize, u32_at(body, 4)?, payload)
+        }
+    };
+    let frame = frame(id & !CAN_MSG_EXT, id & CAN_MSG_EXT != 0, payload).ok_or_else(malformed)?;
+    Ok(Some((timestamp, channel, frame)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::can_frame_default;
+    use crate::logformats::fixtures::{assert_same, frames};
+
+    // log of the frames at 1 ms each, alternately on the two interfaces
+    fn log(count: usize) -> (Vec<u8>, Vec<Arc<str>>) {
+        let names: Vec<Arc<str>> = vec!["can0".into(), "can1".into()];
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut writer = Writer::new(names.clone(), start);
+        let mut body = Vec::new();
+        for i in 0..count {
+            let frame = frames()[i % 5];
+            let timestamp = start + Duration::from_millis(i as u64);
+            body.extend(writer.frame(&names[i % 2], &frame, timestamp));
+        }
+        body.extend(writer.flush());
+        ([writer.header(), body].concat(), names)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (log, names) = log(5);
+        assert!(is_blf(&log));
+        let parsed = parse(&log, &names).unwrap();
+        assert_eq!(parsed.len(), 5);
+        for (i, replayed) in parsed.iter().enumerate() {
+            assert_eq!(replayed.offset, Duration::from_millis(i as u64));
+            assert_eq!(replayed.interface, &*names[i % 2]);
+            assert_same(&replayed.frame, &frames()[i]);
+        }
+    }
+
+    #[test]
+    fn round_trip_of_containers() {
+        // in several containers, the header counting the objects and their size
+        let (log, names) = log(10_000);
+        assert_eq!(u32_at(&log, 32).unwrap(), 10_000);
+        assert_eq!(u64_at(&log, 16).unwrap(), log.len() as u64);
+        let parsed = parse(&log, &names).unwrap();
+        assert_eq!(parsed.len(), 10_000);
+        assert_eq!(parsed[9_999].offset, Duration::from_millis(9_999));
+        assert_same(&parsed[9_999].frame, &frames()[4]);
+    }
+
+    #[test]
+    fn error_frames_not_logged() {
+        let mut writer = Writer::new(Vec::new(), SystemTime::UNIX_EPOCH);
+        let mut error = can_frame_default();
+        // bus-off, see linux/can/error.h
+        error.can_id = 0x2000_0040;
+        error.can_dlc = 8;
+        let error = CanAnyFrame::from(error);
+        assert!(matches!(error, CanAnyFrame::Error(_)));
+        assert!(writer.frame("can0", &error, SystemTime::UNIX_EPOCH).is_empty());
+        assert!(writer.flush().is_empty());
+    }
+
+    // log of a single container of the compressed objects, declaring the uncompressed size
+    fn container(objects: &[u8], declared: u32) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(objects).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let size = (CONTAINER_HEADER_SIZE + compressed.len()) as u32;
+        let header = Writer::new(Vec::new(), SystemTime::UNIX_EPOCH).header();
+        [
+            &header[..], OBJECT_SIGNATURE, &16u16.to_le_bytes(), &1u16.to_le_bytes(), &size.to_le_bytes(),
+            &LOG_CONTAINER.to_le_bytes(), &ZLIB_DEFLATE.to_le_bytes(), &[0; 6], &declared.to_le_bytes(), &[0; 4],
+            &compressed,
+        ].concat()
+    }
+
+    #[test]
+    fn decompressed_up_to_declared_size() {
+        // zeros, read only up to the size declared, and rejected declaring a size beyond the bound
+        let zeros = vec![0; 8 * 1024 * 1024];
+        let error = parse(&container(&zeros, 1024), &[]).err();
+        assert_eq!(error.as_deref(), Some("invalid object at offset 0"));
+        let error = parse(&container(&zeros, u32::MAX), &[]).err();
+        assert_eq!(error.as_deref(), Some("log exceeds 64 MiB uncompressed"));
+
+        // a declared size short of the objects truncating them
+        let (log, names) = log(2);
+        let objects_size = u32_at(&log, FILE_HEADER_SIZE + 24).unwrap();
+        let mut truncated = log.clone();
+        truncated[FILE_HEADER_SIZE + 24..FILE_HEADER_SIZE + 28].copy_from_slice(&(objects_size - 1).to_le_bytes());
+        assert_eq!(parse(&truncated, &names).err().as_deref(), Some(TRUNCATED));
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::frame::{
+    CanAnyFrame, CanDataFrame, CanFdFrame, CanRemoteFrame, EmbeddedFrame, ExtendedId, FdFlags, Id, StandardId,
+};
+
+pub mod asc;
+pub mod blf;
+
+// Vector logs number the channels from 1, in the order of the configured CAN interfaces
+fn channel(names: &[Arc<str>], interface: &str) -> usize {
+    names.iter().position(|name| &**name == interface).unwrap_or(0) + 1
+}
+
+// interface of the channel, empty for the default interface if not configured
+fn interface(names: &[Arc<str>], channel: usize) -> String {
+    channel.checked_sub(1).and_then(|i| names.get(i)).map(|name| name.to_string()).unwrap_or_default()
+}
+
+// payload of a logged frame
+enum Payload<'a> {
+    Data(&'a [u8]),
+    Remote(usize),
+    Fd(&'a [u8], FdFlags),
+}
+
+// frame of the logged fields, None if invalid
+fn frame(id: u32, extended: bool, payload: Payload) -> Option<CanAnyFrame> {
+    let id = if extended {
+        Id::Extended(ExtendedId::new(id)?)
+    } else {
+        Id::Standard(StandardId::new(u16::try_from(id).ok()?)?)
+    };
+    match payload {
+        Payload::Data(data) => CanDataFrame::new(id, data).map(CanAnyFrame::Normal),
+        Payload::Remote(dlc) => CanRemoteFrame::new_remote(id, dlc).map(CanAnyFrame::Remote),
+        Payload::Fd(data, flags) => CanFdFrame::with_flags(id, data, flags).map(CanAnyFrame::Fd),
+    }
+}
+
+/// Calendar date and time in UTC, of the headers of the log formats
+struct DateTime {
+    year: u16,
+    month: u16,
+    // days since Sunday
+    weekday: u16,
+    day: u16,
+    hour: u16,
+    minute: u16,
+    second: u16,
+    millis: u16,
+}
+
+impl From<SystemTime> for DateTime {
+    fn from(time: SystemTime) -> DateTime {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let days = (secs / 86_400) as i64;
+        // civil date of the days since epoch, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        DateTime {
+            year: year as u16,
+            month: month as u16,
+            // 1970-01-01 was a Thursday
+            weekday: ((days + 4) % 7) as u16,
+            day: (doy - (153 * mp + 2) / 5 + 1) as u16,
+            hour: (secs / 3600 % 24) as u16,
+            minute: (secs / 60 % 60) as u16,
+            second: (secs % 60) as u16,
+            millis: since_epoch.subsec_millis() as u16,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    /// Frames of each kind logged, of standard and extended ids, the FD frames of either flag
+    pub fn frames() -> Vec<CanAnyFrame> {
+        let standard = StandardId::new(0x7DF).unwrap();
+        let extended = ExtendedId::new(0x18DAF110).unwrap();
+        vec![
+            CanAnyFrame::Normal(CanDataFrame::new(standard, &[0x02, 0x01, 0x0C]).unwrap()),
+            CanAnyFrame::Normal(CanDataFrame::new(extended, &[0xFF; 8]).unwrap()),
+            CanAnyFrame::Remote(CanRemoteFrame::new_remote(standard, 3).unwrap()),
+            CanAnyFrame::Fd(CanFdFrame::with_flags(extended, &[0xA5; 12], FdFlags::BRS).unwrap()),
+            CanAnyFrame::Fd(CanFdFrame::with_flags(standard, &[1; 64], FdFlags::ESI).unwrap()),
+        ]
+    }
+
+    /// Assert the frame parsed is the one logged, of the same kind and FD flags
+    pub fn assert_same(parsed: &CanAnyFrame, logged: &CanAnyFrame) {
+        let fd_flags = |frame: &CanAnyFrame| match frame {
+            CanAnyFrame::Fd(fd) => Some((fd.is_brs(), fd.is_esi())),
+            _ => None,
+        };
+        assert!(crate::can::same_frame(parsed, logged), "parsed {:?}, logged {:?}", parsed, logged);
+        assert_eq!(fd_flags(parsed), fd_flags(logged));
+    }
+}
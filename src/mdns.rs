@@ -0,0 +1,387 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use local_ip_address::list_afinet_netifas;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+
+use crate::config::Config;
+use crate::server::AppState;
+
+// type of the service announced, see RFC 6763
+const SERVICE: [&str; 3] = ["_can-monitor", "_tcp", "local"];
+// browsing for the types of services of the link, e.g. by `avahi-browse -a`
+const SERVICES: [&str; 4] = ["_services", "_dns-sd", "_udp", "local"];
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+// record types and the class of the internet, its top bit flushing the caches of unique records
+const A: u16 = 1;
+const PTR: u16 = 12;
+const TXT: u16 = 16;
+const AAAA: u16 = 28;
+const SRV: u16 = 33;
+const ANY: u16 = 255;
+const IN: u16 = 1;
+const CACHE_FLUSH: u16 = 0x8000;
+// response of an authoritative answer
+const FLAGS: u16 = 0x8400;
+// TTLs recommended by RFC 6762, capped for legacy resolvers querying of another port
+const HOST_TTL: u32 = 120;
+const TTL: u32 = 4500;
+const LEGACY_TTL: u32 = 10;
+// announcements on startup, a second apart
+const ANNOUNCEMENTS: usize = 2;
+// bound of the labels and compression pointers of a name, against loops of malformed packets
+const MAX_LABELS: usize = 128;
+
+type Name = Vec<String>;
+
+// resource record of a response
+#[derive(Clone, PartialEq)]
+struct Record {
+    name: Name,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    data: Vec<u8>,
+}
+
+/// Service announced by mDNS, the instance of `_can-monitor._tcp.local` at the port of the web UI
+pub struct Service {
+    instance: Name,
+    host: Name,
+    port: u16,
+    txt: Vec<String>,
+    // addresses of `--bind` at the port, the interfaces of the host if unspecified
+    binds: Vec<SocketAddr>,
+}
+
+impl Service {
+    /// Service of `--mdns-name`, else named by the host, listening at the port
+    pub fn new(config: &Config, port: u16) -> Service {
+        let host = host_name();
+        let name = config.mdns_name.clone().unwrap_or_else(|| host.clone());
+        Service {
+            instance: std::iter::once(name).chain(SERVICE.map(String::from)).collect(),
+            host: vec![host, "local".to_string()],
+            port,
+            txt: vec!["path=/".to_string(), format!("scheme={}", config.scheme()), format!("version={}", env!("CARGO_PKG_VERSION"))],
+            binds: config.listen_addrs().into_iter().filter(|addr| addr.port() == port).collect(),
+        }
+    }
+
+    // the service of its type, pointing to the instance
+    fn pointer(&self, ttl: u32) -> Record {
+        record(SERVICE.map(String::from).to_vec(), PTR, IN, ttl, name_data(&self.instance))
+    }
+
+    // the type of service, answering the browsing for all types
+    fn service_type(&self, ttl: u32) -> Record {
+        record(SERVICES.map(String::from).to_vec(), PTR, IN, ttl, name_data(&SERVICE.map(String::from)))
+    }
+
+    fn srv(&self, ttl: u32) -> Record {
+        // priority and weight 0, the only server of the instance
+        let mut data = vec![0, 0, 0, 0];
+        data.extend_from_slice(&self.port.to_be_bytes());
+        data.extend(name_data(&self.host));
+        record(self.instance.clone(), SRV, IN | CACHE_FLUSH, ttl, data)
+    }
+
+    fn txt(&self, ttl: u32) -> Record {
+        let mut data = Vec::new();
+        for entry in &self.txt {
+            data.push(entry.len() as u8);
+            data.extend_from_slice(entry.as_bytes());
+        }
+        record(self.instance.clone(), TXT, IN | CACHE_FLUSH, ttl, data)
+    }
+
+    // A and AAAA records of the host, re-read of the interfaces for addresses changed since startup
+    fn addresses(&self, rtype: u16, ttl: u32) -> Vec<Record> {
+        let interfaces: Vec<IpAddr> = list_afinet_netifas().unwrap_or_default().into_iter().map(|(_, ip)| ip).collect();
+        let mut ips: Vec<IpAddr> = Vec::new();
+        for bind in &self.binds {
+            if bind.ip().is_unspecified() {
+                // IPv6 of `::` dual-stack
+                ips.extend(interfaces.iter().filter(|ip| ip.is_ipv4() || bind.is_ipv6()));
+            } else {
+                ips.push(bind.ip());
+            }
+        }
+        // link-local IPv6 addresses requiring the zone of the interface
+        ips.retain(|ip| match ip {
+            IpAddr::V4(ip) => !ip.is_loopback(),
+            IpAddr::V6(ip) => !ip.is_loopback() && (ip.segments()[0] & 0xffc0) != 0xfe80,
+        });
+        ips.sort();
+        ips.dedup();
+        ips.into_iter()
+            .filter_map(|ip| match ip {
+                IpAddr::V4(ip) if rtype == A || rtype == ANY => Some(record(self.host.clone(), A, IN | CACHE_FLUSH, ttl, ip.octets().to_vec())),
+                IpAddr::V6(ip) if rtype == AAAA || rtype == ANY => Some(record(self.host.clone(), AAAA, IN | CACHE_FLUSH, ttl, ip.octets().to_vec())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // all records, announced on startup and withdrawn on shutdown by a TTL of 0
+    fn announcement(&self, goodbye: bool) -> Vec<u8> {
+        let ttl = |ttl| if goodbye { 0 } else { ttl };
+        let mut answers = vec![self.pointer(ttl(TTL)), self.service_type(ttl(TTL)), self.srv(ttl(HOST_TTL)), self.txt(ttl(TTL))];
+        answers.extend(self.addresses(ANY, ttl(HOST_TTL)));
+        response(0, &[], &answers, &[])
+    }
+
+    /// Answers and additional records of the questions, none if not asking for the service
+    fn answer(&self, questions: &[(Name, u16)]) -> (Vec<Record>, Vec<Record>) {
+        let mut answers = Vec::new();
+        let mut additionals = Vec::new();
+        let asks = |qtype: u16, rtype| qtype == rtype || qtype == ANY;
+        for (name, qtype) in questions {
+            if same_name(name, &SERVICES) && asks(*qtype, PTR) {
+                answers.push(self.service_type(TTL));
+            }
+            if same_name(name, &SERVICE) && asks(*qtype, PTR) {
+                answers.push(self.pointer(TTL));
+                additionals.extend([self.srv(HOST_TTL), self.txt(TTL)]);
+                additionals.extend(self.addresses(ANY, HOST_TTL));
+            }
+            if same_name(name, &self.instance) {
+                if asks(*qtype, SRV) {
+                    answers.push(self.srv(HOST_TTL));
+                    additionals.extend(self.addresses(ANY, HOST_TTL));
+                }
+                if asks(*qtype, TXT) {
+                    answers.push(self.txt(TTL));
+                }
+            }
+            if same_name(name, &self.host) {
+                answers.extend(self.addresses(*qtype, HOST_TTL));
+            }
+        }
+        answers.dedup();
+        let mut unique = Vec::new();
+        for additional in additionals {
+            if !answers.contains(&additional) && !unique.contains(&additional) {
+                unique.push(additional);
+            }
+        }
+        (answers, unique)
+    }
+}
+
+fn record(name: Name, rtype: u16, class: u16, ttl: u32, data: Vec<u8>) -> Record {
+    Record { name, rtype, class, ttl, data }
+}
+
+/// Name of the host, its first label of the characters allowed in host names
+fn host_name() -> String {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_default();
+    let label: String = name
+        .trim()
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .take(63)
+        .collect();
+    if label.is_empty() { "rust-vue-demo".to_string() } else { label }
+}
+
+// names are compared case-insensitively
+fn same_name<S: AsRef<str>>(name: &[String], other: &[S]) -> bool {
+    name.len() == other.len() && name.iter().zip(other).all(|(label, other)| label.eq_ignore_ascii_case(other.as_ref()))
+}
+
+fn put_name<S: AsRef<str>>(packet: &mut Vec<u8>, name: &[S]) {
+    for label in name {
+        packet.push(label.as_ref().len() as u8);
+        packet.extend_from_slice(label.as_ref().as_bytes());
+    }
+    packet.push(0);
+}
+
+fn name_data<S: AsRef<str>>(name: &[S]) -> Vec<u8> {
+    let mut data = Vec::new();
+    put_name(&mut data, name);
+    data
+}
+
+/// Response of the id, echoing the questions of legacy resolvers, names not compressed
+fn response(id: u16, questions: &[(Name, u16)], answers: &[Record], additionals: &[Record]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(512);
+    for word in [id, FLAGS, questions.len() as u16, answers.len() as u16, 0, additionals.len() as u16] {
+        packet.extend_from_slice(&word.to_be_bytes());
+    }
+    for (name, qtype) in questions {
+        put_name(&mut packet, name);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&IN.to_be_bytes());
+    }
+    for record in answers.iter().chain(additionals) {
+        put_name(&mut packet, &record.name);
+        packet.extend_from_slice(&record.rtype.to_be_bytes());
+        packet.extend_from_slice(&record.class.to_be_bytes());
+        packet.extend_from_slice(&record.ttl.to_be_bytes());
+        packet.extend_from_slice(&(record.data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&record.data);
+    }
+    packet
+}
+
+/// Id and questions of a query, none of responses or malformed packets
+fn query(packet: &[u8]) -> Option<(u16, Vec<(Name, u16)>)> {
+    let word = |pos: usize| packet.get(pos..pos + 2).map(|word| u16::from_be_bytes([word[0], word[1]]));
+    let (id, flags, count) = (word(0)?, word(2)?, word(4)?);
+    if flags & 0x8000 != 0 {
+        return None;
+    }
+    let mut questions = Vec::new();
+    let mut pos = 12;
+    for _ in 0..count {
+        let (name, end) = read_name(packet, pos)?;
+        // the class ignored, its top bit asking for a unicast response, answered by multicast
+        questions.push((name, word(end)?));
+        word(end + 2)?;
+        pos = end + 4;
+    }
+    Some((id, questions))
+}
+
+/// Name at the position, following compression pointers, and the position after it
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(Name, usize)> {
+    let mut name = Vec::new();
+    let mut end = None;
+    for _ in 0..MAX_LABELS {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => return Some((name, end.unwrap_or(pos + 1))),
+            len if len & 0xc0 == 0xc0 => {
+                end.get_or_insert(pos + 2);
+                pos = (len & 0x3f) << 8 | *packet.get(pos + 1)? as usize;
+            }
+            len if len < 64 => {
+                name.push(String::from_utf8_lossy(packet.get(pos + 1..pos + 1 + len)?).into_owned());
+                pos += 1 + len;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+// IPv4 addresses of the interfaces of the host joining the group, loopback excluded
+fn interfaces() -> Vec<Ipv4Addr> {
+    let mut interfaces: Vec<Ipv4Addr> = list_afinet_netifas()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(_, ip)| match ip {
+            IpAddr::V4(ip) if !ip.is_loopback() => Some(ip),
+            _ => None,
+        })
+        .collect();
+    interfaces.sort();
+    interfaces.dedup();
+    interfaces
+}
+
+/// Socket of the mDNS group on each interface, sharing the port with other responders of the
+/// host, e.g. avahi
+pub fn socket() -> Result<UdpSocket, String> {
+    let failed = |e: std::io::Error| format!("failed to join mDNS group: {}", e);
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).map_err(failed)?;
+    socket.set_reuse_address(true).map_err(failed)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true).map_err(failed)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, PORT)).into()).map_err(failed)?;
+    let mut joined = false;
+    for interface in interfaces() {
+        match socket.join_multicast_v4(&GROUP, &interface) {
+            Ok(()) => joined = true,
+            Err(e) => tracing::debug!(%interface, error = %e, "failed to join mDNS group"),
+        }
+    }
+    if !joined {
+        socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED).map_err(failed)?;
+    }
+    // browsers of the same host receiving the announcements too
+    socket.set_multicast_loop_v4(true).map_err(failed)?;
+    socket.set_multicast_ttl_v4(255).map_err(failed)?;
+    socket.set_nonblocking(true).map_err(failed)?;
+    Ok(socket.into())
+}
+
+// send to the group on each interface, else on the default interface only
+async fn multicast(socket: &tokio::net::UdpSocket, packet: &[u8]) {
+    let group = SocketAddr::from((GROUP, PORT));
+    let interfaces = interfaces();
+    if interfaces.is_empty() {
+        if let Err(e) = socket.send_to(packet, group).await {
+            tracing::debug!(error = %e, "failed to send mDNS response");
+        }
+    }
+    for interface in interfaces {
+        let sent = match SockRef::from(socket).set_multicast_if_v4(&interface) {
+            Ok(()) => socket.send_to(packet, group).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = sent {
+            tracing::debug!(%interface, error = %e, "failed to send mDNS response");
+        }
+    }
+}
+
+/// Responder task, announcing the service on startup, answering the queries for it, and
+/// withdrawing it on shutdown
+///
+/// The instance name is not probed for conflicts, two gateways of the same name replacing each
+/// other in the caches of the browsers; see `--mdns-name`.
+pub async fn responder(state: AppState, socket: UdpSocket, service: Service) {
+    let socket = match tokio::net::UdpSocket::from_std(socket) {
+        Ok(socket) => socket,
+        Err(e) => return tracing::error!(error = %e, "failed to announce the service by mDNS"),
+    };
+    let mut announce = tokio::time::interval(Duration::from_secs(1));
+    let mut announced = 0;
+    let mut buf = vec![0u8; 9000];
+    loop {
+        tokio::select! {
+            _ = announce.tick(), if announced < ANNOUNCEMENTS => {
+                multicast(&socket, &service.announcement(false)).await;
+                announced += 1;
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (len, peer) = match received {
+                    Ok(received) => received,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "failed to receive mDNS query");
+                        continue;
+                    }
+                };
+                let Some((id, questions)) = query(&buf[..len]) else { continue };
+                let (mut answers, mut additionals) = service.answer(&questions);
+                if answers.is_empty() {
+                    continue;
+                }
+                tracing::debug!(%peer, "answering mDNS query");
+                if peer.port() == PORT {
+                    multicast(&socket, &response(0, &[], &answers, &additionals)).await;
+                } else {
+                    // a legacy resolver, e.g. `dig -p 5353 @224.0.0.251`, answered by unicast
+                    for record in answers.iter_mut().chain(additionals.iter_mut()) {
+                        record.ttl = record.ttl.min(LEGACY_TTL);
+                        record.class &= !CACHE_FLUSH;
+                    }
+                    let _ = socket.send_to(&response(id, &questions, &answers, &additionals), peer).await;
+                }
+            }
+            _ = state.shutdown.cancelled() => break,
+        }
+    }
+    multicast(&socket, &service.announcement(true)).await;
+}
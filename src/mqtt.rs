@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+
+use crate::audit::{self, Origin};
+use crate::can::Subscription;
+use crate::frame::{CanAnyFrame, EmbeddedFrame};
+use crate::protocol::{format_frame, format_id, parse_frame_command};
+use crate::server::AppState;
+
+const DEFAULT_PORT: u16 = 1883;
+const TOPIC_PREFIX: &str = "can";
+const REQUEST_QUEUE_LEN: usize = 256;
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Connection options of the broker URL, e.g. `tcp://host:1883`
+pub fn broker_options(url: &str) -> Result<MqttOptions, String> {
+    let address = url
+        .strip_prefix("tcp://")
+        .or_else(|| url.strip_prefix("mqtt://"))
+        .ok_or_else(|| format!("unsupported MQTT broker URL {}, expecting tcp://host:port", url))?;
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port.parse().map_err(|_| format!("invalid port of MQTT broker URL {}", url))?;
+            (host, port)
+        }
+        None => (address, DEFAULT_PORT),
+    };
+
+    let mut options = MqttOptions::new(format!("rust-vue-{}", std::process::id()), host, port);
+    options.set_keep_alive(KEEP_ALIVE);
+    Ok(options)
+}
+
+/// Bridge task, publishing every received frame to `can/<iface>/<id>` in `cansend` notation
+///
+/// Frames published to the command topic, in `cansend` notation with optional interface
+/// prefix, are written to the CAN bus. The broker is re-connected until shutdown.
+pub async fn bridge(state: AppState, options: MqttOptions, command_topic: Option<String>) {
+    let (client, mut eventloop) = AsyncClient::new(options, REQUEST_QUEUE_LEN);
+    let mut events = Subscription::new(state.events.subscribe(), state.shutdown.clone(), "MQTT bridge");
+    let mut dropped: u64 = 0;
+
+    loop {
+        tokio::select! {
+            frame = events.next_frame() => match frame {
+                Some((interface, frame, _, _)) if !matches!(frame, CanAnyFrame::Error(_)) => {
+                    let topic = format!("{}/{}/{}", TOPIC_PREFIX, interface, format_id(frame.id()));
+                    let (payload, _) = format_frame(&frame);
+                    // publish without blocking the bridge while the broker is unreachable
+                    if client.try_publish(topic, QoS::AtMostOnce, false, payload).is_err() {
+                        dropped += 1;
+                        if dropped.is_power_of_two() {
+                            tracing::warn!(dropped, "MQTT queue full, dropping frames");
+                        }
+                    }
+                }
+                Some(_) => (),
+                None => {
+                    let _ = client.try_disconnect();
+                    return;
+                }
+            },
+            notification = eventloop.poll() => match notification {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    tracing::info!("connected to MQTT broker");
+                    // subscribed again on each connect, as the session is not persistent
+                    if let Some(topic) = &command_topic {
+                        let _ = client.try_subscribe(topic, QoS::AtMostOnce);
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) if Some(&publish.topic) == command_topic.as_ref() => {
+                    let command = String::from_utf8_lossy(&publish.payload);
+                    match parse_frame_command(&command) {
+                        Ok((interface, frame)) => {
+                            let result = state.buses.write_frame(interface, &frame).await;
+                            audit::record(&state, &Origin::service("mqtt"), interface, &frame, &result);
+                            if result.is_err() {
+                                tracing::warn!(command = %command, "MQTT command failed writing frame");
+                            }
+                        }
+                        Err(e) => tracing::warn!(command = %command, error = %e, "MQTT command malformed"),
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    tracing::warn!(error = %e, "MQTT connection failed");
+                    tokio::select! {
+                        _ = tokio::time::sleep(RECONNECT_DELAY) => (),
+                        _ = state.shutdown.cancelled() => return,
+                    }
+                }
+            },
+        }
+    }
+}
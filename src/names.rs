@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Id};
+use crate::protocol::parse_frame_id;
+
+// DTO - name of the frames of an id, attached to each frame forwarded, e.g. `Brake Status`
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FrameName {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+// id of the names file with its name, the id as hex string in `cansend` notation, 8 digits or
+// exceeding `7FF` for extended ids
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Message {
+    id: String,
+    #[serde(flatten)]
+    name: FrameName,
+}
+
+// Names file of `--names`, a `[[message]]` table per id
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct NamesFile {
+    #[serde(default, rename = "message")]
+    messages: Vec<Message>,
+}
+
+/// Names of the CAN ids, without the signals of a DBC database
+pub struct Names {
+    names: HashMap<Id, FrameName>,
+}
+
+impl Names {
+    /// Load the names file in TOML format, e.g. `[[message]]` of `id = "1A0"`, `name = "Brake Status"`
+    pub fn load(path: &Path) -> Result<Names, String> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read names {}: {}", path.display(), e))?;
+        let file: NamesFile = toml::from_str(&s)
+            .map_err(|e| format!("invalid names {}: {}", path.display(), e))?;
+        let mut names = HashMap::new();
+        for message in file.messages {
+            let digits = message.id.strip_prefix("0x").unwrap_or(&message.id);
+            let id = parse_frame_id(digits).map_err(|_| format!("{}: invalid id {}", path.display(), message.id))?;
+            if names.insert(id, message.name).is_some() {
+                return Err(format!("{}: duplicate id {}", path.display(), message.id));
+            }
+        }
+        Ok(Names { names })
+    }
+
+    pub fn get(&self, frame: &CanAnyFrame) -> Option<&FrameName> {
+        self.names.get(&frame.id())
+    }
+}
@@ -0,0 +1,162 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use neli::{
+    consts::nl::{NlmF, NlmFFlags},
+    consts::rtnl::{Arphrd, Iff, IffFlags, Ifla, IflaInfo, RtAddrFamily, Rtm},
+    consts::socket::NlFamily,
+    nl::{NlPayload, Nlmsghdr},
+    rtnl::{Ifinfomsg, Rtattr},
+    socket::NlSocketHandle,
+    types::RtBuffer,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "linux")]
+use socketcan::nl::{CanState, InterfaceCanParams};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::can::{self, Buses, CanEvent};
+use crate::transport::Transport;
+
+// DTO - state of a SocketCAN interface, as reported by `ip -details -statistics link show`
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone, Default)]
+pub struct InterfaceState {
+    pub interface: String,
+    pub up: bool,
+    // `error-active`, `error-warning`, `error-passive`, `bus-off`, `stopped` or `sleeping`,
+    // none for virtual interfaces
+    pub state: Option<String>,
+    pub bitrate: Option<u32>,
+    // sample point in tenths of a percent
+    pub sample_point: Option<u32>,
+    pub data_bitrate: Option<u32>,
+    // delay of the automatic restart after bus-off, 0 if disabled
+    pub restart_ms: Option<u32>,
+    pub tx_errors: Option<u16>,
+    pub rx_errors: Option<u16>,
+    // counters of the CAN device since it was brought up
+    pub stats: Option<DeviceStats>,
+    // failure of the query, e.g. a missing interface
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// DTO - statistics of the CAN device, `struct can_device_stats` of the kernel
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone, Copy, Default)]
+pub struct DeviceStats {
+    pub bus_error: u32,
+    pub error_warning: u32,
+    pub error_passive: u32,
+    pub bus_off: u32,
+    pub arbitration_lost: u32,
+    pub restarts: u32,
+}
+
+impl DeviceStats {
+    #[cfg(target_os = "linux")]
+    fn parse(buf: &[u8]) -> Option<DeviceStats> {
+        let field = |i: usize| buf.get(4 * i..4 * i + 4).map(|b| u32::from_ne_bytes(b.try_into().unwrap()));
+        Some(DeviceStats {
+            bus_error: field(0)?,
+            error_warning: field(1)?,
+            error_passive: field(2)?,
+            bus_off: field(3)?,
+            arbitration_lost: field(4)?,
+            restarts: field(5)?,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn state_name(state: CanState) -> &'static str {
+    match state {
+        CanState::ErrorActive => "error-active",
+        CanState::ErrorWarning => "error-warning",
+        CanState::ErrorPassive => "error-passive",
+        CanState::BusOff => "bus-off",
+        CanState::Stopped => "stopped",
+        CanState::Sleeping => "sleeping",
+    }
+}
+
+/// Query the state of the interface by RTM_GETLINK, blocking on the netlink socket
+#[cfg(target_os = "linux")]
+pub fn query(name: &str) -> Result<InterfaceState, String> {
+    let mut socket = NlSocketHandle::connect(NlFamily::Route, None, &[]).map_err(|e| e.to_string())?;
+    let mut attrs = RtBuffer::new();
+    attrs.push(Rtattr::new(None, Ifla::Ifname, name).map_err(|e| e.to_string())?);
+    // the interface is looked up by name without index
+    let info = Ifinfomsg::new(RtAddrFamily::Unspecified, Arphrd::Netrom, 0, IffFlags::empty(), IffFlags::empty(), attrs);
+    let request = Nlmsghdr::new(None, Rtm::Getlink, NlmFFlags::new(&[NlmF::Request]), None, None, NlPayload::Payload(info));
+    socket.send(request).map_err(|e| e.to_string())?;
+    let response = socket.recv::<Rtm, Ifinfomsg>().map_err(|e| e.to_string())?;
+    let link = response.as_ref().and_then(|response| response.get_payload().ok()).ok_or("no link information")?;
+
+    let mut state = InterfaceState { interface: name.to_string(), up: link.ifi_flags.contains(&Iff::Up), ..Default::default() };
+    let Some(link_info) = link.rtattrs.iter().find(|attr| attr.rta_type == Ifla::Linkinfo) else {
+        return Ok(state);
+    };
+    let params = InterfaceCanParams::try_from(link_info).map_err(|e| e.to_string())?;
+    state.state = params.state.map(|state| state_name(state).to_string());
+    state.bitrate = params.bit_timing.map(|timing| timing.bitrate);
+    state.sample_point = params.bit_timing.map(|timing| timing.sample_point);
+    state.data_bitrate = params.data_bit_timing.map(|timing| timing.bitrate).filter(|bitrate| *bitrate > 0);
+    state.restart_ms = params.restart_ms;
+    state.tx_errors = params.berr_counter.map(|counter| counter.txerr);
+    state.rx_errors = params.berr_counter.map(|counter| counter.rxerr);
+    // the device statistics are not parsed by socketcan
+    let infos = link_info.get_attr_handle::<IflaInfo>().map_err(|e| e.to_string())?;
+    state.stats = infos.get_attrs()
+        .iter()
+        .find(|info| info.rta_type == IflaInfo::Xstats)
+        .and_then(|xstats| DeviceStats::parse(xstats.rta_payload.as_ref()));
+    Ok(state)
+}
+
+/// Netlink is available on Linux only, as the SocketCAN interfaces queried
+#[cfg(not(target_os = "linux"))]
+pub fn query(_name: &str) -> Result<InterfaceState, String> {
+    Err("netlink requires Linux".to_string())
+}
+
+/// Query the state of the SocketCAN interfaces, the error reported with each interface failing
+pub async fn query_all(buses: &Buses) -> Vec<InterfaceState> {
+    let names: Vec<Arc<str>> = buses.iter()
+        .filter(|bus| bus.transport == Transport::SocketCan)
+        .map(|bus| bus.name.clone())
+        .collect();
+    tokio::task::spawn_blocking(move || {
+        names.iter()
+            .map(|name| query(name).unwrap_or_else(|e| InterfaceState {
+                interface: name.to_string(),
+                error: Some(e),
+                ..Default::default()
+            }))
+            .collect()
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Publish the state of the SocketCAN interfaces once per period until shutdown, unless none is
+/// configured
+pub async fn monitor(buses: Buses, events: broadcast::Sender<CanEvent>, shutdown: CancellationToken) {
+    const PERIOD: Duration = Duration::from_secs(2);
+    if !buses.iter().any(|bus| bus.transport == Transport::SocketCan) {
+        return;
+    }
+    let mut ticker = tokio::time::interval(PERIOD);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let states = query_all(&buses).await;
+                can::publish(&events, CanEvent::Interface(states.into()));
+            }
+            _ = shutdown.cancelled() => return,
+        }
+    }
+}
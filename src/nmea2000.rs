@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use crate::can::{self, CanEvent, Subscription};
+use crate::decode::SignalValue;
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Id};
+use crate::j1939::Header;
+
+// max time between the frames of a fast packet
+const FAST_PACKET_TIMEOUT: Duration = Duration::from_millis(750);
+// max size of a fast packet, 6 bytes of the first frame and 31 frames of 7 bytes
+const FAST_PACKET_MAX_SIZE: usize = 223;
+
+const RAD_TO_DEG: f64 = 180.0 / std::f64::consts::PI;
+const KELVIN: f64 = 273.15;
+
+// DTO - PGN of a NMEA 2000 network decoded into its fields, of a single frame or reassembled of a
+// fast packet, data as hex string; the fields not available omitted
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct PgnMessage {
+    pub interface: String,
+    pub pgn: u32,
+    pub name: String,
+    pub priority: u8,
+    pub source: u8,
+    pub destination: u8,
+    pub fields: Vec<SignalValue>,
+    pub data: String,
+    // whether reassembled of the frames of a fast packet
+    pub fast_packet: bool,
+}
+
+// PGN decoded, of the fields of its payload
+struct Definition {
+    pgn: u32,
+    name: &'static str,
+    fast_packet: bool,
+    decode: fn(&mut Fields),
+}
+
+const DEFINITIONS: &[Definition] = &[
+    Definition { pgn: 127250, name: "Vessel Heading", fast_packet: false, decode: vessel_heading },
+    Definition { pgn: 127488, name: "Engine Parameters, Rapid Update", fast_packet: false, decode: engine_rapid },
+    Definition { pgn: 127489, name: "Engine Parameters, Dynamic", fast_packet: true, decode: engine_dynamic },
+    Definition { pgn: 127508, name: "Battery Status", fast_packet: false, decode: battery_status },
+    Definition { pgn: 128259, name: "Speed", fast_packet: false, decode: speed },
+    Definition { pgn: 128267, name: "Water Depth", fast_packet: false, decode: water_depth },
+    Definition { pgn: 129025, name: "Position, Rapid Update", fast_packet: false, decode: position_rapid },
+    Definition { pgn: 129026, name: "COG & SOG, Rapid Update", fast_packet: false, decode: cog_sog_rapid },
+    Definition { pgn: 129029, name: "GNSS Position Data", fast_packet: true, decode: gnss_position },
+    Definition { pgn: 130306, name: "Wind Data", fast_packet: false, decode: wind_data },
+    Definition { pgn: 130312, name: "Temperature", fast_packet: false, decode: temperature },
+];
+
+/// Fields of a payload, little endian; the values of all bits set, or the maximum of signed values,
+/// and the one below, the error indication, are not available
+struct Fields<'a> {
+    data: &'a [u8],
+    fields: Vec<SignalValue>,
+}
+
+impl Fields<'_> {
+    fn bytes<const N: usize>(&self, pos: usize) -> Option<[u8; N]> {
+        self.data.get(pos..pos + N)?.try_into().ok()
+    }
+
+    fn unsigned(&self, pos: usize, size: usize) -> Option<f64> {
+        let mut bytes = [0; 8];
+        bytes[..size].copy_from_slice(self.data.get(pos..pos + size)?);
+        let value = u64::from_le_bytes(bytes);
+        let max = u64::MAX >> (64 - 8 * size);
+        (value < max - 1).then_some(value as f64)
+    }
+
+    fn signed(&self, pos: usize, size: usize) -> Option<f64> {
+        let value = match size {
+            1 => self.bytes::<1>(pos).map(|b| i8::from_le_bytes(b) as i64),
+            2 => self.bytes::<2>(pos).map(|b| i16::from_le_bytes(b) as i64),
+            4 => self.bytes::<4>(pos).map(|b| i32::from_le_bytes(b) as i64),
+            _ => self.bytes::<8>(pos).map(i64::from_le_bytes),
+        }?;
+        let max = i64::MAX >> (64 - 8 * size);
+        (value < max - 1).then_some(value as f64)
+    }
+
+    fn push(&mut self, name: &str, value: Option<f64>, unit: &str) {
+        if let Some(value) = value {
+            self.fields.push(SignalValue { name: name.to_string(), value, unit: unit.to_string() });
+        }
+    }
+
+    fn u8(&mut self, name: &str, pos: usize, scale: f64, unit: &str) {
+        self.push(name, self.unsigned(pos, 1).map(|v| v * scale), unit);
+    }
+
+    fn u16(&mut self, name: &str, pos: usize, scale: f64, unit: &str) {
+        self.push(name, self.unsigned(pos, 2).map(|v| v * scale), unit);
+    }
+
+    fn u32(&mut self, name: &str, pos: usize, scale: f64, unit: &str) {
+        self.push(name, self.unsigned(pos, 4).map(|v| v * scale), unit);
+    }
+
+    fn i8(&mut self, name: &str, pos: usize, scale: f64, unit: &str) {
+        self.push(name, self.signed(pos, 1).map(|v| v * scale), unit);
+    }
+
+    fn i16(&mut self, name: &str, pos: usize, scale: f64, unit: &str) {
+        self.push(name, self.signed(pos, 2).map(|v| v * scale), unit);
+    }
+
+    fn i32(&mut self, name: &str, pos: usize, scale: f64, unit: &str) {
+        self.push(name, self.signed(pos, 4).map(|v| v * scale), unit);
+    }
+
+    fn i64(&mut self, name: &str, pos: usize, scale: f64, unit: &str) {
+        self.push(name, self.signed(pos, 8).map(|v| v * scale), unit);
+    }
+
+    // angle of 0.0001 rad in degrees
+    fn angle(&mut self, name: &str, pos: usize) {
+        self.push(name, self.unsigned(pos, 2).map(|v| v * 1e-4 * RAD_TO_DEG), "deg");
+    }
+
+    fn signed_angle(&mut self, name: &str, pos: usize) {
+        self.push(name, self.signed(pos, 2).map(|v| v * 1e-4 * RAD_TO_DEG), "deg");
+    }
+
+    // temperature of the resolution in K in degrees Celsius
+    fn temperature(&mut self, name: &str, pos: usize, scale: f64) {
+        self.push(name, self.unsigned(pos, 2).map(|v| v * scale - KELVIN), "degC");
+    }
+
+    // reference of the bits of the byte, e.g. true or magnetic north
+    fn reference(&mut self, name: &str, pos: usize, mask: u8) {
+        let value = self.data.get(pos).map(|byte| byte & mask).filter(|value| *value != mask);
+        self.push(name, value.map(f64::from), "");
+    }
+}
+
+fn vessel_heading(f: &mut Fields) {
+    f.angle("heading", 1);
+    f.signed_angle("deviation", 3);
+    f.signed_angle("variation", 5);
+    f.reference("reference", 7, 0x03);
+}
+
+fn engine_rapid(f: &mut Fields) {
+    f.u8("instance", 0, 1.0, "");
+    f.u16("speed", 1, 0.25, "rpm");
+    f.u16("boost_pressure", 3, 0.1, "kPa");
+    f.i8("tilt_trim", 5, 1.0, "%");
+}
+
+fn engine_dynamic(f: &mut Fields) {
+    f.u8("instance", 0, 1.0, "");
+    f.u16("oil_pressure", 1, 0.1, "kPa");
+    f.temperature("oil_temperature", 3, 0.1);
+    f.temperature("temperature", 5, 0.01);
+    f.i16("alternator_potential", 7, 0.01, "V");
+    f.i16("fuel_rate", 9, 0.1, "L/h");
+    f.u32("total_hours", 11, 1.0 / 3600.0, "h");
+    f.u16("coolant_pressure", 15, 0.1, "kPa");
+    f.u16("fuel_pressure", 17, 1.0, "kPa");
+    f.i8("load", 24, 1.0, "%");
+    f.i8("torque", 25, 1.0, "%");
+}
+
+fn battery_status(f: &mut Fields) {
+    f.u8("instance", 0, 1.0, "");
+    f.i16("voltage", 1, 0.01, "V");
+    f.i16("current", 3, 0.1, "A");
+    f.temperature("temperature", 5, 0.01);
+}
+
+fn speed(f: &mut Fields) {
+    f.u16("speed_water", 1, 0.01, "m/s");
+    f.u16("speed_ground", 3, 0.01, "m/s");
+}
+
+fn water_depth(f: &mut Fields) {
+    f.u32("depth", 1, 0.01, "m");
+    f.i16("offset", 5, 0.001, "m");
+}
+
+fn position_rapid(f: &mut Fields) {
+    f.i32("latitude", 0, 1e-7, "deg");
+    f.i32("longitude", 4, 1e-7, "deg");
+}
+
+fn cog_sog_rapid(f: &mut Fields) {
+    f.reference("cog_reference", 1, 0x03);
+    f.angle("cog", 2);
+    f.u16("sog", 4, 0.01, "m/s");
+}
+
+fn gnss_position(f: &mut Fields) {
+    f.u16("date", 1, 1.0, "d");
+    f.u32("time", 3, 1e-4, "s");
+    f.i64("latitude", 7, 1e-16, "deg");
+    f.i64("longitude", 15, 1e-16, "deg");
+    f.i64("altitude", 23, 1e-6, "m");
+    f.u8("satellites", 33, 1.0, "");
+    f.i16("hdop", 34, 0.01, "");
+    f.i16("pdop", 36, 0.01, "");
+}
+
+fn wind_data(f: &mut Fields) {
+    f.u16("speed", 1, 0.01, "m/s");
+    f.angle("angle", 3);
+    f.reference("reference", 5, 0x07);
+}
+
+fn temperature(f: &mut Fields) {
+    f.u8("instance", 1, 1.0, "");
+    f.u8("source", 2, 1.0, "");
+    f.temperature("actual", 3, 0.01);
+    f.temperature("set", 5, 0.01);
+}
+
+// fast packet of a source, the frames following in order of their counter
+struct FastPacket {
+    sequence: u8,
+    size: usize,
+    data: Vec<u8>,
+    last: Instant,
+}
+
+/// Decoder of the PGNs of NMEA 2000 of 29-bit frames, reassembling the fast packets per interface,
+/// source and PGN
+///
+/// Only the PGNs of position, navigation and engine parameters of [DEFINITIONS] are decoded.
+#[derive(Default)]
+pub struct Decoder {
+    packets: HashMap<(Arc<str>, u8, u32), FastPacket>,
+}
+
+impl Decoder {
+    /// The PGN of the frame, if decoded and complete
+    pub fn decode(&mut self, interface: &Arc<str>, frame: &CanAnyFrame) -> Option<PgnMessage> {
+        let CanAnyFrame::Normal(frame) = frame else { return None };
+        let Id::Extended(id) = frame.id() else { return None };
+        let header = Header::from_id(id.as_raw());
+        let definition = DEFINITIONS.iter().find(|definition| definition.pgn == header.pgn)?;
+        let data = frame.data();
+
+        let reassembled;
+        let payload = if definition.fast_packet {
+            reassembled = self.fast_packet((interface.clone(), header.source, header.pgn), data)?;
+            &reassembled
+        } else {
+            data
+        };
+        let mut fields = Fields { data: payload, fields: Vec::new() };
+        (definition.decode)(&mut fields);
+        Some(PgnMessage {
+            interface: interface.to_string(),
+            pgn: header.pgn,
+            name: definition.name.to_string(),
+            priority: header.priority,
+            source: header.source,
+            destination: header.destination,
+            fields: fields.fields,
+            data: hex::encode_upper(payload),
+            fast_packet: definition.fast_packet,
+        })
+    }
+
+    /// Payload of the fast packet once complete, the first frame of the counter 0 giving the size,
+    /// a missing frame or timeout dropping the packet
+    fn fast_packet(&mut self, key: (Arc<str>, u8, u32), data: &[u8]) -> Option<Vec<u8>> {
+        let (sequence, counter) = (*data.first()? >> 5, *data.first()? & 0x1F);
+        if counter == 0 {
+            let size = *data.get(1)? as usize;
+            let data = data.get(2..).unwrap_or_default();
+            if size <= data.len() {
+                self.packets.remove(&key);
+                return Some(data[..size].to_vec());
+            }
+            if size <= FAST_PACKET_MAX_SIZE {
+                self.packets.insert(key, FastPacket { sequence, size, data: data.to_vec(), last: Instant::now() });
+            }
+            return None;
+        }
+        let packet = self.packets.get_mut(&key)?;
+        // frames numbered from 1 after the first one of 6 bytes
+        let expected = ((packet.data.len() + 1) / 7) as u8;
+        if sequence != packet.sequence || counter != expected || packet.last.elapsed() > FAST_PACKET_TIMEOUT {
+            self.packets.remove(&key);
+            return None;
+        }
+        packet.data.extend_from_slice(&data[1..]);
+        packet.last = Instant::now();
+        if packet.data.len() < packet.size {
+            return None;
+        }
+        let mut packet = self.packets.remove(&key)?;
+        packet.data.truncate(packet.size);
+        Some(packet.data)
+    }
+}
+
+/// Decode the PGNs of all received frames, publishing them to all sessions until shutdown
+pub async fn decoder(events: broadcast::Sender<CanEvent>, shutdown: CancellationToken) {
+    let mut decoder = Decoder::default();
+    let mut received = Subscription::new(events.subscribe(), shutdown, "NMEA 2000 decoder");
+    while let Some((interface, frame, _, _)) = received.next_frame().await {
+        if let Some(message) = decoder.decode(&interface, &frame) {
+            can::publish(&events, CanEvent::Nmea2000(Arc::new(message)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{CanDataFrame, ExtendedId};
+
+    const ENGINE_DYNAMIC: u32 = 127489;
+    const BATTERY_STATUS: u32 = 127508;
+
+    fn frame(pgn: u32, source: u8, data: &[u8]) -> CanAnyFrame {
+        let id = ExtendedId::new(2 << 26 | pgn << 8 | source as u32).unwrap();
+        CanAnyFrame::Normal(CanDataFrame::new(id, data).unwrap())
+    }
+
+    // frames of the fast packet of the sequence counter, padded with 0xFF
+    fn fast_packet(pgn: u32, source: u8, sequence: u8, payload: &[u8]) -> Vec<CanAnyFrame> {
+        let mut first = vec![sequence << 5, payload.len() as u8];
+        first.extend(payload.iter().take(6));
+        let mut frames = vec![first];
+        for (i, chunk) in payload.get(6..).unwrap_or_default().chunks(7).enumerate() {
+            let mut data = vec![sequence << 5 | (i + 1) as u8];
+            data.extend(chunk);
+            frames.push(data);
+        }
+        frames.into_iter()
+            .map(|mut data| {
+                data.resize(8, 0xFF);
+                frame(pgn, source, &data)
+            })
+            .collect()
+    }
+
+    fn decode_all(decoder: &mut Decoder, frames: &[CanAnyFrame]) -> Vec<PgnMessage> {
+        let interface: Arc<str> = "can0".into();
+        frames.iter().filter_map(|frame| decoder.decode(&interface, frame)).collect()
+    }
+
+    fn field(message: &PgnMessage, name: &str) -> Option<f64> {
+        message.fields.iter().find(|field| field.name == name).map(|field| field.value)
+    }
+
+    #[test]
+    fn fast_packet_reassembled() {
+        let mut payload = vec![0xFF; 26];
+        payload[0] = 1;
+        payload[1..3].copy_from_slice(&4000u16.to_le_bytes());
+        payload[7..9].copy_from_slice(&1380i16.to_le_bytes());
+        let mut decoder = Decoder::default();
+        let messages = decode_all(&mut decoder, &fast_packet(ENGINE_DYNAMIC, 0x23, 5, &payload));
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        assert_eq!((message.pgn, message.priority, message.source, message.fast_packet), (ENGINE_DYNAMIC, 2, 0x23, true));
+        assert_eq!(message.data, hex::encode_upper(&payload));
+        assert_eq!(field(message, "instance"), Some(1.0));
+        assert_eq!(field(message, "oil_pressure"), Some(400.0));
+        assert_eq!(field(message, "alternator_potential"), Some(13.8));
+        // all bits set, not available
+        assert_eq!(field(message, "oil_temperature"), None);
+        assert_eq!(field(message, "total_hours"), None);
+    }
+
+    #[test]
+    fn fast_packet_of_max_size() {
+        let key = || -> (Arc<str>, u8, u32) { ("can0".into(), 0x23, ENGINE_DYNAMIC) };
+        let mut decoder = Decoder::default();
+        let payload: Vec<u8> = (0..FAST_PACKET_MAX_SIZE).map(|i| i as u8).collect();
+        let frames = fast_packet(ENGINE_DYNAMIC, 0x23, 0, &payload);
+        assert_eq!(frames.len(), 32);
+        let reassembled: Vec<Vec<u8>> = frames.iter().filter_map(|frame| decoder.fast_packet(key(), frame.data())).collect();
+        assert_eq!(reassembled, [payload]);
+
+        // beyond the frame counter of 5 bits
+        let announce = [0, FAST_PACKET_MAX_SIZE as u8 + 1, 0, 1, 2, 3, 4, 5];
+        assert_eq!(decoder.fast_packet(key(), &announce), None);
+        assert!(decoder.packets.is_empty());
+    }
+
+    #[test]
+    fn fast_packet_dropped_on_sequence_or_frame_counter() {
+        let payload = vec![0x11; 26];
+        let frames = fast_packet(ENGINE_DYNAMIC, 0x23, 2, &payload);
+        let mut decoder = Decoder::default();
+
+        // a frame missing
+        let mut missing = frames.clone();
+        missing.remove(2);
+        assert!(decode_all(&mut decoder, &missing).is_empty());
+        assert!(decoder.packets.is_empty());
+
+        // a frame of another sequence
+        let mut other = frames.clone();
+        other[2] = fast_packet(ENGINE_DYNAMIC, 0x23, 3, &payload)[2];
+        assert!(decode_all(&mut decoder, &other).is_empty());
+
+        // a new sequence started over the pending one
+        let mut restarted = frames[..2].to_vec();
+        restarted.extend(fast_packet(ENGINE_DYNAMIC, 0x23, 3, &payload));
+        assert_eq!(decode_all(&mut decoder, &restarted).len(), 1);
+
+        // the packets of the sources interleaved
+        let other = fast_packet(ENGINE_DYNAMIC, 0x24, 2, &[0x22; 26]);
+        let interleaved: Vec<CanAnyFrame> = frames.iter().zip(&other).flat_map(|(a, b)| [*a, *b]).collect();
+        let messages = decode_all(&mut decoder, &interleaved);
+        assert_eq!(messages.iter().map(|message| message.source).collect::<Vec<_>>(), [0x23, 0x24]);
+    }
+
+    #[test]
+    fn not_available_and_error_values_omitted() {
+        let mut decoder = Decoder::default();
+        let mut voltage = |raw: i16| {
+            let mut data = [2, 0, 0, 0x10, 0x00, 0xFF, 0xFF, 0];
+            data[1..3].copy_from_slice(&raw.to_le_bytes());
+            let message = decode_all(&mut decoder, &[frame(BATTERY_STATUS, 0x10, &data)]).pop().unwrap();
+            assert_eq!((field(&message, "current"), field(&message, "temperature")), (Some(1.6), None));
+            field(&message, "voltage")
+        };
+        assert_eq!(voltage(1250), Some(12.5));
+        assert_eq!(voltage(-100), Some(-1.0));
+        // the maximum not available, and the one below an error
+        assert_eq!(voltage(i16::MAX), None);
+        assert_eq!(voltage(i16::MAX - 1), None);
+        assert_eq!(voltage(i16::MAX - 2).map(|v| (v * 100.0).round()), Some(32765.0));
+
+        // a reference of all bits set
+        let mut heading = |reference: u8| {
+            let data = [0, 0x10, 0x27, 0xFF, 0x7F, 0xFF, 0x7F, 0xFC | reference];
+            decode_all(&mut decoder, &[frame(127250, 0x10, &data)]).pop().unwrap()
+        };
+        let message = heading(1);
+        assert_eq!(field(&message, "reference"), Some(1.0));
+        assert!((field(&message, "heading").unwrap() - 1e4 * 1e-4 * RAD_TO_DEG).abs() < 1e-9);
+        assert_eq!(field(&message, "deviation"), None);
+        assert_eq!(field(&heading(3), "reference"), None);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::can::{self, CanEvent, Subscription};
+use crate::decode::SignalValue;
+use crate::frame::{CanAnyFrame, CanDataFrame, EmbeddedFrame, Frame, StandardId};
+use crate::server::AppState;
+
+// functional request id, addressing all emission related ECUs
+const REQUEST_ID: u16 = 0x7DF;
+// physical response ids of ECU #1 to #8
+const RESPONSE_IDS: std::ops::RangeInclusive<u32> = 0x7E8..=0x7EF;
+const SERVICE_CURRENT_DATA: u8 = 0x01;
+const POSITIVE_RESPONSE: u8 = 0x40;
+const PADDING: u8 = 0xCC;
+// time to collect the responses of all ECUs to a single request
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(100);
+
+// DTO - values of the standard PIDs reported by an ECU in the last polling cycle
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Telemetry {
+    pub ecu: String,
+    pub values: Vec<SignalValue>,
+}
+
+/// Standard PID of service 01, decoded from the data bytes A, B, ...
+struct Pid {
+    pid: u8,
+    name: &'static str,
+    unit: &'static str,
+    len: usize,
+    decode: fn(&[u8]) -> f64,
+}
+
+static PIDS: &[Pid] = &[
+    Pid { pid: 0x04, name: "EngineLoad", unit: "%", len: 1, decode: |d| d[0] as f64 * 100.0 / 255.0 },
+    Pid { pid: 0x05, name: "CoolantTemp", unit: "C", len: 1, decode: |d| d[0] as f64 - 40.0 },
+    Pid { pid: 0x0C, name: "EngineSpeed", unit: "rpm", len: 2, decode: |d| (d[0] as f64 * 256.0 + d[1] as f64) / 4.0 },
+    Pid { pid: 0x0D, name: "VehicleSpeed", unit: "km/h", len: 1, decode: |d| d[0] as f64 },
+    Pid { pid: 0x11, name: "ThrottlePosition", unit: "%", len: 1, decode: |d| d[0] as f64 * 100.0 / 255.0 },
+];
+
+/// Decode a single frame response `[len, 0x41, pid, A, B, ...]` to the requested PID
+fn decode_response(pid: &Pid, frame: &CanAnyFrame) -> Option<SignalValue> {
+    let CanAnyFrame::Normal(frame) = frame else { return None };
+    if !RESPONSE_IDS.contains(&frame.raw_id()) || frame.is_extended() {
+        return None;
+    }
+    match frame.data() {
+        [len, sid, p, data @ ..]
+            if *sid == SERVICE_CURRENT_DATA | POSITIVE_RESPONSE && *p == pid.pid
+                && *len as usize >= pid.len + 2 && data.len() >= pid.len => {
+            Some(SignalValue { name: pid.name.to_string(), value: (pid.decode)(data), unit: pid.unit.to_string() })
+        }
+        _ => None,
+    }
+}
+
+fn request(pid: &Pid) -> CanAnyFrame {
+    let id = StandardId::new(REQUEST_ID).unwrap();
+    let data = [0x02, SERVICE_CURRENT_DATA, pid.pid, PADDING, PADDING, PADDING, PADDING, PADDING];
+    CanAnyFrame::Normal(CanDataFrame::new(id, &data).unwrap())
+}
+
+/// Poller task, requesting the standard PIDs on the default interface once per period and
+/// publishing the responses of each ECU to all WebSocket sessions, until shutdown
+pub async fn poller(state: AppState, period: Duration) {
+    let Some(interface) = state.buses.get(None).map(|bus| bus.name.clone()) else { return };
+    let mut events = Subscription::new(state.events.subscribe(), state.shutdown.clone(), "OBD poller");
+    let mut ticker = tokio::time::interval(period);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => (),
+            _ = state.shutdown.cancelled() => return,
+        }
+
+        let mut values: BTreeMap<u32, Vec<SignalValue>> = BTreeMap::new();
+        for pid in PIDS {
+            if state.buses.write_frame(Some(&interface), &request(pid)).await.is_err() {
+                // device missing, retry next period
+                break;
+            }
+            let deadline = Instant::now() + RESPONSE_TIMEOUT;
+            while let Ok(frame) = tokio::time::timeout_at(deadline, events.next_frame()).await {
+                let Some((name, frame, _, _)) = frame else { return };
+                if name != interface {
+                    continue;
+                }
+                if let Some(value) = decode_response(pid, &frame) {
+                    values.entry(frame.raw_id()).or_default().push(value);
+                }
+            }
+        }
+
+        for (ecu, values) in values {
+            let telemetry = Telemetry { ecu: format!("{:03X}", ecu), values };
+            can::publish(&state.events, CanEvent::Telemetry(Arc::new(telemetry)));
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use axum::{
+    response::{Html, IntoResponse},
+    Json,
+};
+use utoipa::OpenApi;
+
+use crate::{api, auth, canopen, diag, health, version};
+
+/// OpenAPI document of the REST API, generated from the annotated handlers and DTOs
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "rust-vue CAN bridge", description = "REST API of the CAN-to-WebSocket bridge"),
+    paths(
+        api::post_frame,
+        api::get_frames,
+        api::post_replay,
+        api::post_cyclic,
+        api::list_cyclic,
+        api::put_cyclic,
+        api::delete_cyclic,
+        api::post_capture,
+        api::list_captures,
+        api::get_capture,
+        api::get_capture_log,
+        api::delete_capture,
+        api::get_history,
+        api::get_interface,
+        api::get_overview,
+        api::get_time,
+        api::get_audit,
+        api::get_schema,
+        api::post_sequence,
+        api::delete_sequence,
+        api::list_presets,
+        api::get_preset,
+        api::put_preset,
+        api::delete_preset,
+        api::send_preset,
+        api::list_alerts,
+        api::put_alert,
+        api::delete_alert,
+        api::list_clients,
+        api::delete_client,
+        api::list_gateway,
+        api::put_gateway,
+        api::post_reload,
+        auth::login,
+        auth::logout,
+        canopen::sdo_read,
+        diag::rdbi,
+        diag::tester_present,
+        diag::reset,
+        diag::read_dtc,
+        diag::clear_dtc,
+        health::healthz,
+        version::get_version,
+    )
+)]
+struct ApiDoc;
+
+/// `GET /api/openapi.json` - OpenAPI document of the REST API
+pub async fn openapi_json() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
+
+/// `GET /api/docs` - Swagger UI of the OpenAPI document, loading the assets of Swagger UI from unpkg
+pub async fn swagger_ui() -> impl IntoResponse {
+    Html(SWAGGER_UI)
+}
+
+static SWAGGER_UI: &str = r##"<!doctype html>
+<html lang="en">
+<head>
+  <meta charset="utf-8"/>
+  <title>rust-vue REST API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css"/>
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+<script>
+  window.onload = () => {
+    window.ui = SwaggerUIBundle({url: "/api/openapi.json", dom_id: "#swagger-ui"});
+  };
+</script>
+</body>
+</html>
+"##;
@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use flate2::{write::DeflateEncoder, Compression as Level};
+use futures_util::{stream::SplitSink, SinkExt};
+use tokio::sync::Notify;
+use tracing::{error, warn};
+
+use crate::protocol::{Compression, Encoding, ServerMessage};
+
+/// Message queued for a websocket client, encoded by the writer in the format and compression at
+/// the time of queuing
+pub(crate) enum Outgoing {
+    // boxed, frame messages being much larger than the other items
+    Message(Encoding, Box<ServerMessage>),
+    Ping,
+    Close(CloseFrame<'static>),
+}
+
+/// Bounded queue of the messages to a websocket client, dropping the oldest message if full
+///
+/// A slow client neither stalls the session nor grows the queue, the count of dropped messages
+/// is reported by `dropped_count` of the next message sent.
+pub(crate) struct Outbox {
+    inner: Mutex<Inner>,
+    // wakes the writer, a single consumer
+    notify: Notify,
+    capacity: usize,
+}
+
+struct Inner {
+    queue: VecDeque<Outgoing>,
+    dropped: u64,
+    // set once the session ended or the client disconnected
+    closed: bool,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize) -> Arc<Outbox> {
+        Arc::new(Outbox {
+            inner: Mutex::new(Inner { queue: VecDeque::new(), dropped: 0, closed: false }),
+            notify: Notify::new(),
+            capacity: capacity.max(1),
+        })
+    }
+
+    /// Queue the message, false if the client disconnected
+    pub fn push(&self, item: Outgoing) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed {
+            return false;
+        }
+        if inner.queue.len() >= self.capacity {
+            inner.queue.pop_front();
+            inner.dropped += 1;
+        }
+        inner.queue.push_back(item);
+        drop(inner);
+        self.notify.notify_one();
+        true
+    }
+
+    /// Count messages dropped before being queued, e.g. events skipped by the session lagging
+    pub fn dropped(&self, count: u64) {
+        self.inner.lock().unwrap().dropped += count;
+    }
+
+    /// Stop queuing, the writer sending the messages queued so far
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    /// The next message and the count of dropped messages to report with it, none once closed and empty
+    async fn pop(&self) -> Option<(Outgoing, u64)> {
+        loop {
+            {
+                let mut inner = self.inner.lock().unwrap();
+                if let Some(item) = inner.queue.pop_front() {
+                    // pings and close frames carry no count, leaving it to the next message
+                    let dropped = match item {
+                        Outgoing::Message(..) => std::mem::take(&mut inner.dropped),
+                        _ => 0,
+                    };
+                    return Some((item, dropped));
+                }
+                if inner.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+/// Send the queued messages to the client until the outbox is closed or the client disconnects
+pub(crate) async fn writer(outbox: Arc<Outbox>, mut sink: SplitSink<WebSocket, Message>) {
+    // deflate stream of the messages compressed since the client switched to deflate
+    let mut deflate = None;
+    // encoded message, reused while compressing and else handed over to the websocket message
+    let mut buf = Vec::new();
+    while let Some((item, dropped)) = outbox.pop().await {
+        let msg = match item {
+            Outgoing::Message(encoding, message) => {
+                if dropped > 0 {
+                    warn!(dropped, "client too slow, dropped messages");
+                }
+                let mut envelope = message.envelope();
+                envelope.dropped_count = (dropped > 0).then_some(dropped);
+                if let Err(e) = encoding.format.encode(&envelope, &mut buf) {
+                    error!(error = %e, "failed to encode message");
+                    continue;
+                }
+                match encoding.compression {
+                    Compression::None => {
+                        deflate = None;
+                        // the next buffer preallocated by the size of this message
+                        let capacity = buf.len();
+                        match encoding.format.message(std::mem::replace(&mut buf, Vec::with_capacity(capacity))) {
+                            Ok(msg) => msg,
+                            Err(e) => {
+                                error!(error = %e, "failed to encode message");
+                                continue;
+                            }
+                        }
+                    }
+                    Compression::Deflate => {
+                        let encoder = deflate.get_or_insert_with(|| DeflateEncoder::new(Vec::new(), Level::fast()));
+                        match compress(encoder, &buf) {
+                            Ok(msg) => msg,
+                            Err(e) => {
+                                // the stream is broken for the client
+                                error!(error = %e, "failed to compress message");
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            Outgoing::Ping => Message::Ping(Vec::new()),
+            Outgoing::Close(close) => Message::Close(Some(close)),
+        };
+        if sink.send(msg).await.is_err() {
+            break;
+        }
+    }
+    outbox.close();
+}
+
+// chunk of the deflate stream of the encoded message, completed by a sync flush
+fn compress(encoder: &mut DeflateEncoder<Vec<u8>>, data: &[u8]) -> std::io::Result<Message> {
+    encoder.write_all(data)?;
+    encoder.flush()?;
+    let capacity = encoder.get_ref().len();
+    Ok(Message::Binary(std::mem::replace(encoder.get_mut(), Vec::with_capacity(capacity))))
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::can::{CanEvent, Subscription, Timestamp};
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Frame};
+use crate::protocol::{format_frame, format_id, FrameTimestamp};
+
+// DTO - last frame of an id, as listed by `GET /api/overview`
+#[derive(Serialize, JsonSchema, ToSchema, Debug)]
+pub struct OverviewEntry {
+    pub interface: String,
+    pub id: String,
+    pub extended: bool,
+    // last frame in `cansend` notation
+    pub frame: String,
+    pub count: u64,
+    // interval between the last two frames, none after the first frame
+    pub period_ms: Option<f64>,
+    pub timestamp: FrameTimestamp,
+}
+
+// interface, extended flag and raw id
+type Key = (Arc<str>, bool, u32);
+
+struct Entry {
+    frame: CanAnyFrame,
+    count: u64,
+    period_ms: Option<f64>,
+    timestamp: Timestamp,
+}
+
+/// Table of the last frame of each id and interface, like the overview of `cansniffer`
+#[derive(Default)]
+pub struct Overview {
+    entries: Mutex<BTreeMap<Key, Entry>>,
+}
+
+impl Overview {
+    // bound of the ids tracked, ignoring further ids once reached, e.g. on random ids
+    const MAX_IDS: usize = 8192;
+
+    fn update(&self, interface: Arc<str>, frame: CanAnyFrame, timestamp: Timestamp) {
+        let mut entries = self.entries.lock().unwrap();
+        let full = entries.len() >= Overview::MAX_IDS;
+        let key = (interface, frame.is_extended(), frame.raw_id());
+        match entries.get_mut(&key) {
+            Some(entry) => {
+                let period = timestamp.monotonic.saturating_sub(entry.timestamp.monotonic);
+                *entry = Entry {
+                    frame,
+                    count: entry.count + 1,
+                    period_ms: Some(period.as_secs_f64() * 1000.0),
+                    timestamp,
+                };
+            }
+            None if !full => {
+                entries.insert(key, Entry { frame, count: 1, period_ms: None, timestamp });
+            }
+            None => (),
+        }
+    }
+
+    /// All ids seen, by interface and id
+    pub fn snapshot(&self) -> Vec<OverviewEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((interface, extended, _), entry)| OverviewEntry {
+                interface: interface.to_string(),
+                id: format_id(entry.frame.id()),
+                extended: *extended,
+                frame: format_frame(&entry.frame).0,
+                count: entry.count,
+                period_ms: entry.period_ms,
+                timestamp: entry.timestamp.into(),
+            })
+            .collect()
+    }
+}
+
+/// Update the overview by the received frames until shutdown, error frames excluded
+pub async fn tracker(overview: Arc<Overview>, events: broadcast::Receiver<CanEvent>, shutdown: CancellationToken) {
+    let mut events = Subscription::new(events, shutdown, "overview");
+    while let Some((interface, frame, timestamp, _)) = events.next_frame().await {
+        if !matches!(frame, CanAnyFrame::Error(_)) {
+            overview.update(interface, frame, timestamp);
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::frame::{can_frame_default, canfd_frame_default, CanAnyFrame, EmbeddedFrame, Frame};
+use crate::replay::ReplayFrame;
+
+// link type of SocketCAN frames, the CAN id in network byte order, see
+// https://www.tcpdump.org/linktypes/LINKTYPE_CAN_SOCKETCAN.html
+const LINKTYPE_CAN_SOCKETCAN: u16 = 227;
+
+// pcapng block types and options, see https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-02.html
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D0D0A;
+const BLOCK_INTERFACE: u32 = 0x0000_0001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+const OPT_END: u16 = 0;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+const OPT_SHB_USERAPPL: u16 = 4;
+
+// magics of pcap files, by timestamps in micro- or nanoseconds
+const PCAP_MICROS: u32 = 0xA1B2C3D4;
+const PCAP_NANOS: u32 = 0xA1B23C4D;
+
+// flags of the id word and FD frames, see linux/can.h
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CANFD_FDF: u8 = 0x04;
+// header of `struct can_frame` and `struct canfd_frame`, followed by the data
+const HEADER_LEN: usize = 8;
+const CAN_MTU: usize = 16;
+
+/// Writer of a pcapng capture, a section per recording with an interface per CAN device
+///
+/// Sections may be appended to an existing capture, each starting by its header.
+#[derive(Default)]
+pub struct Writer {
+    // index of each interface in the section, described on its first frame
+    interfaces: HashMap<String, u32>,
+}
+
+impl Writer {
+    /// Section header block, to be written first
+    pub fn header(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // section length not given
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        option(&mut body, OPT_SHB_USERAPPL, concat!("rust-vue ", env!("CARGO_PKG_VERSION")).as_bytes());
+        option(&mut body, OPT_END, &[]);
+        block(BLOCK_SECTION_HEADER, &body)
+    }
+
+    /// Enhanced packet block of the frame, preceded by the interface description block on the
+    /// first frame of the interface
+    pub fn frame(&mut self, interface: &str, frame: &CanAnyFrame, timestamp: SystemTime) -> Vec<u8> {
+        let mut blocks = Vec::new();
+        let index = match self.interfaces.get(interface) {
+            Some(index) => *index,
+            None => {
+                let index = self.interfaces.len() as u32;
+                self.interfaces.insert(interface.to_string(), index);
+                blocks.extend(interface_block(interface));
+                index
+            }
+        };
+
+        let packet = packet(frame);
+        // timestamps in nanoseconds, see `if_tsresol` of the interface
+        let nanos = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        let mut body = Vec::new();
+        body.extend_from_slice(&index.to_le_bytes());
+        body.extend_from_slice(&((nanos >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(nanos as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&packet);
+        pad(&mut body);
+        blocks.extend(block(BLOCK_ENHANCED_PACKET, &body));
+        blocks
+    }
+}
+
+fn interface_block(name: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&LINKTYPE_CAN_SOCKETCAN.to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    // no snapshot length limit
+    body.extend_from_slice(&0u32.to_le_bytes());
+    option(&mut body, OPT_IF_NAME, name.as_bytes());
+    option(&mut body, OPT_IF_TSRESOL, &[9]);
+    option(&mut body, OPT_END, &[]);
+    block(BLOCK_INTERFACE, &body)
+}
+
+fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+    let len = (12 + body.len()) as u32;
+    let mut block = Vec::with_capacity(len as usize);
+    block.extend_from_slice(&kind.to_le_bytes());
+    block.extend_from_slice(&len.to_le_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&len.to_le_bytes());
+    block
+}
+
+fn option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    pad(body);
+}
+
+fn pad(body: &mut Vec<u8>) {
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+/// The frame as `struct can_frame` or `struct canfd_frame`, the id word in network byte order
+fn packet(frame: &CanAnyFrame) -> Vec<u8> {
+    // the id with the flags of extended, remote and error frames
+    let id = frame.id_word();
+    let (len, flags, mtu) = match frame {
+        CanAnyFrame::Fd(fd) => (fd.data().len(), fd.flags().bits() | CANFD_FDF, HEADER_LEN + 64),
+        CanAnyFrame::Remote(_) => (frame.dlc(), 0, CAN_MTU),
+        CanAnyFrame::Normal(_) | CanAnyFrame::Error(_) => (frame.data().len(), 0, CAN_MTU),
+    };
+    let mut packet = Vec::with_capacity(mtu);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&[len as u8, flags, 0, 0]);
+    if !frame.is_remote_frame() {
+        packet.extend_from_slice(frame.data());
+    }
+    packet.resize(mtu, 0);
+    packet
+}
+
+/// Parse a pcapng or pcap capture of SocketCAN frames, with timestamps relative to the first frame
+///
+/// Packets of other link types are skipped; the interfaces are named by `if_name` of pcapng, left
+/// empty by pcap. Captures may have either byte order.
+pub fn parse(capture: &[u8]) -> Result<Vec<ReplayFrame>, String> {
+    let mut frames = match capture.get(..4).map(|magic| u32::from_le_bytes(magic.try_into().unwrap())) {
+        Some(BLOCK_SECTION_HEADER) => parse_pcapng(capture)?,
+        _ => parse_pcap(capture)?,
+    };
+    frames.sort_by_key(|(timestamp, _)| *timestamp);
+    let first = frames.first().map(|(timestamp, _)| *timestamp).unwrap_or_default();
+    Ok(frames
+        .into_iter()
+        .map(|(timestamp, mut frame)| {
+            frame.offset = timestamp.saturating_sub(first);
+            frame
+        })
+        .collect())
+}
+
+/// Whether the content is a pcapng or pcap capture, by its magic
+pub fn is_capture(content: &[u8]) -> bool {
+    let Some(magic) = content.get(..4) else { return false };
+    let (le, be) = (u32::from_le_bytes(magic.try_into().unwrap()), u32::from_be_bytes(magic.try_into().unwrap()));
+    le == BLOCK_SECTION_HEADER || [le, be].iter().any(|magic| [PCAP_MICROS, PCAP_NANOS].contains(magic))
+}
+
+// reader of integers of the byte order of the section or file
+#[derive(Clone, Copy)]
+struct Order {
+    big_endian: bool,
+}
+
+impl Order {
+    fn u16(self, bytes: &[u8], at: usize) -> Result<u16, String> {
+        let bytes: [u8; 2] = bytes.get(at..at + 2).ok_or(TRUNCATED)?.try_into().unwrap();
+        Ok(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(self, bytes: &[u8], at: usize) -> Result<u32, String> {
+        let bytes: [u8; 4] = bytes.get(at..at + 4).ok_or(TRUNCATED)?.try_into().unwrap();
+        Ok(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+}
+
+const TRUNCATED: &str = "truncated capture";
+
+// interface of a pcapng section
+struct Interface {
+    name: String,
+    can: bool,
+    // timestamp units per second
+    resolution: u64,
+}
+
+fn parse_pcapng(capture: &[u8]) -> Result<Vec<(Duration, ReplayFrame)>, String> {
+    let mut frames = Vec::new();
+    let mut order = Order { big_endian: false };
+    let mut interfaces = Vec::new();
+    let mut pos = 0;
+    while pos < capture.len() {
+        let block = &capture[pos..];
+        // the block type of the section header reads the same in either byte order
+        if order.u32(block, 0)? == BLOCK_SECTION_HEADER {
+            // a new section, of its own byte order and interfaces
+            let magic = block.get(8..12).ok_or(TRUNCATED)?;
+            order.big_endian = match u32::from_le_bytes(magic.try_into().unwrap()) {
+                BYTE_ORDER_MAGIC => false,
+                m if m.swap_bytes() == BYTE_ORDER_MAGIC => true,
+                _ => return Err("invalid pcapng byte order".to_string()),
+            };
+            interfaces.clear();
+        }
+        let kind = order.u32(block, 0)?;
+        let len = order.u32(block, 4)? as usize;
+        if len < 12 || !len.is_multiple_of(4) || len > block.len() {
+            return Err(format!("invalid pcapng block at offset {}", pos));
+        }
+        let body = &block[8..len - 4];
+        match kind {
+            BLOCK_INTERFACE => interfaces.push(parse_interface(order, body)?),
+            BLOCK_ENHANCED_PACKET => {
+                let interface = interfaces.get(order.u32(body, 0)? as usize)
+                    .ok_or_else(|| format!("undescribed interface at offset {}", pos))?;
+                let units = (order.u32(body, 4)? as u64) << 32 | order.u32(body, 8)? as u64;
+                let captured = order.u32(body, 12)? as usize;
+                let packet = body.get(20..20 + captured).ok_or(TRUNCATED)?;
+                if interface.can {
+                    let timestamp = Duration::from_nanos((units as u128 * 1_000_000_000 / interface.resolution as u128) as u64);
+                    let frame = parse_packet(packet).ok_or_else(|| format!("malformed frame at offset {}", pos))?;
+                    frames.push((timestamp, ReplayFrame { offset: Duration::ZERO, interface: interface.name.clone(), frame }));
+                }
+            }
+            _ => (),
+        }
+        pos += len;
+    }
+    Ok(frames)
+}
+
+fn parse_interface(order: Order, body: &[u8]) -> Result<Interface, String> {
+    let mut interface = Interface {
+        name: String::new(),
+        can: order.u16(body, 0)? == LINKTYPE_CAN_SOCKETCAN,
+        resolution: 1_000_000,
+    };
+    let mut pos = 8;
+    while pos + 4 <= body.len() {
+        let code = order.u16(body, pos)?;
+        let len = order.u16(body, pos + 2)? as usize;
+        let value = body.get(pos + 4..pos + 4 + len).ok_or(TRUNCATED)?;
+        match code {
+            OPT_END => break,
+            OPT_IF_NAME => interface.name = String::from_utf8_lossy(value).trim_end_matches('\0').to_string(),
+            OPT_IF_TSRESOL => {
+                // a power of 10, or of 2 if the most significant bit is set
+                let exponent = *value.first().ok_or(TRUNCATED)? as u32;
+                interface.resolution = match exponent & 0x80 {
+                    0 => 10u64.checked_pow(exponent),
+                    _ => 2u64.checked_pow(exponent & 0x7F),
+                }
+                .filter(|resolution| *resolution > 0)
+                .ok_or("invalid pcapng timestamp resolution")?;
+            }
+            _ => (),
+        }
+        pos += 4 + len.next_multiple_of(4);
+    }
+    Ok(interface)
+}
+
+fn parse_pcap(capture: &[u8]) -> Result<Vec<(Duration, ReplayFrame)>, String> {
+    let magic = capture.get(..4).ok_or("empty capture")?;
+    let (order, nanos) = match u32::from_le_bytes(magic.try_into().unwrap()) {
+        PCAP_MICROS => (Order { big_endian: false }, false),
+        PCAP_NANOS => (Order { big_endian: false }, true),
+        m if m.swap_bytes() == PCAP_MICROS => (Order { big_endian: true }, false),
+        m if m.swap_bytes() == PCAP_NANOS => (Order { big_endian: true }, true),
+        _ => return Err("not a pcap or pcapng capture".to_string()),
+    };
+    if order.u32(capture, 20)? & 0xFFFF != LINKTYPE_CAN_SOCKETCAN as u32 {
+        return Err("pcap capture not of link type CAN_SOCKETCAN".to_string());
+    }
+
+    let mut frames = Vec::new();
+    let mut pos = 24;
+    while pos < capture.len() {
+        let secs = order.u32(capture, pos)? as u64;
+        let fraction = order.u32(capture, pos + 4)? as u64;
+        let captured = order.u32(capture, pos + 8)? as usize;
+        let packet = capture.get(pos + 16..pos + 16 + captured).ok_or(TRUNCATED)?;
+        let timestamp = Duration::from_secs(secs)
+            + if nanos { Duration::from_nanos(fraction) } else { Duration::from_micros(fraction) };
+        let frame = parse_packet(packet).ok_or_else(|| format!("malformed frame at offset {}", pos))?;
+        frames.push((timestamp, ReplayFrame { offset: Duration::ZERO, interface: String::new(), frame }));
+        pos += 16 + captured;
+    }
+    Ok(frames)
+}
+
+/// Parse the frame of `struct can_frame` or `struct canfd_frame`, FD frames flagged by FDF or
+/// by their size
+fn parse_packet(packet: &[u8]) -> Option<CanAnyFrame> {
+    let id = u32::from_be_bytes(packet.get(..4)?.try_into().unwrap());
+    let (len, flags) = (*packet.get(4)? as usize, *packet.get(5)?);
+    if flags & CANFD_FDF != 0 || packet.len() > CAN_MTU {
+        let mut frame = canfd_frame_default();
+        frame.can_id = id;
+        frame.len = len.min(64) as u8;
+        frame.flags = flags & !CANFD_FDF;
+        let data = packet.get(HEADER_LEN..HEADER_LEN + frame.len as usize)?;
+        frame.data[..data.len()].copy_from_slice(data);
+        return Some(frame.into());
+    }
+    let mut frame = can_frame_default();
+    frame.can_id = id;
+    frame.can_dlc = len.min(8) as u8;
+    if id & CAN_RTR_FLAG == 0 {
+        let data = packet.get(HEADER_LEN..HEADER_LEN + frame.can_dlc as usize)?;
+        frame.data[..data.len()].copy_from_slice(data);
+    }
+    Some(frame.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logformats::fixtures::{assert_same, frames};
+
+    #[test]
+    fn pcapng_round_trip() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut capture = Vec::new();
+        // two sections, the interfaces described again in the second one
+        for section in 0..2u64 {
+            let mut writer = Writer::default();
+            capture.extend(writer.header());
+            for (i, frame) in frames().iter().enumerate() {
+                let interface = if i % 2 == 0 { "can0" } else { "vcan1" };
+                let timestamp = start + Duration::from_micros(section * 2000 + i as u64 * 250);
+                capture.extend(writer.frame(interface, frame, timestamp));
+            }
+        }
+        assert!(is_capture(&capture));
+
+        let parsed = parse(&capture).unwrap();
+        assert_eq!(parsed.len(), 10);
+        for (n, replayed) in parsed.iter().enumerate() {
+            let (section, i) = (n / 5, n % 5);
+            assert_eq!(replayed.offset, Duration::from_micros(section as u64 * 2000 + i as u64 * 250));
+            assert_eq!(replayed.interface, if i % 2 == 0 { "can0" } else { "vcan1" });
+            assert_same(&replayed.frame, &frames()[i]);
+        }
+    }
+
+    // pcapng section of big-endian byte order, of an interface of the timestamp resolution
+    fn big_endian_section(tsresol: u8, units: &[u64]) -> Vec<u8> {
+        let block = |kind: u32, body: Vec<u8>| {
+            let len = (12 + body.len()) as u32;
+            [&kind.to_be_bytes()[..], &len.to_be_bytes(), &body, &len.to_be_bytes()].concat()
+        };
+        let option = |code: u16, value: &[u8]| {
+            let mut option = [&code.to_be_bytes()[..], &(value.len() as u16).to_be_bytes(), value].concat();
+            pad(&mut option);
+            option
+        };
+        let mut section = block(BLOCK_SECTION_HEADER, [&BYTE_ORDER_MAGIC.to_be_bytes()[..], &[0, 1, 0, 0], &[0xFF; 8]].concat());
+        let interface = [
+            &LINKTYPE_CAN_SOCKETCAN.to_be_bytes()[..], &[0; 6],
+            &option(OPT_IF_NAME, b"can2"), &option(OPT_IF_TSRESOL, &[tsresol]), &option(OPT_END, &[]),
+        ].concat();
+        section.extend(block(BLOCK_INTERFACE, interface));
+        for (i, units) in units.iter().enumerate() {
+            let packet = packet(&frames()[i % 5]);
+            let mut body = [
+                &0u32.to_be_bytes()[..], &((units >> 32) as u32).to_be_bytes(), &(*units as u32).to_be_bytes(),
+                &(packet.len() as u32).to_be_bytes(), &(packet.len() as u32).to_be_bytes(), &packet,
+            ].concat();
+            pad(&mut body);
+            section.extend(block(BLOCK_ENHANCED_PACKET, body));
+        }
+        section
+    }
+
+    #[test]
+    fn pcapng_big_endian_by_timestamp_resolution() {
+        // milliseconds
+        let parsed = parse(&big_endian_section(3, &[5000, 5001, 5250])).unwrap();
+        let offsets: Vec<_> = parsed.iter().map(|frame| frame.offset).collect();
+        assert_eq!(offsets, [Duration::ZERO, Duration::from_millis(1), Duration::from_millis(250)]);
+        assert!(parsed.iter().all(|frame| frame.interface == "can2"));
+        assert_same(&parsed[1].frame, &frames()[1]);
+
+        // 1024 units per second, a power of 2
+        let parsed = parse(&big_endian_section(0x80 | 10, &[0, 1024])).unwrap();
+        assert_eq!(parsed[1].offset, Duration::from_secs(1));
+
+        assert!(parse(&big_endian_section(0x80 | 64, &[0])).is_err());
+        assert!(parse(&big_endian_section(20, &[0])).is_err());
+    }
+
+    #[test]
+    fn pcapng_sections_of_either_byte_order() {
+        let mut writer = Writer::default();
+        let start = UNIX_EPOCH + Duration::from_secs(10);
+        let mut capture = writer.header();
+        capture.extend(writer.frame("can0", &frames()[0], start));
+        capture.extend(big_endian_section(9, &[10_000_000_000 + 500]));
+        let parsed = parse(&capture).unwrap();
+        assert_eq!(parsed[1].interface, "can2");
+        assert_eq!(parsed[1].offset, Duration::from_nanos(500));
+    }
+
+    // pcap file of the magic, the records written by the byte order
+    fn pcap(magic: u32, big_endian: bool, records: &[(u32, u32)]) -> Vec<u8> {
+        let u32 = |n: u32| if big_endian { n.to_be_bytes() } else { n.to_le_bytes() };
+        let mut capture = [&u32(magic)[..], &[0; 16], &u32(LINKTYPE_CAN_SOCKETCAN as u32)].concat();
+        for (i, (secs, fraction)) in records.iter().enumerate() {
+            let packet = packet(&frames()[i % 5]);
+            let len = u32(packet.len() as u32);
+            capture.extend([&u32(*secs)[..], &u32(*fraction), &len, &len, &packet].concat());
+        }
+        capture
+    }
+
+    #[test]
+    fn pcap_of_either_byte_order_and_resolution() {
+        let capture = pcap(PCAP_MICROS, false, &[(100, 999_999), (101, 0), (101, 250)]);
+        assert!(is_capture(&capture));
+        let parsed = parse(&capture).unwrap();
+        let offsets: Vec<_> = parsed.iter().map(|frame| frame.offset).collect();
+        assert_eq!(offsets, [Duration::ZERO, Duration::from_micros(1), Duration::from_micros(251)]);
+        assert!(parsed.iter().all(|frame| frame.interface.is_empty()));
+        for (i, replayed) in parsed.iter().enumerate() {
+            assert_same(&replayed.frame, &frames()[i]);
+        }
+
+        let capture = pcap(PCAP_NANOS, true, &[(7, 0), (7, 1), (7, 2), (8, 0)]);
+        assert!(is_capture(&capture));
+        let parsed = parse(&capture).unwrap();
+        assert_eq!(parsed[1].offset, Duration::from_nanos(1));
+        assert_eq!(parsed[3].offset, Duration::from_secs(1));
+        assert_same(&parsed[3].frame, &frames()[3]);
+
+        let mut truncated = pcap(PCAP_MICROS, false, &[(1, 0)]);
+        truncated.pop();
+        assert_eq!(parse(&truncated).err().as_deref(), Some(TRUNCATED));
+        assert!(!is_capture(b"(1.0) can0 123#00"));
+    }
+}
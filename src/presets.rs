@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::api::SendFrame;
+
+// DTO - named frames of `PUT /api/presets/:name`, written in order by `POST /api/presets/:name/send`
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct Preset {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub frames: Vec<SendFrame>,
+}
+
+// DTO - preset with its name, as listed by `GET /api/presets`
+#[derive(Serialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct NamedPreset {
+    pub name: String,
+    #[serde(flatten)]
+    pub preset: Preset,
+}
+
+/// Library of presets, persisted as JSON object of the presets by name, e.g. buttons of a test
+/// bench like "Unlock doors"
+pub struct Presets {
+    path: PathBuf,
+    presets: Mutex<BTreeMap<String, Preset>>,
+}
+
+impl Presets {
+    /// Load the presets of the file, none if the file does not exist yet
+    pub fn open(path: &Path) -> Result<Presets, String> {
+        let presets = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| format!("malformed presets {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("failed to read presets {}: {}", path.display(), e)),
+        };
+        Ok(Presets { path: path.to_path_buf(), presets: Mutex::new(presets) })
+    }
+
+    pub fn list(&self) -> Vec<NamedPreset> {
+        self.presets
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, preset)| NamedPreset { name: name.clone(), preset: preset.clone() })
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Preset> {
+        self.presets.lock().unwrap().get(name).cloned()
+    }
+
+    /// Store the preset, replacing a preset of the same name; true if created
+    pub fn put(&self, name: &str, preset: Preset) -> Result<bool, String> {
+        let mut presets = self.presets.lock().unwrap();
+        let mut changed = presets.clone();
+        let created = changed.insert(name.to_string(), preset).is_none();
+        self.save(&changed)?;
+        *presets = changed;
+        Ok(created)
+    }
+
+    /// Remove the preset, false if unknown
+    pub fn delete(&self, name: &str) -> Result<bool, String> {
+        let mut presets = self.presets.lock().unwrap();
+        if !presets.contains_key(name) {
+            return Ok(false);
+        }
+        let mut changed = presets.clone();
+        changed.remove(name);
+        self.save(&changed)?;
+        *presets = changed;
+        Ok(true)
+    }
+
+    fn save(&self, presets: &BTreeMap<String, Preset>) -> Result<(), String> {
+        write_json_atomic(&self.path, presets)
+    }
+}
+
+/// Write the value as JSON file, replacing the file by a complete one, not leaving a
+/// truncated file on failure
+pub(crate) fn write_json_atomic<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, json)
+        .and_then(|_| std::fs::rename(&temporary, path))
+        .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
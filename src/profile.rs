@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::decode::{self, Decoder};
+use crate::frame::{ExtendedId, Id, StandardId};
+use crate::simulate::Message;
+
+// cycle time of a message neither given by the profile nor by `GenMsgCycleTime` of the DBC
+const DEFAULT_CYCLE: Duration = Duration::from_millis(100);
+
+// curve of a signal over the time since the start of the simulation, in physical units
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Curve {
+    Constant(f64),
+    // starting at `min`, reaching `max` after half the period
+    Sine { min: f64, max: f64, period_s: f64 },
+    // holding `to` after the duration, unless repeated
+    Ramp { from: f64, to: f64, duration_s: f64, #[serde(default)] repeat: bool },
+    // `[<s>, <value>]` interpolated linearly, repeated after the last point
+    Points(Vec<(f64, f64)>),
+}
+
+impl Curve {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Curve::Sine { period_s, .. } if period_s.is_nan() || *period_s <= 0.0 => Err(format!("invalid period {}", period_s)),
+            Curve::Ramp { duration_s, .. } if duration_s.is_nan() || *duration_s <= 0.0 => Err(format!("invalid duration {}", duration_s)),
+            Curve::Points(points) if points.is_empty() => Err("empty points".to_string()),
+            Curve::Points(points)
+                if points.iter().any(|p| !p.0.is_finite() || p.0 < 0.0) || points.windows(2).any(|w| w[0].0 >= w[1].0) => {
+                Err("points not in increasing time".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn value(&self, t: f64) -> f64 {
+        match self {
+            Curve::Constant(value) => *value,
+            Curve::Sine { min, max, period_s } => min + (max - min) * (1.0 - (2.0 * PI * t / period_s).cos()) / 2.0,
+            Curve::Ramp { from, to, duration_s, repeat } => {
+                let t = if *repeat { t % duration_s } else { t.min(*duration_s) };
+                from + (to - from) * t / duration_s
+            }
+            Curve::Points(points) => {
+                let last = points[points.len() - 1];
+                let t = if last.0 > 0.0 { t % last.0 } else { t };
+                match points.windows(2).find(|w| t < w[1].0) {
+                    Some([(t0, v0), (t1, v1)]) if t >= *t0 => v0 + (v1 - v0) * (t - t0) / (t1 - t0),
+                    // the first point held until its time
+                    Some(_) => points[0].1,
+                    None => last.1,
+                }
+            }
+        }
+    }
+}
+
+// signal of the profile, e.g. `EngineSpeed = { points = [[0, 800], [10, 6000]], noise = 20 }`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct SignalProfile {
+    #[serde(flatten)]
+    curve: Curve,
+    // amplitude of the random noise added to the curve
+    #[serde(default)]
+    noise: f64,
+}
+
+// message of the profile, of its name in the DBC database
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct MessageProfile {
+    name: String,
+    cycle_ms: Option<u64>,
+    #[serde(default)]
+    signals: HashMap<String, SignalProfile>,
+}
+
+// profile of `--demo-profile`, a `[[message]]` table per message
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    #[serde(default, rename = "message")]
+    messages: Vec<MessageProfile>,
+}
+
+/// Signal curves of a message of the DBC database, encoded into its payload
+#[derive(Debug, PartialEq)]
+pub struct Signals {
+    message: can_dbc::Message,
+    signals: HashMap<String, SignalProfile>,
+}
+
+impl Signals {
+    /// Payload of the values at `t` seconds, `noise` giving random numbers in [-1, 1]
+    pub fn encode(&self, t: f64, mut noise: impl FnMut() -> f64) -> Vec<u8> {
+        let values: HashMap<&str, f64> = self.signals.iter()
+            .map(|(name, signal)| (name.as_str(), signal.curve.value(t) + signal.noise * noise()))
+            .collect();
+        decode::encode(&self.message, |signal| values.get(signal.name.as_str()).copied())
+    }
+}
+
+// id of the frames of the message, keyed by the DBC database as of [decode::message_id]
+fn frame_id(key: u32) -> Option<Id> {
+    if key & 1 << 31 != 0 {
+        ExtendedId::new(key & !(1 << 31)).map(Id::Extended)
+    } else {
+        u16::try_from(key).ok().and_then(StandardId::new).map(Id::Standard)
+    }
+}
+
+/// Load the profile in TOML format, the messages of the DBC database sent at the cycle time
+/// given or `GenMsgCycleTime` of the DBC, with their signals following the curves given and
+/// the ones missing sent as raw 0
+pub fn load(path: &Path, decoder: Option<&Decoder>) -> Result<Arc<Vec<Message>>, String> {
+    let decoder = decoder.ok_or_else(|| "--demo-profile requires --dbc".to_string())?;
+    let s = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read demo profile {}: {}", path.display(), e))?;
+    let file: ProfileFile = toml::from_str(&s)
+        .map_err(|e| format!("invalid demo profile {}: {}", path.display(), e))?;
+    let mut messages = Vec::new();
+    for profile in file.messages {
+        let invalid = |e: String| format!("{}: message {}: {}", path.display(), profile.name, e);
+        let (key, message) = decoder.message_by_name(&profile.name).ok_or_else(|| invalid("not found in DBC".to_string()))?;
+        let id = frame_id(key).ok_or_else(|| invalid(format!("invalid id {:X}", key)))?;
+        if message.size > 8 {
+            return Err(invalid(format!("{} bytes exceeding a classic frame", message.size)));
+        }
+        for (name, signal) in &profile.signals {
+            if !message.signals.iter().any(|s| s.name == *name) {
+                return Err(invalid(format!("signal {} not found", name)));
+            }
+            if signal.noise.is_nan() || signal.noise < 0.0 {
+                return Err(invalid(format!("signal {}: invalid noise {}", name, signal.noise)));
+            }
+            signal.curve.validate().map_err(|e| invalid(format!("signal {}: {}", name, e)))?;
+        }
+        let period = match profile.cycle_ms {
+            Some(0) => return Err(invalid("invalid cycle time 0".to_string())),
+            Some(ms) => Duration::from_millis(ms),
+            None => decoder.cycle_time(key).unwrap_or(DEFAULT_CYCLE),
+        };
+        let signals = Signals { message: message.clone(), signals: profile.signals };
+        messages.push(Message::signals(id, period, signals));
+    }
+    if messages.is_empty() {
+        return Err(format!("{}: empty demo profile", path.display()));
+    }
+    Ok(Arc::new(messages))
+}
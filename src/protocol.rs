@@ -0,0 +1,663 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use axum::extract::ws::Message;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::alerts::AlertEvent;
+use crate::busstate::BusStateEvent;
+use crate::can::{CanError, Priority, Timestamp};
+use crate::canopen::{NodeEvent, Service};
+use crate::decode::DecodedFrame;
+use crate::frame::{
+    CanAnyFrame, CanDataFrame, CanFdFrame, CanRemoteFrame, EmbeddedFrame, ExtendedId, FdFlags, Id, StandardId,
+    CAN_SFF_MASK,
+};
+use crate::names::FrameName;
+use crate::netlink::InterfaceState;
+use crate::obd::Telemetry;
+use crate::stats::BusStats;
+use crate::timeouts::TimeoutEvent;
+use crate::version::BuildInfo;
+
+/// Version of the messages to the client, incremented on incompatible changes
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Message to the client, tagged by its type, the content given by `data`
+///
+/// Sent within an [Envelope] carrying the protocol version, e.g.
+/// `{"version": 1, "type": "frame", "data": {"interface": "vcan0", "frame": "123#DEADBEEF", ...}}`
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Frame(FrameMessage),
+    // frames coalesced by the batch interval of the client, in order of reception
+    Frames(Vec<FrameMessage>),
+    Notice(NoticeMessage),
+    Status(StatusMessage),
+    // state of the SocketCAN interfaces, bitrate, bus state and error counters
+    Interface(Vec<InterfaceState>),
+    Error(ErrorMessage),
+    Ack(AckMessage),
+    Isotp(IsoTpMessage),
+    Telemetry(Telemetry),
+    BusState(BusStateEvent),
+    Canopen(NodeEvent),
+    Alert(AlertEvent),
+    // periodic message missing, or received again, see `--cycle-time`
+    Timeout(TimeoutEvent),
+    // value of a signal of `{"subscribe_signal": ...}`, changed beyond the deadband
+    Signal(SignalMessage),
+    // frame of a LIN bus of the `sllin` transport, instead of `frame`
+    Lin(crate::lin::LinMessage),
+    // summary of a pause, sent on resuming before the frames held meanwhile
+    Resume(ResumeMessage),
+    #[cfg(feature = "j1939")]
+    J1939(crate::j1939::ParameterGroup),
+    #[cfg(feature = "nmea2000")]
+    Nmea2000(crate::nmea2000::PgnMessage),
+}
+
+impl ServerMessage {
+    pub fn notice(message: impl Into<String>) -> ServerMessage {
+        ServerMessage::Notice(NoticeMessage { message: message.into() })
+    }
+
+    pub fn error(reason: ErrorReason, message: impl Into<String>) -> ServerMessage {
+        ServerMessage::Error(ErrorMessage { reason, message: message.into(), input: None, bus_error: None })
+    }
+
+    /// Error of a frame sent by the client, given the frame as sent
+    pub fn rejected(reason: ErrorReason, message: impl Into<String>, input: &str) -> ServerMessage {
+        ServerMessage::Error(ErrorMessage {
+            reason,
+            message: message.into(),
+            input: Some(input.to_string()),
+            bus_error: None,
+        })
+    }
+
+    pub fn ack(command: &str, detail: impl Into<String>) -> ServerMessage {
+        ServerMessage::Ack(AckMessage { command: command.to_string(), detail: detail.into() })
+    }
+
+    pub fn envelope(&self) -> Envelope<'_> {
+        Envelope { version: PROTOCOL_VERSION, dropped_count: None, server_time: ServerTime::now(), message: self }
+    }
+}
+
+// DTO - message as sent to the client, with the protocol version and the count of messages
+// dropped before it if the client is too slow
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct Envelope<'a> {
+    pub version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropped_count: Option<u64>,
+    // clock of the service at sending the message
+    pub server_time: ServerTime,
+    #[serde(flatten)]
+    pub message: &'a ServerMessage,
+}
+
+// DTO - frame received from the CAN bus, in `cansend` notation
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct FrameMessage {
+    // shared with the events of the interface rather than copied for each frame and client
+    pub interface: Arc<str>,
+    pub frame: String,
+    pub extended: bool,
+    // remote transmission request, without data, see [parse_frame]
+    pub remote: bool,
+    pub fd: Option<FdInfo>,
+    pub timestamp: FrameTimestamp,
+    // received of the bus or transmitted by this service, by the websocket client of the id if
+    // written by a client, see `GET /api/clients`
+    #[serde(default)]
+    pub direction: FrameDirection,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<u64>,
+    pub decoded: Option<DecodedFrame>,
+    // name of the id by `--names`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<Box<FrameName>>,
+    // CANopen service of `--canopen`
+    pub canopen: Option<Service>,
+    // payload decoded by a decoder registered for the id, see [crate::codec::FrameDecoder]
+    pub custom: Option<serde_json::Value>,
+    // bitmap of the bytes changed since the last frame of the id in delta mode, bit 0 for byte 0,
+    // in hex, e.g. `5` for bytes 0 and 2; all bytes of the first frame of an id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed: Option<String>,
+}
+
+// DTO - value of a signal subscribed by the client, decoded by `--dbc`; sent of the first frame
+// of the signal on each interface, then once changed by more than the deadband of the subscription
+// since the value last sent
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct SignalMessage {
+    pub interface: Arc<str>,
+    pub message: String,
+    pub signal: String,
+    pub value: f64,
+    pub unit: String,
+    pub timestamp: FrameTimestamp,
+}
+
+// DTO - direction of a frame, `rx` of the frames observed on the bus, `tx` of the frames written
+// by this service, echoed by SocketCAN and simulated interfaces
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameDirection {
+    #[default]
+    Rx,
+    Tx,
+}
+
+// DTO - informational message, e.g. a CAN device connected or the progress of a replay
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct NoticeMessage {
+    pub message: String,
+}
+
+// DTO - status of the service, sent on connecting and with the statistics once per second; the
+// build of the service and whether it is read-only only on connecting
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct StatusMessage {
+    pub service_url: String,
+    pub stats: Option<Vec<BusStats>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<BuildInfo>,
+    // writing disabled by `--read-only`, sent only on connecting too, the UI hiding the controls
+    // writing frames
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+}
+
+// DTO - error reported to the client, the input given for frames sent by the client, the
+// bus error only for error frames
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ErrorMessage {
+    pub reason: ErrorReason,
+    pub message: String,
+    pub input: Option<String>,
+    pub bus_error: Option<BusError>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorReason {
+    // error frame of the CAN controller
+    Bus,
+    // CAN device missing or failed
+    CanDevice,
+    // frame sent by the client not in `cansend` notation
+    Parse,
+    // frame rejected by the CAN device, e.g. its transmit queue being full
+    Write,
+    UnknownInterface,
+    RateLimit,
+    InvalidFilter,
+    Isotp,
+    // frame or ISO-TP message of a session of `/ws/monitor`, or of any session with `--read-only`
+    ReadOnly,
+    // id not in the allowlist of the token of the session
+    Forbidden,
+    // signal subscribed missing in the DBC database, or no database given
+    UnknownSignal,
+    // message of the client exceeding `--max-message-size`, closing the connection
+    TooLarge,
+}
+
+// DTO - acknowledge of a control message, e.g. `subscribe` with the filter subscribed to,
+// or `frame` with a frame written to the CAN bus
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct AckMessage {
+    pub command: String,
+    pub detail: String,
+}
+
+// DTO - summary of a pause of the stream by `{"control": "pause"}`, its duration in seconds, the
+// frames held meanwhile, sent next, and the frames dropped by exceeding `--pause-len`
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ResumeMessage {
+    pub paused: f64,
+    pub held: u64,
+    pub dropped: u64,
+}
+
+// DTO - reception time of a frame in seconds, the wall clock since epoch, the monotonic clock
+// since service start, and the raw clock of the CAN controller if supported
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone, Copy)]
+pub struct FrameTimestamp {
+    pub wall: f64,
+    pub monotonic: f64,
+    pub hardware: Option<f64>,
+}
+
+impl From<Timestamp> for FrameTimestamp {
+    fn from(timestamp: Timestamp) -> FrameTimestamp {
+        let wall = timestamp.wall.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        FrameTimestamp {
+            wall: wall.as_secs_f64(),
+            monotonic: timestamp.monotonic.as_secs_f64(),
+            hardware: timestamp.hardware.map(|hardware| hardware.as_secs_f64()),
+        }
+    }
+}
+
+// DTO - clock of the service in seconds, the wall clock since epoch and the monotonic clock of the
+// frame timestamps, for clients to correlate the timestamps with their own clock and to measure
+// the latency of the messages
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone, Copy)]
+pub struct ServerTime {
+    pub wall: f64,
+    pub monotonic: f64,
+}
+
+impl ServerTime {
+    pub fn now() -> ServerTime {
+        let now = FrameTimestamp::from(Timestamp::now());
+        ServerTime { wall: now.wall, monotonic: now.monotonic }
+    }
+}
+
+// CAN FD specific flags, present only if `data` is a CAN FD frame
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct FdInfo {
+    pub brs: bool,
+    pub esi: bool,
+}
+
+// DTO - error frame of the CAN controller, by the classes of errors it reports
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct BusError {
+    pub interface: String,
+    pub classes: Vec<ErrorClass>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    TxTimeout,
+    ArbitrationLost,
+    ControllerOverflow,
+    ErrorWarning,
+    ErrorPassive,
+    ErrorActive,
+    ProtocolViolation,
+    TransceiverError,
+    NoAck,
+    BusOff,
+    BusError,
+    Restarted,
+}
+
+impl ErrorClass {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ErrorClass::TxTimeout => "transmit timeout",
+            ErrorClass::ArbitrationLost => "arbitration lost",
+            ErrorClass::ControllerOverflow => "controller buffer overflow",
+            ErrorClass::ErrorWarning => "error warning",
+            ErrorClass::ErrorPassive => "error passive",
+            ErrorClass::ErrorActive => "error active",
+            ErrorClass::ProtocolViolation => "protocol violation",
+            ErrorClass::TransceiverError => "transceiver error",
+            ErrorClass::NoAck => "no ack",
+            ErrorClass::BusOff => "bus-off",
+            ErrorClass::BusError => "bus error",
+            ErrorClass::Restarted => "restarted",
+        }
+    }
+}
+
+// Control messages sent by the WebUI, e.g. `{"subscribe": {"id": "0x123", "mask": "0x7FF"}}`
+#[derive(Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlMessage {
+    Subscribe(FilterSpec),
+    Unsubscribe(FilterSpec),
+    Format(Format),
+    // compression of the messages to the client, e.g. `{"compress": "deflate"}`
+    Compress(Compression),
+    Isotp(IsoTpMessage),
+    // batch interval in milliseconds, e.g. `{"batch": 50}`, 0 sending each frame at once
+    Batch(u64),
+    // filter expression applied in addition to the subscriptions, e.g.
+    // `{"filter": "id == 0x123 && data[0] > 0x80"}`, `{"filter": null}` removing it
+    Filter(Option<String>),
+    // delta mode, e.g. `{"delta": true}`, sending frames only if their payload changed
+    Delta(bool),
+    // pause or resume the frames, e.g. `{"control": "pause"}`, other messages sent meanwhile
+    Control(StreamControl),
+    // signal of `--dbc`, optionally qualified by its message, sent on changing, e.g.
+    // `{"subscribe_signal": "EngineSpeed"}` or `{"subscribe_signal": {"signal":
+    // "EngineData.EngineSpeed", "deadband": 50}}`; the frames are sent only of the ids subscribed
+    // then, none if no `subscribe`
+    SubscribeSignal(SignalSpec),
+    UnsubscribeSignal(String),
+    // precedence of the frames written next in the transmit queue of the interface, e.g.
+    // `{"priority": "high"}`, `normal` initially
+    Priority(Priority),
+}
+
+// Signal subscribed by its name, or with the deadband of its changes, 0 sending any change
+#[derive(Deserialize, JsonSchema, Debug)]
+#[serde(untagged)]
+pub enum SignalSpec {
+    Name(String),
+    Deadband {
+        signal: String,
+        #[serde(default)]
+        deadband: f64,
+    },
+}
+
+impl SignalSpec {
+    pub fn signal(&self) -> &str {
+        match self {
+            SignalSpec::Name(signal) | SignalSpec::Deadband { signal, .. } => signal.trim(),
+        }
+    }
+
+    pub fn deadband(&self) -> f64 {
+        match self {
+            SignalSpec::Name(_) => 0.0,
+            SignalSpec::Deadband { deadband, .. } => *deadband,
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamControl {
+    Pause,
+    Resume,
+}
+
+// CAN id and mask as hex strings; mask defaults to an exact match of the id
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct FilterSpec {
+    pub id: String,
+    pub mask: Option<String>,
+}
+
+// ISO-TP payload as hex string, sent by the client on `tx_id` or received on `rx_id` in reply;
+// without data the client just opens the channel for reception
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct IsoTpMessage {
+    pub tx_id: String,
+    pub rx_id: String,
+    pub data: Option<String>,
+    pub interface: Option<String>,
+}
+
+/// Encoding of the messages to the client, negotiated by `{"format": "cbor"}`
+///
+/// JSON is sent as text messages, CBOR and MessagePack as binary messages with the same
+/// structure as the JSON messages.
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Cbor,
+    #[serde(alias = "messagepack")]
+    Msgpack,
+}
+
+impl Format {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Cbor => "cbor",
+            Format::Msgpack => "msgpack",
+        }
+    }
+
+    /// Encode the message into the buffer, cleared first, so a buffer reused for the messages of
+    /// a client is grown to the size of the largest batch once rather than for each message
+    pub fn encode(&self, data: &Envelope, buf: &mut Vec<u8>) -> Result<(), ProtocolError> {
+        buf.clear();
+        let encoded = match self {
+            Format::Json => serde_json::to_writer(&mut *buf, data).map_err(|e| e.to_string()),
+            Format::Cbor => ciborium::ser::into_writer(data, &mut *buf).map_err(|e| e.to_string()),
+            Format::Msgpack => rmp_serde::encode::write_named(buf, data).map_err(|e| e.to_string()),
+        };
+        encoded.map_err(ProtocolError::Encode)
+    }
+
+    /// Websocket message of the encoded data, taking over the buffer; text for JSON
+    pub(crate) fn message(&self, data: Vec<u8>) -> Result<Message, ProtocolError> {
+        match self {
+            Format::Json => String::from_utf8(data).map(Message::Text).map_err(|e| ProtocolError::Encode(e.to_string())),
+            Format::Cbor | Format::Msgpack => Ok(Message::Binary(data)),
+        }
+    }
+
+    /// Decode a control message of a binary message; JSON text messages are handled by [parse_control]
+    pub fn decode_control(&self, buf: &[u8]) -> Result<ControlMessage, ProtocolError> {
+        let decoded = match self {
+            Format::Json => serde_json::from_slice(buf).map_err(|e| e.to_string()),
+            Format::Cbor => ciborium::de::from_reader(buf).map_err(|e| e.to_string()),
+            Format::Msgpack => rmp_serde::from_slice(buf).map_err(|e| e.to_string()),
+        };
+        decoded.map_err(ProtocolError::Control)
+    }
+}
+
+/// Compression of the messages to the client, negotiated by `{"compress": "deflate"}`
+///
+/// Deflated messages are sent as binary messages, each one a chunk of a single raw deflate stream
+/// of the encoded messages completed by a sync flush, so the repetitive messages are compressed by
+/// the history of the stream like the context takeover of permessage-deflate, an extension not
+/// negotiated by the websocket of axum. Each switch to `deflate` starts a new stream,
+/// e.g. decompressed by a `DecompressionStream("deflate-raw")` of the browser.
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+
+impl Compression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Deflate => "deflate",
+        }
+    }
+}
+
+/// Format and compression of the messages to a client
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Encoding {
+    pub format: Format,
+    pub compression: Compression,
+}
+
+/// Parse a control message of a JSON text message, e.g. `{"subscribe": {"id": "123"}}`
+pub fn parse_control(t: &str) -> Result<ControlMessage, ProtocolError> {
+    serde_json::from_str(t).map_err(|e| ProtocolError::Control(e.to_string()))
+}
+
+/// Parse a frame in `cansend` notation
+///
+/// * classic CAN: `<id>#<data>`, e.g. `123#DEADBEEF`
+/// * CAN FD: `<id>##<flags><data>`, e.g. `123##1DEADBEEF`, where the single
+///   hex digit `<flags>` carries BRS (0x1) and ESI (0x2)
+/// * remote transmission request: `<id>#R`, optionally with the requested length, e.g. `123#R4`
+///
+/// The id is extended (29 bit) if given by 8 hex digits, e.g. `00000123#DEADBEEF`,
+/// or if exceeding the standard range of 0x7FF.
+pub fn parse_frame(t: &str) -> Result<CanAnyFrame, FrameError> {
+    let (id, hexdata) = t.split_once('#').ok_or(FrameError::MissingSeparator)?;
+    let id = parse_frame_id(id)?;
+    if let Some(fddata) = hexdata.strip_prefix('#') {
+        return parse_fd_frame(id, fddata);
+    }
+    if let Some(dlc) = hexdata.strip_prefix('R') {
+        return parse_remote_frame(id, dlc);
+    }
+    let data = parse_data(hexdata, MAX_DATA)?;
+    CanDataFrame::new(id, &data).map(CanAnyFrame::Normal).ok_or(FrameError::PayloadTooLong(data.len()))
+}
+
+// payload of classic frames and of CAN FD frames in bytes
+const MAX_DATA: usize = 8;
+const MAX_FD_DATA: usize = 64;
+
+/// Reason of a frame rejected by [parse_frame], sent to the client in the `message` of the error
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    #[error("missing # between id and data")]
+    MissingSeparator,
+    // empty, not hex or more than 8 digits
+    #[error("invalid id, expecting 3 or 8 hex digits")]
+    InvalidId,
+    // exceeding the 29 bits of extended ids
+    #[error("id {0:X} exceeding 29 bits")]
+    IdOutOfRange(u32),
+    #[error("invalid hex data")]
+    InvalidHex,
+    #[error("odd count of hex digits")]
+    OddLength,
+    // bytes given, exceeding 8 bytes of classic frames or 64 bytes of CAN FD frames
+    #[error("payload of {0} bytes too long")]
+    PayloadTooLong(usize),
+    #[error("invalid CAN FD flags, expecting a hex digit")]
+    InvalidFlags,
+    #[error("invalid remote frame length, expecting 0 to 8")]
+    InvalidDlc,
+}
+
+/// Error of the messages exchanged with the clients, of their frames, numbers and control
+/// messages, or of encoding a message
+#[derive(thiserror::Error, Debug)]
+pub enum ProtocolError {
+    #[error("invalid frame, {0}")]
+    Frame(#[from] FrameError),
+    #[error("invalid hex number {0}")]
+    Number(String),
+    #[error("invalid control message: {0}")]
+    Control(String),
+    #[error("failed to encode message: {0}")]
+    Encode(String),
+}
+
+impl From<&CanError> for ErrorReason {
+    fn from(error: &CanError) -> ErrorReason {
+        match error {
+            CanError::UnknownInterface => ErrorReason::UnknownInterface,
+            CanError::Missing => ErrorReason::CanDevice,
+            CanError::Failed(_) | CanError::QueueFull | CanError::InvalidLin => ErrorReason::Write,
+            CanError::ReadOnly => ErrorReason::ReadOnly,
+        }
+    }
+}
+
+/// Parse a frame in `cansend` notation with optional interface prefix, e.g. `can1 123#DEADBEEF`
+pub(crate) fn parse_frame_command(t: &str) -> Result<(Option<&str>, CanAnyFrame), FrameError> {
+    let (interface, t) = match t.trim().split_once(' ') {
+        Some((interface, t)) => (Some(interface), t),
+        None => (None, t.trim()),
+    };
+    Ok((interface, parse_frame(t)?))
+}
+
+pub(crate) fn parse_frame_id(t: &str) -> Result<Id, FrameError> {
+    const EXTENDED_ID_DIGITS: usize = 8;
+    if t.is_empty() || t.len() > EXTENDED_ID_DIGITS || !t.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(FrameError::InvalidId);
+    }
+    let id = u32::from_str_radix(t, 16).or(Err(FrameError::InvalidId))?;
+
+    if t.len() == EXTENDED_ID_DIGITS || id > CAN_SFF_MASK {
+        ExtendedId::new(id).map(Id::Extended).ok_or(FrameError::IdOutOfRange(id))
+    } else {
+        StandardId::new(id as u16).map(Id::Standard).ok_or(FrameError::IdOutOfRange(id))
+    }
+}
+
+/// Decode the hex payload of at most `max` bytes, rejecting longer input before decoding it
+fn parse_data(hexdata: &str, max: usize) -> Result<Vec<u8>, FrameError> {
+    if hexdata.len() > 2 * max {
+        return Err(FrameError::PayloadTooLong(hexdata.len().div_ceil(2)));
+    }
+    hex::decode(hexdata.as_bytes()).map_err(|e| match e {
+        hex::FromHexError::OddLength => FrameError::OddLength,
+        _ => FrameError::InvalidHex,
+    })
+}
+
+pub(crate) fn parse_fd_frame(id: Id, fddata: &str) -> Result<CanAnyFrame, FrameError> {
+    let mut chars = fddata.chars();
+    let flags = chars.next().and_then(|c| c.to_digit(16)).ok_or(FrameError::InvalidFlags)?;
+    let flags = FdFlags::from_bits_truncate(flags as u8) & (FdFlags::BRS | FdFlags::ESI);
+    let data = parse_data(chars.as_str(), MAX_FD_DATA)?;
+
+    CanFdFrame::with_flags(id, &data, flags)
+        .map(CanAnyFrame::Fd)
+        .ok_or(FrameError::PayloadTooLong(data.len()))
+}
+
+pub(crate) fn parse_remote_frame(id: Id, dlc: &str) -> Result<CanAnyFrame, FrameError> {
+    let dlc = match dlc {
+        "" => 0,
+        dlc if dlc.len() == 1 => dlc.parse::<usize>().or(Err(FrameError::InvalidDlc))?,
+        _ => return Err(FrameError::InvalidDlc),
+    };
+    CanRemoteFrame::new_remote(id, dlc)
+        .map(CanAnyFrame::Remote)
+        .ok_or(FrameError::InvalidDlc)
+}
+
+pub(crate) fn parse_hex_u32(t: &str) -> Result<u32, ProtocolError> {
+    let digits = t.trim();
+    let digits = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")).unwrap_or(digits);
+    u32::from_str_radix(digits, 16).map_err(|_| ProtocolError::Number(t.to_string()))
+}
+
+/// Format a received frame in `cansend` notation, see [parse_frame]
+///
+/// Standard ids are formatted by 3 hex digits, extended ids by 8 hex digits.
+pub fn format_frame(frame: &CanAnyFrame) -> (String, Option<FdInfo>) {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    // formatted into a single allocation of the id, the separators and flags and the payload
+    let data = frame.data();
+    let mut fmt = String::with_capacity(8 + 3 + 2 * data.len());
+    let _ = match frame.id() {
+        Id::Standard(id) => write!(fmt, "{:03X}#", id.as_raw()),
+        Id::Extended(id) => write!(fmt, "{:08X}#", id.as_raw()),
+    };
+    let info = match frame {
+        CanAnyFrame::Fd(fd) => {
+            let flags = fd.flags() & (FdFlags::BRS | FdFlags::ESI);
+            let _ = write!(fmt, "#{:X}", flags.bits());
+            Some(FdInfo { brs: fd.is_brs(), esi: fd.is_esi() })
+        }
+        CanAnyFrame::Remote(remote) => {
+            fmt.push('R');
+            if remote.dlc() > 0 {
+                let _ = write!(fmt, "{}", remote.dlc());
+            }
+            return (fmt, None);
+        }
+        _ => None,
+    };
+    for byte in data {
+        fmt.push(HEX[(byte >> 4) as usize] as char);
+        fmt.push(HEX[(byte & 0xF) as usize] as char);
+    }
+    (fmt, info)
+}
+
+pub(crate) fn format_id(id: Id) -> String {
+    match id {
+        Id::Standard(id) => format!("{:03X}", id.as_raw()),
+        Id::Extended(id) => format!("{:08X}", id.as_raw()),
+    }
+}
@@ -0,0 +1,148 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::io::{AsyncSeekExt, AsyncWriteExt, BufWriter};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use crate::can::{CanEvent, Subscription};
+use crate::frame::CanAnyFrame;
+use crate::logformats::{asc, blf};
+use crate::protocol::format_frame;
+
+/// Format a frame as line of candump log format, e.g. `(1436509052.249713) vcan0 123#DEADBEEF`,
+/// to be replayed by `canplayer`
+pub fn log_line(timestamp: SystemTime, interface: &str, frame: &CanAnyFrame) -> String {
+    let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (fmt, _) = format_frame(frame);
+    format!("({}.{:06}) {} {}\n", since_epoch.as_secs(), since_epoch.subsec_micros(), interface, fmt)
+}
+
+/// Create the log file, replacing existing content, for formats of a single recording per file
+pub fn create(path: &Path) -> Result<tokio::fs::File, String> {
+    std::fs::File::create(path)
+        .map(tokio::fs::File::from_std)
+        .map_err(|e| format!("failed to create record file {}: {}", path.display(), e))
+}
+
+/// Open the log file, appending to existing content
+pub fn open(path: &Path) -> Result<tokio::fs::File, String> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map(tokio::fs::File::from_std)
+        .map_err(|e| format!("failed to open record file {}: {}", path.display(), e))
+}
+
+/// Format of a recording, a candump log of `--record`, a pcapng capture of `--record-pcap`, or a
+/// Vector log of `--record-asc` or `--record-blf`
+pub enum Format {
+    Candump,
+    Pcapng(crate::pcap::Writer),
+    Asc(asc::Writer),
+    Blf(blf::Writer),
+}
+
+impl Format {
+    fn header(&self) -> Vec<u8> {
+        match self {
+            Format::Candump => Vec::new(),
+            Format::Pcapng(writer) => writer.header(),
+            Format::Asc(writer) => writer.header(),
+            Format::Blf(writer) => writer.header(),
+        }
+    }
+
+    fn encode(&mut self, interface: &str, frame: &CanAnyFrame, timestamp: SystemTime) -> Vec<u8> {
+        match self {
+            Format::Candump => log_line(timestamp, interface, frame).into_bytes(),
+            Format::Pcapng(writer) => writer.frame(interface, frame, timestamp),
+            Format::Asc(writer) => writer.frame(interface, frame, timestamp),
+            Format::Blf(writer) => writer.frame(interface, frame, timestamp),
+        }
+    }
+
+    // frames buffered by the format
+    fn flush(&mut self) -> Vec<u8> {
+        match self {
+            Format::Blf(writer) => writer.flush(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn footer(&mut self) -> Vec<u8> {
+        match self {
+            Format::Asc(writer) => writer.footer(),
+            Format::Blf(writer) => writer.flush(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Recorder task, writing every received frame to the file in the format until shutdown
+pub async fn recorder(
+    file: tokio::fs::File,
+    mut format: Format,
+    events: broadcast::Receiver<CanEvent>,
+    shutdown: CancellationToken,
+) {
+    const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+    let mut events = Subscription::new(events, shutdown, "recorder");
+    let mut writer = BufWriter::new(file);
+    let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+
+    // appended pcapng captures start a new section
+    if let Err(e) = writer.write_all(&format.header()).await {
+        tracing::error!(error = %e, "recorder failed writing");
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            frame = events.next_frame() => match frame {
+                Some((interface, frame, timestamp, _)) => {
+                    let bytes = format.encode(&interface, &frame, timestamp.wall);
+                    if let Err(e) = writer.write_all(&bytes).await {
+                        tracing::error!(error = %e, "recorder failed writing");
+                        return;
+                    }
+                }
+                None => break,
+            },
+            _ = flush.tick() => {
+                let _ = writer.write_all(&format.flush()).await;
+                let _ = writer.flush().await;
+            }
+        }
+    }
+
+    if let Err(e) = writer.write_all(&format.footer()).await {
+        tracing::error!(error = %e, "recorder failed writing");
+    }
+    // the header of BLF logs carries the count of objects and the file size
+    if let Format::Blf(blf) = &format {
+        let rewritten = async {
+            writer.seek(std::io::SeekFrom::Start(0)).await?;
+            writer.write_all(&blf.header()).await
+        };
+        if let Err(e) = rewritten.await {
+            tracing::error!(error = %e, "recorder failed writing BLF header");
+        }
+    }
+    let _ = writer.flush().await;
+}
+
+/// Parse a line of candump log format, see [log_line]
+pub fn parse_log_line(line: &str) -> Option<(Duration, String, CanAnyFrame)> {
+    let mut fields = line.split_whitespace();
+    let timestamp = fields.next()?.strip_prefix('(')?.strip_suffix(')')?;
+    let interface = fields.next()?;
+    let frame = crate::protocol::parse_frame(fields.next()?).ok()?;
+
+    let (secs, fraction) = timestamp.split_once('.')?;
+    let micros = format!("{:0<6}", fraction).get(..6)?.parse::<u64>().ok()?;
+    let timestamp = Duration::from_secs(secs.parse().ok()?) + Duration::from_micros(micros);
+
+    Some((timestamp, interface.to_string(), frame))
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::Level;
+
+use crate::config::Config;
+use crate::decode::Decoder;
+use crate::names::Names;
+use crate::transport::RxFilter;
+
+/// Settings applied at runtime, re-read from the config file on SIGHUP or by `POST /api/reload`
+///
+/// The receive filters, the DBC database, the names of the ids and the log level are propagated to the running tasks
+/// by watch channels; other settings require a restart.
+pub(crate) struct Settings {
+    pub filters: watch::Sender<Vec<RxFilter>>,
+    pub decoder: watch::Sender<Option<Arc<Decoder>>>,
+    pub names: watch::Sender<Option<Arc<Names>>>,
+    pub log_level: watch::Sender<Level>,
+}
+
+impl Settings {
+    pub fn new(config: &Config) -> Result<Settings, String> {
+        Ok(Settings {
+            filters: watch::Sender::new(config.filter.clone()),
+            decoder: watch::Sender::new(load_decoder(config)?),
+            names: watch::Sender::new(load_names(config)?),
+            log_level: watch::Sender::new(config.log_level),
+        })
+    }
+
+    /// Re-read the config file on top of the arguments the config was loaded of, and apply the
+    /// settings which changed; nothing is applied if the config, the DBC or the names file is invalid
+    pub fn reload(&self, config: &Config) -> Result<(), String> {
+        let config = config.reload()?;
+        let decoder = load_decoder(&config)?;
+        let names = load_names(&config)?;
+
+        if self.filters.send_if_modified(|filters| replace(filters, config.filter.clone())) {
+            tracing::info!(filters = ?config.filter, "receive filters changed");
+        }
+        self.decoder.send_replace(decoder);
+        self.names.send_replace(names);
+        if self.log_level.send_if_modified(|level| replace(level, config.log_level)) {
+            tracing::info!(level = %config.log_level, "log level changed");
+        }
+        match &config.config {
+            Some(path) => tracing::info!(path = %path.display(), "config reloaded"),
+            None => tracing::info!("config reloaded, no config file"),
+        }
+        Ok(())
+    }
+}
+
+fn load_decoder(config: &Config) -> Result<Option<Arc<Decoder>>, String> {
+    match &config.dbc {
+        Some(path) => Ok(Some(Arc::new(Decoder::from_file(path)?))),
+        None => Ok(None),
+    }
+}
+
+fn load_names(config: &Config) -> Result<Option<Arc<Names>>, String> {
+    match &config.names {
+        Some(path) => Ok(Some(Arc::new(Names::load(path)?))),
+        None => Ok(None),
+    }
+}
+
+// true if the value changed
+fn replace<T: PartialEq>(current: &mut T, value: T) -> bool {
+    let changed = *current != value;
+    *current = value;
+    changed
+}
+
+/// Reload the settings on each SIGHUP until shutdown, keeping the current ones if invalid
+#[cfg(unix)]
+pub(crate) async fn hangup(settings: Arc<Settings>, config: Arc<Config>, shutdown: CancellationToken) {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .expect("failed to install SIGHUP handler");
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => (),
+            _ = shutdown.cancelled() => return,
+        }
+        if let Err(e) = settings.reload(&config) {
+            tracing::error!(error = %e, "failed to reload config, keeping the current settings");
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::audit::{self, Origin};
+use crate::can::{self, CanEvent};
+use crate::frame::CanAnyFrame;
+use crate::logformats::{asc, blf};
+use crate::server::AppState;
+
+/// Frame of a recording, with timestamp relative to the first frame
+pub struct ReplayFrame {
+    pub offset: Duration,
+    pub interface: String,
+    pub frame: CanAnyFrame,
+}
+
+/// Parse a candump log, skipping empty lines; Err carries the number of the first malformed line
+pub fn parse_log(log: &str) -> Result<Vec<ReplayFrame>, usize> {
+    let mut first = None;
+    let mut frames = Vec::new();
+    for (n, line) in log.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (timestamp, interface, frame) = crate::record::parse_log_line(line).ok_or(n + 1)?;
+        let first = *first.get_or_insert(timestamp);
+        let offset = timestamp.saturating_sub(first);
+        frames.push(ReplayFrame { offset, interface, frame });
+    }
+    Ok(frames)
+}
+
+/// Parse a pcapng or pcap capture or a BLF log, detected by its magic, else an ASC log detected by
+/// its header, or else a candump log
+///
+/// The channels of ASC and BLF logs are mapped to the CAN interfaces of the names by their order.
+pub fn parse(recording: &[u8], names: &[Arc<str>]) -> Result<Vec<ReplayFrame>, String> {
+    if crate::pcap::is_capture(recording) {
+        return crate::pcap::parse(recording).map_err(|e| format!("malformed capture: {}", e));
+    }
+    if blf::is_blf(recording) {
+        return blf::parse(recording, names).map_err(|e| format!("malformed BLF log: {}", e));
+    }
+    let log = std::str::from_utf8(recording).map_err(|_| "neither capture nor log".to_string())?;
+    if asc::is_asc(log) {
+        return asc::parse(log, names).map_err(|line| format!("malformed ASC log at line {}", line));
+    }
+    parse_log(log).map_err(|line| format!("malformed log at line {}", line))
+}
+
+fn notify(state: &AppState, notice: String) {
+    tracing::info!("{}", notice);
+    can::publish(&state.events, CanEvent::Notice(notice.into()));
+}
+
+/// Replay task, writing the frames with their original timing divided by `speed`
+///
+/// Frames are written to `interface` if given, otherwise to the recorded interface if
+/// configured, or the default interface. Progress is notified to all WebSocket sessions.
+pub async fn replay(state: AppState, origin: Origin, frames: Vec<ReplayFrame>, speed: f64, interface: Option<String>) {
+    const PROGRESS_STEPS: usize = 10;
+    let total = frames.len();
+    let step = (total / PROGRESS_STEPS).max(1);
+    let start = Instant::now();
+    let mut failed = 0;
+
+    notify(&state, format!("replay started, {} frames", total));
+    for (n, replay) in frames.iter().enumerate() {
+        let due = start + replay.offset.div_f64(speed);
+        tokio::select! {
+            _ = tokio::time::sleep_until(due) => (),
+            _ = state.shutdown.cancelled() => return,
+        }
+
+        let target = interface.as_deref()
+            .or_else(|| state.buses.get(Some(&replay.interface)).map(|bus| &*bus.name));
+        let result = state.buses.write_frame(target, &replay.frame).await;
+        audit::record(&state, &origin, target, &replay.frame, &result);
+        if result.is_err() {
+            failed += 1;
+        }
+
+        if (n + 1) % step == 0 && n + 1 < total {
+            notify(&state, format!("replay {}% ({}/{} frames)", (n + 1) * 100 / total, n + 1, total));
+        }
+    }
+    notify(&state, format!("replay finished, {} frames, {} failed", total, failed));
+}
@@ -0,0 +1,340 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::Path as UrlPath,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use rhai::{Array, Blob, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde::Serialize;
+
+use crate::api::api_error;
+use crate::audit::{self, Origin};
+use crate::can::{self, CanEvent, Direction, Subscription};
+use crate::frame::{CanAnyFrame, CanDataFrame, CanFdFrame, EmbeddedFrame, ExtendedId, Frame, Id, StandardId};
+use crate::server::AppState;
+
+// limits of a single call of a script, terminating runaway scripts
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 16;
+const MAX_STRING_SIZE: usize = 4096;
+const MAX_ARRAY_SIZE: usize = 1024;
+const MAX_MAP_SIZE: usize = 256;
+
+// DTO - script of `/api/scripts`, with the count of calls and failed calls
+#[derive(Serialize, Debug, Clone)]
+pub struct ScriptInfo {
+    pub name: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub last_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+// DTO - scripts listed by `GET /api/scripts`
+#[derive(Serialize, Debug)]
+pub struct ScriptList {
+    scripts: Vec<ScriptInfo>,
+}
+
+// action of a script, carried out once the script returned
+enum Action {
+    Send(Option<String>, CanAnyFrame),
+    Alert(String),
+}
+
+struct Script {
+    source: String,
+    ast: AST,
+    // `this` of the calls, a map kept across calls as state of the script
+    this: Dynamic,
+    calls: u64,
+    errors: u64,
+    last_error: Option<String>,
+}
+
+impl Script {
+    fn info(&self, name: &str, source: bool) -> ScriptInfo {
+        ScriptInfo {
+            name: name.to_string(),
+            calls: self.calls,
+            errors: self.errors,
+            last_error: self.last_error.clone(),
+            source: source.then(|| self.source.clone()),
+        }
+    }
+}
+
+/// Rhai scripts reacting to received frames by `fn on_frame(frame)`, stored as `<name>.rhai` in
+/// the directory of `--scripts`
+///
+/// The frame is a map of `interface`, `id`, `extended`, `fd` and a blob of `data`. Scripts may
+/// write frames by `send(id, data)` or `send(interface, id, data)`, and notify all clients by
+/// `alert(message)`. Each call is limited in operations, call depth and sizes, the engine
+/// providing no access to files or the network.
+pub struct Scripts {
+    dir: PathBuf,
+    engine: Engine,
+    scripts: Mutex<BTreeMap<String, Script>>,
+    // actions of the script running
+    actions: Arc<Mutex<Vec<Action>>>,
+}
+
+fn script_frame(id: i64, data: &[u8]) -> Result<CanAnyFrame, Box<EvalAltResult>> {
+    let id = u32::try_from(id).map_err(|_| format!("invalid id {}", id))?;
+    let id = match u16::try_from(id).ok().and_then(StandardId::new) {
+        Some(id) => Id::Standard(id),
+        None => Id::Extended(ExtendedId::new(id).ok_or(format!("id {:X} exceeds 29 bits", id))?),
+    };
+    let frame = if data.len() > 8 {
+        CanFdFrame::new(id, data).map(CanAnyFrame::Fd)
+    } else {
+        CanDataFrame::new(id, data).map(CanAnyFrame::Normal)
+    };
+    Ok(frame.ok_or("data exceeds 64 bytes")?)
+}
+
+fn array_bytes(data: Array) -> Result<Blob, Box<EvalAltResult>> {
+    data.into_iter()
+        .map(|byte| byte.as_int().ok().and_then(|byte| u8::try_from(byte).ok()).ok_or("data of bytes expected".into()))
+        .collect()
+}
+
+fn frame_map(interface: &str, frame: &CanAnyFrame) -> Map {
+    let mut map = Map::new();
+    map.insert("interface".into(), interface.into());
+    map.insert("id".into(), (frame.raw_id() as i64).into());
+    map.insert("extended".into(), frame.is_extended().into());
+    map.insert("fd".into(), matches!(frame, CanAnyFrame::Fd(_)).into());
+    map.insert("data".into(), Dynamic::from_blob(frame.data().to_vec()));
+    map
+}
+
+fn engine(actions: &Arc<Mutex<Vec<Action>>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_ARRAY_SIZE)
+        .set_max_map_size(MAX_MAP_SIZE);
+    engine.disable_symbol("eval");
+    engine.on_print(|message| tracing::info!(target: "rust_vue::script", "{}", message));
+    engine.on_debug(|message, _, _| tracing::debug!(target: "rust_vue::script", "{}", message));
+
+    let queue = actions.clone();
+    engine.register_fn("send", move |id: i64, data: Blob| -> Result<(), Box<EvalAltResult>> {
+        queue.lock().unwrap().push(Action::Send(None, script_frame(id, &data)?));
+        Ok(())
+    });
+    let queue = actions.clone();
+    engine.register_fn("send", move |id: i64, data: Array| -> Result<(), Box<EvalAltResult>> {
+        queue.lock().unwrap().push(Action::Send(None, script_frame(id, &array_bytes(data)?)?));
+        Ok(())
+    });
+    let queue = actions.clone();
+    engine.register_fn("send", move |interface: &str, id: i64, data: Blob| -> Result<(), Box<EvalAltResult>> {
+        queue.lock().unwrap().push(Action::Send(Some(interface.to_string()), script_frame(id, &data)?));
+        Ok(())
+    });
+    let queue = actions.clone();
+    engine.register_fn("send", move |interface: &str, id: i64, data: Array| -> Result<(), Box<EvalAltResult>> {
+        let frame = script_frame(id, &array_bytes(data)?)?;
+        queue.lock().unwrap().push(Action::Send(Some(interface.to_string()), frame));
+        Ok(())
+    });
+    let queue = actions.clone();
+    engine.register_fn("alert", move |message: &str| {
+        queue.lock().unwrap().push(Action::Alert(message.to_string()));
+    });
+    engine
+}
+
+/// Whether the name is valid for a script file, e.g. `auto-respond`
+fn valid_name(name: &str) -> bool {
+    const MAX_NAME_LEN: usize = 64;
+    !name.is_empty() && name.len() <= MAX_NAME_LEN
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+impl Scripts {
+    /// Load the scripts of the directory, failing on a script not compiling
+    pub fn open(dir: &Path) -> Result<Scripts, String> {
+        let actions = Arc::default();
+        let scripts = Scripts { dir: dir.to_path_buf(), engine: engine(&actions), scripts: Mutex::default(), actions };
+        let entries = std::fs::read_dir(dir).map_err(|e| format!("failed to read scripts {}: {}", dir.display(), e))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_stem().and_then(|name| name.to_str()).filter(|name| valid_name(name)) else {
+                continue;
+            };
+            if path.extension().is_none_or(|extension| extension != "rhai") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| format!("failed to read script {}: {}", path.display(), e))?;
+            let script = scripts.compile(source).map_err(|e| format!("script {}: {}", path.display(), e))?;
+            scripts.scripts.lock().unwrap().insert(name.to_string(), script);
+        }
+        tracing::info!(count = scripts.scripts.lock().unwrap().len(), dir = %dir.display(), "scripts loaded");
+        Ok(scripts)
+    }
+
+    fn compile(&self, source: String) -> Result<Script, String> {
+        let ast = self.engine.compile(&source).map_err(|e| e.to_string())?;
+        if !ast.iter_functions().any(|f| f.name == "on_frame" && f.params.len() == 1) {
+            return Err("missing fn on_frame(frame)".to_string());
+        }
+        Ok(Script { source, ast, this: Map::new().into(), calls: 0, errors: 0, last_error: None })
+    }
+
+    pub fn list(&self) -> Vec<ScriptInfo> {
+        self.scripts.lock().unwrap().iter().map(|(name, script)| script.info(name, false)).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<ScriptInfo> {
+        self.scripts.lock().unwrap().get(name).map(|script| script.info(name, true))
+    }
+
+    /// Compile and store the script, replacing a script of the same name and its state; true if created
+    pub fn put(&self, name: &str, source: String) -> Result<bool, (StatusCode, String)> {
+        let script = self.compile(source).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let path = self.dir.join(format!("{}.rhai", name));
+        std::fs::write(&path, &script.source).map_err(|e| {
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("failed to write script {}: {}", path.display(), e))
+        })?;
+        Ok(self.scripts.lock().unwrap().insert(name.to_string(), script).is_none())
+    }
+
+    /// Remove the script, false if unknown
+    pub fn delete(&self, name: &str) -> Result<bool, String> {
+        let mut scripts = self.scripts.lock().unwrap();
+        if !scripts.contains_key(name) {
+            return Ok(false);
+        }
+        let path = self.dir.join(format!("{}.rhai", name));
+        std::fs::remove_file(&path).map_err(|e| format!("failed to remove script {}: {}", path.display(), e))?;
+        scripts.remove(name);
+        Ok(true)
+    }
+
+    /// Call all scripts for the frame, returning their actions tagged by the name of the script
+    fn on_frame(&self, interface: &str, frame: &CanAnyFrame) -> Vec<(String, Action)> {
+        let map = frame_map(interface, frame);
+        let mut actions = Vec::new();
+        for (name, script) in self.scripts.lock().unwrap().iter_mut() {
+            let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut script.this);
+            let result = self.engine.call_fn_with_options::<Dynamic>(
+                options, &mut Scope::new(), &script.ast, "on_frame", (map.clone(),));
+            script.calls += 1;
+            if let Err(e) = result {
+                script.errors += 1;
+                tracing::debug!(script = name, error = %e, "script failed");
+                script.last_error = Some(e.to_string());
+            }
+            actions.extend(self.actions.lock().unwrap().drain(..).map(|action| (name.clone(), action)));
+        }
+        actions
+    }
+}
+
+/// Script task, calling the scripts for each received frame until shutdown, excluded the error
+/// frames and the frames transmitted, e.g. the echoes of the frames written by the scripts
+///
+/// The scripts run on the blocking threads of the runtime, each call of up to 100k operations.
+pub async fn runner(state: AppState, scripts: Arc<Scripts>) {
+    let mut events = Subscription::new(state.events.subscribe(), state.shutdown.clone(), "scripts");
+    while let Some((interface, frame, _, direction)) = events.next_frame().await {
+        if matches!(frame, CanAnyFrame::Error(_)) || direction != Direction::Rx {
+            continue;
+        }
+        let called = scripts.clone();
+        let actions = match tokio::task::spawn_blocking(move || called.on_frame(&interface, &frame)).await {
+            Ok(actions) => actions,
+            Err(e) => {
+                tracing::error!(error = %e, "scripts failed");
+                continue;
+            }
+        };
+        for (name, action) in actions {
+            match action {
+                Action::Send(interface, frame) => {
+                    let result = state.buses.write_frame(interface.as_deref(), &frame).await;
+                    audit::record(&state, &Origin::service("script"), interface.as_deref(), &frame, &result);
+                    if result.is_err() {
+                        tracing::warn!(script = name, "script failed writing frame");
+                    }
+                }
+                Action::Alert(message) => {
+                    let notice = format!("script {}: {}", name, message);
+                    tracing::info!("{}", notice);
+                    can::publish(&state.events, CanEvent::Notice(notice.into()));
+                }
+            }
+        }
+    }
+}
+
+static MSG_NO_SCRIPTS: &str = "scripting disabled, missing --scripts";
+
+/// `GET /api/scripts` - list the scripts with their count of calls and errors
+pub async fn list_scripts(Extension(state): Extension<AppState>) -> Response {
+    let Some(scripts) = &state.scripts else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_SCRIPTS).into_response();
+    };
+    (StatusCode::OK, Json(ScriptList { scripts: scripts.list() })).into_response()
+}
+
+/// `GET /api/scripts/:name` - the script with its source, 404 if unknown
+pub async fn get_script(Extension(state): Extension<AppState>, UrlPath(name): UrlPath<String>) -> Response {
+    let Some(scripts) = &state.scripts else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_SCRIPTS).into_response();
+    };
+    match scripts.get(&name) {
+        Some(script) => (StatusCode::OK, Json(script)).into_response(),
+        None => api_error(StatusCode::NOT_FOUND, "unknown script").into_response(),
+    }
+}
+
+/// `PUT /api/scripts/:name` - store the script of the request body, replacing a script of the same name
+///
+/// Responds with 201 if created, 200 if replaced, 400 if the name is invalid or the script does
+/// not compile, and 500 if the script can not be written.
+pub async fn put_script(
+    Extension(state): Extension<AppState>,
+    UrlPath(name): UrlPath<String>,
+    source: String,
+) -> Response {
+    let Some(scripts) = &state.scripts else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_SCRIPTS).into_response();
+    };
+    if !valid_name(&name) {
+        return api_error(StatusCode::BAD_REQUEST, "invalid name, letters, digits, - and _ expected").into_response();
+    }
+    match scripts.put(&name, source) {
+        Ok(created) => {
+            tracing::info!(script = name, created, "script stored");
+            let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+            (status, Json(scripts.get(&name))).into_response()
+        }
+        Err((status, e)) => api_error(status, &e).into_response(),
+    }
+}
+
+/// `DELETE /api/scripts/:name` - remove the script, 404 if unknown
+pub async fn delete_script(Extension(state): Extension<AppState>, UrlPath(name): UrlPath<String>) -> Response {
+    let Some(scripts) = &state.scripts else {
+        return api_error(StatusCode::NOT_FOUND, MSG_NO_SCRIPTS).into_response();
+    };
+    match scripts.delete(&name) {
+        Ok(true) => {
+            tracing::info!(script = name, "script deleted");
+            StatusCode::OK.into_response()
+        }
+        Ok(false) => api_error(StatusCode::NOT_FOUND, "unknown script").into_response(),
+        Err(e) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &e).into_response(),
+    }
+}
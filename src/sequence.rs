@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::audit::{self, Origin};
+use crate::can::{self, Buses, CanEvent};
+use crate::frame::CanAnyFrame;
+use crate::protocol::parse_frame_command;
+use crate::server::AppState;
+
+// DTO - sequence of `POST /api/sequences`, run `repeat` times (once by default)
+#[derive(Deserialize, JsonSchema, ToSchema, Debug)]
+pub struct SequenceRequest {
+    pub steps: Vec<Step>,
+    pub repeat: Option<u32>,
+    // CAN interface of the steps without interface prefix, the default interface if missing
+    pub interface: Option<String>,
+}
+
+// DTO - frame in `cansend` notation with optional interface prefix, e.g. `can1 123#DEADBEEF`,
+// and the delay after writing it
+#[derive(Deserialize, JsonSchema, ToSchema, Debug)]
+pub struct Step {
+    pub frame: String,
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+struct ParsedStep {
+    interface: Option<String>,
+    frame: CanAnyFrame,
+    delay: Duration,
+}
+
+/// Parsed sequence, ready to be run by [Sequences::start]
+pub struct Sequence {
+    steps: Vec<ParsedStep>,
+    repeat: u32,
+}
+
+impl Sequence {
+    /// Parse the steps, the error naming the first malformed step
+    pub fn parse(req: SequenceRequest) -> Result<Sequence, String> {
+        if req.steps.is_empty() {
+            return Err("sequence without steps".to_string());
+        }
+        let repeat = req.repeat.unwrap_or(1);
+        if repeat == 0 {
+            return Err("repeat must be positive".to_string());
+        }
+        let steps = req.steps.iter().enumerate().map(|(i, step)| {
+            let (interface, frame) = parse_frame_command(&step.frame)
+                .map_err(|e| format!("step {}: invalid frame {}: {}", i + 1, step.frame, e))?;
+            let interface = interface.map(str::to_string).or_else(|| req.interface.clone());
+            Ok(ParsedStep { interface, frame, delay: Duration::from_millis(step.delay_ms) })
+        }).collect::<Result<_, String>>()?;
+        Ok(Sequence { steps, repeat })
+    }
+
+    /// Number of the first step of an interface not configured
+    pub fn unknown_interface(&self, buses: &Buses) -> Option<usize> {
+        self.steps.iter().position(|step| buses.get(step.interface.as_deref()).is_none()).map(|i| i + 1)
+    }
+
+    /// Number of frames written by all runs
+    pub fn frames(&self) -> usize {
+        self.steps.len() * self.repeat as usize
+    }
+}
+
+/// Sequences running in the background, each cancelled by `DELETE /api/sequences/:sequence` or
+/// on shutdown
+#[derive(Default)]
+pub struct Sequences {
+    running: Mutex<BTreeMap<u64, CancellationToken>>,
+    next: AtomicU64,
+}
+
+impl Sequences {
+    /// Start the sequence of the client, returning its number
+    pub fn start(&self, state: &AppState, origin: Origin, sequence: Sequence) -> u64 {
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        let cancel = state.shutdown.child_token();
+        self.running.lock().unwrap().insert(id, cancel.clone());
+        state.tasks.spawn(run(state.clone(), origin, id, sequence, cancel));
+        id
+    }
+
+    /// Cancel the sequence, false if unknown or finished
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.running.lock().unwrap().remove(&id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn notify(state: &AppState, notice: String) {
+    tracing::info!("{}", notice);
+    can::publish(&state.events, CanEvent::Notice(notice.into()));
+}
+
+/// Sequence task, writing the steps of each run in order, aborting on the first frame failing
+async fn run(state: AppState, origin: Origin, id: u64, sequence: Sequence, cancel: CancellationToken) {
+    let steps = sequence.steps.len();
+    notify(&state, format!("sequence {} started, {} steps, {} runs", id, steps, sequence.repeat));
+    let outcome = 'runs: {
+        for run in 1..=sequence.repeat {
+            for (n, step) in sequence.steps.iter().enumerate() {
+                let result = state.buses.write_frame(step.interface.as_deref(), &step.frame).await;
+                audit::record(&state, &origin, step.interface.as_deref(), &step.frame, &result);
+                if let Err(e) = result {
+                    break 'runs format!("aborted at step {} of run {}: {}", n + 1, run, e);
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(step.delay) => (),
+                    _ = cancel.cancelled() => break 'runs format!("cancelled at step {} of run {}", n + 1, run),
+                }
+            }
+            if run < sequence.repeat {
+                notify(&state, format!("sequence {} run {}/{} done", id, run, sequence.repeat));
+            }
+        }
+        format!("finished, {} frames", sequence.frames())
+    };
+    state.sequences.running.lock().unwrap().remove(&id);
+    notify(&state, format!("sequence {} {}", id, outcome));
+}
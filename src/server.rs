@@ -0,0 +1,639 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use axum::{
+    extract::DefaultBodyLimit,
+    http::HeaderValue,
+    middleware,
+    routing::{delete, get, post, put},
+    Extension, Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use clap::Parser;
+use futures_util::{future::try_join_all, TryFutureExt};
+use local_ip_address::list_afinet_netifas;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::sync::broadcast;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tower_http::cors::{self, CorsLayer};
+use tower_http::trace::{DefaultMakeSpan, TraceLayer};
+use tracing::{info, warn, Level};
+
+use crate::can::{Buses, CanEvent, CanTransport, Timestamp};
+use crate::config::Config;
+use crate::frame::Id;
+use crate::reload::Settings;
+use crate::stats::Bitrate;
+use crate::transport::{Custom, Transport};
+use crate::codec::FrameDecoder;
+use crate::logformats::{asc, blf};
+use crate::{alerts, api, assets, audit, auth, buffer, busstate, cannelloni, canopen, capture, clients, codec, cyclic, diag, gateway, health, history, influx, limit, mqtt, netlink, obd, openapi, overview, presets, profile, record, sequence, setup, simulate, slcan, sse, stats, supervisor, timeouts, version, ws};
+
+// Shared state of the service, handed to every WebSocket session
+#[derive(Clone)]
+pub(crate) struct AppState {
+    pub config: Arc<Config>,
+    // custom decoders registered by the embedding application
+    pub decoders: Arc<codec::Registry>,
+    // receive filters, DBC database and log level, changed on reload
+    pub settings: Arc<Settings>,
+    pub events: broadcast::Sender<CanEvent>,
+    pub buses: Buses,
+    pub cyclic: Arc<cyclic::Scheduler>,
+    pub sequences: Arc<sequence::Sequences>,
+    // captures armed by `POST /api/captures`
+    pub captures: Arc<capture::Captures>,
+    // websocket sessions listed by `GET /api/clients`
+    pub clients: Arc<clients::Registry>,
+    // health of each bus, changes sent to the clients as `bus_state`
+    pub bus_states: Arc<busstate::BusStates>,
+    // last frame of each id, listed by `GET /api/overview`
+    pub overview: Arc<overview::Overview>,
+    // latest frames, listed by `GET /api/frames`
+    pub buffer: Option<Arc<buffer::Buffer>>,
+    pub history: Option<Arc<history::History>>,
+    // frames written by clients, listed by `GET /api/audit`
+    pub audit: Option<Arc<audit::Audit>>,
+    pub presets: Option<Arc<presets::Presets>>,
+    pub alerts: Option<Arc<alerts::Alerts>>,
+    // periodic messages monitored by `--cycle-time` and `--message-timeouts`
+    pub timeouts: Option<Arc<timeouts::Timeouts>>,
+    #[cfg(feature = "scripting")]
+    pub scripts: Option<Arc<crate::scripting::Scripts>>,
+    pub gateway: Option<Arc<gateway::Gateway>>,
+    // problems found at startup, reported by `GET /healthz`
+    pub startup_errors: Arc<Vec<health::StartupError>>,
+    // cancelled on SIGINT/SIGTERM, closing all sessions and CAN sockets
+    pub shutdown: CancellationToken,
+    // sessions and CAN readers, drained on shutdown
+    pub tasks: TaskTracker,
+}
+
+/// Builder of a [Server], see [Server::builder]
+#[derive(Default)]
+pub struct ServerBuilder {
+    config: Option<Config>,
+    shutdown: Option<CancellationToken>,
+    decoders: codec::Registry,
+    // transports of the embedding application by CAN device
+    transports: Vec<(String, Arc<dyn CanTransport>)>,
+}
+
+impl ServerBuilder {
+    /// Configuration of the service, the defaults and environment variables if not given
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Shut down once the token is cancelled, instead of on SIGINT/SIGTERM
+    pub fn shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Decode the payload of the frames of the id by the decoder, forwarding the result as `custom` of
+    /// each frame; a decoder registered before for the id is replaced
+    pub fn decoder(mut self, id: impl Into<Id>, decoder: impl FrameDecoder + 'static) -> Self {
+        self.decoders.register(id.into(), Arc::new(decoder));
+        self
+    }
+
+    /// Read and write the CAN device of `--can-dev` by the transport, instead of the transport
+    /// configured, e.g. a [crate::can::MockTransport] of tests
+    pub fn transport(mut self, can_dev: &str, transport: Arc<dyn CanTransport>) -> Self {
+        self.transports.push((can_dev.to_string(), transport));
+        self
+    }
+
+    /// Load the configured resources, set up the CAN devices and spawn the CAN readers and
+    /// background jobs; to be called within a Tokio runtime
+    pub fn build(self) -> Result<Server, String> {
+        Timestamp::start();
+        let mut config = self.config.unwrap_or_else(|| Config::parse_from(["rust-vue"]));
+        let signals = self.shutdown.is_none();
+        if config.no_can {
+            config.can_dev.clear();
+        }
+
+        let settings = Arc::new(Settings::new(&config)?);
+
+        if config.transport.len() > config.can_dev.len() {
+            return Err("more transports than CAN devices given".to_string());
+        }
+        let mut transports = if config.simulate {
+            let messages = match &config.demo_profile {
+                Some(path) => profile::load(path, settings.decoder.borrow().as_deref())?,
+                None => simulate::load(config.simulate_messages.as_deref())?,
+            };
+            vec![Transport::Simulated(messages); config.can_dev.len()]
+        } else {
+            config.transport.clone()
+        };
+        for (name, transport) in self.transports {
+            let Some(i) = config.can_dev.iter().position(|can_dev| *can_dev == name) else {
+                return Err(format!("transport of unknown CAN device {}", name));
+            };
+            if transports.len() <= i {
+                transports.resize(i + 1, Transport::SocketCan);
+            }
+            transports[i] = Transport::Custom(Custom(transport));
+        }
+        let slcan = transports.iter().any(|t| matches!(t, Transport::Slcan { .. }));
+        if slcan && slcan::bitrate_code(config.bitrate).is_none() {
+            return Err(format!("bitrate {} not supported by SLCAN", config.bitrate));
+        }
+        #[cfg(not(feature = "gs_usb"))]
+        if transports.iter().any(|t| matches!(t, Transport::GsUsb { .. })) {
+            return Err("gs_usb transport requires cargo build --features gs_usb".to_string());
+        }
+        // interfaces without a transport default to SocketCAN
+        #[cfg(not(target_os = "linux"))]
+        if transports.len() < config.can_dev.len() || transports.iter().any(|t| matches!(t, Transport::SocketCan | Transport::Sllin)) {
+            return Err("SocketCAN requires Linux, use --transport slcan:<serial port> or gs_usb, or --simulate".to_string());
+        }
+
+        if config.setup {
+            for (i, name) in config.can_dev.iter().enumerate() {
+                // SLCAN and gs_usb adapters are set up when opened, simulated ones need none
+                if transports.get(i).is_some_and(|t| *t != Transport::SocketCan) {
+                    continue;
+                }
+                setup::setup(name, &config.bit_timing())?;
+            }
+        }
+        // missing virtual interfaces are created if permitted, as in a container of CAP_NET_ADMIN,
+        // else reported by `GET /healthz`
+        let mut startup_errors = Vec::new();
+        for (i, name) in config.can_dev.iter().enumerate() {
+            if !name.starts_with("vcan") || transports.get(i).is_some_and(|t| *t != Transport::SocketCan) {
+                continue;
+            }
+            if let Err(error) = setup::create_vcan(name) {
+                warn!(interface = %name, %error, "virtual CAN interface missing");
+                startup_errors.push(health::StartupError { interface: Some(name.clone()), error });
+            }
+        }
+
+        let presets = match &config.presets {
+            Some(path) => Some(Arc::new(presets::Presets::open(path)?)),
+            None => None,
+        };
+        #[cfg(feature = "scripting")]
+        let scripts = match &config.scripts {
+            Some(dir) => Some(Arc::new(crate::scripting::Scripts::open(dir)?)),
+            None => None,
+        };
+        let alerts = match &config.alerts {
+            Some(path) => Some(Arc::new(alerts::Alerts::open(path)?)),
+            None => None,
+        };
+        let history = match &config.db {
+            Some(path) => Some(Arc::new(history::History::open(path)?)),
+            None => None,
+        };
+
+        let (audit, audit_log) = match &config.audit_log {
+            Some(path) => {
+                let (audit, log) = audit::Audit::open(path)?;
+                (Some(Arc::new(audit)), Some(log))
+            }
+            None => (None, None),
+        };
+
+        let gateway = match &config.gateway {
+            Some(path) => Some(Arc::new(gateway::Gateway::load(path, &config.can_dev)?)),
+            None => None,
+        };
+
+        const EVENT_QUEUE_LEN: usize = 1024;
+        let (events, _) = broadcast::channel(EVENT_QUEUE_LEN);
+        let state = AppState {
+            config: Arc::new(config.clone()),
+            decoders: Arc::new(self.decoders),
+            buses: Buses::new(&config.can_dev, &transports, config.bitrate, config.read_only, settings.filters.subscribe()),
+            settings,
+            events,
+            cyclic: Arc::default(),
+            sequences: Arc::default(),
+            captures: Arc::default(),
+            clients: Arc::default(),
+            bus_states: Arc::default(),
+            overview: Arc::default(),
+            buffer: (config.buffer_len > 0).then(|| Arc::new(buffer::Buffer::new(config.buffer_len))),
+            history,
+            audit,
+            presets,
+            alerts,
+            timeouts: timeouts::Timeouts::new(&config).map(Arc::new),
+            #[cfg(feature = "scripting")]
+            scripts,
+            gateway,
+            startup_errors: Arc::new(startup_errors),
+            shutdown: self.shutdown.unwrap_or_default(),
+            tasks: TaskTracker::new(),
+        };
+        if let Some(path) = &config.record {
+            let file = record::open(path)?;
+            let format = record::Format::Candump;
+            state.tasks.spawn(record::recorder(file, format, state.events.subscribe(), state.shutdown.clone()));
+        }
+        if let Some(path) = &config.record_pcap {
+            let file = record::open(path)?;
+            let format = record::Format::Pcapng(Default::default());
+            state.tasks.spawn(record::recorder(file, format, state.events.subscribe(), state.shutdown.clone()));
+        }
+        if let Some(path) = &config.record_asc {
+            let file = record::create(path)?;
+            let format = record::Format::Asc(asc::Writer::new(state.buses.names(), SystemTime::now()));
+            state.tasks.spawn(record::recorder(file, format, state.events.subscribe(), state.shutdown.clone()));
+        }
+        if let Some(path) = &config.record_blf {
+            let file = record::create(path)?;
+            let format = record::Format::Blf(blf::Writer::new(state.buses.names(), SystemTime::now()));
+            state.tasks.spawn(record::recorder(file, format, state.events.subscribe(), state.shutdown.clone()));
+        }
+        if let Some(buffer) = &state.buffer {
+            state.tasks.spawn(buffer::recorder(buffer.clone(), state.events.subscribe(), state.shutdown.clone()));
+        }
+        if let Some(history) = &state.history {
+            state.tasks.spawn(history::writer(history.clone(), state.events.subscribe(), state.shutdown.clone()));
+        }
+        if let Some(log) = audit_log {
+            state.tasks.spawn(audit::writer(log, state.shutdown.clone()));
+        }
+        if let Some(gateway) = &state.gateway {
+            let events = state.events.subscribe();
+            state.tasks.spawn(gateway::forwarder(gateway.clone(), state.buses.clone(), events, state.shutdown.clone()));
+        }
+        let overview = state.overview.clone();
+        state.tasks.spawn(overview::tracker(overview, state.events.subscribe(), state.shutdown.clone()));
+        let bitrate = Bitrate { nominal: config.bitrate, data: config.data_bitrate };
+        state.tasks.spawn(stats::collector(state.buses.clone(), bitrate, state.events.clone(), state.shutdown.clone()));
+        state.tasks.spawn(netlink::monitor(state.buses.clone(), state.events.clone(), state.shutdown.clone()));
+        state.tasks.spawn(busstate::monitor(state.clone()));
+        if let Some(alerts) = &state.alerts {
+            state.tasks.spawn(alerts::monitor(state.clone(), alerts.clone()));
+        }
+        if let Some(timeouts) = &state.timeouts {
+            state.tasks.spawn(timeouts::monitor(state.clone(), timeouts.clone()));
+        }
+        if let Some(url) = &config.mqtt_broker {
+            let options = mqtt::broker_options(url)?;
+            state.tasks.spawn(mqtt::bridge(state.clone(), options, config.mqtt_command_topic.clone()));
+        }
+        if let Some(url) = &config.influx_url {
+            let url = influx::write_url(url)?;
+            state.tasks.spawn(influx::exporter(state.clone(), url, config.influx_auth.clone()));
+        }
+        if let Some(remote) = config.cannelloni {
+            let socket = cannelloni::socket(remote, config.cannelloni_port.unwrap_or(remote.port()))?;
+            state.tasks.spawn(cannelloni::tunnel(state.clone(), socket, remote));
+        }
+        #[cfg(feature = "kafka")]
+        if let Some(spec) = &config.kafka {
+            let options = crate::kafka::options(spec)?;
+            state.tasks.spawn(crate::kafka::producer(state.clone(), options));
+        }
+        if config.obd {
+            let period = std::time::Duration::from_millis(config.obd_interval.max(1));
+            state.tasks.spawn(obd::poller(state.clone(), period));
+        }
+        if config.canopen {
+            state.tasks.spawn(canopen::monitor(state.events.clone(), state.shutdown.clone()));
+        }
+        #[cfg(feature = "j1939")]
+        if config.j1939 {
+            state.tasks.spawn(crate::j1939::decoder(state.events.clone(), state.shutdown.clone()));
+        }
+        #[cfg(feature = "nmea2000")]
+        if config.nmea2000 {
+            state.tasks.spawn(crate::nmea2000::decoder(state.events.clone(), state.shutdown.clone()));
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(scripts) = &state.scripts {
+            state.tasks.spawn(crate::scripting::runner(state.clone(), scripts.clone()));
+        }
+        supervisor::spawn(&state.buses, &state.tasks, &state.events, &state.shutdown, config.can_thread)?;
+
+        Ok(Server { state, signals })
+    }
+}
+
+/// CORS of the origins of `--cors-origin`, none if no origin is allowed
+fn cors_layer(config: &Config) -> Option<CorsLayer> {
+    if config.cors_origin.is_empty() {
+        return None;
+    }
+    let cors = CorsLayer::new().allow_methods(config.cors_methods.clone()).allow_headers(config.cors_headers.clone());
+    if config.cors_origin.iter().any(|origin| origin == "*") {
+        return Some(cors.allow_origin(cors::Any));
+    }
+    // credentials, the bearer token or the cookie, only of the origins listed
+    let origins: Vec<HeaderValue> = config.cors_origin.iter().filter_map(|origin| HeaderValue::from_str(origin).ok()).collect();
+    Some(cors.allow_origin(origins).allow_credentials(true))
+}
+
+/// The CAN-to-WebSocket bridge, serving the web-page, the WebSocket and the REST API
+pub struct Server {
+    state: AppState,
+    // shut down on SIGINT/SIGTERM, unless shut down by the embedding application
+    signals: bool,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// Routes of the service, to be served or nested by an embedding application
+    pub fn router(&self) -> Router {
+        let router = Router::new()
+            .fallback(assets::static_handler)
+            // routes are matched from bottom to top, so we have to put `nest` at the
+            // top since it matches all routes
+            .route("/ws", get(ws::ws_handler))
+            .route("/ws/control", get(ws::ws_handler))
+            .route("/ws/monitor", get(ws::monitor_handler))
+            .route("/events", get(sse::events))
+            .route("/api/frames", post(api::post_frame).get(api::get_frames))
+            .route("/api/replay", post(api::post_replay))
+            .route("/api/cyclic", post(api::post_cyclic).get(api::list_cyclic))
+            .route("/api/cyclic/:job", put(api::put_cyclic).delete(api::delete_cyclic))
+            .route("/api/captures", post(api::post_capture).get(api::list_captures))
+            .route("/api/captures/:capture", get(api::get_capture).delete(api::delete_capture))
+            .route("/api/captures/:capture/log", get(api::get_capture_log))
+            .route("/api/history", get(api::get_history))
+            .route("/api/interface", get(api::get_interface))
+            .route("/api/overview", get(api::get_overview))
+            .route("/api/audit", get(api::get_audit))
+            .route("/api/schema", get(api::get_schema))
+            .route("/api/openapi.json", get(openapi::openapi_json))
+            .route("/api/docs", get(openapi::swagger_ui))
+            .route("/api/sequences", post(api::post_sequence))
+            .route("/api/sequences/:sequence", delete(api::delete_sequence))
+            .route("/api/presets", get(api::list_presets))
+            .route("/api/presets/:name", get(api::get_preset).put(api::put_preset).delete(api::delete_preset))
+            .route("/api/presets/:name/send", post(api::send_preset))
+            .route("/api/alerts", get(api::list_alerts))
+            .route("/api/alerts/:name", put(api::put_alert).delete(api::delete_alert))
+            .route("/api/clients", get(api::list_clients))
+            .route("/api/clients/:client", delete(api::delete_client))
+            .route("/api/gateway", get(api::list_gateway))
+            .route("/api/gateway/:route", put(api::put_gateway))
+            .route("/api/canopen/sdo/read", post(canopen::sdo_read))
+            .route("/api/uds/rdbi", post(diag::rdbi))
+            .route("/api/uds/tester-present", post(diag::tester_present))
+            .route("/api/uds/reset", post(diag::reset))
+            .route("/api/uds/dtc", post(diag::read_dtc))
+            .route("/api/uds/dtc/clear", post(diag::clear_dtc))
+            .route("/api/reload", post(api::post_reload))
+            .route("/api/time", get(api::get_time))
+            .route("/api/version", get(version::get_version));
+        #[cfg(feature = "scripting")]
+        let router = router
+            .route("/api/scripts", get(crate::scripting::list_scripts))
+            .route(
+                "/api/scripts/:name",
+                get(crate::scripting::get_script).put(crate::scripting::put_script).delete(crate::scripting::delete_script),
+            );
+        let router = router
+            .route_layer(middleware::from_fn(auth::require_token))
+            .route("/api/login", post(auth::login))
+            .route("/api/logout", post(auth::logout))
+            .route("/healthz", get(health::healthz))
+            // bounding the bodies buffered by the extractors, the declared length checked before
+            .layer(DefaultBodyLimit::max(self.state.config.max_body_size))
+            .layer(middleware::from_fn(limit::limit_body))
+            .layer(Extension(self.state.clone()))
+            // logging so we can see whats going on
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(DefaultMakeSpan::default().include_headers(true)),
+            );
+        // answering the preflight requests before the token is required
+        match cors_layer(&self.state.config) {
+            Some(cors) => router.layer(cors),
+            None => router,
+        }
+    }
+
+    /// Subscribe to the events of all CAN devices
+    pub fn subscribe(&self) -> broadcast::Receiver<CanEvent> {
+        self.state.events.subscribe()
+    }
+
+    /// Log level of the config, changed on reload; applied by the embedding application, which
+    /// sets up the logging
+    pub fn log_level(&self) -> tokio::sync::watch::Receiver<Level> {
+        self.state.settings.log_level.subscribe()
+    }
+
+    /// Token shutting down the service once cancelled
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.state.shutdown.clone()
+    }
+
+    /// Serve at the configured address until shutdown, then drain all sessions
+    pub async fn run(self) -> Result<(), String> {
+        let config = self.state.config.clone();
+        let shutdown = self.state.shutdown.clone();
+        let tasks = self.state.tasks.clone();
+        let app = self.router();
+
+        if let Some(path) = &config.config {
+            info!(path = %path.display(), "loaded config file");
+        }
+        if config.no_can {
+            info!("no CAN devices opened, serving the web-page only");
+        } else {
+            info!(can_dev = %config.can_dev.join(","), "reading/writing CAN devices");
+        }
+        if let Some(dir) = &config.assets {
+            info!(dir = %dir.display(), "serving the web-page of the assets directory");
+        }
+        if config.auth_token.is_some() && config.tls_cert.is_none() {
+            warn!("auth token is transmitted in plaintext, consider --tls-cert/--tls-key");
+        }
+        let listeners = self.listeners()?;
+
+        if !config.webhook.is_empty() {
+            let webhooks = config.webhook.iter().filter_map(|url| crate::webhook::url(url).ok()).collect();
+            tasks.spawn(crate::webhook::dispatcher(self.state.clone(), webhooks));
+        }
+        if self.signals {
+            tokio::spawn(shutdown_signal(shutdown.clone()));
+            #[cfg(unix)]
+            tokio::spawn(crate::reload::hangup(self.state.settings.clone(), config.clone(), shutdown.clone()));
+        }
+        #[cfg(feature = "grpc")]
+        if let Some(port) = config.grpc_port {
+            for (addr, listener) in crate::grpc::listeners(&self.state, port)? {
+                info!("gRPC listening on {}", addr);
+                tasks.spawn(crate::grpc::serve(self.state.clone(), listener));
+            }
+        }
+        if config.mdns {
+            // the port of the first listener, the one passed by systemd too
+            let service = crate::mdns::Service::new(&config, listeners[0].0.port());
+            tasks.spawn(crate::mdns::responder(self.state.clone(), crate::mdns::socket()?, service));
+            info!("announcing the service by mDNS");
+        }
+        #[cfg(feature = "systemd")]
+        {
+            crate::systemd::ready();
+            tokio::spawn(crate::systemd::watchdog(shutdown.clone()));
+        }
+        match (&config.tls_cert, &config.tls_key) {
+            (Some(cert), Some(key)) => {
+                let tls = RustlsConfig::from_pem_file(cert, key)
+                    .await
+                    .map_err(|e| format!("failed to load TLS certificate {}: {}", cert.display(), e))?;
+                let handle = axum_server::Handle::new();
+                tokio::spawn({
+                    let handle = handle.clone();
+                    let shutdown = shutdown.clone();
+                    async move {
+                        shutdown.cancelled().await;
+                        handle.graceful_shutdown(None);
+                    }
+                });
+                let servers = listeners.into_iter().map(|(addr, listener)| {
+                    axum_server::from_tcp_rustls(listener, tls.clone())
+                        .handle(handle.clone())
+                        .serve(app.clone().into_make_service_with_connect_info::<SocketAddr>())
+                        .map_err(move |e| format!("failed to serve at {}: {}", addr, e))
+                });
+                try_join_all(servers).await?;
+            }
+            _ => {
+                let mut servers = Vec::new();
+                for (addr, listener) in listeners {
+                    let server = axum::Server::from_tcp(listener)
+                        .map_err(|e| format!("failed to serve at {}: {}", addr, e))?
+                        .serve(app.clone().into_make_service_with_connect_info::<SocketAddr>())
+                        .with_graceful_shutdown(shutdown.clone().cancelled_owned())
+                        .map_err(move |e| format!("failed to serve at {}: {}", addr, e));
+                    servers.push(server);
+                }
+                try_join_all(servers).await?;
+            }
+        }
+
+        // upgraded WebSocket connections are not drained by the server itself
+        const DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+        #[cfg(feature = "systemd")]
+        crate::systemd::stopping();
+        tasks.close();
+        if tokio::time::timeout(DRAIN_TIMEOUT, tasks.wait()).await.is_err() {
+            warn!(count = tasks.len(), "timeout draining connections");
+        }
+        info!("shutdown complete");
+        Ok(())
+    }
+}
+
+impl Server {
+    /// Listeners of the addresses of `--bind`, or of the sockets passed by systemd, logging the
+    /// URLs the service is reachable at
+    fn listeners(&self) -> Result<Vec<(SocketAddr, std::net::TcpListener)>, String> {
+        let config = &self.state.config;
+        #[cfg(feature = "systemd")]
+        {
+            let mut listeners = Vec::new();
+            for listener in crate::systemd::listeners()? {
+                let addr = listener.local_addr().map_err(|e| format!("invalid socket passed by systemd: {}", e))?;
+                info!(%addr, "serving socket passed by systemd");
+                listeners.push((addr, listener));
+            }
+            if !listeners.is_empty() {
+                return Ok(listeners);
+            }
+        }
+        let addrs = config.listen_addrs();
+        let mut listeners = Vec::new();
+        for &addr in &addrs {
+            let dual_stack = dual_stack(&addrs, addr);
+            listeners.push((addr, bind(addr, dual_stack)?));
+            for url in reachable_urls(config, addr, dual_stack) {
+                info!("listening on {}", url);
+            }
+        }
+        Ok(listeners)
+    }
+}
+
+/// Whether the address of the addresses bound is dual-stack, `::` unless IPv4 is bound to the same
+/// port by a socket of its own
+pub(crate) fn dual_stack(addrs: &[SocketAddr], addr: SocketAddr) -> bool {
+    let ipv4 = addrs.iter().any(|other| other.is_ipv4() && other.port() == addr.port());
+    addr.is_ipv6() && addr.ip().is_unspecified() && !ipv4
+}
+
+/// Listener of the address, an IPv6 socket accepting IPv4 too if dual-stack
+pub(crate) fn bind(addr: SocketAddr, dual_stack: bool) -> Result<std::net::TcpListener, String> {
+    let failed = |e: std::io::Error| format!("failed to bind {}: {}", addr, e);
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP)).map_err(failed)?;
+    if addr.is_ipv6() {
+        // the default differing by platform
+        socket.set_only_v6(!dual_stack).map_err(failed)?;
+    }
+    // as of std, a restart not failing on the connections of the last run in TIME_WAIT
+    #[cfg(unix)]
+    socket.set_reuse_address(true).map_err(failed)?;
+    socket.bind(&addr.into()).map_err(failed)?;
+    socket.listen(1024).map_err(failed)?;
+    socket.set_nonblocking(true).map_err(failed)?;
+    Ok(socket.into())
+}
+
+/// URLs of the address, of each local address of its family if unspecified, and of IPv4 too if
+/// dual-stack; IPv6 link-local addresses are skipped, requiring the zone of the interface
+pub(crate) fn reachable_urls(config: &Config, addr: SocketAddr, dual_stack: bool) -> Vec<String> {
+    let url = |ip| format!("{}://{}", config.scheme(), SocketAddr::new(ip, addr.port()));
+    if !addr.ip().is_unspecified() {
+        return vec![url(addr.ip())];
+    }
+    let mut ips: Vec<IpAddr> = list_afinet_netifas()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(_, ip)| ip)
+        .filter(|ip| match ip {
+            IpAddr::V4(_) => addr.is_ipv4() || dual_stack,
+            IpAddr::V6(ip) => addr.is_ipv6() && (ip.segments()[0] & 0xffc0) != 0xfe80,
+        })
+        .collect();
+    ips.sort();
+    ips.dedup();
+    if ips.is_empty() {
+        ips.push(if addr.is_ipv4() { IpAddr::V4(Ipv4Addr::LOCALHOST) } else { IpAddr::V6(Ipv6Addr::LOCALHOST) });
+    }
+    ips.into_iter().map(url).collect()
+}
+
+/// Wait for SIGINT or SIGTERM, then signal shutdown to all sessions and CAN readers
+async fn shutdown_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install SIGINT handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    // no SIGTERM on Windows, Ctrl-C only
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => (),
+        _ = terminate => (),
+    }
+    info!("shutting down");
+    shutdown.cancel();
+}
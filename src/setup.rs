@@ -0,0 +1,109 @@
+#[cfg(target_os = "linux")]
+use std::fmt::Display;
+
+#[cfg(target_os = "linux")]
+use neli::err::{NlError, WrappedError};
+#[cfg(target_os = "linux")]
+use socketcan::{nl::Mtu, CanCtrlMode, CanInterface};
+
+#[cfg(target_os = "linux")]
+const EPERM: i32 = 1;
+#[cfg(target_os = "linux")]
+const ENODEV: i32 = 19;
+
+/// Bit timing applied to the CAN interfaces at startup, sample points in tenths of a percent
+#[derive(Debug, Clone, Copy)]
+pub struct BitTiming {
+    pub bitrate: u32,
+    pub sample_point: Option<u32>,
+    // data bitrate and sample point, enabling CAN FD
+    pub fd: Option<(u32, Option<u32>)>,
+}
+
+#[cfg(target_os = "linux")]
+fn describe<T, P>(name: &str, action: &str, err: NlError<T, P>) -> String
+where
+    NlError<T, P>: Display,
+{
+    let permission_denied = match &err {
+        NlError::Nlmsgerr(e) => e.error.abs() == EPERM,
+        NlError::Wrapped(WrappedError::IOError(e)) => e.kind() == std::io::ErrorKind::PermissionDenied,
+        _ => false,
+    };
+    if permission_denied {
+        format!("permission denied to {} of CAN interface {}, CAP_NET_ADMIN is required: \
+                 run as root or grant `sudo setcap cap_net_admin+ep <binary>`", action, name)
+    } else {
+        format!("failed to {} of CAN interface {}: {}", action, name, err)
+    }
+}
+
+/// Set the bit timing of the interface and bring it up, like `ip link set <name> up type can bitrate ...`
+///
+/// Virtual interfaces have no bit timing, these are just brought up, with the FD MTU if FD is enabled.
+#[cfg(target_os = "linux")]
+pub fn setup(name: &str, timing: &BitTiming) -> Result<(), String> {
+    let iface = CanInterface::open(name).map_err(|e| format!("failed to open CAN interface {}: {}", name, e))?;
+    let details = iface.details().map_err(|e| describe(name, "read the details", e))?;
+
+    if details.is_up {
+        iface.bring_down().map_err(|e| describe(name, "bring down", e))?;
+    }
+    if details.can.bit_timing_const.is_none() {
+        if timing.fd.is_some() {
+            iface.set_mtu(Mtu::Fd).map_err(|e| describe(name, "set the FD MTU", e))?;
+        }
+    } else {
+        if let Some((data_bitrate, data_sample_point)) = timing.fd {
+            // the data bit timing is accepted only in FD mode
+            iface.set_ctrlmode(CanCtrlMode::Fd, true).map_err(|e| describe(name, "enable FD mode", e))?;
+            iface.set_bitrate(timing.bitrate, timing.sample_point).map_err(|e| describe(name, "set the bitrate", e))?;
+            iface.set_data_bitrate(data_bitrate, data_sample_point)
+                .map_err(|e| describe(name, "set the data bitrate", e))?;
+        } else {
+            iface.set_bitrate(timing.bitrate, timing.sample_point).map_err(|e| describe(name, "set the bitrate", e))?;
+        }
+    }
+    iface.bring_up().map_err(|e| describe(name, "bring up", e))?;
+
+    tracing::info!(interface = name, ?timing, "CAN interface set up");
+    Ok(())
+}
+
+/// Restart the interface after bus-off, like `ip link set <name> type can restart`
+#[cfg(target_os = "linux")]
+pub fn restart(name: &str) -> Result<(), String> {
+    let iface = CanInterface::open(name).map_err(|e| format!("failed to open CAN interface {}: {}", name, e))?;
+    iface.restart().map_err(|e| describe(name, "restart", e))
+}
+
+/// Create the virtual interface and bring it up, like `ip link add dev <name> type vcan`, unless
+/// it exists already; returns whether it was created
+#[cfg(target_os = "linux")]
+pub fn create_vcan(name: &str) -> Result<bool, String> {
+    match CanInterface::open(name).map_err(std::io::Error::from) {
+        Ok(_) => return Ok(false),
+        Err(e) if e.raw_os_error() == Some(ENODEV) => (),
+        Err(e) => return Err(format!("failed to open CAN interface {}: {}", name, e)),
+    }
+    let iface = CanInterface::create_vcan(name, None).map_err(|e| describe(name, "create the virtual interface", e))?;
+    iface.bring_up().map_err(|e| describe(name, "bring up", e))?;
+    tracing::info!(interface = name, "virtual CAN interface created");
+    Ok(true)
+}
+
+/// Netlink is available on Linux only, as the SocketCAN interfaces set up
+#[cfg(not(target_os = "linux"))]
+pub fn setup(name: &str, _timing: &BitTiming) -> Result<(), String> {
+    Err(format!("failed to set up CAN interface {}: netlink requires Linux", name))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn restart(name: &str) -> Result<(), String> {
+    Err(format!("failed to restart CAN interface {}: netlink requires Linux", name))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn create_vcan(name: &str) -> Result<bool, String> {
+    Err(format!("failed to create CAN interface {}: netlink requires Linux", name))
+}
@@ -0,0 +1,176 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::frame::{CanAnyFrame, CanDataFrame, EmbeddedFrame, Id};
+use crate::profile::Signals;
+use crate::protocol::parse_frame_id;
+
+/// Message set of `--simulate` if no file is given: `<id> <period ms> <payload>`, the payload
+/// of hex bytes, `++` for a counter byte and `??` for a random byte
+pub const DEFAULT_MESSAGES: &str = "
+# engine speed and load
+0C0 10  ++??????00000000
+# wheel speeds
+1A0 20  ????????????????
+# vehicle status with alive counter
+2B0 100 0102++00
+# extended id of a body controller
+18FF0010 500 ??00++
+# periodic diagnostic response
+7E8 1000 0641??????????
+";
+
+const QUEUE_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Byte {
+    Fixed(u8),
+    Counter,
+    Random,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Payload {
+    Bytes(Vec<Byte>),
+    // encoded of the signal curves of `--demo-profile`
+    Signals(Arc<Signals>),
+}
+
+/// Frame sent periodically by the simulator
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    id: Id,
+    period: Duration,
+    payload: Payload,
+}
+
+impl Message {
+    /// Message of the payload encoded of the signals at the time of sending
+    pub(crate) fn signals(id: Id, period: Duration, signals: Signals) -> Message {
+        Message { id, period, payload: Payload::Signals(Arc::new(signals)) }
+    }
+}
+
+fn parse_payload(s: &str) -> Option<Vec<Byte>> {
+    if !s.len().is_multiple_of(2) || s.len() > 16 {
+        return None;
+    }
+    (0..s.len()).step_by(2)
+        .map(|i| match &s[i..i + 2] {
+            "++" => Some(Byte::Counter),
+            "??" => Some(Byte::Random),
+            byte => u8::from_str_radix(byte, 16).ok().map(Byte::Fixed),
+        })
+        .collect()
+}
+
+/// Parse a message set, a message per line, ignoring empty lines and `#` comments
+pub fn parse_messages(s: &str) -> Result<Vec<Message>, String> {
+    let mut messages = Vec::new();
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || format!("invalid message at line {}: {}", i + 1, line);
+        let mut fields = line.split_whitespace();
+        let (Some(id), Some(period), Some(payload), None) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+            return Err(invalid());
+        };
+        let id = parse_frame_id(id).map_err(|_| invalid())?;
+        let period = period.parse::<u64>().ok().filter(|&ms| ms > 0).ok_or_else(invalid)?;
+        let payload = parse_payload(payload).ok_or_else(invalid)?;
+        messages.push(Message { id, period: Duration::from_millis(period), payload: Payload::Bytes(payload) });
+    }
+    if messages.is_empty() {
+        return Err("empty message set".to_string());
+    }
+    Ok(messages)
+}
+
+/// Load the message set of the file, or the default set
+pub fn load(path: Option<&Path>) -> Result<Arc<Vec<Message>>, String> {
+    let messages = match path {
+        Some(path) => {
+            let s = std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read message set {}: {}", path.display(), e))?;
+            parse_messages(&s).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        None => parse_messages(DEFAULT_MESSAGES)?,
+    };
+    Ok(Arc::new(messages))
+}
+
+/// Traffic generator of a simulated interface, delivering the written frames as loopback
+pub struct Simulator {
+    messages: Arc<Vec<Message>>,
+    // next transmission and counter per message
+    due: Vec<Instant>,
+    counters: Vec<u8>,
+    // time of the signal curves
+    started: Instant,
+    random: u64,
+    loopback: mpsc::Receiver<CanAnyFrame>,
+}
+
+impl Simulator {
+    /// The generator, and the sender of frames written to the interface
+    pub fn new(messages: Arc<Vec<Message>>) -> (Simulator, mpsc::Sender<CanAnyFrame>) {
+        let (tx, loopback) = mpsc::channel(QUEUE_LEN);
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        let due = vec![Instant::now(); messages.len()];
+        let counters = vec![0; messages.len()];
+        let started = Instant::now();
+        (Simulator { messages, due, counters, started, random: seed | 1, loopback }, tx)
+    }
+
+    // xorshift, good enough for random payloads
+    fn random_byte(&mut self) -> u8 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        self.random as u8
+    }
+
+    // in [-1, 1], the noise of the signal curves
+    fn random_unit(&mut self) -> f64 {
+        self.random_byte();
+        (self.random >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+
+    fn frame(&mut self, i: usize) -> CanAnyFrame {
+        let messages = self.messages.clone();
+        let message = &messages[i];
+        let counter = self.counters[i];
+        self.counters[i] = counter.wrapping_add(1);
+        let data: Vec<u8> = match &message.payload {
+            Payload::Bytes(payload) => payload.iter()
+                .map(|byte| match byte {
+                    Byte::Fixed(byte) => *byte,
+                    Byte::Counter => counter,
+                    Byte::Random => self.random_byte(),
+                })
+                .collect(),
+            Payload::Signals(signals) => signals.encode(self.started.elapsed().as_secs_f64(), || self.random_unit()),
+        };
+        // payload of at most 8 bytes, checked on parsing
+        CanAnyFrame::Normal(CanDataFrame::new(message.id, &data).unwrap())
+    }
+
+    /// Wait for the next frame, either generated when due or written to the interface
+    pub async fn next(&mut self) -> Option<CanAnyFrame> {
+        let (i, due) = self.due.iter().copied().enumerate().min_by_key(|(_, due)| *due)?;
+        tokio::select! {
+            _ = tokio::time::sleep_until(due) => {
+                // skip transmissions missed while the reader was busy
+                self.due[i] = (due + self.messages[i].period).max(Instant::now());
+                Some(self.frame(i))
+            }
+            frame = self.loopback.recv() => frame,
+        }
+    }
+}
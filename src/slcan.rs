@@ -0,0 +1,128 @@
+use std::io;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
+use tokio::sync::Mutex;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::frame::{CanAnyFrame, CanDataFrame, CanRemoteFrame, EmbeddedFrame, ExtendedId, Id, StandardId};
+
+// end of a command or response, errors are signalled by a bell instead
+const CR: u8 = b'\r';
+const BELL: u8 = 0x07;
+
+/// Command `S<n>` of the standard bitrates supported by SLCAN adapters
+pub fn bitrate_code(bitrate: u32) -> Option<u8> {
+    match bitrate {
+        10_000 => Some(0),
+        20_000 => Some(1),
+        50_000 => Some(2),
+        100_000 => Some(3),
+        125_000 => Some(4),
+        250_000 => Some(5),
+        500_000 => Some(6),
+        800_000 => Some(7),
+        1_000_000 => Some(8),
+        _ => None,
+    }
+}
+
+fn invalid(error: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
+/// Encode a classic data or remote frame as SLCAN command, e.g. `t1234DEADBEEF\r`
+pub fn encode(frame: &CanAnyFrame) -> io::Result<String> {
+    let (kind, data) = match frame {
+        CanAnyFrame::Normal(frame) => ('t', hex::encode_upper(frame.data())),
+        CanAnyFrame::Remote(_) => ('r', String::new()),
+        _ => return Err(invalid("SLCAN supports classic frames only")),
+    };
+    let (kind, id) = match frame.id() {
+        Id::Standard(id) => (kind, format!("{:03X}", id.as_raw())),
+        Id::Extended(id) => (kind.to_ascii_uppercase(), format!("{:08X}", id.as_raw())),
+    };
+    Ok(format!("{}{}{}{}\r", kind, id, frame.dlc(), data))
+}
+
+/// Decode a received frame of SLCAN format, e.g. `t1234DEADBEEF`, ignoring a trailing timestamp
+pub fn decode(line: &[u8]) -> Option<CanAnyFrame> {
+    let line = std::str::from_utf8(line).ok()?;
+    let kind = line.chars().next()?;
+    let id_len = if kind.is_ascii_uppercase() { 8 } else { 3 };
+    let id = u32::from_str_radix(line.get(1..1 + id_len)?, 16).ok()?;
+    let id: Id = match id_len {
+        8 => ExtendedId::new(id)?.into(),
+        _ => StandardId::new(id as u16)?.into(),
+    };
+    let dlc = line.get(1 + id_len..2 + id_len)?.parse::<usize>().ok()?;
+
+    match kind {
+        't' | 'T' => {
+            let data = hex::decode(line.get(2 + id_len..2 + id_len + 2 * dlc)?).ok()?;
+            CanDataFrame::new(id, &data).map(CanAnyFrame::Normal)
+        }
+        'r' | 'R' => CanRemoteFrame::new_remote(id, dlc).map(CanAnyFrame::Remote),
+        _ => None,
+    }
+}
+
+/// Receiving half of the serial port
+pub struct Reader {
+    port: BufReader<ReadHalf<SerialStream>>,
+    line: Vec<u8>,
+}
+
+impl Reader {
+    /// Wait for the next received frame, skipping the responses to commands
+    pub async fn next(&mut self) -> Option<io::Result<CanAnyFrame>> {
+        loop {
+            self.line.clear();
+            match self.port.read_until(CR, &mut self.line).await {
+                Ok(0) => return None,
+                Ok(_) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            let line = self.line.strip_suffix(&[CR]).unwrap_or(&self.line);
+            let errors = line.iter().take_while(|&&c| c == BELL).count();
+            if errors > 0 {
+                tracing::warn!(errors, "SLCAN adapter rejected command");
+            }
+            // other lines respond to commands, e.g. `z` acknowledging a transmitted frame
+            if let Some(frame) = decode(&line[errors..]) {
+                return Some(Ok(frame));
+            }
+        }
+    }
+}
+
+/// Transmitting half of the serial port
+pub struct Writer {
+    port: Mutex<WriteHalf<SerialStream>>,
+}
+
+impl Writer {
+    pub async fn write_frame(&self, frame: &CanAnyFrame) -> io::Result<()> {
+        let command = encode(frame)?;
+        self.command(&command).await
+    }
+
+    async fn command(&self, command: &str) -> io::Result<()> {
+        let mut port = self.port.lock().await;
+        port.write_all(command.as_bytes()).await?;
+        port.flush().await
+    }
+}
+
+/// Open the adapter at the serial port and the CAN channel at the bitrate
+pub async fn open(path: &str, baud: u32, bitrate: u32) -> io::Result<(Reader, Writer)> {
+    let code = bitrate_code(bitrate).ok_or_else(|| invalid("bitrate not supported by SLCAN"))?;
+    let port = tokio_serial::new(path, baud).open_native_async().map_err(io::Error::from)?;
+    let (rx, tx) = tokio::io::split(port);
+    let writer = Writer { port: Mutex::new(tx) };
+
+    // close a channel left open, responded by a bell if closed already
+    writer.command("C\r").await?;
+    writer.command(&format!("S{}\r", code)).await?;
+    writer.command("O\r").await?;
+    Ok((Reader { port: BufReader::new(rx), line: Vec::new() }, writer))
+}
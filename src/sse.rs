@@ -0,0 +1,85 @@
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use axum::{
+    extract::ConnectInfo,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
+use futures_util::stream::{self, Stream};
+
+use crate::can::{CanEvent, Subscription};
+use crate::frame::CanAnyFrame;
+use crate::protocol::{BusError, ErrorReason, ServerMessage};
+use crate::server::AppState;
+use crate::ws::{bus_error_message, frame_message, initial_messages, status_message, MSG_CAN_CONNECTED, MSG_CAN_FAILED};
+
+// live feed of a single SSE client, starting with the initial messages
+struct Feed {
+    state: AppState,
+    events: Subscription,
+    last_bus_error: Option<(BusError, Instant)>,
+    initial: std::vec::IntoIter<ServerMessage>,
+}
+
+impl Feed {
+    /// Message of the event, if to be forwarded to the client
+    fn message(&mut self, event: CanEvent) -> Option<ServerMessage> {
+        let state = &self.state;
+        match event {
+            CanEvent::Frame(interface, CanAnyFrame::Error(frame), _, _) => {
+                bus_error_message(&mut self.last_bus_error, &interface, &frame)
+            }
+            CanEvent::Frame(interface, frame, timestamp, direction) => {
+                Some(frame_message(state, &interface, &frame, timestamp, direction))
+            }
+            CanEvent::Connected(interface) => {
+                Some(ServerMessage::notice(format!("{} {}", MSG_CAN_CONNECTED, interface)))
+            }
+            CanEvent::Disconnected(interface) => {
+                Some(ServerMessage::error(ErrorReason::CanDevice, format!("{} {}", MSG_CAN_FAILED, interface)))
+            }
+            CanEvent::Notice(notice) => Some(ServerMessage::notice(&*notice)),
+            CanEvent::Stats(stats) => Some(status_message(state, Some(stats.to_vec()))),
+            CanEvent::Interface(states) => Some(ServerMessage::Interface(states.to_vec())),
+            CanEvent::Telemetry(telemetry) => Some(ServerMessage::Telemetry((*telemetry).clone())),
+            CanEvent::BusState(event) => Some(ServerMessage::BusState((*event).clone())),
+            CanEvent::Canopen(event) => Some(ServerMessage::Canopen((*event).clone())),
+            CanEvent::Alert(alert) => Some(ServerMessage::Alert((*alert).clone())),
+            CanEvent::Timeout(timeout) => Some(ServerMessage::Timeout((*timeout).clone())),
+            #[cfg(feature = "j1939")]
+            CanEvent::J1939(group) => Some(ServerMessage::J1939((*group).clone())),
+            #[cfg(feature = "nmea2000")]
+            CanEvent::Nmea2000(message) => Some(ServerMessage::Nmea2000((*message).clone())),
+        }
+    }
+
+    /// Wait for the next message, the stream ends on shutdown
+    async fn next(mut self) -> Option<(Result<Event, serde_json::Error>, Feed)> {
+        if let Some(message) = self.initial.next() {
+            return Some((Event::default().json_data(message.envelope()), self));
+        }
+        loop {
+            let event = self.events.next().await?;
+            if let Some(message) = self.message(event) {
+                return Some((Event::default().json_data(message.envelope()), self));
+            }
+        }
+    }
+}
+
+/// `GET /events` - Server-Sent Events stream of the JSON messages sent to WebSocket clients,
+/// without filters, for clients unable to use WebSockets
+pub async fn events(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Extension(state): Extension<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, serde_json::Error>>> {
+    tracing::info!(%peer, "SSE client connected");
+    let feed = Feed {
+        initial: initial_messages(&state).await.into_iter(),
+        events: Subscription::new(state.events.subscribe(), state.shutdown.clone(), "SSE client"),
+        last_bus_error: None,
+        state,
+    };
+    Sse::new(stream::unfold(feed, Feed::next)).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::can::{self, Buses, CanEvent, Subscription};
+use crate::frame::{CanAnyFrame, EmbeddedFrame};
+
+// DTO - statistics of a CAN interface over the last reporting period
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct BusStats {
+    pub interface: String,
+    pub frames_per_sec: f64,
+    pub bytes_per_sec: f64,
+    // estimated bus load in percent of the configured bitrate
+    pub bus_load: f64,
+    // error frames received since start
+    pub errors: u64,
+    // frames awaiting their write in the transmit queue, at the end of the period
+    #[serde(default)]
+    pub tx_queue: usize,
+}
+
+/// Bitrates of the CAN bus, used to estimate the bus load
+#[derive(Clone, Copy, Debug)]
+pub struct Bitrate {
+    pub nominal: u32,
+    // bitrate of the data phase of CAN FD frames with bit rate switch
+    pub data: u32,
+}
+
+#[derive(Default)]
+struct Counter {
+    frames: u64,
+    bytes: u64,
+    // bus time occupied by the frames, in seconds
+    busy: f64,
+    errors: u64,
+}
+
+/// Estimate the bus time occupied by the frame, ignoring stuff bits
+///
+/// Classic frames take 47 bits (standard id) or 67 bits (extended id) plus the data, CAN FD
+/// frames add the longer CRC; the data phase of FD frames with BRS runs at the data bitrate.
+fn frame_time(frame: &CanAnyFrame, bitrate: Bitrate) -> f64 {
+    let arbitration = if frame.is_extended() { 67.0 } else { 47.0 };
+    let data = 8.0 * frame.data().len() as f64;
+    let nominal = bitrate.nominal as f64;
+    match frame {
+        CanAnyFrame::Fd(fd) => {
+            let crc = if fd.data().len() > 16 { 21.0 } else { 17.0 };
+            let data_rate = if fd.is_brs() { bitrate.data as f64 } else { nominal };
+            arbitration / nominal + (data + crc) / data_rate
+        }
+        _ => (arbitration + data) / nominal,
+    }
+}
+
+/// Statistics task, publishing the statistics of all interfaces once per period until shutdown
+pub async fn collector(
+    buses: Buses,
+    bitrate: Bitrate,
+    events: broadcast::Sender<CanEvent>,
+    shutdown: CancellationToken,
+) {
+    const PERIOD: Duration = Duration::from_secs(1);
+    let mut rx = Subscription::new(events.subscribe(), shutdown, "statistics");
+    let mut counters: HashMap<Arc<str>, Counter> = HashMap::new();
+    let mut since = Instant::now();
+    let mut ticker = tokio::time::interval_at(since + PERIOD, PERIOD);
+
+    loop {
+        tokio::select! {
+            frame = rx.next_frame() => match frame {
+                Some((interface, frame, _, _)) => {
+                    let counter = counters.entry(interface).or_default();
+                    if let CanAnyFrame::Error(_) = frame {
+                        counter.errors += 1;
+                    } else {
+                        counter.frames += 1;
+                        counter.bytes += frame.data().len() as u64;
+                        counter.busy += frame_time(&frame, bitrate);
+                    }
+                }
+                None => return,
+            },
+            _ = ticker.tick() => {
+                let elapsed = since.elapsed().as_secs_f64().max(f64::EPSILON);
+                since = Instant::now();
+                let stats = buses
+                    .iter()
+                    .map(|bus| {
+                        let counter = counters.entry(bus.name.clone()).or_default();
+                        let stats = BusStats {
+                            interface: bus.name.to_string(),
+                            frames_per_sec: counter.frames as f64 / elapsed,
+                            bytes_per_sec: counter.bytes as f64 / elapsed,
+                            bus_load: (100.0 * counter.busy / elapsed).min(100.0),
+                            errors: counter.errors,
+                            tx_queue: bus.queue_len(),
+                        };
+                        // reset the period, keeping the error count
+                        *counter = Counter { errors: counter.errors, ..Default::default() };
+                        stats
+                    })
+                    .collect::<Vec<_>>();
+                can::publish(&events, CanEvent::Stats(stats.into()));
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use neli::{consts::{nl::NlTypeWrapper, rtnl::{Iff, Ifla, Rtm}, socket::NlFamily}, nl::NlPayload, rtnl::Ifinfomsg};
+use tokio::runtime::Handle;
+use tokio::sync::broadcast;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+use crate::can::{self, Bus, Buses, CanEvent};
+#[cfg(target_os = "linux")]
+use crate::transport::Transport;
+
+// multicast group of link notifications, see linux/rtnetlink.h
+#[cfg(target_os = "linux")]
+const RTNLGRP_LINK: u32 = 1;
+
+/// Delay of reconnect attempts, doubling up to a maximum and reset once connected
+struct Backoff {
+    delay: Duration,
+}
+
+impl Backoff {
+    const INITIAL: Duration = Duration::from_millis(100);
+    const MAX: Duration = Duration::from_secs(10);
+
+    fn new() -> Backoff {
+        Backoff { delay: Backoff::INITIAL }
+    }
+
+    fn next(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (delay * 2).min(Backoff::MAX);
+        delay
+    }
+}
+
+/// Supervisor of the CAN device, the single reader publishing all frames to the sessions
+///
+/// The device is re-opened with exponential backoff while missing, or as soon as the link
+/// monitor reports it up. Connection changes are published to all sessions. On shutdown the
+/// device is closed, waiting for a pending write to complete. Receive filters changed on reload
+/// are applied to the open device.
+async fn supervise(bus: Arc<Bus>, events: broadcast::Sender<CanEvent>, shutdown: CancellationToken) {
+    let mut backoff = Backoff::new();
+    let mut filters = bus.filters.clone();
+    loop {
+        let current = filters.borrow_and_update().clone();
+        match bus.open(&current).await {
+            Ok(mut rx) => {
+                backoff = Backoff::new();
+                tracing::info!(interface = %bus.name, transport = %bus.transport, "CAN device connected");
+                can::publish(&events, CanEvent::Connected(bus.name.clone()));
+
+                loop {
+                    tokio::select! {
+                        frame = rx.next() => match frame {
+                            Some((frame, timestamp)) => {
+                                let direction = bus.direction(&frame);
+                                can::publish(&events, CanEvent::Frame(bus.name.clone(), frame, timestamp, direction));
+                            }
+                            None => break,
+                        },
+                        Ok(()) = filters.changed() => {
+                            let current = filters.borrow_and_update().clone();
+                            if let Err(e) = rx.set_filters(&current) {
+                                tracing::warn!(interface = %bus.name, error = %e, "failed to change receive filters");
+                            }
+                        }
+                        _ = shutdown.cancelled() => {
+                            bus.close().await;
+                            return;
+                        }
+                    }
+                }
+
+                bus.close().await;
+                tracing::warn!(interface = %bus.name, "CAN device lost");
+                can::publish(&events, CanEvent::Disconnected(bus.name.clone()));
+            }
+            Err(e) => tracing::debug!(interface = %bus.name, error = %e, "failed to open CAN device"),
+        }
+
+        let delay = backoff.next();
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => (),
+            _ = bus.link_up.notified() => tracing::debug!(interface = %bus.name, "CAN device up, reconnecting"),
+            _ = shutdown.cancelled() => return,
+        }
+    }
+}
+
+/// Monitor of the network links via netlink, notifying the supervisor of a SocketCAN device
+/// once it is added or set up
+#[cfg(target_os = "linux")]
+async fn link_monitor(buses: Buses, shutdown: CancellationToken) {
+    let socket = neli::socket::NlSocket::connect(NlFamily::Route, None, &[RTNLGRP_LINK])
+        .and_then(neli::socket::tokio::NlSocket::new);
+    let mut socket = match socket {
+        Ok(socket) => socket,
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to monitor network links, reconnecting by backoff only");
+            return;
+        }
+    };
+
+    let mut buffer = Vec::new();
+    loop {
+        let messages = tokio::select! {
+            messages = socket.recv::<NlTypeWrapper, Ifinfomsg>(&mut buffer) => messages,
+            _ = shutdown.cancelled() => return,
+        };
+        let messages = match messages {
+            Ok(messages) => messages,
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to receive network link notifications");
+                return;
+            }
+        };
+        for message in messages {
+            let NlPayload::Payload(link) = &message.nl_payload else { continue };
+            if message.nl_type != NlTypeWrapper::Rtm(Rtm::Newlink) || !link.ifi_flags.contains(&Iff::Up) {
+                continue;
+            }
+            let Ok(name) = link.rtattrs.get_attr_handle().get_attr_payload_as_with_len::<String>(Ifla::Ifname) else {
+                continue;
+            };
+            if let Some(bus) = buses.get(Some(&name)).filter(|bus| matches!(bus.transport, Transport::SocketCan | Transport::Sllin)) {
+                bus.link_up.notify_one();
+            }
+        }
+    }
+}
+
+/// Single-threaded runtime of the CAN devices by `--can-thread`, on a thread of its own running
+/// until the process exits
+///
+/// The devices are opened by the supervisors, so the runtime drives their sockets, the frames
+/// written by the sessions on the other runtime included.
+fn dedicated_runtime() -> Result<Handle, String> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("failed to start the CAN runtime: {}", e))?;
+    let handle = runtime.handle().clone();
+    std::thread::Builder::new()
+        .name("can-io".to_string())
+        .spawn(move || runtime.block_on(std::future::pending::<()>()))
+        .map_err(|e| format!("failed to start the CAN thread: {}", e))?;
+    tracing::debug!("reading the CAN devices on a dedicated thread");
+    Ok(handle)
+}
+
+/// Spawn a supervisor and a transmitter per interface and the link monitor of the SocketCAN
+/// devices, running until shutdown; on a dedicated thread if `dedicated`, else on the current runtime
+pub fn spawn(buses: &Buses, tasks: &TaskTracker, events: &broadcast::Sender<CanEvent>, shutdown: &CancellationToken,
+             dedicated: bool) -> Result<(), String> {
+    let runtime = if dedicated { dedicated_runtime()? } else { Handle::current() };
+    for bus in buses.iter() {
+        tasks.spawn_on(supervise(bus.clone(), events.clone(), shutdown.clone()), &runtime);
+        tasks.spawn_on(can::transmitter(bus.clone(), shutdown.clone()), &runtime);
+    }
+    #[cfg(target_os = "linux")]
+    if buses.iter().any(|bus| matches!(bus.transport, Transport::SocketCan | Transport::Sllin)) {
+        tasks.spawn_on(link_monitor(buses.clone(), shutdown.clone()), &runtime);
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::net::TcpListener;
+use std::os::fd::FromRawFd;
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+use tokio_util::sync::CancellationToken;
+
+/// The listening sockets passed by systemd socket activation, the sockets of `LISTEN_FDS`, none
+/// if not socket activated
+pub fn listeners() -> Result<Vec<TcpListener>, String> {
+    let fds = sd_notify::listen_fds().map_err(|e| format!("invalid socket activation: {}", e))?;
+    let mut listeners = Vec::new();
+    for fd in fds {
+        // the fd is owned by this process once passed by systemd
+        let listener = unsafe { TcpListener::from_raw_fd(fd) };
+        listener.set_nonblocking(true).map_err(|e| format!("invalid socket passed by systemd: {}", e))?;
+        listeners.push(listener);
+    }
+    Ok(listeners)
+}
+
+/// Notify systemd of the service being ready, a no-op unless started by systemd
+pub fn ready() {
+    notify(&[NotifyState::Ready]);
+}
+
+pub fn stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(state) {
+        tracing::warn!(error = %e, "failed to notify systemd");
+    }
+}
+
+/// Keep the watchdog of `WatchdogSec` alive until shutdown, at half its timeout
+pub async fn watchdog(shutdown: CancellationToken) {
+    let Some(timeout) = sd_notify::watchdog_enabled() else { return };
+    let mut interval = tokio::time::interval((timeout / 2).max(Duration::from_millis(100)));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => notify(&[NotifyState::Watchdog]),
+            _ = shutdown.cancelled() => return,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::time::MissedTickBehavior;
+use utoipa::ToSchema;
+
+use crate::can::{self, CanEvent, Subscription, Timestamp};
+use crate::config::Config;
+use crate::decode::{message_id, Decoder};
+use crate::frame::{CanAnyFrame, EmbeddedFrame, Id};
+use crate::protocol::{format_id, parse_frame_id, FrameTimestamp};
+use crate::server::AppState;
+
+// interval of checking the messages for timeouts, bounding the delay of reporting them
+const CHECK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Expected cycle time of a periodic message of `--cycle-time`, parsed of `<id>:<ms>` with the
+/// id in hex, e.g. `0x123:100`, extended if of 8 digits or exceeding 0x7FF
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CycleTime {
+    id: Id,
+    cycle: Duration,
+}
+
+impl FromStr for CycleTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid cycle time {}, expecting <id>:<ms>, e.g. 0x123:100", s);
+        let (id, ms) = s.split_once(':').ok_or_else(invalid)?;
+        let id = id.trim();
+        let id = parse_frame_id(id.strip_prefix("0x").or_else(|| id.strip_prefix("0X")).unwrap_or(id)).map_err(|_| invalid())?;
+        let ms: u64 = ms.trim().parse().map_err(|_| invalid())?;
+        if ms == 0 {
+            return Err(invalid());
+        }
+        Ok(CycleTime { id, cycle: Duration::from_millis(ms) })
+    }
+}
+
+// id keyed like the messages of the DBC database, see [message_id]
+fn key(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw() as u32,
+        Id::Extended(id) => id.as_raw() | 1 << 31,
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutState {
+    TimedOut,
+    Recovered,
+}
+
+// DTO - periodic message missing for `--timeout-factor` cycles, or received again, sent to the
+// clients as `timeout`, with the name of the message of `--dbc` and the time since the last frame
+// of the id
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct TimeoutEvent {
+    pub interface: String,
+    pub id: String,
+    pub message: Option<String>,
+    pub state: TimeoutState,
+    pub cycle_ms: f64,
+    pub missing_ms: f64,
+    pub timestamp: FrameTimestamp,
+}
+
+struct Entry {
+    id: Id,
+    cycle: Duration,
+    // monotonic time of the last frame
+    last: Duration,
+    timed_out: bool,
+}
+
+/// Timeouts of the periodic messages, by the cycle times of `--cycle-time` and of the DBC
+/// database; a message is monitored once received, an ECU not started yet raising no timeouts
+pub struct Timeouts {
+    cycle_times: HashMap<u32, Duration>,
+    // cycle times of the DBC database too, by `--message-timeouts`
+    dbc: bool,
+    factor: f64,
+    entries: Mutex<HashMap<(Arc<str>, u32), Entry>>,
+}
+
+impl Timeouts {
+    /// Timeouts of `--cycle-time` and `--message-timeouts`, none if neither is given
+    pub fn new(config: &Config) -> Option<Timeouts> {
+        if config.cycle_time.is_empty() && !config.message_timeouts {
+            return None;
+        }
+        Some(Timeouts {
+            cycle_times: config.cycle_time.iter().map(|cycle_time| (key(cycle_time.id), cycle_time.cycle)).collect(),
+            dbc: config.message_timeouts,
+            factor: config.timeout_factor,
+            entries: Mutex::default(),
+        })
+    }
+
+    /// Record the frame of a periodic message, the recovery if its message had timed out
+    fn received(&self, interface: &Arc<str>, frame: &CanAnyFrame, timestamp: Timestamp, decoder: Option<&Decoder>) -> Option<TimeoutEvent> {
+        let key = message_id(frame);
+        // the cycle times configured taking precedence, those of the DBC changing on reload
+        let cycle = self.cycle_times.get(&key).copied()
+            .or_else(|| decoder.filter(|_| self.dbc).and_then(|decoder| decoder.cycle_time(key)))?;
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry((interface.clone(), key)).or_insert(Entry {
+            id: frame.id(),
+            cycle,
+            last: timestamp.monotonic,
+            timed_out: false,
+        });
+        let missing = timestamp.monotonic.saturating_sub(entry.last);
+        entry.cycle = cycle;
+        entry.last = timestamp.monotonic;
+        if !std::mem::replace(&mut entry.timed_out, false) {
+            return None;
+        }
+        Some(event(interface, entry, TimeoutState::Recovered, missing, timestamp, decoder))
+    }
+
+    /// Timeouts of the messages missing for the cycles of the factor, each reported once
+    fn expired(&self, now: Timestamp, decoder: Option<&Decoder>) -> Vec<TimeoutEvent> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut events = Vec::new();
+        for ((interface, _), entry) in entries.iter_mut() {
+            let missing = now.monotonic.saturating_sub(entry.last);
+            if entry.timed_out || missing.as_secs_f64() <= entry.cycle.as_secs_f64() * self.factor {
+                continue;
+            }
+            entry.timed_out = true;
+            events.push(event(interface, entry, TimeoutState::TimedOut, missing, now, decoder));
+        }
+        events
+    }
+
+    /// Timeouts of the messages currently missing, sent to a client on connecting
+    pub fn active(&self, decoder: Option<&Decoder>) -> Vec<TimeoutEvent> {
+        let now = Timestamp::now();
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .filter(|(_, entry)| entry.timed_out)
+            .map(|((interface, _), entry)| {
+                let missing = now.monotonic.saturating_sub(entry.last);
+                event(interface, entry, TimeoutState::TimedOut, missing, now, decoder)
+            })
+            .collect()
+    }
+}
+
+fn event(interface: &str, entry: &Entry, state: TimeoutState, missing: Duration, timestamp: Timestamp, decoder: Option<&Decoder>) -> TimeoutEvent {
+    TimeoutEvent {
+        interface: interface.to_string(),
+        id: format_id(entry.id),
+        message: decoder.and_then(|decoder| decoder.message_name(key(entry.id))).map(str::to_string),
+        state,
+        cycle_ms: entry.cycle.as_secs_f64() * 1000.0,
+        missing_ms: missing.as_secs_f64() * 1000.0,
+        timestamp: timestamp.into(),
+    }
+}
+
+/// Monitor the periodic messages received until shutdown, publishing their timeouts and
+/// recoveries to the clients
+pub async fn monitor(state: AppState, timeouts: Arc<Timeouts>) {
+    let mut events = Subscription::new(state.events.subscribe(), state.shutdown.clone(), "message timeouts");
+    let mut check = tokio::time::interval(CHECK_INTERVAL);
+    check.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        let reported = tokio::select! {
+            frame = events.next_frame() => match frame {
+                Some((_, CanAnyFrame::Error(_), _, _)) => continue,
+                Some((interface, frame, timestamp, _)) => {
+                    let decoder = state.settings.decoder.borrow().clone();
+                    timeouts.received(&interface, &frame, timestamp, decoder.as_deref()).into_iter().collect()
+                }
+                None => return,
+            },
+            _ = check.tick() => {
+                let decoder = state.settings.decoder.borrow().clone();
+                timeouts.expired(Timestamp::now(), decoder.as_deref())
+            }
+        };
+        for event in reported {
+            match event.state {
+                TimeoutState::TimedOut => tracing::warn!(interface = event.interface, id = event.id, missing_ms = event.missing_ms, "message timed out"),
+                TimeoutState::Recovered => tracing::info!(interface = event.interface, id = event.id, missing_ms = event.missing_ms, "message recovered"),
+            }
+            can::publish(&state.events, CanEvent::Timeout(Arc::new(event)));
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[cfg(target_os = "linux")]
+use socketcan::{
+    tokio::CanFdSocket, SocketOptions, SOF_TIMESTAMPING_OPT_CMSG, SOF_TIMESTAMPING_RAW_HARDWARE,
+    SOF_TIMESTAMPING_RX_HARDWARE, SOF_TIMESTAMPING_RX_SOFTWARE, SOF_TIMESTAMPING_SOFTWARE,
+};
+use tokio::sync::mpsc;
+
+use crate::can::{CanTransport, Timestamp};
+use crate::frame::{CanAnyFrame, CanFilter};
+#[cfg(feature = "gs_usb")]
+use crate::gs_usb;
+use crate::simulate::{Message, Simulator};
+use crate::slcan;
+
+// serial baud rate of SLCAN adapters if not given, ignored by USB CDC adapters like CANable
+const DEFAULT_BAUD: u32 = 115_200;
+
+/// Transport of a CAN interface, parsed of `socketcan`, `slcan:<serial port>[@<baud rate>]`,
+/// `gs_usb[:<serial number>]` or `sllin`, or registered by the embedding application
+///
+/// SocketCAN is available on Linux only, on other platforms the interfaces need another transport.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Transport {
+    #[default]
+    SocketCan,
+    Slcan { path: String, baud: u32 },
+    // userspace driver of candleLight adapters, the first adapter found if no serial number
+    GsUsb { serial: Option<String> },
+    // LIN bus of a serial adapter by the sllin line discipline, a SocketCAN interface of the LIN
+    // frames, see [crate::lin]
+    Sllin,
+    // traffic generator of `--simulate`
+    Simulated(Arc<Vec<Message>>),
+    // of [crate::server::ServerBuilder::transport]
+    Custom(Custom),
+}
+
+/// Transport of the embedding application, equal to another if the same instance
+#[derive(Clone)]
+pub struct Custom(pub Arc<dyn CanTransport>);
+
+impl PartialEq for Custom {
+    fn eq(&self, other: &Custom) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl std::fmt::Debug for Custom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Custom")
+    }
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(':') {
+            None if s.trim() == "socketcan" => Ok(Transport::SocketCan),
+            None if s.trim() == "gs_usb" => Ok(Transport::GsUsb { serial: None }),
+            None if s.trim() == "sllin" => Ok(Transport::Sllin),
+            Some(("gs_usb", serial)) if !serial.is_empty() => Ok(Transport::GsUsb { serial: Some(serial.to_string()) }),
+            Some(("slcan", port)) => {
+                let (path, baud) = match port.split_once('@') {
+                    Some((path, baud)) => (path, baud.parse().map_err(|_| format!("invalid baud rate {}", baud))?),
+                    None => (port, DEFAULT_BAUD),
+                };
+                if path.is_empty() {
+                    return Err(format!("missing serial port of transport {}", s));
+                }
+                Ok(Transport::Slcan { path: path.to_string(), baud })
+            }
+            _ => Err(format!("invalid transport {}, expecting socketcan, slcan:<port>[@<baud>], gs_usb[:<serial>] or sllin", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transport::SocketCan => write!(f, "socketcan"),
+            Transport::Slcan { path, baud } => write!(f, "slcan:{}@{}", path, baud),
+            Transport::GsUsb { serial: None } => write!(f, "gs_usb"),
+            Transport::GsUsb { serial: Some(serial) } => write!(f, "gs_usb:{}", serial),
+            Transport::Sllin => write!(f, "sllin"),
+            Transport::Simulated(_) => write!(f, "simulated"),
+            Transport::Custom(_) => write!(f, "custom"),
+        }
+    }
+}
+
+/// Receive filter of the kernel, parsed of `<id>:<mask>` or inverted `<id>~<mask>` in hex like candump,
+/// e.g. `0x100:0x700` passing the ids 0x100 to 0x1FF
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RxFilter {
+    id: u32,
+    mask: u32,
+    inverted: bool,
+}
+
+impl FromStr for RxFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, mask, inverted) = match (s.split_once(':'), s.split_once('~')) {
+            (Some((id, mask)), None) => (id, mask, false),
+            (None, Some((id, mask))) => (id, mask, true),
+            _ => return Err(format!("invalid filter {}, expecting <id>:<mask> or <id>~<mask>", s)),
+        };
+        let hex = |s: &str| {
+            let s = s.trim();
+            u32::from_str_radix(s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s), 16)
+                .map_err(|_| format!("invalid filter {}, expecting hex id and mask", s))
+        };
+        Ok(RxFilter { id: hex(id)?, mask: hex(mask)?, inverted })
+    }
+}
+
+impl From<RxFilter> for CanFilter {
+    fn from(filter: RxFilter) -> CanFilter {
+        if filter.inverted {
+            CanFilter::new_inverted(filter.id, filter.mask)
+        } else {
+            CanFilter::new(filter.id, filter.mask)
+        }
+    }
+}
+
+/// Receiving side of an open CAN interface
+pub enum Rx {
+    #[cfg(target_os = "linux")]
+    SocketCan(CanFdSocket),
+    Slcan(slcan::Reader),
+    #[cfg(feature = "gs_usb")]
+    GsUsb(gs_usb::Reader),
+    Simulated(Simulator),
+    Custom(Arc<dyn CanTransport>),
+}
+
+impl Rx {
+    /// Wait for the next frame and its reception time, `None` if the device is gone or failed
+    ///
+    /// SocketCAN frames are timestamped by the kernel, by the CAN controller too if supported;
+    /// the frames of other transports at reading them.
+    pub async fn next(&mut self) -> Option<(CanAnyFrame, Timestamp)> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Rx::SocketCan(socket) => {
+                let (frame, timestamps) = socket.read_frame_with_timestamps().await.ok()?;
+                Some((frame, Timestamp::new(timestamps.sw, timestamps.hw)))
+            }
+            Rx::Slcan(reader) => Some((reader.next().await?.ok()?, Timestamp::now())),
+            #[cfg(feature = "gs_usb")]
+            Rx::GsUsb(reader) => Some((reader.next().await?.ok()?, Timestamp::now())),
+            Rx::Simulated(simulator) => Some((simulator.next().await?, Timestamp::now())),
+            Rx::Custom(transport) => transport.recv().await,
+        }
+    }
+
+    /// Replace the receive filters of a SocketCAN interface, all frames passing if empty
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub fn set_filters(&self, filters: &[RxFilter]) -> io::Result<()> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Rx::SocketCan(socket) if filters.is_empty() => socket.set_filter_accept_all(),
+            #[cfg(target_os = "linux")]
+            Rx::SocketCan(socket) => socket.set_filters(filters),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Transmitting side of an open CAN interface
+pub enum Tx {
+    #[cfg(target_os = "linux")]
+    SocketCan(CanFdSocket),
+    Slcan(slcan::Writer),
+    #[cfg(feature = "gs_usb")]
+    GsUsb(gs_usb::Writer),
+    Simulated(mpsc::Sender<CanAnyFrame>),
+    Custom(Arc<dyn CanTransport>),
+}
+
+impl Tx {
+    pub async fn write_frame(&self, frame: &CanAnyFrame) -> io::Result<()> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Tx::SocketCan(socket) => socket.write_frame(frame).await,
+            Tx::Slcan(writer) => writer.write_frame(frame).await,
+            #[cfg(feature = "gs_usb")]
+            Tx::GsUsb(writer) => writer.write_frame(frame).await,
+            Tx::Simulated(loopback) => loopback.send(*frame).await.map_err(|_| io::ErrorKind::BrokenPipe.into()),
+            Tx::Custom(transport) => transport.send(frame).await,
+        }
+    }
+}
+
+impl Transport {
+    /// Whether the frames written are received back by the reader, of the loopback of SocketCAN
+    /// to the other sockets of the interface, or of the simulation
+    pub fn echoes(&self) -> bool {
+        matches!(self, Transport::SocketCan | Transport::Sllin | Transport::Simulated(_))
+    }
+
+    /// Whether a LIN bus, the frames of the LIN ids sent to the clients as `lin`
+    pub fn is_lin(&self) -> bool {
+        *self == Transport::Sllin
+    }
+
+    /// Open the CAN interface of the name, SLCAN and gs_usb adapters are set up with the bitrate
+    ///
+    /// The receive filters are applied by the kernel to SocketCAN interfaces, passing the frames
+    /// matching any of them; ignored by other transports. See [Rx::set_filters] to change them.
+    pub async fn open(&self, name: &str, bitrate: u32, filters: &[RxFilter]) -> io::Result<(Rx, Tx)> {
+        if !filters.is_empty() && !matches!(self, Transport::SocketCan | Transport::Sllin) {
+            tracing::warn!(interface = name, transport = %self, "receive filters only supported by SocketCAN, ignored");
+        }
+        match self {
+            // sllin attached to the serial port by `ldattach 28 <serial port>`, the kernel computing the checksums
+            #[cfg(target_os = "linux")]
+            Transport::SocketCan | Transport::Sllin => {
+                let (rx, tx) = (CanFdSocket::open(name)?, CanFdSocket::open(name)?);
+                if !filters.is_empty() {
+                    rx.set_filters(filters)?;
+                }
+                // the frames written echoed to the receiving socket, the default of the kernel
+                if let Err(e) = tx.set_loopback(true) {
+                    tracing::warn!(interface = name, error = %e, "failed to enable loopback of the frames written");
+                }
+                if let Err(e) = rx.set_error_filter_accept_all() {
+                    tracing::warn!(interface = name, error = %e, "failed to enable error frames");
+                }
+                let mut timestamping = SOF_TIMESTAMPING_RX_SOFTWARE | SOF_TIMESTAMPING_SOFTWARE | SOF_TIMESTAMPING_OPT_CMSG;
+                if rx.has_hw_timestamps() {
+                    timestamping |= SOF_TIMESTAMPING_RX_HARDWARE | SOF_TIMESTAMPING_RAW_HARDWARE;
+                }
+                if let Err(e) = rx.set_timestamping(timestamping) {
+                    tracing::warn!(interface = name, error = %e, "failed to enable timestamps");
+                }
+                Ok((Rx::SocketCan(rx), Tx::SocketCan(tx)))
+            }
+            #[cfg(not(target_os = "linux"))]
+            Transport::SocketCan | Transport::Sllin => Err(io::Error::new(io::ErrorKind::Unsupported, "SocketCAN requires Linux")),
+            Transport::Slcan { path, baud } => {
+                let (rx, tx) = slcan::open(path, *baud, bitrate).await?;
+                Ok((Rx::Slcan(rx), Tx::Slcan(tx)))
+            }
+            #[cfg(feature = "gs_usb")]
+            Transport::GsUsb { serial } => {
+                let (rx, tx) = gs_usb::open(serial.as_deref(), bitrate).await?;
+                Ok((Rx::GsUsb(rx), Tx::GsUsb(tx)))
+            }
+            #[cfg(not(feature = "gs_usb"))]
+            Transport::GsUsb { .. } => {
+                Err(io::Error::new(io::ErrorKind::Unsupported, "gs_usb transport requires cargo build --features gs_usb"))
+            }
+            Transport::Simulated(messages) => {
+                let (simulator, loopback) = Simulator::new(messages.clone());
+                Ok((Rx::Simulated(simulator), Tx::Simulated(loopback)))
+            }
+            Transport::Custom(Custom(transport)) => Ok((Rx::Custom(transport.clone()), Tx::Custom(transport.clone()))),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use axum::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// DTO - build of the service, identifying a deployed instance; the build time in seconds since
+// epoch, the commit suffixed by `-dirty` if built of uncommitted changes
+#[derive(Serialize, Deserialize, JsonSchema, ToSchema, Debug, Clone)]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_commit: String,
+    pub build_timestamp: u64,
+    pub rustc: String,
+    pub cargo: String,
+    pub profile: String,
+    pub features: Vec<String>,
+}
+
+/// Build info embedded by build.rs
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: env!("BUILD_GIT_COMMIT").to_string(),
+        build_timestamp: env!("BUILD_TIMESTAMP").parse().unwrap_or_default(),
+        rustc: env!("BUILD_RUSTC").to_string(),
+        cargo: env!("BUILD_CARGO").to_string(),
+        profile: env!("BUILD_PROFILE").to_string(),
+        features: env!("BUILD_FEATURES").split(',').filter(|feature| !feature.is_empty()).map(str::to_string).collect(),
+    }
+}
+
+/// `GET /api/version` - version, git commit, build time, toolchain and enabled features of the service
+#[utoipa::path(get, path = "/api/version", responses(
+    (status = 200, description = "build of the service", body = BuildInfo),
+))]
+pub async fn get_version() -> Json<BuildInfo> {
+    Json(build_info())
+}
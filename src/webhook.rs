@@ -0,0 +1,137 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper::{client::HttpConnector, header, Body, Client, Method, Request, Uri};
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::alerts::AlertEvent;
+use crate::busstate::BusStateEvent;
+use crate::can::{CanEvent, Subscription};
+use crate::server::AppState;
+use crate::timeouts::TimeoutEvent;
+use crate::ws::service_url;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// a notification failing is retried after 1, 2, 4 and 8 seconds
+const ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+// bound of the notifications queued for each webhook, dropped while the webhook keeps failing
+const QUEUE_LEN: usize = 256;
+
+// DTO - notification posted to the webhooks of `--webhook`, by the service of its URL, tagged by
+// the event, e.g. `{"service": "http://192.0.2.2:3000", "timestamp": 1436509052.2,
+// "event": "bus_state", "data": {"interface": "can0", "state": "bus_off", ...}}`
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct Notification {
+    pub service: String,
+    // wall clock in seconds since epoch
+    pub timestamp: f64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Serialize, JsonSchema, Debug)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Event {
+    Started,
+    Stopped,
+    Connected { interface: String },
+    Disconnected { interface: String },
+    BusState(BusStateEvent),
+    Alert(AlertEvent),
+    Timeout(TimeoutEvent),
+}
+
+/// Webhook URL, plain HTTP like the InfluxDB exporter
+pub fn url(url: &str) -> Result<Uri, String> {
+    let uri: Uri = url.parse().map_err(|e| format!("invalid webhook URL {}: {}", url, e))?;
+    if uri.scheme_str() != Some("http") || uri.host().is_none() {
+        return Err(format!("unsupported webhook URL {}, expecting http://host:port/path", url));
+    }
+    Ok(uri)
+}
+
+/// Post the JSON body to the webhook, retried with exponential backoff; false if all attempts
+/// failed, on shutdown after the current attempt
+pub async fn deliver(client: &Client<HttpConnector>, uri: &Uri, body: &str, shutdown: &CancellationToken) -> bool {
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=ATTEMPTS {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let error = match tokio::time::timeout(REQUEST_TIMEOUT, client.request(request)).await {
+            Ok(Ok(response)) if response.status().is_success() => return true,
+            Ok(Ok(response)) => format!("rejected with {}", response.status()),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "timed out".to_string(),
+        };
+        if attempt == ATTEMPTS || shutdown.is_cancelled() {
+            tracing::warn!(%uri, %error, attempt, "webhook failed, dropped notification");
+            return false;
+        }
+        tracing::debug!(%uri, %error, attempt, retry = ?backoff, "webhook failed, retrying");
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => (),
+            _ = shutdown.cancelled() => (),
+        }
+        backoff *= 2;
+    }
+    false
+}
+
+/// Deliver the notifications queued for the webhook in order, until the dispatcher stopped
+async fn worker(client: Client<HttpConnector>, uri: Uri, mut queue: mpsc::Receiver<Arc<String>>, shutdown: CancellationToken) {
+    while let Some(body) = queue.recv().await {
+        deliver(&client, &uri, &body, &shutdown).await;
+    }
+}
+
+/// Dispatcher task, notifying the webhooks of the service started, the CAN devices connected and
+/// disconnected, the bus state changes and the alerts raised and cleared, and of the service
+/// stopping on shutdown
+///
+/// Each webhook has a queue of its own, a failing webhook not delaying the others.
+pub async fn dispatcher(state: AppState, webhooks: Vec<Uri>) {
+    let client = Client::new();
+    let service = service_url(&state.config);
+    let queues: Vec<mpsc::Sender<Arc<String>>> = webhooks
+        .into_iter()
+        .map(|uri| {
+            let (queue, receiver) = mpsc::channel(QUEUE_LEN);
+            state.tasks.spawn(worker(client.clone(), uri, receiver, state.shutdown.clone()));
+            queue
+        })
+        .collect();
+    let notify = |event: Event| {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+        let notification = Notification { service: service.clone(), timestamp, event };
+        let body = Arc::new(serde_json::to_string(&notification).unwrap_or_default());
+        for queue in &queues {
+            if queue.try_send(body.clone()).is_err() {
+                tracing::warn!("webhook queue full, dropped notification");
+            }
+        }
+    };
+
+    notify(Event::Started);
+    let mut events = Subscription::new(state.events.subscribe(), state.shutdown.clone(), "webhook dispatcher");
+    while let Some(event) = events.next().await {
+        let event = match event {
+            CanEvent::Connected(interface) => Event::Connected { interface: interface.to_string() },
+            CanEvent::Disconnected(interface) => Event::Disconnected { interface: interface.to_string() },
+            CanEvent::BusState(event) => Event::BusState((*event).clone()),
+            CanEvent::Alert(alert) => Event::Alert((*alert).clone()),
+            CanEvent::Timeout(timeout) => Event::Timeout((*timeout).clone()),
+            _ => continue,
+        };
+        notify(event);
+    }
+    // the workers stop once their queue is drained
+    notify(Event::Stopped);
+}
@@ -0,0 +1,967 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, TypedHeader,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use futures_util::{future::BoxFuture, stream::{FuturesOrdered, SplitStream}, FutureExt, StreamExt};
+use local_ip_address::local_ip;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+use tracing::{debug, error, info, info_span, trace, warn, Instrument};
+
+use crate::api::api_error;
+use crate::audit::{self, Origin};
+use crate::auth::{Access, Restriction, Scope};
+use crate::can::{self, CanError, CanEvent, Direction, Priority, Timestamp, Transmit};
+use crate::clients::Registration;
+use crate::config::Config;
+use crate::decode::{message_id, DecodedFrame, SignalValue};
+use crate::frame::{CanAnyFrame, CanErrorFrame, CanFilter, EmbeddedFrame, Frame, CAN_EFF_MASK, CAN_SFF_MASK};
+use crate::{canopen, isotp, lin, version};
+use crate::filter::Filter;
+use crate::limit::TokenBucket;
+use crate::outbox::{self, Outbox, Outgoing};
+use crate::protocol::{
+    format_frame, format_id, parse_control, parse_frame_command, parse_frame_id, parse_hex_u32, BusError, ControlMessage, ErrorMessage,
+    Encoding, ErrorReason, FilterSpec, FrameDirection, FrameMessage, IsoTpMessage, ProtocolError, ResumeMessage, ServerMessage, SignalMessage, SignalSpec,
+    StatusMessage, StreamControl,
+};
+use crate::server::{reachable_urls, AppState};
+use crate::stats::BusStats;
+
+// Session of a WebSocket client, owning its connection, its subscription to the CAN events and the
+// options negotiated by the client, handling the events until the session ends
+struct Session {
+    state: AppState,
+    stream: SplitStream<WebSocket>,
+    // messages are sent by a writer of their own, a slow client not blocking the session
+    outbox: Arc<Outbox>,
+    writer: JoinHandle<()>,
+    events: broadcast::Receiver<CanEvent>,
+    // filters subscribed by this client, applied to the shared stream of frames
+    filters: Vec<CanFilter>,
+    // filter expression, applied to the frames accepted by the subscriptions
+    expr: Option<Filter>,
+    encoding: Encoding,
+    isotp: isotp::Channels,
+    // last error frame reported, repetitions are suppressed for a while
+    last_bus_error: Option<(BusError, std::time::Instant)>,
+    // precedence of the frames written in the transmit queue of the interface
+    priority: Priority,
+    // frames queued for transmission, acknowledged in order once written while the session goes on
+    written: FuturesOrdered<BoxFuture<'static, Written>>,
+    // limit of frames written by this client, and whether the client has been notified of exceeding it
+    tx_limit: Option<TokenBucket>,
+    tx_limited: bool,
+    heartbeat: Option<Heartbeat>,
+    batch: Batch,
+    // payloads last sent in delta mode, none if disabled
+    delta: Option<Delta>,
+    // frames held while the client paused the stream, none if not paused
+    paused: Option<Pause>,
+    // signals subscribed, sent on changing
+    signals: Vec<SignalSubscription>,
+    // sessions of `/ws/monitor` may not write frames, nor any session with `--read-only`, by the
+    // reason sent to the client
+    read_only: Option<&'static str>,
+    // ids writable by a token of `[[auth.tokens]]`, any if missing
+    restricted: Option<Restriction>,
+    // client recorded with the frames written in the audit log
+    origin: Origin,
+    // listed by `GET /api/clients` until the session ends, and cancelled by `DELETE /api/clients/:id`
+    registration: Registration,
+}
+
+// Frame written by the client and the result of its write
+struct Written {
+    input: String,
+    interface: Option<String>,
+    frame: CanAnyFrame,
+    result: Result<(), CanError>,
+}
+
+// Frames received within the batch interval, sent as a single message once it elapsed
+#[derive(Default)]
+struct Batch {
+    // zero sending each frame at once
+    interval: Duration,
+    frames: Vec<FrameMessage>,
+    // deadline of the pending frames, set by the first frame of the batch
+    flush_at: Option<Instant>,
+}
+
+impl Batch {
+    // bound of the frames pending, flushed early on buses exceeding it within the interval
+    const MAX_FRAMES: usize = 1000;
+
+    fn new(interval: u64) -> Batch {
+        Batch { interval: Duration::from_millis(interval), ..Default::default() }
+    }
+
+    fn take(&mut self) -> ServerMessage {
+        self.flush_at = None;
+        // the next batch preallocated by the size of this one, the bus load changing slowly
+        let capacity = self.frames.len();
+        ServerMessage::Frames(std::mem::replace(&mut self.frames, Vec::with_capacity(capacity)))
+    }
+}
+
+// Payloads sent by interface and id in delta mode, suppressing frames of an unchanged payload
+#[derive(Default)]
+struct Delta {
+    payloads: HashMap<(Arc<str>, u32), Vec<u8>>,
+}
+
+impl Delta {
+    // bound of the ids tracked, forgetting all of them once exceeded, e.g. on random ids
+    const MAX_IDS: usize = 8192;
+
+    /// Bitmap of the bytes changed, bytes beyond the last payload changed too; none if unchanged
+    fn changed(&mut self, interface: &Arc<str>, frame: &CanAnyFrame) -> Option<u64> {
+        let data = frame.data();
+        let key = (interface.clone(), frame.id_word());
+        let changed = match self.payloads.get(&key) {
+            Some(last) if last.as_slice() == data => return None,
+            Some(last) => (0..data.len()).filter(|&i| last.get(i) != Some(&data[i])).fold(0, |bits, i| bits | 1 << i),
+            None => (0..data.len()).fold(0, |bits, i| bits | 1 << i),
+        };
+        if self.payloads.len() >= Delta::MAX_IDS && !self.payloads.contains_key(&key) {
+            self.payloads.clear();
+        }
+        self.payloads.insert(key, data.to_vec());
+        Some(changed)
+    }
+}
+
+// Signal subscribed by the client, sent once changed by more than the deadband since last sent
+struct SignalSubscription {
+    // as subscribed, optionally qualified by the message
+    name: String,
+    message: Option<String>,
+    signal: String,
+    deadband: f64,
+    // values last sent by interface and message, see [message_id]
+    sent: HashMap<(Arc<str>, u32), f64>,
+}
+
+impl SignalSubscription {
+    fn new(name: &str, deadband: f64) -> SignalSubscription {
+        let (message, signal) = match name.split_once('.') {
+            Some((message, signal)) => (Some(message.to_string()), signal.to_string()),
+            None => (None, name.to_string()),
+        };
+        SignalSubscription { name: name.to_string(), message, signal, deadband, sent: HashMap::new() }
+    }
+
+    /// Value of the signal to send of the decoded frame, none if missing or not changed enough
+    fn changed<'a>(&mut self, interface: &Arc<str>, id: u32, decoded: &'a DecodedFrame) -> Option<&'a SignalValue> {
+        if self.message.as_ref().is_some_and(|message| *message != decoded.message) {
+            return None;
+        }
+        let found = decoded.signals.iter().find(|value| value.name == self.signal)?;
+        let last = self.sent.entry((interface.clone(), id)).or_insert(f64::NAN);
+        if !last.is_nan() && (found.value - *last).abs() <= self.deadband {
+            return None;
+        }
+        *last = found.value;
+        Some(found)
+    }
+}
+
+// Frames received while paused, sent on resuming, the oldest dropped beyond `--pause-len`
+struct Pause {
+    since: Instant,
+    frames: VecDeque<FrameMessage>,
+    dropped: u64,
+}
+
+impl Pause {
+    fn new() -> Pause {
+        Pause { since: Instant::now(), frames: VecDeque::new(), dropped: 0 }
+    }
+
+    fn hold(&mut self, frame: FrameMessage, len: usize) {
+        if len == 0 {
+            self.dropped += 1;
+            return;
+        }
+        if self.frames.len() >= len {
+            self.frames.pop_front();
+            self.dropped += 1;
+        }
+        self.frames.push_back(frame);
+    }
+}
+
+/// Wait for the batch interval to elapse, forever if no frames are pending
+async fn batch_deadline(batch: &Batch) {
+    match batch.flush_at {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+// Pings sent by the server, detecting half-open connections by the client not responding
+struct Heartbeat {
+    interval: Interval,
+    timeout: Duration,
+    // any message of the client proves the connection alive, not only a pong
+    last_seen: Instant,
+}
+
+impl Heartbeat {
+    fn new(config: &Config) -> Option<Heartbeat> {
+        if config.ping_interval == 0 {
+            return None;
+        }
+        let period = Duration::from_secs(config.ping_interval);
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let timeout = Duration::from_secs(config.ping_timeout);
+        Some(Heartbeat { interval, timeout, last_seen: Instant::now() })
+    }
+}
+
+/// Wait for the next ping to send, forever if the heartbeat is disabled
+async fn heartbeat_tick(heartbeat: &mut Option<Heartbeat>) {
+    match heartbeat {
+        Some(heartbeat) => { heartbeat.interval.tick().await; }
+        None => std::future::pending().await,
+    }
+}
+
+/// `GET /ws`, `GET /ws/control` - session receiving and writing frames, requiring the control scope
+///
+/// Responds 403 to a token of the monitor scope.
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Extension(access): Extension<Access>,
+    Extension(state): Extension<AppState>,
+) -> Response {
+    if access.scope != Scope::Control {
+        return api_error(StatusCode::FORBIDDEN, "token of read-only access, see /ws/monitor").into_response();
+    }
+    upgrade(ws, user_agent, peer, state, access)
+}
+
+/// `GET /ws/monitor` - session only receiving frames, rejecting frames written by the client
+pub async fn monitor_handler(
+    ws: WebSocketUpgrade,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Extension(state): Extension<AppState>,
+) -> Response {
+    upgrade(ws, user_agent, peer, state, Access::unrestricted(Scope::Monitor))
+}
+
+fn upgrade(ws: WebSocketUpgrade, user_agent: Option<TypedHeader<headers::UserAgent>>, peer: SocketAddr,
+           state: AppState, access: Access) -> Response {
+    let user_agent = user_agent.map(|TypedHeader(user_agent)| user_agent.to_string());
+    // all events of the session carry the client's address, user agent, CAN devices and client id
+    let span = info_span!("ws",
+        peer = %peer,
+        user_agent = user_agent.as_deref().unwrap_or(""),
+        can_dev = %state.config.can_dev.join(","),
+        scope = ?access.scope,
+        token = access.restricted.as_ref().map_or("", |restriction| restriction.name.as_str()),
+        client = tracing::field::Empty);
+    let registration = state.clients.register(peer, user_agent, access.scope);
+    span.record("client", registration.id);
+    span.in_scope(|| info!("client connected"));
+
+    let tasks = state.tasks.clone();
+    let max = state.config.max_message_size;
+    ws.max_message_size(max).max_frame_size(max).on_upgrade(move |socket| {
+        let session = async move { Session::new(socket, state, peer, access, registration).run().await };
+        tasks.track_future(session.instrument(span))
+    })
+}
+
+/// Reason of ending the session of a client, propagated by the handlers of its events
+#[derive(thiserror::Error, Debug)]
+enum WsError {
+    #[error("client disconnected")]
+    Disconnected,
+    #[error("CAN events closed")]
+    EventsClosed,
+    #[error("closed by admin")]
+    Kicked,
+    #[error("server shutdown")]
+    Shutdown,
+    #[error("client not responding")]
+    TimedOut,
+    #[error("message exceeding {0} bytes")]
+    TooLarge(usize),
+}
+
+/// End of the session by the connection failing, or by the client exceeding `--max-message-size`
+fn read_error(error: axum::Error, max: usize) -> WsError {
+    match error.into_inner().downcast_ref::<tungstenite::Error>() {
+        Some(tungstenite::Error::Capacity(_)) => WsError::TooLarge(max),
+        _ => WsError::Disconnected,
+    }
+}
+
+/// The URL the service is reachable at, preferring the primary IP if bound to any address, else
+/// the first one reachable
+pub(crate) fn service_url(config: &Config) -> String {
+    let addr = config.listen_addr();
+    match local_ip() {
+        Ok(ip) if addr.ip().is_unspecified() => format!("{}://{}", config.scheme(), SocketAddr::new(ip, addr.port())),
+        _ => reachable_urls(config, addr, false).swap_remove(0),
+    }
+}
+
+/// Message of a received frame, decoded if a DBC database is given
+pub fn frame_message(state: &AppState, interface: &Arc<str>, frame: &CanAnyFrame, timestamp: Timestamp, direction: Direction) -> ServerMessage {
+    lin_message(state, interface, frame, timestamp, direction)
+        .unwrap_or_else(|| ServerMessage::Frame(frame_data(state, interface, frame, timestamp, direction)))
+}
+
+/// Message of a frame of a LIN bus, `None` unless a LIN frame of a `sllin` interface
+fn lin_message(state: &AppState, interface: &Arc<str>, frame: &CanAnyFrame, timestamp: Timestamp, direction: Direction) -> Option<ServerMessage> {
+    state.buses.get(Some(interface)).filter(|bus| bus.transport.is_lin())?;
+    lin::lin_message(interface, frame, timestamp, direction).map(ServerMessage::Lin)
+}
+
+pub(crate) fn frame_data(state: &AppState, interface: &Arc<str>, frame: &CanAnyFrame, timestamp: Timestamp, direction: Direction) -> FrameMessage {
+    let (data, fd) = format_frame(frame);
+    let (direction, client) = match direction {
+        Direction::Rx => (FrameDirection::Rx, None),
+        Direction::Tx(client) => (FrameDirection::Tx, client),
+    };
+    FrameMessage {
+        interface: interface.clone(),
+        frame: data,
+        extended: frame.is_extended(),
+        remote: frame.is_remote_frame(),
+        fd,
+        timestamp: timestamp.into(),
+        direction,
+        client,
+        decoded: state.settings.decoder.borrow().as_ref().and_then(|decoder| decoder.decode(frame)),
+        name: state.settings.names.borrow().as_ref().and_then(|names| names.get(frame).cloned().map(Box::new)),
+        canopen: if state.config.canopen { canopen::classify(frame) } else { None },
+        custom: state.decoders.decode(frame),
+        changed: None,
+    }
+}
+
+pub fn status_message(state: &AppState, stats: Option<Vec<BusStats>>) -> ServerMessage {
+    ServerMessage::Status(StatusMessage { service_url: service_url(&state.config), stats, build: None, read_only: None })
+}
+
+/// Messages sent on connecting, the status with the build of the service and whether writing is
+/// disabled, whether the CAN devices are missing, the alerts currently raised and the messages
+/// timed out
+pub async fn initial_messages(state: &AppState) -> Vec<ServerMessage> {
+    let hello = StatusMessage {
+        service_url: service_url(&state.config),
+        stats: None,
+        build: Some(version::build_info()),
+        read_only: Some(state.config.read_only),
+    };
+    let mut messages = vec![ServerMessage::Status(hello)];
+    if !state.buses.any_connected().await {
+        messages.push(ServerMessage::error(ErrorReason::CanDevice, MSG_CAN_FAILED));
+    }
+    if let Some(alerts) = &state.alerts {
+        messages.extend(alerts.active().into_iter().map(ServerMessage::Alert));
+    }
+    if let Some(timeouts) = &state.timeouts {
+        let decoder = state.settings.decoder.borrow().clone();
+        messages.extend(timeouts.active(decoder.as_deref()).into_iter().map(ServerMessage::Timeout));
+    }
+    messages
+}
+
+fn parse_filter(spec: &FilterSpec) -> Result<CanFilter, ProtocolError> {
+    let id = parse_hex_u32(&spec.id)?;
+    let mask = match &spec.mask {
+        Some(mask) => parse_hex_u32(mask)?,
+        None if id <= CAN_SFF_MASK => CAN_SFF_MASK,
+        None => CAN_EFF_MASK,
+    };
+    Ok(CanFilter::new(id, mask))
+}
+
+impl Session {
+    /// Session of the socket subscribed to the shared CAN reader, JSON and no filters initially
+    fn new(socket: WebSocket, state: AppState, peer: SocketAddr, access: Access, registration: Registration) -> Session {
+        let (sink, stream) = socket.split();
+        let outbox = Outbox::new(state.config.client_queue_len);
+        let writer = tokio::spawn(outbox::writer(outbox.clone(), sink).in_current_span());
+        let origin = Origin { peer: Some(peer), user: access.user, source: "ws" };
+        Session {
+            stream,
+            outbox,
+            writer,
+            events: state.events.subscribe(),
+            filters: Vec::new(),
+            expr: None,
+            encoding: Encoding::default(),
+            isotp: isotp::Channels::new(origin.with_source("isotp")),
+            last_bus_error: None,
+            priority: Priority::Normal,
+            written: FuturesOrdered::new(),
+            tx_limit: state.config.tx_rate_limit.map(TokenBucket::new),
+            tx_limited: false,
+            heartbeat: Heartbeat::new(&state.config),
+            batch: Batch::new(state.config.batch_interval),
+            delta: None,
+            paused: None,
+            signals: Vec::new(),
+            read_only: if state.config.read_only {
+                Some(MSG_READ_ONLY_SERVICE)
+            } else {
+                (access.scope == Scope::Monitor).then_some(MSG_READ_ONLY)
+            },
+            restricted: access.restricted,
+            origin,
+            registration,
+            state,
+        }
+    }
+
+    /// Handle the events of the session until it ends, then close the connection
+    async fn run(mut self) {
+        // a client disconnected meanwhile ends the session by the first event
+        for message in initial_messages(&self.state).await {
+            let _ = self.send(message);
+        }
+
+        let end = loop {
+            if let Err(end) = self.next_event().await {
+                break end;
+            }
+        };
+        // the frames queued are audited once written, and acknowledged unless the client is gone
+        const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        while let Ok(Some(written)) = tokio::time::timeout_at(deadline, self.written.next()).await {
+            let _ = self.handle_written(written);
+        }
+        match end {
+            WsError::Disconnected => info!("client disconnected"),
+            WsError::EventsClosed => error!(error = %end, "internal server error"),
+            WsError::TimedOut => {
+                // a half-open connection would not receive a close frame anyway
+                warn!(timeout = self.state.config.ping_timeout, "client not responding, closing connection");
+                self.writer.abort();
+                return;
+            }
+            WsError::Kicked => {
+                let close = CloseFrame { code: close_code::POLICY, reason: end.to_string().into() };
+                self.outbox.push(Outgoing::Close(close));
+                info!("client kicked");
+            }
+            WsError::Shutdown => {
+                let close = CloseFrame { code: close_code::AWAY, reason: end.to_string().into() };
+                self.outbox.push(Outgoing::Close(close));
+                info!("client closed on shutdown");
+            }
+            WsError::TooLarge(max) => {
+                // the reason is sent as error too, the close frame being shown rarely by clients
+                let _ = self.send(ServerMessage::error(ErrorReason::TooLarge, end.to_string()));
+                let close = CloseFrame { code: close_code::SIZE, reason: end.to_string().into() };
+                self.outbox.push(Outgoing::Close(close));
+                warn!(max, "client exceeded max message size, closing connection");
+            }
+        }
+
+        // send the messages queued so far, unless the client stopped reading
+        self.outbox.close();
+        if tokio::time::timeout(FLUSH_TIMEOUT, &mut self.writer).await.is_err() {
+            self.writer.abort();
+        }
+    }
+
+    /// Handle the next message of the client or event of the service, failing once the session ends
+    async fn next_event(&mut self) -> Result<(), WsError> {
+        tokio::select! {
+            msg = self.stream.next() => {
+                match msg {
+                    Some(Ok(msg)) => self.handle_message(msg).await,
+                    Some(Err(e)) => Err(read_error(e, self.state.config.max_message_size)),
+                    None => Err(WsError::Disconnected),
+                }
+            }
+            event = self.events.recv() => self.handle_can_event(event),
+            Some(written) = self.written.next() => self.handle_written(written),
+            Some(event) = self.isotp.received.recv() => self.handle_isotp_event(event),
+            _ = batch_deadline(&self.batch) => self.handle_batch(),
+            _ = heartbeat_tick(&mut self.heartbeat) => self.handle_heartbeat(),
+            _ = self.registration.kicked.cancelled() => Err(WsError::Kicked),
+            _ = self.state.shutdown.cancelled() => Err(WsError::Shutdown),
+        }
+    }
+
+    /// Queue the message for the client, encoded by the writer of the outbox
+    fn send(&self, message: ServerMessage) -> Result<(), WsError> {
+        if self.outbox.push(Outgoing::Message(self.encoding, Box::new(message))) {
+            Ok(())
+        } else {
+            Err(WsError::Disconnected)
+        }
+    }
+
+    /// Queue the frame sent by the client for transmission, acknowledged by [Session::handle_written]
+    async fn write_frame(&mut self, input: &str, interface: Option<&str>, frame: CanAnyFrame) -> Result<(), WsError> {
+        if let Some(limit) = &mut self.tx_limit {
+            if !limit.try_acquire() {
+                // notify once, until frames are accepted again
+                if std::mem::replace(&mut self.tx_limited, true) {
+                    return Ok(());
+                }
+                warn!("client exceeded tx rate limit");
+                let error = ServerMessage::rejected(ErrorReason::RateLimit, "tx rate limit exceeded, dropping frames", input);
+                return self.send(error);
+            }
+            self.tx_limited = false;
+        }
+
+        // acknowledged once written to the bus, the frames of this client in order
+        let transmit = Transmit { client: Some(self.registration.id), priority: self.priority };
+        let queued = self.state.buses.enqueue(interface, &frame, transmit).await;
+        let (input, interface) = (input.to_string(), interface.map(str::to_string));
+        self.written.push_back(async move {
+            let result = match queued {
+                Ok(confirmation) => confirmation.await,
+                Err(e) => Err(e),
+            };
+            Written { input, interface, frame, result }
+        }.boxed());
+        Ok(())
+    }
+
+    /// Acknowledge the frame written, or report the error with the input
+    fn handle_written(&self, written: Written) -> Result<(), WsError> {
+        let Written { input, interface, frame, result } = written;
+        audit::record(&self.state, &self.origin, interface.as_deref(), &frame, &result);
+        let error = match result {
+            Ok(_) => {
+                debug!(interface = interface.unwrap_or_default(), "write frame succeeded");
+                self.registration.activity.frames_received.fetch_add(1, Ordering::Relaxed);
+                return self.send(ServerMessage::ack("frame", &input));
+            }
+            // on a missing device signal to UI, the CAN supervisor takes care of re-opening the device
+            Err(e) => {
+                if let CanError::Failed(e) = &e {
+                    warn!(error = %e, "write frame failed");
+                }
+                ServerMessage::rejected(ErrorReason::from(&e), e.to_string(), &input)
+            }
+        };
+        self.send(error)
+    }
+
+    async fn handle_control(&mut self, control: ControlMessage) -> Result<(), WsError> {
+        let (spec, subscribe) = match &control {
+            ControlMessage::Subscribe(spec) => (spec, true),
+            ControlMessage::Unsubscribe(spec) => (spec, false),
+            ControlMessage::Format(format) => {
+                // the acknowledge is the first message in the new format
+                self.encoding.format = *format;
+                info!(format = format.name(), "client switched format");
+                return self.send(ServerMessage::ack("format", format.name()));
+            }
+            ControlMessage::Compress(compression) => {
+                // the acknowledge is the first message compressed, or the first one not compressed
+                self.encoding.compression = *compression;
+                info!(compression = compression.name(), "client switched compression");
+                return self.send(ServerMessage::ack("compress", compression.name()));
+            }
+            ControlMessage::Isotp(msg) => match self.read_only {
+                Some(reason) => return self.send(ServerMessage::error(ErrorReason::ReadOnly, reason)),
+                None => return self.handle_isotp(msg).await,
+            },
+            ControlMessage::Filter(expr) => return self.handle_filter(expr.as_deref()),
+            ControlMessage::Control(control) => return self.handle_pause(*control),
+            ControlMessage::Priority(priority) => {
+                self.priority = *priority;
+                info!(priority = priority.name(), "client set priority");
+                return self.send(ServerMessage::ack("priority", priority.name()));
+            }
+            ControlMessage::SubscribeSignal(spec) => return self.handle_subscribe_signal(spec),
+            ControlMessage::UnsubscribeSignal(name) => {
+                let name = name.trim();
+                self.signals.retain(|subscription| subscription.name != name);
+                info!(signal = name, "client unsubscribed signal");
+                return self.send(ServerMessage::ack("unsubscribe_signal", name));
+            }
+            ControlMessage::Delta(enabled) => {
+                // the first frame of each id is sent in full again
+                self.delta = enabled.then(Delta::default);
+                info!(enabled, "client switched delta mode");
+                let detail = if *enabled { "on" } else { "off" };
+                return self.send(ServerMessage::ack("delta", detail));
+            }
+            ControlMessage::Batch(interval) => {
+                // pending frames are sent before switching
+                if !self.batch.frames.is_empty() {
+                    let frames = self.batch.take();
+                    self.send(frames)?;
+                }
+                self.batch.interval = Duration::from_millis(*interval);
+                info!(interval, "client set batch interval");
+                return self.send(ServerMessage::ack("batch", format!("{}ms", interval)));
+            }
+        };
+        let filter = match parse_filter(spec) {
+            Ok(filter) => filter,
+            Err(e) => {
+                let error = ServerMessage::error(ErrorReason::InvalidFilter, format!("invalid filter: {}", e));
+                return self.send(error);
+            }
+        };
+
+        if subscribe {
+            if !self.filters.contains(&filter) {
+                self.filters.push(filter);
+            }
+        } else {
+            self.filters.retain(|f| f != &filter);
+        }
+        self.registration.activity.set_subscriptions(&self.filters);
+
+        let command = if subscribe { "subscribe" } else { "unsubscribe" };
+        let detail = format!("{}/{}", spec.id, spec.mask.as_deref().unwrap_or("exact"));
+        self.send(ServerMessage::ack(command, detail))
+    }
+
+    fn handle_filter(&mut self, expr: Option<&str>) -> Result<(), WsError> {
+        let expr = match expr.map(str::trim).filter(|expr| !expr.is_empty()).map(Filter::parse).transpose() {
+            Ok(expr) => expr,
+            Err(e) => {
+                let error = ServerMessage::error(ErrorReason::InvalidFilter, format!("invalid filter: {}", e));
+                return self.send(error);
+            }
+        };
+        let detail = expr.as_ref().map_or("none", Filter::source).to_string();
+        info!(filter = %detail, "client set filter");
+        self.registration.activity.set_filter(expr.as_ref().map(Filter::source));
+        self.expr = expr;
+        self.send(ServerMessage::ack("filter", detail))
+    }
+
+    fn handle_subscribe_signal(&mut self, spec: &SignalSpec) -> Result<(), WsError> {
+        let (name, deadband) = (spec.signal(), spec.deadband());
+        if deadband.is_nan() || deadband < 0.0 {
+            let error = format!("invalid deadband {} of signal {}", deadband, name);
+            return self.send(ServerMessage::error(ErrorReason::InvalidFilter, error));
+        }
+        let subscription = SignalSubscription::new(name, deadband);
+        let known = self.state.settings.decoder.borrow().as_ref()
+            .map(|decoder| decoder.has_signal(subscription.message.as_deref(), &subscription.signal));
+        let error = match known {
+            Some(true) => None,
+            Some(false) => Some(format!("unknown signal {}", name)),
+            None => Some(format!("no DBC database to decode signal {}, see --dbc", name)),
+        };
+        if let Some(error) = error {
+            return self.send(ServerMessage::error(ErrorReason::UnknownSignal, error));
+        }
+        // subscribing again changes the deadband, the next value sent at once
+        self.signals.retain(|subscription| subscription.name != name);
+        self.signals.push(subscription);
+        info!(signal = name, deadband, "client subscribed signal");
+        self.send(ServerMessage::ack("subscribe_signal", format!("{} deadband {}", name, deadband)))
+    }
+
+    fn handle_pause(&mut self, control: StreamControl) -> Result<(), WsError> {
+        let pause = match (control, self.paused.take()) {
+            (StreamControl::Pause, pause) => {
+                // pausing again keeps the frames held so far
+                self.paused = Some(pause.unwrap_or_else(Pause::new));
+                info!("client paused the stream");
+                return self.send(ServerMessage::ack("control", "pause"));
+            }
+            (StreamControl::Resume, None) => {
+                return self.send(ServerMessage::ack("control", "resume"));
+            }
+            (StreamControl::Resume, Some(pause)) => pause,
+        };
+        let held = pause.frames.len() as u64;
+        info!(held, dropped = pause.dropped, "client resumed the stream");
+        let summary = ResumeMessage { paused: pause.since.elapsed().as_secs_f64(), held, dropped: pause.dropped };
+        self.send(ServerMessage::Resume(summary))?;
+        // the frames held are sent in batches, in order of reception
+        self.registration.activity.frames_sent.fetch_add(held, Ordering::Relaxed);
+        let mut frames = pause.frames.into_iter().peekable();
+        while frames.peek().is_some() {
+            let batch = frames.by_ref().take(Batch::MAX_FRAMES).collect();
+            self.send(ServerMessage::Frames(batch))?;
+        }
+        Ok(())
+    }
+
+    async fn handle_isotp(&mut self, msg: &IsoTpMessage) -> Result<(), WsError> {
+        let (Ok(tx_id), Ok(rx_id)) = (parse_frame_id(&msg.tx_id), parse_frame_id(&msg.rx_id)) else {
+            return self.send(ServerMessage::error(ErrorReason::Isotp, "invalid ISO-TP id"));
+        };
+        let Ok(data) = msg.data.as_deref().map(|data| hex::decode(data.trim())).transpose() else {
+            return self.send(ServerMessage::error(ErrorReason::Isotp, "invalid ISO-TP data"));
+        };
+        // the flow control frames of received messages are sent by the tx id too
+        if let Some(Err(e)) = self.restricted.as_ref().map(|restriction| restriction.check_id(tx_id)) {
+            warn!(error = %e, "client wrote forbidden id");
+            return self.send(ServerMessage::error(ErrorReason::Forbidden, e));
+        }
+
+        match self.isotp.send(&self.state, msg.interface.as_deref(), tx_id, rx_id, data).await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let error = format!("isotp {}/{}: {}", msg.tx_id, msg.rx_id, e);
+                self.send(ServerMessage::error(ErrorReason::Isotp, error))
+            }
+        }
+    }
+
+    fn handle_isotp_event(&self, event: isotp::Event) -> Result<(), WsError> {
+        match event {
+            isotp::Event::Received { interface, tx_id, rx_id, data } => {
+                let isotp = IsoTpMessage {
+                    tx_id: format_id(tx_id),
+                    rx_id: format_id(rx_id),
+                    data: Some(hex::encode_upper(data)),
+                    interface: Some(interface.to_string()),
+                };
+                self.send(ServerMessage::Isotp(isotp))
+            }
+            isotp::Event::Failed { tx_id, rx_id, error } => {
+                let error = format!("isotp {}/{}: {}", format_id(tx_id), format_id(rx_id), error);
+                self.send(ServerMessage::error(ErrorReason::Isotp, error))
+            }
+        }
+    }
+
+    async fn handle_message(&mut self, msg: Message) -> Result<(), WsError> {
+        if let Some(heartbeat) = &mut self.heartbeat {
+            heartbeat.last_seen = Instant::now();
+        }
+        match msg {
+            Message::Text(t) => {
+                debug!(text = ?t, "client sent");
+                if let Ok(control) = parse_control(&t) {
+                    return self.handle_control(control).await;
+                }
+                let input = t.trim();
+                if let Some(reason) = self.read_only {
+                    return self.send(ServerMessage::rejected(ErrorReason::ReadOnly, reason, input));
+                }
+                let (interface, frame) = match parse_frame_command(input) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        let message = format!("{}, expected e.g. 123#DEADBEEF", ProtocolError::from(e));
+                        return self.send(ServerMessage::rejected(ErrorReason::Parse, message, input));
+                    }
+                };
+                if let Some(Err(e)) = self.restricted.as_ref().map(|restriction| restriction.check_id(frame.id())) {
+                    warn!(error = %e, "client wrote forbidden id");
+                    return self.send(ServerMessage::rejected(ErrorReason::Forbidden, e, input));
+                }
+                return self.write_frame(input, interface, frame).await;
+            }
+            Message::Binary(b) => {
+                // control messages in the negotiated binary format
+                if let Ok(control) = self.encoding.format.decode_control(&b) {
+                    return self.handle_control(control).await;
+                }
+                debug!("client sent binary data");
+                Ok(())
+            }
+            Message::Ping(_) => {
+                trace!("socket ping");
+                Ok(())
+            }
+            Message::Pong(_) => {
+                trace!("socket pong");
+                Ok(())
+            }
+            Message::Close(_) => Err(WsError::Disconnected),
+        }
+    }
+
+    fn handle_heartbeat(&self) -> Result<(), WsError> {
+        let Some(heartbeat) = &self.heartbeat else { return Ok(()) };
+        if heartbeat.last_seen.elapsed() > heartbeat.timeout {
+            return Err(WsError::TimedOut);
+        }
+        trace!("socket ping");
+        if !self.outbox.push(Outgoing::Ping) {
+            return Err(WsError::Disconnected);
+        }
+        Ok(())
+    }
+
+    fn handle_stats(&self, stats: &[BusStats]) -> Result<(), WsError> {
+        trace!("statistics - updating service url and bus load");
+        self.send(status_message(&self.state, Some(stats.to_vec())))
+    }
+
+    fn handle_can_frame(&mut self, interface: &Arc<str>, frame: CanAnyFrame, timestamp: Timestamp, direction: Direction) -> Result<(), WsError> {
+        // formatted just if logged, the message formats the frame on its own
+        debug!(interface = &**interface, frame = %format_frame(&frame).0, "received can frame");
+        // sent at once, neither batched nor held by a pause
+        if let Some(message) = lin_message(&self.state, interface, &frame, timestamp, direction) {
+            self.registration.activity.frames_sent.fetch_add(1, Ordering::Relaxed);
+            return self.send(message);
+        }
+        let changed = match &mut self.delta {
+            Some(delta) => match delta.changed(interface, &frame) {
+                Some(changed) => Some(format!("{:X}", changed)),
+                None => return Ok(()),
+            },
+            None => None,
+        };
+        let data = FrameMessage { changed, ..frame_data(&self.state, interface, &frame, timestamp, direction) };
+        if let Some(pause) = &mut self.paused {
+            pause.hold(data, self.state.config.pause_len);
+            return Ok(());
+        }
+        self.registration.activity.frames_sent.fetch_add(1, Ordering::Relaxed);
+        let batch = &mut self.batch;
+        if batch.interval.is_zero() {
+            return self.send(ServerMessage::Frame(data));
+        }
+        batch.frames.push(data);
+        batch.flush_at.get_or_insert_with(|| Instant::now() + batch.interval);
+        if batch.frames.len() >= Batch::MAX_FRAMES {
+            return self.handle_batch();
+        }
+        Ok(())
+    }
+
+    fn handle_signals(&mut self, interface: &Arc<str>, frame: &CanAnyFrame, timestamp: Timestamp) -> Result<(), WsError> {
+        if self.signals.is_empty() {
+            return Ok(());
+        }
+        let Some(decoded) = self.state.settings.decoder.borrow().as_ref().and_then(|decoder| decoder.decode(frame)) else {
+            return Ok(());
+        };
+        let id = message_id(frame);
+        let changed: Vec<SignalValue> = self.signals
+            .iter_mut()
+            .filter_map(|subscription| subscription.changed(interface, id, &decoded).cloned())
+            .collect();
+        for value in changed {
+            self.send(ServerMessage::Signal(SignalMessage {
+                interface: interface.clone(),
+                message: decoded.message.clone(),
+                signal: value.name,
+                value: value.value,
+                unit: value.unit,
+                timestamp: timestamp.into(),
+            }))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the frame is subscribed by the filters and the filter expression; once signals are
+    /// subscribed, the frames only of the ids subscribed too
+    fn frame_subscribed(&self, interface: &str, frame: &CanAnyFrame) -> bool {
+        let subscribed = if self.filters.is_empty() { self.signals.is_empty() } else { filters_match(&self.filters, frame) };
+        subscribed && self.expr.as_ref().is_none_or(|expr| expr.matches(interface, frame))
+    }
+
+    fn handle_batch(&mut self) -> Result<(), WsError> {
+        trace!(count = self.batch.frames.len(), "sending batch of frames");
+        let frames = self.batch.take();
+        self.send(frames)
+    }
+
+    fn handle_error_frame(&mut self, interface: &str, frame: &CanErrorFrame) -> Result<(), WsError> {
+        match bus_error_message(&mut self.last_bus_error, interface, frame) {
+            Some(message) => self.send(message),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_can_event(&mut self, event: Result<CanEvent, broadcast::error::RecvError>) -> Result<(), WsError> {
+        match event {
+            // error frames are not subject to the client's filters
+            Ok(CanEvent::Frame(interface, CanAnyFrame::Error(frame), _, _)) => {
+                self.handle_error_frame(&interface, &frame)
+            }
+            Ok(CanEvent::Frame(interface, frame, timestamp, direction)) => {
+                self.handle_signals(&interface, &frame, timestamp)?;
+                if !self.frame_subscribed(&interface, &frame) {
+                    return Ok(());
+                }
+                self.handle_can_frame(&interface, frame, timestamp, direction)
+            }
+            Ok(CanEvent::Connected(interface)) => {
+                let notice = ServerMessage::notice(format!("{} {}", MSG_CAN_CONNECTED, interface));
+                self.send(notice)
+            }
+            Ok(CanEvent::Disconnected(interface)) => {
+                let error = ServerMessage::error(ErrorReason::CanDevice, format!("{} {}", MSG_CAN_FAILED, interface));
+                self.send(error)
+            }
+            Ok(CanEvent::Notice(notice)) => self.send(ServerMessage::notice(&*notice)),
+            Ok(CanEvent::Stats(stats)) => self.handle_stats(&stats),
+            Ok(CanEvent::Interface(states)) => self.send(ServerMessage::Interface(states.to_vec())),
+            Ok(CanEvent::BusState(event)) => self.send(ServerMessage::BusState((*event).clone())),
+            Ok(CanEvent::Canopen(event)) => self.send(ServerMessage::Canopen((*event).clone())),
+            Ok(CanEvent::Alert(alert)) => self.send(ServerMessage::Alert((*alert).clone())),
+            Ok(CanEvent::Timeout(timeout)) => self.send(ServerMessage::Timeout((*timeout).clone())),
+            #[cfg(feature = "j1939")]
+            Ok(CanEvent::J1939(group)) => self.send(ServerMessage::J1939((*group).clone())),
+            #[cfg(feature = "nmea2000")]
+            Ok(CanEvent::Nmea2000(message)) => self.send(ServerMessage::Nmea2000((*message).clone())),
+            Ok(CanEvent::Telemetry(telemetry)) => {
+                self.send(ServerMessage::Telemetry((*telemetry).clone()))
+            }
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                warn!(count, "client lagging, skipped events");
+                self.outbox.dropped(count);
+                Ok(())
+            }
+            Err(broadcast::error::RecvError::Closed) => Err(WsError::EventsClosed),
+        }
+    }
+}
+
+/// Kernel filter semantics: `<received_can_id> & mask == can_id & mask`, an empty list accepts all frames
+fn filters_match(filters: &[CanFilter], frame: &CanAnyFrame) -> bool {
+    filters.is_empty() || filters.iter().any(|f| {
+        let f = f.as_ref();
+        frame.id_word() & f.can_mask == f.can_id & f.can_mask
+    })
+}
+
+/// Message reporting the error frame, suppressing repetitions of the last reported errors for a second
+pub fn bus_error_message(last_bus_error: &mut Option<(BusError, std::time::Instant)>,
+                         interface: &str, frame: &CanErrorFrame) -> Option<ServerMessage> {
+    const REPEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+    let error = BusError { interface: interface.to_string(), classes: can::error_classes(frame) };
+    if let Some((last, at)) = last_bus_error {
+        if *last == error && at.elapsed() < REPEAT_INTERVAL {
+            return None;
+        }
+    }
+    *last_bus_error = Some((error.clone(), std::time::Instant::now()));
+
+    let labels: Vec<&str> = error.classes.iter().map(|class| class.label()).collect();
+    let message = format!("CAN error on {}: {}", interface, labels.join(", "));
+    debug!(interface, errors = %labels.join(", "), "received error frame");
+    Some(ServerMessage::Error(ErrorMessage { reason: ErrorReason::Bus, message, input: None, bus_error: Some(error) }))
+}
+
+pub static MSG_CAN_FAILED: &str = "missing CAN device";
+pub static MSG_CAN_CONNECTED: &str = "connected to CAN device";
+static MSG_READ_ONLY: &str = "read-only session, writing requires /ws/control";
+static MSG_READ_ONLY_SERVICE: &str = "writing disabled by --read-only";
@@ -0,0 +1,35 @@
+use hyper::{Body, Client, Request};
+use rust_vue::protocol::ServerMessage;
+
+use crate::harness::{expect, TestServer};
+
+const ORIGIN: &str = "https://dashboard.example.com";
+
+#[tokio::test]
+async fn preflight_allowed_of_listed_origin() {
+    let server = TestServer::start(&["--simulate", "--cors-origin", ORIGIN, "--auth-token", "secret"]).await;
+    let preflight = |origin: &str| {
+        Request::options(format!("http://127.0.0.1:{}/api/frames", server.port))
+            .header("origin", origin)
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "authorization")
+            .body(Body::empty())
+            .unwrap()
+    };
+    // answered without the token
+    let response = Client::new().request(preflight(ORIGIN)).await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["access-control-allow-origin"], ORIGIN);
+    assert_eq!(response.headers()["access-control-allow-credentials"], "true");
+    let response = Client::new().request(preflight("https://other.example.com")).await.unwrap();
+    assert!(!response.headers().contains_key("access-control-allow-origin"));
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn websocket_authorized_by_query() {
+    let server = TestServer::start(&["--simulate", "--auth-token", "secret"]).await;
+    let mut client = server.connect("/ws?access_token=secret").await;
+    expect(&mut client, |message| matches!(message, ServerMessage::Status(_)).then_some(())).await;
+    server.stop().await;
+}
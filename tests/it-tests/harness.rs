@@ -0,0 +1,104 @@
+use std::net::TcpListener;
+use std::time::Duration;
+
+use clap::Parser;
+use futures_util::{SinkExt, StreamExt};
+use rust_vue::config::Config;
+use rust_vue::protocol::ServerMessage;
+use rust_vue::server::{Server, ServerBuilder};
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tokio_util::sync::CancellationToken;
+
+// bound of waiting for a message or the server to start
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// message of the server, the envelope of the protocol version ignored
+#[derive(Deserialize)]
+struct Received {
+    #[serde(flatten)]
+    message: ServerMessage,
+}
+
+/// Server of a test, running until stopped or the runtime of the test ends
+pub struct TestServer {
+    pub port: u16,
+    shutdown: CancellationToken,
+    task: JoinHandle<Result<(), String>>,
+}
+
+impl TestServer {
+    /// Start the server bound to localhost on an ephemeral port, with the arguments of the command line
+    pub async fn start(args: &[&str]) -> TestServer {
+        TestServer::start_with(args, |builder| builder).await
+    }
+
+    /// Start the server set up by `setup` too, e.g. registering the transports of the devices
+    pub async fn start_with(args: &[&str], setup: impl FnOnce(ServerBuilder) -> ServerBuilder) -> TestServer {
+        // the port is free once the probe is closed, taken by the server right after
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let port_arg = port.to_string();
+        let mut argv = vec!["rust-vue", "--bind", "127.0.0.1", "--port", &port_arg];
+        argv.extend_from_slice(args);
+        let config = Config::parse_from(argv);
+        let shutdown = CancellationToken::new();
+        let server = setup(Server::builder().config(config).shutdown(shutdown.clone())).build().unwrap();
+        let task = tokio::spawn(server.run());
+        let started = tokio::time::timeout(TIMEOUT, async {
+            while tokio::net::TcpStream::connect(("127.0.0.1", port)).await.is_err() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        started.await.expect("server not listening");
+        TestServer { port, shutdown, task }
+    }
+
+    /// Connect a websocket client to the path, e.g. `/ws`
+    pub async fn connect(&self, path: &str) -> Client {
+        let url = format!("ws://127.0.0.1:{}{}", self.port, path);
+        let (client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        client
+    }
+
+    /// Shut down gracefully, failing if the server failed
+    pub async fn stop(self) {
+        self.shutdown.cancel();
+        let result = tokio::time::timeout(TIMEOUT, self.task).await.expect("server not stopping");
+        result.unwrap().unwrap();
+    }
+}
+
+pub async fn send(client: &mut Client, text: &str) {
+    client.send(Message::Text(text.to_string())).await.unwrap();
+}
+
+/// The next message of the server accepted by `select`, skipping the others, e.g. the status
+/// sent on connecting or the frames of other ids
+pub async fn expect<T>(client: &mut Client, mut select: impl FnMut(ServerMessage) -> Option<T>) -> T {
+    let receive = async {
+        while let Some(message) = client.next().await {
+            let Message::Text(text) = message.unwrap() else { continue };
+            let received: Received = serde_json::from_str(&text).unwrap();
+            if let Some(selected) = select(received.message) {
+                return selected;
+            }
+        }
+        panic!("connection closed");
+    };
+    tokio::time::timeout(TIMEOUT, receive).await.expect("message not received")
+}
+
+/// The next frame received of the id, in `cansend` notation
+pub async fn expect_frame(client: &mut Client, id: &str) -> String {
+    let prefix = format!("{}#", id);
+    expect(client, |message| match message {
+        ServerMessage::Frame(frame) if frame.frame.starts_with(&prefix) => Some(frame.frame),
+        ServerMessage::Frames(frames) => frames.into_iter().map(|frame| frame.frame).find(|frame| frame.starts_with(&prefix)),
+        _ => None,
+    })
+    .await
+}
@@ -0,0 +1,35 @@
+use hyper::{Body, Client, Request, StatusCode};
+use rust_vue::protocol::{ErrorReason, ServerMessage};
+
+use crate::harness::{expect, send, TestServer};
+
+#[tokio::test]
+async fn oversized_message_closes_connection() {
+    let server = TestServer::start(&["--simulate", "--max-message-size", "1024"]).await;
+    let mut client = server.connect("/ws").await;
+    send(&mut client, &format!("123#{}", "00".repeat(1024))).await;
+    let error = expect(&mut client, |message| match message {
+        ServerMessage::Error(error) if error.reason == ErrorReason::TooLarge => Some(error),
+        _ => None,
+    })
+    .await;
+    assert_eq!(error.message, "message exceeding 1024 bytes");
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn oversized_body_rejected() {
+    let server = TestServer::start(&["--simulate", "--max-body-size", "1024"]).await;
+    let request = |body: String| {
+        Request::post(format!("http://127.0.0.1:{}/api/replay", server.port)).body(Body::from(body)).unwrap()
+    };
+    let response = Client::new().request(request("x".repeat(2048))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(error["error"].as_str().unwrap().contains("exceeding the limit of 1024 bytes"), "{}", error);
+    // a recording within the limit is parsed
+    let response = Client::new().request(request("(0.0) vcan0 123#11\n".to_string())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    server.stop().await;
+}
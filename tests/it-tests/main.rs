@@ -0,0 +1,15 @@
+//! Integration tests of the WebSocket to CAN bridge, each starting the server on an ephemeral port
+//! and driving it by a WebSocket client
+//!
+//! The tests of SocketCAN create a vcan interface of their own, skipped if unprivileged or the
+//! vcan module is missing, e.g. `sudo modprobe vcan && sudo -E cargo test --test it-tests`; the
+//! tests of mocked and simulated interfaces run everywhere.
+
+mod cors;
+mod harness;
+mod limits;
+mod mdns;
+mod mock;
+mod simulated;
+#[cfg(target_os = "linux")]
+mod vcan;
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use crate::harness::TestServer;
+
+// query of a legacy resolver for the PTR records of the type, answered by unicast
+fn query(name: &str) -> Vec<u8> {
+    let mut packet = vec![0x12, 0x34, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.extend_from_slice(&[0, 0, 12, 0, 1]);
+    packet
+}
+
+#[tokio::test]
+async fn service_answered_by_mdns() {
+    let server = TestServer::start(&["--simulate", "--mdns", "--mdns-name", "it-test bench"]).await;
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut buf = vec![0u8; 9000];
+    // the responder started right after the web server
+    let mut response = None;
+    for _ in 0..50 {
+        socket.send_to(&query("_can-monitor._tcp.local"), "127.0.0.1:5353").await.unwrap();
+        if let Ok(received) = tokio::time::timeout(Duration::from_millis(100), socket.recv(&mut buf)).await {
+            response = Some(buf[..received.unwrap()].to_vec());
+            break;
+        }
+    }
+    let response = response.expect("no mDNS response");
+    assert_eq!(&response[..2], &[0x12, 0x34]);
+    let contains = |bytes: &[u8]| response.windows(bytes.len()).any(|window| window == bytes);
+    assert!(contains(b"\x0dit-test bench\x0c_can-monitor"));
+    // the SRV record of the port of the web UI
+    let mut srv = vec![0, 0, 0, 0];
+    srv.extend_from_slice(&server.port.to_be_bytes());
+    assert!(contains(&srv));
+    server.stop().await;
+}
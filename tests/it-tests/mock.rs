@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_vue::can::MockTransport;
+use rust_vue::frame::{CanAnyFrame, CanDataFrame, EmbeddedFrame, ExtendedId, StandardId};
+use rust_vue::protocol::{ErrorReason, ServerMessage};
+
+use crate::harness::{expect, expect_frame, send, TestServer};
+
+async fn start(transport: &Arc<MockTransport>) -> TestServer {
+    let transport = transport.clone();
+    TestServer::start_with(&["--can-dev", "mock0"], |builder| builder.transport("mock0", transport)).await
+}
+
+#[tokio::test]
+async fn frame_received_by_client() {
+    let transport = MockTransport::new();
+    let server = start(&transport).await;
+    let mut client = server.connect("/ws").await;
+    let frame = CanDataFrame::new(StandardId::new(0x123).unwrap(), &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
+    transport.receive(CanAnyFrame::Normal(frame));
+    assert_eq!(expect_frame(&mut client, "123").await, "123#DEADBEEF");
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn frame_written_by_client() {
+    let transport = MockTransport::new();
+    let server = start(&transport).await;
+    let mut client = server.connect("/ws").await;
+    send(&mut client, "mock0 18FEF100#0102").await;
+    expect(&mut client, |message| match message {
+        ServerMessage::Ack(ack) if ack.command == "frame" => Some(()),
+        ServerMessage::Error(error) => panic!("frame rejected: {}", error.message),
+        _ => None,
+    })
+    .await;
+    let frame = transport.written().await.unwrap();
+    assert_eq!(frame.id(), ExtendedId::new(0x18FEF100).unwrap().into());
+    assert_eq!(frame.data(), [1, 2]);
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn write_failure_reported() {
+    let transport = MockTransport::new();
+    transport.fail_writes(true);
+    let server = start(&transport).await;
+    let mut client = server.connect("/ws").await;
+    send(&mut client, "123#11").await;
+    let error = expect(&mut client, |message| match message {
+        ServerMessage::Error(error) if error.input.is_some() => Some(error),
+        _ => None,
+    })
+    .await;
+    assert_eq!((error.reason, error.input.as_deref()), (ErrorReason::Write, Some("123#11")));
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn unknown_interface_rejected() {
+    let transport = MockTransport::new();
+    let server = start(&transport).await;
+    let mut client = server.connect("/ws").await;
+    send(&mut client, "can9 123#11").await;
+    let error = expect(&mut client, |message| match message {
+        ServerMessage::Error(error) if error.input.is_some() => Some(error),
+        _ => None,
+    })
+    .await;
+    assert_eq!(error.reason, ErrorReason::UnknownInterface);
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn periodic_message_timed_out() {
+    let transport = MockTransport::new();
+    let mock = transport.clone();
+    let args = ["--can-dev", "mock0", "--cycle-time", "0x123:20", "--timeout-factor", "2"];
+    let server = TestServer::start_with(&args, |builder| builder.transport("mock0", mock)).await;
+    let mut client = server.connect("/ws").await;
+    let frame = CanAnyFrame::Normal(CanDataFrame::new(StandardId::new(0x123).unwrap(), &[1]).unwrap());
+    transport.receive(frame);
+    let timed_out = expect(&mut client, |message| match message {
+        ServerMessage::Timeout(timeout) => Some(timeout),
+        _ => None,
+    })
+    .await;
+    assert_eq!((timed_out.interface.as_str(), timed_out.id.as_str()), ("mock0", "123"));
+    assert_eq!(serde_json::to_value(timed_out.state).unwrap(), "timed_out");
+    assert!(timed_out.missing_ms > 40.0);
+    transport.receive(frame);
+    let recovered = expect(&mut client, |message| match message {
+        ServerMessage::Timeout(timeout) => Some(timeout),
+        _ => None,
+    })
+    .await;
+    assert_eq!(serde_json::to_value(recovered.state).unwrap(), "recovered");
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn frames_written_by_priority() {
+    let transport = MockTransport::new();
+    let server = start(&transport).await;
+    let mut clients = Vec::new();
+    for priority in ["low", "low", "high"] {
+        let mut client = server.connect("/ws").await;
+        send(&mut client, &format!(r#"{{"priority": "{}"}}"#, priority)).await;
+        expect(&mut client, |message| matches!(message, ServerMessage::Ack(ack) if ack.command == "priority").then_some(()))
+            .await;
+        clients.push(client);
+    }
+    // the first frame taken by the transmitter, the others queued meanwhile
+    transport.hold_writes(true);
+    for (client, frame) in clients.iter_mut().zip(["100#01", "200#02", "300#03"]) {
+        send(client, frame).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    transport.hold_writes(false);
+    let mut written = Vec::new();
+    for _ in 0..3 {
+        written.push(transport.written().await.unwrap().data()[0]);
+    }
+    assert_eq!(written, [1, 3, 2]);
+    // each client acknowledged once its frame is written
+    for client in &mut clients {
+        expect(client, |message| matches!(message, ServerMessage::Ack(ack) if ack.command == "frame").then_some(())).await;
+    }
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn session_handled_while_frame_queued() {
+    let transport = MockTransport::new();
+    let server = start(&transport).await;
+    let mut client = server.connect("/ws").await;
+    transport.hold_writes(true);
+    send(&mut client, "123#11").await;
+    send(&mut client, r#"{"priority": "high"}"#).await;
+    let ack = expect(&mut client, |message| match message {
+        ServerMessage::Ack(ack) => Some(ack),
+        _ => None,
+    })
+    .await;
+    assert_eq!(ack.command, "priority");
+    transport.hold_writes(false);
+    expect(&mut client, |message| matches!(message, ServerMessage::Ack(ack) if ack.command == "frame").then_some(())).await;
+    assert_eq!(transport.written().await.unwrap().data(), [0x11]);
+    server.stop().await;
+}
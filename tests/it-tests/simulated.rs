@@ -0,0 +1,273 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use rust_vue::protocol::{ErrorReason, FrameDirection, ServerMessage};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::harness::{expect, expect_frame, send, TestServer};
+
+#[tokio::test]
+async fn frame_written_received_by_loopback() {
+    let server = TestServer::start(&["--simulate"]).await;
+    let mut client = server.connect("/ws").await;
+    send(&mut client, "321#C0FFEE").await;
+    let ack = expect(&mut client, |message| match message {
+        ServerMessage::Ack(ack) => Some(ack),
+        ServerMessage::Error(error) => panic!("frame rejected: {}", error.message),
+        _ => None,
+    })
+    .await;
+    assert_eq!((ack.command.as_str(), ack.detail.as_str()), ("frame", "321#C0FFEE"));
+    assert_eq!(expect_frame(&mut client, "321").await, "321#C0FFEE");
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn frames_forwarded_to_all_clients() {
+    let server = TestServer::start(&["--simulate"]).await;
+    let mut writer = server.connect("/ws").await;
+    let mut monitor = server.connect("/ws/monitor").await;
+    // the frames of the simulated traffic filtered by the subscription
+    send(&mut monitor, r#"{"subscribe": {"id": "7FF"}}"#).await;
+    expect(&mut monitor, |message| matches!(message, ServerMessage::Ack(ack) if ack.command == "subscribe").then_some(()))
+        .await;
+    send(&mut writer, "7FF#0102").await;
+    let frame = expect(&mut monitor, |message| match message {
+        ServerMessage::Frame(frame) => Some(frame),
+        _ => None,
+    })
+    .await;
+    assert_eq!((frame.frame.as_str(), &*frame.interface, frame.extended), ("7FF#0102", "vcan0", false));
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn invalid_frame_rejected() {
+    let server = TestServer::start(&["--simulate"]).await;
+    let mut client = server.connect("/ws").await;
+    let invalid = [
+        ("12345#ZZ", "invalid hex data"),
+        ("123#ABC", "odd count of hex digits"),
+        ("123#00112233445566778899", "payload of 10 bytes too long"),
+        ("FFFFFFFF#00", "id FFFFFFFF exceeding 29 bits"),
+        ("123DEADBEEF", "missing # between id and data"),
+    ];
+    for (input, reason) in invalid {
+        send(&mut client, input).await;
+        let error = expect(&mut client, |message| match message {
+            ServerMessage::Error(error) => Some(error),
+            _ => None,
+        })
+        .await;
+        assert_eq!(error.input.as_deref(), Some(input));
+        assert!(error.message.contains(reason), "{}: {}", input, error.message);
+    }
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn monitor_rejects_frames() {
+    let server = TestServer::start(&["--simulate"]).await;
+    let mut client = server.connect("/ws/monitor").await;
+    send(&mut client, "123#11").await;
+    let error = expect(&mut client, |message| match message {
+        ServerMessage::Error(error) => Some(error),
+        ServerMessage::Ack(ack) if ack.command == "frame" => panic!("frame written by monitor"),
+        _ => None,
+    })
+    .await;
+    assert_eq!(error.input.as_deref(), Some("123#11"));
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn hello_identifies_build() {
+    let server = TestServer::start(&["--simulate"]).await;
+    let mut client = server.connect("/ws").await;
+    let build = expect(&mut client, |message| match message {
+        ServerMessage::Status(status) => status.build,
+        _ => None,
+    })
+    .await;
+    assert_eq!(build.version, env!("CARGO_PKG_VERSION"));
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn connected_client_listed() {
+    let server = TestServer::start(&["--simulate"]).await;
+    let mut client = server.connect("/ws").await;
+    expect(&mut client, |message| matches!(message, ServerMessage::Status(_)).then_some(())).await;
+    let url = format!("http://127.0.0.1:{}/api/clients", server.port).parse().unwrap();
+    let response = hyper::Client::new().get(url).await.unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let clients = response["clients"].as_array().unwrap();
+    assert_eq!(clients.len(), 1, "{}", response);
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn closed_client_unlisted() {
+    // neither frames nor a heartbeat sent, failing to write to the client closed, only the
+    // statistics once per second
+    let server = TestServer::start(&["--no-can", "--ping-interval", "0"]).await;
+    let mut client = server.connect("/ws").await;
+    expect(&mut client, |message| matches!(message, ServerMessage::Status(_)).then_some(())).await;
+    client.close(None).await.unwrap();
+    let url: hyper::Uri = format!("http://127.0.0.1:{}/api/clients", server.port).parse().unwrap();
+    let unlisted = tokio::time::timeout(Duration::from_secs(1), async {
+        loop {
+            let response = hyper::Client::new().get(url.clone()).await.unwrap();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            if response["clients"].as_array().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    });
+    unlisted.await.expect("session not ended by the close of the client");
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn written_frames_tagged_tx() {
+    let server = TestServer::start(&["--simulate"]).await;
+    let mut writer = server.connect("/ws").await;
+    let mut monitor = server.connect("/ws/monitor").await;
+    // the simulated traffic observed on the bus
+    let received = expect(&mut monitor, |message| match message {
+        ServerMessage::Frame(frame) => Some(frame),
+        _ => None,
+    })
+    .await;
+    assert_eq!((received.direction, received.client), (FrameDirection::Rx, None));
+    send(&mut writer, "6FE#0102").await;
+    let written = expect(&mut monitor, |message| match message {
+        ServerMessage::Frame(frame) if frame.frame == "6FE#0102" => Some(frame),
+        _ => None,
+    })
+    .await;
+    assert_eq!(written.direction, FrameDirection::Tx);
+    assert!(written.client.is_some());
+    server.stop().await;
+}
+
+const PEDAL_DBC: &str = r#"VERSION ""
+
+BO_ 1789 Pedal: 1 ECU
+ SG_ Position : 0|8@1+ (1,0) [0|255] "%" ECU
+"#;
+
+#[tokio::test]
+async fn signal_sent_on_change_beyond_deadband() {
+    let dbc = std::env::temp_dir().join(format!("it-tests-pedal-{}.dbc", std::process::id()));
+    std::fs::write(&dbc, PEDAL_DBC).unwrap();
+    let server = TestServer::start(&["--simulate", "--dbc", dbc.to_str().unwrap()]).await;
+    let mut client = server.connect("/ws").await;
+    send(&mut client, r#"{"subscribe_signal": {"signal": "Pedal.Position", "deadband": 5}}"#).await;
+    expect(&mut client, |message| match message {
+        ServerMessage::Ack(ack) if ack.command == "subscribe_signal" => Some(()),
+        ServerMessage::Error(error) => panic!("signal rejected: {}", error.message),
+        _ => None,
+    })
+    .await;
+    // the change of 2 within the deadband, the simulated traffic not sent without subscribing frames
+    for frame in ["6FD#10", "6FD#12", "6FD#20"] {
+        send(&mut client, frame).await;
+    }
+    for expected in [16.0, 32.0] {
+        let signal = expect(&mut client, |message| match message {
+            ServerMessage::Signal(signal) => Some(signal),
+            ServerMessage::Frame(frame) => panic!("frame {} sent", frame.frame),
+            _ => None,
+        })
+        .await;
+        assert_eq!((signal.message.as_str(), signal.signal.as_str(), signal.value), ("Pedal", "Position", expected));
+    }
+    send(&mut client, r#"{"subscribe_signal": "Throttle"}"#).await;
+    let error = expect(&mut client, |message| match message {
+        ServerMessage::Error(error) => Some(error),
+        _ => None,
+    })
+    .await;
+    assert_eq!(error.reason, ErrorReason::UnknownSignal);
+    server.stop().await;
+    let _ = std::fs::remove_file(dbc);
+}
+
+#[tokio::test]
+async fn read_only_rejects_writing() {
+    let server = TestServer::start(&["--simulate", "--read-only"]).await;
+    let mut client = server.connect("/ws").await;
+    let read_only = expect(&mut client, |message| match message {
+        ServerMessage::Status(status) => status.read_only,
+        _ => None,
+    })
+    .await;
+    assert!(read_only);
+    send(&mut client, "321#C0FFEE").await;
+    let error = expect(&mut client, |message| match message {
+        ServerMessage::Error(error) => Some(error),
+        ServerMessage::Ack(ack) if ack.command == "frame" => panic!("frame written"),
+        _ => None,
+    })
+    .await;
+    assert_eq!(error.reason, ErrorReason::ReadOnly);
+    let request = hyper::Request::post(format!("http://127.0.0.1:{}/api/frames", server.port))
+        .header("content-type", "application/json")
+        .body(hyper::Body::from(r#"{"id": "123", "data": "01"}"#))
+        .unwrap();
+    let response = hyper::Client::new().request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::FORBIDDEN);
+    server.stop().await;
+}
+
+const ENGINE_DBC: &str = r#"VERSION ""
+
+BO_ 200 Engine: 3 ECU
+ SG_ Speed : 0|16@1+ (0.25,0) [0|16383.75] "rpm" ECU
+ SG_ Temperature : 16|8@1+ (1,-40) [-40|215] "degC" ECU
+"#;
+
+const ENGINE_PROFILE: &str = r#"
+[[message]]
+name = "Engine"
+cycle_ms = 10
+signals.Speed = { constant = 1500 }
+signals.Temperature = { ramp = { from = 90, to = 90, duration_s = 1 } }
+"#;
+
+#[tokio::test]
+async fn demo_profile_encodes_signals() {
+    let dir = std::env::temp_dir();
+    let dbc = dir.join(format!("it-tests-engine-{}.dbc", std::process::id()));
+    let profile = dir.join(format!("it-tests-engine-{}.toml", std::process::id()));
+    std::fs::write(&dbc, ENGINE_DBC).unwrap();
+    std::fs::write(&profile, ENGINE_PROFILE).unwrap();
+    let server = TestServer::start(&["--simulate", "--dbc", dbc.to_str().unwrap(), "--demo-profile", profile.to_str().unwrap()]).await;
+    let mut client = server.connect("/ws/monitor").await;
+    // 6000 * 0.25 rpm and 130 - 40 degC
+    assert_eq!(expect_frame(&mut client, "0C8").await, "0C8#701782");
+    server.stop().await;
+    let _ = std::fs::remove_file(dbc);
+    let _ = std::fs::remove_file(profile);
+}
+
+#[tokio::test]
+async fn messages_timestamped_by_server_clock() {
+    let server = TestServer::start(&["--simulate"]).await;
+    let url = format!("http://127.0.0.1:{}/api/time", server.port).parse().unwrap();
+    let response = hyper::Client::new().get(url).await.unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let time: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let mut client = server.connect("/ws").await;
+    let Some(Ok(Message::Text(text))) = client.next().await else { panic!("no message received") };
+    let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+    // the monotonic clock of the frame timestamps, sent after the time was queried
+    let monotonic = |value: &serde_json::Value| value["monotonic"].as_f64().unwrap();
+    assert!(monotonic(&message["server_time"]) >= monotonic(&time), "{} {}", message, time);
+    assert!((message["server_time"]["wall"].as_f64().unwrap() - time["wall"].as_f64().unwrap()).abs() < 5.0);
+    server.stop().await;
+}
@@ -0,0 +1,131 @@
+use std::process::Command;
+use std::time::Duration;
+
+use socketcan::{CanFrame, CanSocket, EmbeddedFrame, Frame, Socket, StandardId};
+
+use crate::harness::{expect, expect_frame, send, TestServer};
+use rust_vue::protocol::ServerMessage;
+
+/// Virtual CAN interface of a test, deleted when dropped
+struct Vcan {
+    name: String,
+}
+
+impl Vcan {
+    /// Add and set up the interface, none if unprivileged or the vcan module is missing
+    fn create(name: &str) -> Option<Vcan> {
+        let ip = |args: &[&str]| Command::new("ip").args(args).output().is_ok_and(|output| output.status.success());
+        // left over by an aborted run
+        ip(&["link", "delete", "dev", name]);
+        if !ip(&["link", "add", "dev", name, "type", "vcan"]) {
+            eprintln!("skipped, failed to add {}: requires CAP_NET_ADMIN and the vcan module", name);
+            return None;
+        }
+        let vcan = Vcan { name: name.to_string() };
+        ip(&["link", "set", "up", name]).then_some(vcan)
+    }
+
+    fn open(&self) -> CanSocket {
+        let socket = CanSocket::open(&self.name).unwrap();
+        socket.set_read_timeout(Duration::from_secs(5)).unwrap();
+        socket
+    }
+}
+
+impl Drop for Vcan {
+    fn drop(&mut self) {
+        let _ = Command::new("ip").args(["link", "delete", "dev", &self.name]).status();
+    }
+}
+
+#[tokio::test]
+async fn frame_received_by_client() {
+    let Some(vcan) = Vcan::create("vcanit0") else { return };
+    let server = TestServer::start(&["--can-dev", "vcanit0"]).await;
+    let mut client = server.connect("/ws").await;
+    expect(&mut client, |message| matches!(message, ServerMessage::Status(_)).then_some(())).await;
+    let socket = vcan.open();
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
+    // the device may be opened by the server right after the client connected
+    for _ in 0..50 {
+        socket.write_frame(&frame).unwrap();
+        let received = tokio::time::timeout(Duration::from_millis(100), expect_frame(&mut client, "123"));
+        if let Ok(received) = received.await {
+            assert_eq!(received, "123#DEADBEEF");
+            server.stop().await;
+            return;
+        }
+    }
+    panic!("frame not received");
+}
+
+#[tokio::test]
+async fn frame_written_by_client() {
+    let Some(vcan) = Vcan::create("vcanit1") else { return };
+    let socket = vcan.open();
+    let server = TestServer::start(&["--can-dev", "vcanit1"]).await;
+    let mut client = server.connect("/ws").await;
+    let mut written = false;
+    for _ in 0..50 {
+        send(&mut client, "18FEF100#0102030405060708").await;
+        written = expect(&mut client, |message| match message {
+            ServerMessage::Ack(ack) if ack.command == "frame" => Some(true),
+            ServerMessage::Error(error) if error.input.is_some() => Some(false),
+            _ => None,
+        })
+        .await;
+        if written {
+            break;
+        }
+        // until the supervisor opened the device
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(written, "frame not written");
+    let frame = tokio::task::spawn_blocking(move || loop {
+        let frame = socket.read_frame().unwrap();
+        if frame.raw_id() == 0x18FEF100 {
+            return frame;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(frame.is_extended());
+    assert_eq!(frame.data(), [1, 2, 3, 4, 5, 6, 7, 8]);
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn lin_frames_of_sllin() {
+    // a SocketCAN interface of LIN frames, as of the sllin line discipline
+    let Some(vcan) = Vcan::create("vcanit2") else { return };
+    let server = TestServer::start(&["--can-dev", "vcanit2", "--transport", "sllin"]).await;
+    let mut client = server.connect("/ws").await;
+    expect(&mut client, |message| matches!(message, ServerMessage::Status(_)).then_some(())).await;
+    let socket = vcan.open();
+    let frame = CanFrame::new(StandardId::new(0x10).unwrap(), &[0x01, 0x02]).unwrap();
+    let mut received = None;
+    // the device may be opened by the server right after the client connected
+    for _ in 0..50 {
+        socket.write_frame(&frame).unwrap();
+        let lin = expect(&mut client, |message| match message {
+            ServerMessage::Lin(lin) => Some(lin),
+            ServerMessage::Frame(frame) => panic!("LIN frame {} sent as CAN frame", frame.frame),
+            _ => None,
+        });
+        if let Ok(lin) = tokio::time::timeout(Duration::from_millis(100), lin).await {
+            received = Some(lin);
+            break;
+        }
+    }
+    let lin = received.expect("LIN frame not received");
+    assert_eq!((lin.id, lin.pid, lin.data.as_str(), lin.checksum), (0x10, 0x50, "0102", 0xAC));
+    // ids exceeding the 6 bits of LIN
+    send(&mut client, "123#00").await;
+    let error = expect(&mut client, |message| match message {
+        ServerMessage::Error(error) if error.input.is_some() => Some(error),
+        _ => None,
+    })
+    .await;
+    assert!(error.message.contains("LIN"), "{}", error.message);
+    server.stop().await;
+}
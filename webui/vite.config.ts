@@ -20,6 +20,10 @@ const pathSrc = path.resolve(__dirname, 'src')
 export default defineConfig({
   server: {
     port: 8080,
+    // connect the hot module reload directly, also if proxied by `--dev-proxy`
+    hmr: {
+      clientPort: 8080,
+    },
   },
   resolve: {
     alias: {